@@ -25,6 +25,26 @@ pub fn get_args() -> ArgMatches {
             .alias("r")
             .arg(arg!(-f --file <FILE> "path to png"))
             .arg(arg!(-c --chunk <CHUNK> "the chunk type to remove")))
+        .subcommand(
+            Command::new("embed-file")
+            .about("hides a file in the png, keeping its name")
+            .alias("ef")
+            .arg(arg!(-f --file <FILE> "path to png"))
+            .arg(arg!(-i --input <INPUT> "the file to hide"))
+            .arg(arg!(-o --output <FILE> "the output file").required(false)))
+        .subcommand(
+            Command::new("extract-file")
+            .about("writes a file hidden in the png")
+            .alias("xf")
+            .arg(arg!(-f --file <FILE> "path to png"))
+            .arg(arg!(-k --key <KEY> "the name the file was hidden under"))
+            .arg(arg!(-o --output <PATH> "where to write it, defaults to the current directory")
+                .required(false)))
+        .subcommand(
+            Command::new("check")
+            .about("checks the png for errors, like pngcheck")
+            .alias("c")
+            .arg(arg!(-f --file <FILE> "path to png")))
         .subcommand(
             Command::new("print")
             .about("print a message")
@@ -1,4 +1,4 @@
-use std::{fs, io::{Read, Write}, str::FromStr};
+use std::{fs, io::{self, Write}, str::FromStr};
 
 use nice_pics::prelude::*;
 use anyhow::{bail, Result};
@@ -7,38 +7,51 @@ use clap::ArgMatches;
 type Error = anyhow::Error;
 
 pub enum Commands {
-    ENCODE,
-    DECODE,
-    REMOVE,
-    PRINT,
+    Encode,
+    Decode,
+    Remove,
+    EmbedFile,
+    ExtractFile,
+    Check,
+    Print,
 }
 
+#[allow(dead_code, clippy::wrong_self_convention)]
 impl Commands {
     pub fn from_cmd(&self) -> &str {
         match self {
-            Commands::ENCODE => "encode",
-            Commands::DECODE => "decode",
-            Commands::REMOVE => "remove",
-            Commands::PRINT => "print",
+            Commands::Encode => "encode",
+            Commands::Decode => "decode",
+            Commands::Remove => "remove",
+            Commands::EmbedFile => "embed-file",
+            Commands::ExtractFile => "extract-file",
+            Commands::Check => "check",
+            Commands::Print => "print",
         }
     }
 
     pub fn to_cmd(s: &str) -> Commands {
         match s {
-            "encode" => Commands::ENCODE,
-            "decode" => Commands::DECODE,
-            "remove" => Commands::REMOVE,
-            "print" => Commands::PRINT,
-            _ => Commands::PRINT,
+            "encode" => Commands::Encode,
+            "decode" => Commands::Decode,
+            "remove" => Commands::Remove,
+            "embed-file" => Commands::EmbedFile,
+            "extract-file" => Commands::ExtractFile,
+            "check" => Commands::Check,
+            "print" => Commands::Print,
+            _ => Commands::Print,
         }
     }
 
     pub fn alias(&self) -> &str {
         match self {
-            Commands::ENCODE => "e",
-            Commands::DECODE => "d",
-            Commands::REMOVE => "r",
-            Commands::PRINT => "p",
+            Commands::Encode => "e",
+            Commands::Decode => "d",
+            Commands::Remove => "r",
+            Commands::EmbedFile => "ef",
+            Commands::ExtractFile => "xf",
+            Commands::Check => "c",
+            Commands::Print => "p",
         }
     }
 }
@@ -48,10 +61,13 @@ pub fn parse(args: ArgMatches) -> Result<(), Error> {
     if let Some((c, args)) = command {
         let c = Commands::to_cmd(c);
         match c {
-            Commands::ENCODE => encode(args)?,
-            Commands::DECODE => decode(args)?,
-            Commands::REMOVE => remove(args)?,
-            Commands::PRINT => print(args)?,
+            Commands::Encode => encode(args)?,
+            Commands::Decode => decode(args)?,
+            Commands::Remove => remove(args)?,
+            Commands::EmbedFile => embed_file(args)?,
+            Commands::ExtractFile => extract_file(args)?,
+            Commands::Check => check(args)?,
+            Commands::Print => print(args)?,
         }
     } else {
         bail!("no subcommand used");
@@ -69,19 +85,16 @@ fn encode(args: &ArgMatches) -> Result<(), Error> {
 
     let f = args.value_of("file").unwrap();
     let mut p = read_file(f)?;
-    p.remove_chunk("IEND");// end chunk removed as we can only append
     let ct = args.value_of("chunk").unwrap(); //chunk_type
-    p.remove_chunk(ct); // do not return err as it doesnt matter if chunk exists
+    let _ = p.remove_chunk(ct); // do not return err as it doesnt matter if chunk exists
     let m = args.value_of("msg").unwrap();
     let c = Chunk::new(ChunkType::from_str(ct)?, m.bytes().collect());
-    p.append_chunk(c);
-    p.append_chunk(Chunk::new(ChunkType::from_str("IEND")?, Vec::new()));
+    p.insert_before_type("IEND", c)?;
 
     let o = args.value_of("output");
 
-    let mut f = if o.is_some() { fs::File::create(o.unwrap())? } else { fs::File::create(f)? };
-    f.write_all(p.as_bytes().as_ref())?;
-    f.flush()?;
+    let f = if let Some(o) = o { fs::File::create(o)? } else { fs::File::create(f)? };
+    write_file(&p, f)?;
 
     Ok(())
 }
@@ -105,9 +118,42 @@ fn remove(args: &ArgMatches) -> Result<(), Error> {
     let mut p = read_file(f)?;
     let ct = args.value_of("chunk").unwrap();
     p.remove_chunk(ct)?;
-    let mut f = fs::File::create(f)?;
-    f.write_all(p.as_bytes().as_ref())?;
-    f.flush()?;
+    write_file(&p, fs::File::create(f)?)?;
+
+    Ok(())
+}
+
+fn embed_file(args: &ArgMatches) -> Result<(), Error> {
+    let f = args.value_of("file").unwrap();
+    let mut p = read_file(f)?;
+    let i = args.value_of("input").unwrap();
+    let key = p.embed_file(i, &EmbedOptions::default())?;
+    println!("hid {} under key {}", i, key);
+
+    let o = args.value_of("output").unwrap_or(f);
+    write_file(&p, fs::File::create(o)?)?;
+
+    Ok(())
+}
+
+fn extract_file(args: &ArgMatches) -> Result<(), Error> {
+    let f = args.value_of("file").unwrap();
+    let p = read_file(f)?;
+    let key = args.value_of("key").unwrap();
+    let o = args.value_of("output").unwrap_or(".");
+    let info = p.extract_file(key, o)?;
+    println!("extracted {} ({} bytes, crc {:08x})", info.name(), info.size(), info.crc());
+
+    Ok(())
+}
+
+fn check(args: &ArgMatches) -> Result<(), Error> {
+    let f = args.value_of("file").unwrap();
+    let f = fs::read(f)?;
+    let p = Png::from_bytes_with(&f, &ParseOptions::lenient())?;
+    let report = p.check();
+    println!("{}", report);
+    if !report.is_ok() { bail!("{} errors", report.error_count()); }
 
     Ok(())
 }
@@ -122,10 +168,14 @@ fn print(args: &ArgMatches) -> Result<(), Error> {
 
 fn read_file(p: &str) -> Result<Png, Error> {
     println!("reading {}", p);
-    let mut f = fs::File::open(p).unwrap();
-    let mut buf = Vec::new();
-    f.read_to_end(&mut buf);
+    let f = fs::File::open(p)?;
 
-    Png::try_from(buf.as_ref())
+    Ok(Png::from_reader(io::BufReader::new(f))?)
 }
 
+fn write_file(p: &Png, f: fs::File) -> Result<(), Error> {
+    let mut w = io::BufWriter::new(f);
+    p.write_to(&mut w)?;
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use nice_pics::prelude::*;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(nice_pics, PngError, PyException, "A file that is not a valid PNG, or a failed edit.");
+
+fn to_py(e: Error) -> PyErr {
+    PngError::new_err(e.to_string())
+}
+
+/// One chunk of a `Png`, a copy that does not change with it.
+#[pyclass(name = "Chunk", module = "nice_pics", frozen)]
+struct PyChunk {
+    chunk: Chunk,
+}
+
+#[pymethods]
+impl PyChunk {
+    /// The four letter type, e.g. `"IHDR"`.
+    #[getter]
+    fn chunk_type(&self) -> String {
+        self.chunk.chunk_type().to_string()
+    }
+
+    #[getter]
+    fn length(&self) -> u32 {
+        self.chunk.length()
+    }
+
+    #[getter]
+    fn crc(&self) -> u32 {
+        self.chunk.crc()
+    }
+
+    #[getter]
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.chunk.data())
+    }
+
+    /// True if the stored crc matches the type and data.
+    fn crc_ok(&self) -> bool {
+        self.chunk.has_valid_crc()
+    }
+
+    fn is_critical(&self) -> bool {
+        self.chunk.chunk_type().is_critical()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Chunk {} length={} crc={:08x}>", self.chunk.chunk_type(), self.chunk.length(), self.chunk.crc())
+    }
+}
+
+/// A parsed PNG file. Edits happen in memory, `to_bytes` or `save`
+/// write them out.
+#[pyclass(name = "Png", module = "nice_pics")]
+struct PyPng {
+    png: Png,
+}
+
+#[pymethods]
+impl PyPng {
+    /// Parses the bytes of a PNG file.
+    #[new]
+    fn new(data: &[u8]) -> PyResult<PyPng> {
+        Png::try_from(data).map(|png| PyPng { png }).map_err(to_py)
+    }
+
+    /// Reads and parses the file at `path`.
+    #[staticmethod]
+    fn open(path: PathBuf) -> PyResult<PyPng> {
+        let data = std::fs::read(path)?;
+        PyPng::new(&data)
+    }
+
+    /// Every chunk in file order.
+    fn chunks(&self) -> Vec<PyChunk> {
+        self.png.iter().map(|c| PyChunk { chunk: c.clone() }).collect()
+    }
+
+    /// `(keyword, text)` of every tEXt, zTXt and iTXt chunk in file order.
+    fn text_entries(&self) -> Vec<(String, String)> {
+        self.png.text_entries().into_iter().map(|e| (e.keyword().to_string(), e.text().to_string())).collect()
+    }
+
+    /// The text of the first entry under `keyword`, or `None`.
+    fn get_text(&self, keyword: &str) -> Option<String> {
+        self.png.text(keyword)
+    }
+
+    /// Stores `value` under `keyword`, replacing any text with that keyword.
+    fn set_text(&mut self, keyword: &str, value: &str) -> PyResult<()> {
+        self.png.set_text(keyword, value).map_err(to_py)
+    }
+
+    /// Removes every text chunk with `keyword`, returning how many there were.
+    fn remove_text(&mut self, keyword: &str) -> usize {
+        self.png.remove_text(keyword)
+    }
+
+    /// Hides `data` in the file under `key`, deflating it first if
+    /// `compress` is set. Replaces any payload already under `key`.
+    #[pyo3(signature = (key, data, compress = false))]
+    fn embed(&mut self, key: &str, data: &[u8], compress: bool) -> PyResult<()> {
+        let mut options = EmbedOptions::new();
+        if compress {
+            options = options.compression(Compression::Deflate(6));
+        }
+        self.png.embed_payload(key, data, &options).map_err(to_py)
+    }
+
+    /// The payload stored under `key`.
+    fn extract(&self, key: &str) -> PyResult<Cow<'_, [u8]>> {
+        self.png.extract_payload(key).map(Cow::Owned).map_err(to_py)
+    }
+
+    /// Keys of every payload in the file.
+    fn payload_keys(&self) -> Vec<String> {
+        self.png.payload_keys()
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.png.as_bytes())
+    }
+
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        std::fs::write(path, self.png.as_bytes())?;
+        Ok(())
+    }
+
+    fn __bytes__(&self) -> Cow<'_, [u8]> {
+        self.to_bytes()
+    }
+
+    fn __len__(&self) -> usize {
+        self.png.chunks().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Png {} chunks, {} bytes>", self.png.chunks().len(), self.png.file_size())
+    }
+}
+
+/// PNG parsing, text metadata and payloads from Python.
+#[pymodule]
+#[pyo3(name = "nice_pics")]
+fn nice_pics_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPng>()?;
+    m.add_class::<PyChunk>()?;
+    m.add("PngError", m.py().get_type::<PngError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    const IMG: &[u8] = include_bytes!("../../img.png");
+
+    fn run(code: &str) -> PyResult<()> {
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("nice_pics", pyo3::wrap_pymodule!(nice_pics_py)(py))?;
+            globals.set_item("IMG", IMG)?;
+            let code = std::ffi::CString::new(code).unwrap();
+            py.run(&code, Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn test_parse_and_chunks() {
+        run(r#"
+png = nice_pics.Png(bytes(IMG))
+chunks = png.chunks()
+assert len(png) == len(chunks)
+assert chunks[0].chunk_type == "IHDR" and chunks[0].length == 13
+assert chunks[0].crc_ok() and chunks[0].is_critical()
+assert chunks[-1].chunk_type == "IEND"
+assert bytes(png) == bytes(IMG)
+try:
+    nice_pics.Png(b"not a png")
+    raise AssertionError("parsed garbage")
+except nice_pics.PngError:
+    pass
+"#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_text_and_payloads() {
+        run(r#"
+png = nice_pics.Png(bytes(IMG))
+png.set_text("Author", "someone")
+assert png.get_text("Author") == "someone"
+assert ("Author", "someone") in png.text_entries()
+assert png.remove_text("Author") == 1 and png.get_text("Author") is None
+
+png.embed("notes", b"hidden" * 100, compress=True)
+png = nice_pics.Png(png.to_bytes())
+assert png.payload_keys() == ["notes"]
+assert png.extract("notes") == b"hidden" * 100
+try:
+    png.extract("missing")
+    raise AssertionError("extracted a missing payload")
+except nice_pics.PngError:
+    pass
+"#)
+        .unwrap();
+    }
+}
@@ -0,0 +1,1072 @@
+use std::time::Duration;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::extensions::expect;
+#[cfg(feature = "flate")]
+use crate::decode::{crop, image_data_len};
+#[cfg(feature = "flate")]
+use crate::encode::{deflate_idat, filter_image, EncodeOptions};
+#[cfg(feature = "flate")]
+use crate::ihdr::{ColorType, Ihdr};
+#[cfg(feature = "flate")]
+use crate::image::Image;
+use crate::known::KnownChunk;
+#[cfg(feature = "flate")]
+use crate::parse::Limits;
+use crate::png::Png;
+
+/// How many frames an APNG has and how often it plays, from acTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// 0 plays forever.
+    pub num_plays: u32,
+}
+
+impl KnownChunk for AnimationControl {
+    const TYPE: ChunkType = ChunkType::ACTL;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<AnimationControl, Error> {
+        let data = expect(chunk, ChunkType::ACTL)?;
+        if data.len() != 8 {
+            bail!("acTL data must be 8 bytes, got {}", data.len());
+        }
+        let num_frames = be_u32(&data[..4]);
+        if num_frames == 0 {
+            bail!("acTL frame count must be positive");
+        }
+        Ok(AnimationControl { num_frames, num_plays: be_u32(&data[4..]) })
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data = self.num_frames.to_be_bytes().to_vec();
+        data.extend(self.num_plays.to_be_bytes());
+        Chunk::new(ChunkType::ACTL, data)
+    }
+}
+
+/// What happens to the frame area before the next frame is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DisposeOp {
+    /// Left as it is.
+    #[default]
+    None,
+    /// Cleared to transparent black.
+    Background,
+    /// Put back to what it was before the frame.
+    Previous,
+}
+
+/// How a frame is drawn onto the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendOp {
+    /// Replaces the area, alpha included.
+    #[default]
+    Source,
+    /// Alpha composited over the area.
+    Over,
+}
+
+/// The geometry and timing of one frame, from fcTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameControl {
+    /// The place of this chunk among the fcTL and fdAT chunks, from 0.
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    /// The delay is `delay_num / delay_den` seconds, a denominator
+    /// of 0 meaning 100.
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl FrameControl {
+    /// How long the frame is shown.
+    pub fn delay(&self) -> Duration {
+        let den = if self.delay_den == 0 { 100 } else { self.delay_den };
+        Duration::from_nanos(self.delay_num as u64 * 1_000_000_000 / den as u64)
+    }
+}
+
+impl KnownChunk for FrameControl {
+    const TYPE: ChunkType = ChunkType::FCTL;
+
+    fn parse(chunk: &Chunk) -> Result<FrameControl, Error> {
+        let data = expect(chunk, ChunkType::FCTL)?;
+        if data.len() != 26 {
+            bail!("fcTL data must be 26 bytes, got {}", data.len());
+        }
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            n => bail!("unknown fcTL dispose op: {}", n),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            n => bail!("unknown fcTL blend op: {}", n),
+        };
+        let control = FrameControl {
+            sequence_number: be_u32(&data[..4]),
+            width: be_u32(&data[4..8]),
+            height: be_u32(&data[8..12]),
+            x_offset: be_u32(&data[12..16]),
+            y_offset: be_u32(&data[16..20]),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op,
+            blend_op,
+        };
+        if control.width == 0 || control.height == 0 {
+            bail!("fcTL frame is empty: {}x{}", control.width, control.height);
+        }
+        Ok(control)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(26);
+        for n in [self.sequence_number, self.width, self.height, self.x_offset, self.y_offset] {
+            data.extend(n.to_be_bytes());
+        }
+        data.extend(self.delay_num.to_be_bytes());
+        data.extend(self.delay_den.to_be_bytes());
+        data.push(self.dispose_op as u8);
+        data.push(self.blend_op as u8);
+        Chunk::new(ChunkType::FCTL, data)
+    }
+}
+
+/// Part of the compressed data of a frame after the first, from fdAT.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FrameData {
+    pub sequence_number: u32,
+    /// The same kind of data an IDAT chunk holds.
+    pub data: Vec<u8>,
+}
+
+impl KnownChunk for FrameData {
+    const TYPE: ChunkType = ChunkType::FDAT;
+
+    fn parse(chunk: &Chunk) -> Result<FrameData, Error> {
+        let data = expect(chunk, ChunkType::FDAT)?;
+        if data.len() < 4 {
+            bail!("fdAT data must be at least 4 bytes, got {}", data.len());
+        }
+        Ok(FrameData { sequence_number: be_u32(&data[..4]), data: data[4..].to_vec() })
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data = self.sequence_number.to_be_bytes().to_vec();
+        data.extend(&self.data);
+        Chunk::new(ChunkType::FDAT, data)
+    }
+}
+
+/// One frame of an APNG: its fcTL and its compressed image data, joined
+/// from the IDAT chunks for the default image or the fdAT chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Frame {
+    control: FrameControl,
+    data: Vec<u8>,
+    default_image: bool,
+}
+
+impl Frame {
+    pub fn control(&self) -> &FrameControl {
+        &self.control
+    }
+
+    /// The zlib stream of the frame, like the joined IDAT data of a PNG.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether the frame is the default image, the one viewers without
+    /// APNG support show.
+    pub fn is_default_image(&self) -> bool {
+        self.default_image
+    }
+}
+
+impl Png {
+    /// Whether the file has an acTL chunk, so is an APNG.
+    pub fn is_animated(&self) -> bool {
+        self.chunk_by_type("acTL").is_some()
+    }
+
+    /// The acTL chunk, parsed.
+    ///
+    /// # Errors
+    /// returns an Error if it is malformed.
+    pub fn animation_control(&self) -> Result<Option<AnimationControl>, Error> {
+        self.typed_chunk()
+    }
+
+    /// The frames of an APNG in file order, none if it is not animated.
+    /// A default image without an fcTL before it is not a frame. Frames
+    /// with a malformed fcTL are left out, as are fdAT chunks without a
+    /// frame or too short to hold a sequence number.
+    pub fn frames(&self) -> impl Iterator<Item = Frame> {
+        let mut frames: Vec<Frame> = Vec::new();
+        if !self.is_animated() {
+            return frames.into_iter();
+        }
+        let mut seen_idat = false;
+        // whether the fdAT and IDAT chunks so far belong to the last frame
+        let mut open = false;
+        for chunk in self.iter() {
+            match *chunk.chunk_type() {
+                ChunkType::FCTL => {
+                    open = match FrameControl::parse(chunk) {
+                        Ok(control) => {
+                            frames.push(Frame { control, data: Vec::new(), default_image: !seen_idat });
+                            true
+                        }
+                        Err(_) => false,
+                    };
+                }
+                ChunkType::IDAT => {
+                    seen_idat = true;
+                    match frames.last_mut() {
+                        Some(frame) if open && frame.default_image => frame.data.extend(chunk.data()),
+                        _ => open = false,
+                    }
+                }
+                ChunkType::FDAT => match frames.last_mut() {
+                    Some(frame) if open && !frame.default_image && chunk.data().len() >= 4 => {
+                        frame.data.extend(&chunk.data()[4..])
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        frames.into_iter()
+    }
+}
+
+/// An animated PNG, a `Png` known to have a valid acTL chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Apng {
+    png: Png,
+}
+
+impl Apng {
+    /// Encodes an animation showing each image for its duration, all of
+    /// them the full canvas, played `loops` times or forever for 0. The
+    /// first image is also the default image. If the images do not all
+    /// share a bit depth, colour type, palette and tRNS they are stored
+    /// as 8 bit RGBA. Delays are kept to the millisecond when they fit
+    /// an fcTL, longer ones are rounded to hundredths, tenths or seconds.
+    ///
+    /// # Errors
+    /// returns an Error if there are no images, their sizes differ, a
+    /// delay is over 65535 seconds or the image data can not be compressed.
+    #[cfg(feature = "flate")]
+    pub fn from_frames(frames: Vec<(Image, Duration)>, loops: u32) -> Result<Apng, Error> {
+        let Some((first, _)) = frames.first() else {
+            bail!("an animation needs at least one frame");
+        };
+        let (width, height) = (first.width(), first.height());
+        if let Some((image, _)) = frames.iter().find(|(i, _)| (i.width(), i.height()) != (width, height)) {
+            bail!("frame is {}x{}, the first is {}x{}", image.width(), image.height(), width, height);
+        }
+        let same_format = frames.iter().all(|(i, _)| {
+            let (a, b) = (i.header(), first.header());
+            a.bit_depth() == b.bit_depth()
+                && a.color_type() == b.color_type()
+                && i.palette() == first.palette()
+                && i.trns() == first.trns()
+        });
+
+        let (ihdr, palette, trns) = if same_format {
+            (first.header().clone(), first.palette(), first.trns())
+        } else {
+            (Ihdr::new(width, height, 8, ColorType::Rgba)?, None, None)
+        };
+        let mut builder = Png::builder(ihdr.clone());
+        let actl = AnimationControl { num_frames: frames.len() as u32, num_plays: loops };
+        builder = builder.chunk(actl.to_chunk())?;
+        // PLTE is a suggestion for truecolour and not allowed for greyscale
+        if let (Some(palette), ColorType::Indexed | ColorType::Rgb | ColorType::Rgba) =
+            (palette, ihdr.color_type())
+        {
+            builder = builder.palette(palette.to_vec());
+        }
+        if let Some(trns) = trns {
+            builder = builder.chunk(Chunk::new(ChunkType::TRNS, trns.to_vec()))?;
+        }
+
+        let options = EncodeOptions::default();
+        let mut sequence_number = 0;
+        for (n, (image, delay)) in frames.iter().enumerate() {
+            let (delay_num, delay_den) = delay_fraction(*delay)?;
+            let control = FrameControl {
+                sequence_number,
+                width,
+                height,
+                x_offset: 0,
+                y_offset: 0,
+                delay_num,
+                delay_den,
+                dispose_op: DisposeOp::None,
+                blend_op: BlendOp::Source,
+            };
+            sequence_number += 1;
+            let pixels = if same_format { image.data().to_vec() } else { image.to_rgba8()?.into_data() };
+            let parts = deflate_idat(&filter_image(&ihdr, &pixels, options.filter), &options)?;
+            if n == 0 {
+                builder = builder.chunk(control.to_chunk())?;
+                for part in parts {
+                    builder = builder.idat(part);
+                }
+                continue;
+            }
+            builder = builder.trailing_chunk(control.to_chunk())?;
+            for data in parts {
+                builder = builder.trailing_chunk(FrameData { sequence_number, data }.to_chunk())?;
+                sequence_number += 1;
+            }
+        }
+        Ok(Apng { png: builder.build()? })
+    }
+
+    pub fn as_png(&self) -> &Png {
+        &self.png
+    }
+
+    pub fn into_png(self) -> Png {
+        self.png
+    }
+
+    /// The acTL chunk, checked when the `Apng` was made.
+    pub fn animation_control(&self) -> AnimationControl {
+        self.png.animation_control().ok().flatten().expect("Apng without a valid acTL")
+    }
+
+    /// How many frames there are, counting the fcTL chunks rather than
+    /// trusting acTL.
+    pub fn frame_count(&self) -> usize {
+        self.png.frames().count()
+    }
+
+    /// How long one play of the animation takes.
+    pub fn duration(&self) -> Duration {
+        self.png.frames().map(|f| f.control().delay()).sum()
+    }
+
+    /// Shows frame `index` for `delay`, see `from_frames` for how exact.
+    ///
+    /// # Errors
+    /// returns an Error if there is no such frame, the delay is over
+    /// 65535 seconds or the frame chunks are malformed.
+    pub fn set_frame_delay(&mut self, index: usize, delay: Duration) -> Result<(), Error> {
+        let (delay_num, delay_den) = delay_fraction(delay)?;
+        self.edit_frames(|frames| {
+            let Some(frame) = frames.get_mut(index) else {
+                bail!("no frame {}, there are {}", index, frames.len());
+            };
+            frame.control.delay_num = delay_num;
+            frame.control.delay_den = delay_den;
+            Ok(())
+        })
+    }
+
+    /// Removes frame `index`. If it was the default image, that stays
+    /// in the file for viewers without APNG support but is no longer
+    /// part of the animation.
+    ///
+    /// # Errors
+    /// returns an Error if there is no such frame, it is the only one
+    /// or the frame chunks are malformed.
+    pub fn remove_frame(&mut self, index: usize) -> Result<(), Error> {
+        self.edit_frames(|frames| {
+            if index >= frames.len() {
+                bail!("no frame {}, there are {}", index, frames.len());
+            }
+            if frames.len() == 1 {
+                bail!("can not remove the only frame");
+            }
+            frames.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Puts the frames in a new order, frame `order[n]` becoming frame
+    /// `n`. A default image moved away from the start stays the default
+    /// image, with its data copied to fdAT chunks for the animation.
+    ///
+    /// # Errors
+    /// returns an Error if `order` is not a permutation of the frame
+    /// indices or the frame chunks are malformed.
+    pub fn reorder(&mut self, order: &[usize]) -> Result<(), Error> {
+        self.edit_frames(|frames| {
+            let mut sorted = order.to_vec();
+            sorted.sort_unstable();
+            if sorted != (0..frames.len()).collect::<Vec<usize>>() {
+                bail!("{:?} is not an order of {} frames", order, frames.len());
+            }
+            let mut old: Vec<Option<FramePart>> = frames.drain(..).map(Some).collect();
+            frames.extend(order.iter().filter_map(|n| old[*n].take()));
+            Ok(())
+        })
+    }
+
+    /// Plays the animation `loops` times, or forever for 0.
+    pub fn set_loop_count(&mut self, loops: u32) {
+        let actl = AnimationControl { num_plays: loops, ..self.animation_control() };
+        self.png.set_typed_chunk(&actl);
+    }
+
+    /// Applies `edit` to the frames, then writes their fcTL and fdAT chunks
+    /// again with sequence numbers counting up from 0 and updates acTL.
+    fn edit_frames<F>(&mut self, edit: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<FramePart>) -> Result<(), Error>,
+    {
+        let mut frames = self.frame_parts()?;
+        edit(&mut frames)?;
+
+        let chunks = self.png.chunks_mut();
+        chunks.retain(|c| !matches!(*c.chunk_type(), ChunkType::FCTL | ChunkType::FDAT));
+        let first_idat = chunks.iter().position(|c| *c.chunk_type() == ChunkType::IDAT);
+        let mut at = match chunks.iter().rposition(|c| *c.chunk_type() == ChunkType::IDAT) {
+            Some(n) => n + 1,
+            None => chunks.iter().position(|c| *c.chunk_type() == ChunkType::IEND).unwrap_or(chunks.len()),
+        };
+        let mut sequence_number = 0;
+        for (n, frame) in frames.iter().enumerate() {
+            let control = FrameControl { sequence_number, ..frame.control };
+            sequence_number += 1;
+            if n == 0 && frame.default_image {
+                if let Some(first_idat) = first_idat {
+                    chunks.insert(first_idat, control.to_chunk());
+                    at += 1;
+                    continue;
+                }
+            }
+            chunks.insert(at, control.to_chunk());
+            at += 1;
+            for data in &frame.data {
+                chunks.insert(at, FrameData { sequence_number, data: data.clone() }.to_chunk());
+                sequence_number += 1;
+                at += 1;
+            }
+        }
+        let actl = AnimationControl { num_frames: frames.len() as u32, ..self.animation_control() };
+        self.png.set_typed_chunk(&actl);
+        Ok(())
+    }
+
+    /// The frames with their data still split like in the file.
+    fn frame_parts(&self) -> Result<Vec<FramePart>, Error> {
+        let mut frames: Vec<FramePart> = Vec::new();
+        let mut seen_idat = false;
+        for chunk in self.png.iter() {
+            match *chunk.chunk_type() {
+                ChunkType::FCTL => frames.push(FramePart {
+                    control: FrameControl::parse(chunk)?,
+                    data: Vec::new(),
+                    default_image: !seen_idat,
+                }),
+                ChunkType::IDAT => {
+                    seen_idat = true;
+                    if let Some(frame) = frames.last_mut().filter(|f| f.default_image) {
+                        frame.data.push(chunk.data().to_vec());
+                    }
+                }
+                ChunkType::FDAT => match frames.last_mut() {
+                    Some(frame) if !frame.default_image => frame.data.push(FrameData::parse(chunk)?.data),
+                    _ => bail!("fdAT chunk without a frame"),
+                },
+                _ => {}
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Draws the frames onto the canvas one after the other, following
+    /// their blend and dispose ops, and returns what the canvas looks
+    /// like with each one as a standalone 8 bit RGBA `Png`.
+    ///
+    /// # Errors
+    /// returns an Error if a frame does not fit on the canvas or its
+    /// data can not be decoded.
+    #[cfg(feature = "flate")]
+    pub fn extract_frames(&self) -> Result<Vec<Png>, Error> {
+        let ihdr = self.png.header()?;
+        let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+        let mut canvas = vec![0u8; canvas_len(ihdr.width(), ihdr.height())?];
+        let mut out = Vec::new();
+        for (n, frame) in self.png.frames().enumerate() {
+            let c = frame.control();
+            if c.x_offset as u64 + c.width as u64 > width as u64
+                || c.y_offset as u64 + c.height as u64 > height as u64
+            {
+                let (w, h, x, y) = (c.width, c.height, c.x_offset, c.y_offset);
+                bail!("frame {} of {}x{} at {},{} is not inside the canvas", n, w, h, x, y);
+            }
+            let pixels = self.decode_frame(&ihdr, &frame)?;
+            let (x0, y0, w) = (c.x_offset as usize, c.y_offset as usize, c.width as usize);
+            let rows = |y: usize| (y0 + y) * width * 4 + x0 * 4..(y0 + y) * width * 4 + (x0 + w) * 4;
+            let before: Vec<u8> = (0..c.height as usize).flat_map(|y| canvas[rows(y)].to_vec()).collect();
+
+            for y in 0..c.height as usize {
+                let src = &pixels[y * w * 4..(y + 1) * w * 4];
+                let dst = &mut canvas[rows(y)];
+                match c.blend_op {
+                    BlendOp::Source => dst.copy_from_slice(src),
+                    BlendOp::Over => {
+                        for (d, s) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+                            d.copy_from_slice(&over([s[0], s[1], s[2], s[3]], [d[0], d[1], d[2], d[3]]));
+                        }
+                    }
+                }
+            }
+            let options = EncodeOptions::default();
+            out.push(Png::from_pixels(width as u32, height as u32, ColorType::Rgba, &canvas, options)?);
+
+            // a first frame disposed to the previous canvas is cleared
+            let dispose = match c.dispose_op {
+                DisposeOp::Previous if n == 0 => DisposeOp::Background,
+                op => op,
+            };
+            for y in 0..c.height as usize {
+                let row = &mut canvas[rows(y)];
+                match dispose {
+                    DisposeOp::None => {}
+                    DisposeOp::Background => row.fill(0),
+                    DisposeOp::Previous => row.copy_from_slice(&before[y * w * 4..(y + 1) * w * 4]),
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Lays the frames from `extract_frames` out on one 8 bit RGBA image,
+    /// `columns` to a row, left to right and top to bottom. Cells after
+    /// the last frame are transparent.
+    ///
+    /// # Errors
+    /// returns an Error if `columns` is 0, the sheet would be too big or
+    /// a frame can not be decoded.
+    #[cfg(feature = "flate")]
+    pub fn to_sprite_sheet(&self, columns: u32) -> Result<Png, Error> {
+        if columns == 0 {
+            bail!("a sprite sheet needs at least one column");
+        }
+        let ihdr = self.png.header()?;
+        let frames = self.extract_frames()?;
+        let columns = columns.min(frames.len().max(1) as u32);
+        let rows = (frames.len() as u32).div_ceil(columns);
+        let (frame_w, frame_h) = (ihdr.width() as usize, ihdr.height() as usize);
+        let size = (ihdr.width().checked_mul(columns), ihdr.height().checked_mul(rows));
+        let (Some(width), Some(height)) = size else {
+            bail!("sprite sheet of {} by {} frames is too big", columns, rows);
+        };
+        let mut sheet = vec![0u8; canvas_len(width, height)?];
+        for (n, frame) in frames.iter().enumerate() {
+            let pixels = frame.decode_pixels()?.to_rgba8()?;
+            let (x0, y0) = (n % columns as usize * frame_w, n / columns as usize * frame_h);
+            for (y, row) in pixels.data().chunks_exact(frame_w * 4).enumerate() {
+                let at = ((y0 + y) * width as usize + x0) * 4;
+                sheet[at..at + row.len()].copy_from_slice(row);
+            }
+        }
+        Png::from_pixels(width, height, ColorType::Rgba, &sheet, EncodeOptions::default())
+    }
+
+    /// Cuts `sheet` into `frame_width` x `frame_height` cells, left to
+    /// right and top to bottom, and plays them as frames of `delay` each,
+    /// forever. Fully transparent cells at the end are left out, like the
+    /// ones `to_sprite_sheet` pads the last row with. Frames keep the
+    /// format of the sheet.
+    ///
+    /// # Errors
+    /// returns an Error if the sheet is not a whole number of cells, can
+    /// not be decoded, or as `from_frames`.
+    #[cfg(feature = "flate")]
+    pub fn from_sprite_sheet(
+        sheet: &Png,
+        frame_width: u32,
+        frame_height: u32,
+        delay: Duration,
+    ) -> Result<Apng, Error> {
+        let image = sheet.decode_pixels()?;
+        let (width, height) = (image.width(), image.height());
+        if frame_width == 0 || frame_height == 0 || width % frame_width != 0 || height % frame_height != 0 {
+            bail!("{}x{} sheet can not be cut in {}x{} frames", width, height, frame_width, frame_height);
+        }
+        let ihdr = Ihdr::new(frame_width, frame_height, image.bit_depth(), image.color_type())?;
+        let mut frames = Vec::new();
+        for y in (0..height).step_by(frame_height as usize) {
+            for x in (0..width).step_by(frame_width as usize) {
+                let pixels = crop(image.header(), image.data(), x, y, frame_width, frame_height);
+                let palette = image.palette().map(<[u8]>::to_vec);
+                let frame = Image::new(ihdr.clone(), pixels, palette, image.trns().map(<[u8]>::to_vec));
+                frames.push((frame, delay));
+            }
+        }
+        while frames.len() > 1 && is_transparent(&frames[frames.len() - 1].0)? {
+            frames.pop();
+        }
+        Apng::from_frames(frames, 0)
+    }
+
+    /// The pixels of `frame` as 8 bit RGBA, decoded with the palette and
+    /// tRNS of the file.
+    #[cfg(feature = "flate")]
+    fn decode_frame(&self, ihdr: &Ihdr, frame: &Frame) -> Result<Vec<u8>, Error> {
+        let c = frame.control();
+        let header = Ihdr::new(c.width, c.height, ihdr.bit_depth(), ihdr.color_type())?
+            .with_interlace(ihdr.is_interlaced());
+        let mut chunks = vec![header.to_chunk()];
+        chunks.extend(self.png.chunk_by_type("PLTE").cloned());
+        chunks.extend(self.png.chunk_by_type("tRNS").cloned());
+        chunks.push(Chunk::new(ChunkType::IDAT, frame.data().to_vec()));
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        Ok(Png::from_chunks(chunks).decode_pixels()?.to_rgba8()?.into_data())
+    }
+}
+
+/// Bytes of a `width` x `height` 8 bit RGBA canvas.
+///
+/// # Errors
+/// returns `LimitExceeded` if it would not fit `Limits::default`, like
+/// image data of that size, so a crafted header can not make us
+/// allocate it.
+#[cfg(feature = "flate")]
+fn canvas_len(width: u32, height: u32) -> Result<usize, Error> {
+    image_data_len(&Ihdr::new(width, height, 8, ColorType::Rgba)?, Limits::default().max_decompressed)?;
+    Ok(width as usize * height as usize * 4)
+}
+
+/// A frame while the frame chunks are written again.
+#[derive(Debug)]
+struct FramePart {
+    control: FrameControl,
+    data: Vec<Vec<u8>>,
+    default_image: bool,
+}
+
+#[cfg(feature = "flate")]
+fn is_transparent(image: &Image) -> Result<bool, Error> {
+    Ok(image.to_rgba8()?.data().chunks_exact(4).all(|p| p[3] == 0))
+}
+
+/// `src` alpha composited over `dst`, both 8 bit RGBA without
+/// premultiplied alpha.
+#[cfg(feature = "flate")]
+fn over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    match src[3] {
+        255 => return src,
+        0 => return dst,
+        _ => {}
+    }
+    let (sa, da) = (src[3] as u32, dst[3] as u32);
+    let dst_weight = da * (255 - sa) / 255;
+    let alpha = sa + dst_weight;
+    let mix = |s: u8, d: u8| ((s as u32 * sa + d as u32 * dst_weight) / alpha) as u8;
+    [mix(src[0], dst[0]), mix(src[1], dst[1]), mix(src[2], dst[2]), alpha as u8]
+}
+
+impl TryFrom<Png> for Apng {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if there is no acTL chunk or it is malformed.
+    fn try_from(png: Png) -> Result<Apng, Error> {
+        if png.animation_control()?.is_none() {
+            bail!("not an animated PNG, there is no acTL chunk");
+        }
+        Ok(Apng { png })
+    }
+}
+
+/// `delay` as an fcTL numerator and denominator, as exact as fits.
+fn delay_fraction(delay: Duration) -> Result<(u16, u16), Error> {
+    let millis = delay.as_millis();
+    for den in [1000u16, 100, 10, 1] {
+        let num = (millis * den as u128 + 500) / 1000;
+        if let Ok(num) = u16::try_from(num) {
+            return Ok((num, den));
+        }
+    }
+    bail!("frame delay is too long: {:?}", delay)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn control(sequence_number: u32, width: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height: 225,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::Background,
+            blend_op: BlendOp::Over,
+        }
+    }
+
+    /// Checks the fcTL and fdAT sequence numbers count up from 0.
+    fn assert_sequential(png: &Png) {
+        let sequence: Vec<u32> = png
+            .iter()
+            .filter_map(|c| match *c.chunk_type() {
+                ChunkType::FCTL => Some(FrameControl::parse(c).unwrap().sequence_number),
+                ChunkType::FDAT => Some(FrameData::parse(c).unwrap().sequence_number),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sequence, (0..sequence.len() as u32).collect::<Vec<u32>>());
+    }
+
+    /// img.png with a default image frame and one more from fdAT.
+    fn animated() -> Png {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_typed_chunk(&AnimationControl { num_frames: 2, num_plays: 0 });
+        png.insert_before_type("IDAT", control(0, 225).to_chunk()).unwrap();
+        png.insert_before_type("IEND", control(1, 10).to_chunk()).unwrap();
+        let fdat = FrameData { sequence_number: 2, data: vec![1, 2, 3] };
+        png.insert_before_type("IEND", fdat.to_chunk()).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let actl = AnimationControl { num_frames: 3, num_plays: 2 };
+        assert_eq!(actl.to_chunk().data(), [0, 0, 0, 3, 0, 0, 0, 2]);
+        assert_eq!(AnimationControl::parse(&actl.to_chunk()).unwrap(), actl);
+        assert!(AnimationControl::parse(&Chunk::new(ChunkType::ACTL, vec![0; 8])).is_err());
+
+        let fctl = control(7, 20);
+        let chunk = fctl.to_chunk();
+        assert_eq!(chunk.length(), 26);
+        assert_eq!(&chunk.data()[20..], [0, 1, 0, 10, 1, 1]);
+        assert_eq!(FrameControl::parse(&chunk).unwrap(), fctl);
+        assert_eq!(fctl.delay(), Duration::from_millis(100));
+        assert_eq!(FrameControl { delay_den: 0, ..fctl }.delay(), Duration::from_millis(10));
+        let mut bad = chunk.data().to_vec();
+        bad[24] = 3;
+        assert!(FrameControl::parse(&Chunk::new(ChunkType::FCTL, bad)).is_err());
+        assert!(FrameControl::parse(&control(0, 0).to_chunk()).is_err());
+
+        let fdat = FrameData { sequence_number: 1, data: vec![9] };
+        assert_eq!(fdat.to_chunk().data(), [0, 0, 0, 1, 9]);
+        assert_eq!(FrameData::parse(&fdat.to_chunk()).unwrap(), fdat);
+        assert!(FrameData::parse(&Chunk::new(ChunkType::FDAT, vec![0; 3])).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let still = Png::try_from(IMG).unwrap();
+        assert!(!still.is_animated());
+        assert_eq!(still.frames().count(), 0);
+
+        let png = animated();
+        assert!(png.is_animated());
+        assert_eq!(png.animation_control().unwrap().unwrap().num_frames, 2);
+        let frames: Vec<Frame> = png.frames().collect();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_default_image());
+        assert_eq!(frames[0].control().width, 225);
+        let idat: Vec<u8> = png.chunks_of_type("IDAT").flat_map(|c| c.data().to_vec()).collect();
+        assert_eq!(frames[0].data(), idat);
+        assert!(!frames[1].is_default_image());
+        assert_eq!(frames[1].control().sequence_number, 1);
+        assert_eq!(frames[1].data(), [1, 2, 3]);
+
+        let apng = Apng::try_from(png).unwrap();
+        assert_eq!(apng.frame_count(), 2);
+        assert_eq!(apng.duration(), Duration::from_millis(200));
+    }
+
+    #[cfg(feature = "flate")]
+    fn gray(level: u8) -> Image {
+        Image::new(Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap(), vec![level; 4], None, None)
+    }
+
+    /// The pixels of `frame`, decoded as a still image with the header of `png`.
+    #[cfg(feature = "flate")]
+    fn decode_frame(png: &Png, frame: &Frame) -> Vec<u8> {
+        let ihdr = png.chunk_by_type("IHDR").unwrap().clone();
+        let idat = Chunk::new(ChunkType::IDAT, frame.data().to_vec());
+        let still = Png::from_chunks(vec![ihdr, idat, Chunk::new(ChunkType::IEND, vec![])]);
+        still.decode_pixels().unwrap().data().to_vec()
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_from_frames() {
+        let frames = vec![
+            (gray(0), Duration::from_millis(40)),
+            (gray(128), Duration::from_millis(100)),
+            (gray(255), Duration::from_secs(100)),
+        ];
+        let apng = Apng::from_frames(frames, 3).unwrap();
+        assert_eq!(apng.animation_control(), AnimationControl { num_frames: 3, num_plays: 3 });
+        let png = apng.as_png();
+        assert_eq!(png.decode_pixels().unwrap().data(), [0; 4]);
+
+        let frames: Vec<Frame> = png.frames().collect();
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].is_default_image());
+        let delays: Vec<Duration> = frames.iter().map(|f| f.control().delay()).collect();
+        assert_eq!(delays, [40, 100, 100_000].map(Duration::from_millis));
+        assert_eq!((frames[2].control().delay_num, frames[2].control().delay_den), (10000, 100));
+        assert_eq!(decode_frame(png, &frames[1]), [128; 4]);
+        assert_eq!(decode_frame(png, &frames[2]), [255; 4]);
+
+        assert_sequential(png);
+        assert_eq!(png.chunks()[1].chunk_type(), &ChunkType::ACTL);
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_from_frames_mixed_formats() {
+        let rgb = Image::new(Ihdr::new(2, 2, 8, ColorType::Rgb).unwrap(), vec![9; 12], None, None);
+        let apng = Apng::from_frames(vec![(gray(1), Duration::ZERO), (rgb, Duration::ZERO)], 0).unwrap();
+        let png = apng.as_png();
+        assert_eq!(png.header().unwrap().color_type(), ColorType::Rgba);
+        let frames: Vec<Frame> = png.frames().collect();
+        assert_eq!(decode_frame(png, &frames[1]), [9, 9, 9, 255].repeat(4));
+
+        let small = Image::new(Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap(), vec![0], None, None);
+        assert!(Apng::from_frames(vec![(gray(1), Duration::ZERO), (small, Duration::ZERO)], 0).is_err());
+        assert!(Apng::from_frames(vec![], 0).is_err());
+        assert!(Apng::from_frames(vec![(gray(1), Duration::from_secs(70000))], 0).is_err());
+        assert!(Apng::try_from(Png::try_from(IMG).unwrap()).is_err());
+        assert!(Apng::try_from(apng.into_png()).is_ok());
+    }
+
+    /// A frame of 8 bit RGBA `pixels`, compressed like IDAT data.
+    #[cfg(feature = "flate")]
+    fn rgba_frame(control: FrameControl, pixels: &[u8]) -> (FrameControl, Vec<u8>) {
+        let ihdr = Ihdr::new(control.width, control.height, 8, ColorType::Rgba).unwrap();
+        let options = EncodeOptions { max_idat_size: 0, ..Default::default() };
+        let data = deflate_idat(&filter_image(&ihdr, pixels, options.filter), &options).unwrap().concat();
+        (control, data)
+    }
+
+    /// A 2x1 RGBA animation of `frames`, the first one the default image.
+    #[cfg(feature = "flate")]
+    fn assemble(frames: Vec<(FrameControl, Vec<u8>)>) -> Apng {
+        let ihdr = Ihdr::new(2, 1, 8, ColorType::Rgba).unwrap();
+        let actl = AnimationControl { num_frames: frames.len() as u32, num_plays: 0 };
+        let mut chunks = vec![ihdr.to_chunk(), actl.to_chunk()];
+        for (n, (control, data)) in frames.into_iter().enumerate() {
+            chunks.push(control.to_chunk());
+            chunks.push(match n {
+                0 => Chunk::new(ChunkType::IDAT, data),
+                _ => FrameData { sequence_number: control.sequence_number + 1, data }.to_chunk(),
+            });
+        }
+        chunks.push(Chunk::new(ChunkType::IEND, vec![]));
+        Apng::try_from(Png::from_chunks(chunks)).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_extract_frames() {
+        let at = |sequence_number, x, dispose_op, blend_op| FrameControl {
+            sequence_number,
+            width: 1,
+            height: 1,
+            x_offset: x,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 4,
+            dispose_op,
+            blend_op,
+        };
+        let full = FrameControl { width: 2, ..at(0, 0, DisposeOp::None, BlendOp::Source) };
+        let apng = assemble(vec![
+            rgba_frame(full, &[255, 0, 0, 255, 255, 0, 0, 255]),
+            rgba_frame(at(1, 1, DisposeOp::Previous, BlendOp::Over), &[0, 0, 255, 128]),
+            rgba_frame(at(3, 0, DisposeOp::Background, BlendOp::Source), &[0, 255, 0, 255]),
+            rgba_frame(at(5, 1, DisposeOp::None, BlendOp::Source), &[0, 0, 0, 0]),
+        ]);
+        assert_eq!(apng.frame_count(), 4);
+        assert_eq!(apng.duration(), Duration::from_secs(1));
+
+        let pixels: Vec<Vec<u8>> = apng
+            .extract_frames()
+            .unwrap()
+            .iter()
+            .map(|png| png.decode_pixels().unwrap().data().to_vec())
+            .collect();
+        assert_eq!(pixels[0], [255, 0, 0, 255, 255, 0, 0, 255]);
+        assert_eq!(pixels[1], [255, 0, 0, 255, 127, 0, 128, 255]);
+        assert_eq!(pixels[2], [0, 255, 0, 255, 255, 0, 0, 255]);
+        assert_eq!(pixels[3], [0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_extract_frames_checks_bounds() {
+        let control = FrameControl { x_offset: 1, ..control(0, 2) };
+        let apng = assemble(vec![rgba_frame(FrameControl { height: 1, ..control }, &[0; 8])]);
+        assert!(apng.extract_frames().is_err());
+
+        let frames = vec![(gray(7), Duration::from_millis(30)), (gray(8), Duration::from_millis(20))];
+        let apng = Apng::from_frames(frames, 0).unwrap();
+        let extracted = apng.extract_frames().unwrap();
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(apng.duration(), Duration::from_millis(50));
+        assert_eq!(extracted[1].decode_pixels().unwrap().data(), [8, 8, 8, 255].repeat(4));
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_canvas_len() {
+        assert_eq!(canvas_len(3, 2).unwrap(), 24);
+        assert!(matches!(canvas_len(60_000, 60_000), Err(Error::LimitExceeded("max_decompressed"))));
+        assert!(canvas_len(u32::MAX, 1).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_extract_frames_huge_header() {
+        let frame = rgba_frame(FrameControl { height: 1, ..control(0, 1) }, &[0; 4]);
+        let mut png = assemble(vec![frame]).into_png();
+        png.set_header(&Ihdr::new(60_000, 60_000, 8, ColorType::Rgba).unwrap());
+        let apng = Apng::try_from(png).unwrap();
+        assert!(matches!(apng.extract_frames(), Err(Error::LimitExceeded("max_decompressed"))));
+    }
+
+    #[test]
+    fn test_edit_frames() {
+        let mut apng = Apng::try_from(animated()).unwrap();
+        apng.set_frame_delay(1, Duration::from_millis(250)).unwrap();
+        apng.set_loop_count(5);
+        assert!(apng.set_frame_delay(2, Duration::ZERO).is_err());
+        let png = apng.as_png();
+        assert_eq!(png.frames().nth(1).unwrap().control().delay(), Duration::from_millis(250));
+        assert_eq!(apng.animation_control(), AnimationControl { num_frames: 2, num_plays: 5 });
+        assert_sequential(png);
+
+        let idat: Vec<u8> = png.chunks_of_type("IDAT").flat_map(|c| c.data().to_vec()).collect();
+        apng.reorder(&[1, 0]).unwrap();
+        let png = apng.as_png();
+        let frames: Vec<Frame> = png.frames().collect();
+        assert!(frames.iter().all(|f| !f.is_default_image()));
+        assert_eq!(frames[0].data(), [1, 2, 3]);
+        assert_eq!(frames[1].data(), idat);
+        assert_eq!(frames[1].control().width, 225);
+        assert!(png.chunk_by_type("IDAT").is_some());
+        assert_sequential(png);
+        assert!(apng.reorder(&[0, 0]).is_err());
+        assert!(apng.reorder(&[0]).is_err());
+
+        apng.remove_frame(1).unwrap();
+        assert_eq!(apng.frame_count(), 1);
+        assert_eq!(apng.animation_control().num_frames, 1);
+        assert_eq!(apng.as_png().frames().next().unwrap().data(), [1, 2, 3]);
+        assert_sequential(apng.as_png());
+        assert!(apng.remove_frame(0).is_err());
+        assert!(apng.remove_frame(3).is_err());
+    }
+
+    #[test]
+    fn test_remove_default_frame() {
+        let mut apng = Apng::try_from(animated()).unwrap();
+        apng.remove_frame(0).unwrap();
+        let png = apng.as_png();
+        let at = |t| png.chunks().iter().position(|c| *c.chunk_type() == t).unwrap();
+        assert!(at(ChunkType::IDAT) < at(ChunkType::FCTL));
+        let frames: Vec<Frame> = png.frames().collect();
+        assert_eq!(frames.len(), 1);
+        assert!(!frames[0].is_default_image());
+        assert_sequential(png);
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_reorder_extracted() {
+        let frames = (1..=3).map(|n| (gray(n), Duration::from_millis(10))).collect();
+        let mut apng = Apng::from_frames(frames, 0).unwrap();
+        apng.reorder(&[2, 0, 1]).unwrap();
+        let first: Vec<u8> = apng
+            .extract_frames()
+            .unwrap()
+            .iter()
+            .map(|png| png.decode_pixels().unwrap().data()[0])
+            .collect();
+        assert_eq!(first, [3, 1, 2]);
+        assert_eq!(apng.as_png().decode_pixels().unwrap().data(), [1; 4]);
+        apng.reorder(&[1, 2, 0]).unwrap();
+        let first = apng.extract_frames().unwrap()[0].decode_pixels().unwrap();
+        assert_eq!(first.data(), [1, 1, 1, 255].repeat(4));
+        assert_sequential(apng.as_png());
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_sprite_sheet() {
+        let frames = (1..=5).map(|n| (gray(n * 10), Duration::from_millis(50))).collect();
+        let apng = Apng::from_frames(frames, 0).unwrap();
+        let sheet = apng.to_sprite_sheet(2).unwrap();
+        let ihdr = sheet.header().unwrap();
+        assert_eq!((ihdr.width(), ihdr.height()), (4, 6));
+        let pixels = sheet.decode_pixels().unwrap().to_rgba8().unwrap();
+        assert_eq!(pixels.pixel(2, 0), [20, 20, 20, 255]);
+        assert_eq!(pixels.pixel(1, 4), [50, 50, 50, 255]);
+        assert_eq!(pixels.pixel(3, 5), [0, 0, 0, 0]);
+        assert_eq!(apng.to_sprite_sheet(9).unwrap().header().unwrap().width(), 10);
+        assert!(apng.to_sprite_sheet(0).is_err());
+
+        let back = Apng::from_sprite_sheet(&sheet, 2, 2, Duration::from_millis(50)).unwrap();
+        assert_eq!(back.frame_count(), 5);
+        assert_eq!(back.duration(), apng.duration());
+        let extracted: Vec<Vec<u8>> = back
+            .extract_frames()
+            .unwrap()
+            .iter()
+            .map(|png| png.decode_pixels().unwrap().data().to_vec())
+            .collect();
+        assert_eq!(extracted[3], [40, 40, 40, 255].repeat(4));
+        assert!(Apng::from_sprite_sheet(&sheet, 3, 2, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "flate")]
+    fn test_sprite_sheet_keeps_format() {
+        let png = Png::try_from(IMG).unwrap();
+        let apng = Apng::from_sprite_sheet(&png, 75, 225, Duration::from_millis(100)).unwrap();
+        assert_eq!(apng.frame_count(), 3);
+        let header = apng.as_png().header().unwrap();
+        assert_eq!((header.width(), header.color_type()), (75, ColorType::Indexed));
+        assert_eq!(apng.as_png().chunk_by_type("PLTE"), png.chunk_by_type("PLTE"));
+    }
+
+    #[test]
+    fn test_default_image_not_a_frame() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_typed_chunk(&AnimationControl { num_frames: 1, num_plays: 1 });
+        png.insert_before_type("IEND", control(0, 5).to_chunk()).unwrap();
+        png.insert_before_type("IEND", FrameData { sequence_number: 1, data: vec![4] }.to_chunk()).unwrap();
+        let frames: Vec<Frame> = png.frames().collect();
+        assert_eq!(frames.len(), 1);
+        assert!(!frames[0].is_default_image());
+        assert_eq!(frames[0].data(), [4]);
+    }
+}
@@ -0,0 +1,118 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::parse::ParseOptions;
+use crate::png::Png;
+
+impl Png {
+    /// Async version of `from_reader`.
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: R) -> Result<Png, Error> {
+        Png::from_async_reader_with(reader, &ParseOptions::default()).await
+    }
+
+    /// Async version of `from_reader_with`.
+    pub async fn from_async_reader_with<R: AsyncRead + Unpin>(
+        mut reader: R,
+        options: &ParseOptions,
+    ) -> Result<Png, Error> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header).await?;
+        if header != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut chunks = Vec::new();
+        let mut lenb = [0u8; 4];
+        let (mut offset, mut index) = (8u64, 0usize);
+
+        loop {
+            let start = offset;
+            let read = reader.read(&mut lenb).await.map_err(|e| Error::from(e).at(start, index))?;
+            if read == 0 {
+                break;
+            }
+            reader.read_exact(&mut lenb[read..]).await.map_err(|e| Error::from(e).at(start, index))?;
+
+            let len = u32::from_be_bytes(lenb);
+            options.limits.check_chunk(index, len).map_err(|e| e.at(start, index))?;
+            let len = len as u64;
+            let mut bytes = lenb.to_vec();
+            let rest = 4 + len + 4;
+            let read = (&mut reader).take(rest).read_to_end(&mut bytes).await
+                .map_err(|e| Error::from(e).at(start, index))?;
+            let complete = read as u64 == rest;
+            let chunk = Png::chunk_from_read(&bytes, complete, options).map_err(|e| e.at(start, index))?;
+            offset += bytes.len() as u64;
+            index += 1;
+            let end = chunk.as_ref().is_some_and(|c| *c.chunk_type() == ChunkType::IEND);
+            chunks.extend(chunk);
+            if !complete || end {
+                break;
+            }
+        }
+
+        let mut png = Png::from_chunks(chunks);
+        let mut trailer = Vec::new();
+        reader.read_to_end(&mut trailer).await?;
+        png.set_trailer(trailer);
+        Ok(png)
+    }
+
+    /// Async version of `write_to`.
+    pub async fn write_async<W: AsyncWrite + Unpin>(&self, mut writer: W) -> Result<(), Error> {
+        writer.write_all(&Png::STANDARD_HEADER).await?;
+        for c in self.chunks() {
+            writer.write_all(&c.length().to_be_bytes()).await?;
+            writer.write_all(&c.chunk_type().bytes()).await?;
+            writer.write_all(c.data()).await?;
+            writer.write_all(&c.crc().to_be_bytes()).await?;
+        }
+        writer.write_all(self.trailer()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_async_roundtrip() {
+        let png = testing_png();
+        let mut out = Vec::new();
+        png.write_async(&mut out).await.unwrap();
+        assert_eq!(out, png.as_bytes());
+
+        let parsed = Png::from_async_reader(out.as_slice()).await.unwrap();
+        assert_eq!(parsed.as_bytes(), out);
+    }
+
+    #[tokio::test]
+    async fn test_async_trailer() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::IEND, Vec::new()));
+        png.set_trailer(b"hidden".to_vec());
+        let mut out = Vec::new();
+        png.write_async(&mut out).await.unwrap();
+        assert_eq!(out, png.as_bytes());
+        assert_eq!(Png::from_async_reader(out.as_slice()).await.unwrap(), png);
+    }
+
+    #[tokio::test]
+    async fn test_async_truncated() {
+        let bytes = testing_png().as_bytes();
+        assert!(Png::from_async_reader(&bytes[..bytes.len() - 3]).await.is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+/// The background colour from bKGD, in the form the colour type needs.
+/// Samples are at the image bit depth, so 0 to 255 for 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Background {
+    /// A palette index, for indexed images.
+    Index(u8),
+    /// A grey level, for greyscale images with or without alpha.
+    Gray(u16),
+    /// Red, green and blue, for RGB images with or without alpha.
+    Rgb(u16, u16, u16),
+}
+
+impl Background {
+    /// # Errors
+    /// returns an Error if the variant does not match the colour type, a
+    /// sample does not fit the bit depth, or the palette index is past
+    /// `palette_len`.
+    pub fn to_chunk(&self, ihdr: &Ihdr, palette_len: usize) -> Result<Chunk, Error> {
+        let data = match (self, ihdr.color_type()) {
+            (Background::Index(i), ColorType::Indexed) if (*i as usize) < palette_len => vec![*i],
+            (Background::Index(i), ColorType::Indexed) => {
+                bail!("background index {} is past the {} palette entries", i, palette_len)
+            }
+            (Background::Gray(g), ColorType::Grayscale | ColorType::GrayscaleAlpha) => {
+                sample_data(&[*g], ihdr.bit_depth())?
+            }
+            (Background::Rgb(r, g, b), ColorType::Rgb | ColorType::Rgba) => {
+                sample_data(&[*r, *g, *b], ihdr.bit_depth())?
+            }
+            (background, color_type) => bail!("{:?} does not fit a {:?} image", background, color_type),
+        };
+        Ok(Chunk::new(ChunkType::BKGD, data))
+    }
+
+    /// # Errors
+    /// returns an Error if the chunk is not bKGD or its length does not
+    /// match the colour type.
+    pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr) -> Result<Background, Error> {
+        if *chunk.chunk_type() != ChunkType::BKGD {
+            bail!("expected bKGD, got {}", chunk.chunk_type());
+        }
+        Ok(match (ihdr.color_type(), chunk.data()) {
+            (ColorType::Indexed, [i]) => Background::Index(*i),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, [_, _]) => {
+                Background::Gray(parse_samples(chunk.data())[0])
+            }
+            (ColorType::Rgb | ColorType::Rgba, [_, _, _, _, _, _]) => match *parse_samples(chunk.data()) {
+                [r, g, b] => Background::Rgb(r, g, b),
+                _ => unreachable!(),
+            },
+            (color_type, data) => bail!("bKGD of {} bytes does not fit a {:?} image", data.len(), color_type),
+        })
+    }
+}
+
+impl Png {
+    /// The background colour from bKGD.
+    ///
+    /// # Errors
+    /// returns an Error if there is no valid IHDR or bKGD is malformed.
+    pub fn background_color(&self) -> Result<Option<Background>, Error> {
+        match self.chunk_by_type("bKGD") {
+            Some(c) => Ok(Some(Background::from_chunk(c, &self.header()?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces bKGD, or adds it before the image data.
+    ///
+    /// # Errors
+    /// returns an Error if there is no valid IHDR or `background` does not
+    /// fit the image, see `Background::to_chunk`.
+    pub fn set_background_color(&mut self, background: Background) -> Result<(), Error> {
+        let palette_len = self.chunk_by_type("PLTE").map_or(0, |c| c.data().len() / 3);
+        let chunk = background.to_chunk(&self.header()?, palette_len)?;
+        self.replace_unique(chunk, &[ChunkType::IDAT]);
+        Ok(())
+    }
+}
+
+/// Big endian two byte samples, a trailing odd byte is ignored.
+pub(crate) fn parse_samples(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2).map(|s| u16::from_be_bytes([s[0], s[1]])).collect()
+}
+
+/// Packs `samples` as two bytes each, checking they fit `bit_depth`.
+pub(crate) fn sample_data(samples: &[u16], bit_depth: u8) -> Result<Vec<u8>, Error> {
+    let max = ((1u32 << bit_depth) - 1) as u16;
+    if let Some(s) = samples.iter().find(|s| **s > max) {
+        bail!("sample {} does not fit {} bits", s, bit_depth);
+    }
+    Ok(samples.iter().flat_map(|s| s.to_be_bytes()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_background_chunk() {
+        let rgb16 = Ihdr::new(1, 1, 16, ColorType::Rgba).unwrap();
+        let chunk = Background::Rgb(1, 0x200, 0xffff).to_chunk(&rgb16, 0).unwrap();
+        assert_eq!(chunk.data(), [0, 1, 2, 0, 0xff, 0xff]);
+        assert_eq!(Background::from_chunk(&chunk, &rgb16).unwrap(), Background::Rgb(1, 0x200, 0xffff));
+
+        let gray4 = Ihdr::new(1, 1, 4, ColorType::Grayscale).unwrap();
+        let chunk = Background::Gray(15).to_chunk(&gray4, 0).unwrap();
+        assert_eq!(chunk.data(), [0, 15]);
+        assert_eq!(Background::from_chunk(&chunk, &gray4).unwrap(), Background::Gray(15));
+        assert!(Background::Gray(16).to_chunk(&gray4, 0).is_err());
+        assert!(Background::Rgb(0, 0, 0).to_chunk(&gray4, 0).is_err());
+        assert!(Background::from_chunk(&chunk, &rgb16).is_err());
+
+        let indexed = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        assert_eq!(Background::Index(3).to_chunk(&indexed, 4).unwrap().data(), [3]);
+        assert!(Background::Index(4).to_chunk(&indexed, 4).is_err());
+    }
+
+    #[test]
+    fn test_png_background() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_chunks("bKGD");
+        assert_eq!(png.background_color().unwrap(), None);
+        png.set_background_color(Background::Index(2)).unwrap();
+        assert_eq!(png.background_color().unwrap(), Some(Background::Index(2)));
+        let bkgd = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::BKGD).unwrap();
+        let plte = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::PLTE).unwrap();
+        let idat = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IDAT).unwrap();
+        assert!(plte < bkgd && bkgd < idat);
+        assert!(png.set_background_color(Background::Gray(0)).is_err());
+    }
+}
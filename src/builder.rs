@@ -0,0 +1,327 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::standard::OrderingConstraint;
+use crate::validate::MAX_CHUNK_LENGTH;
+
+/// Builds a `Png` from scratch.
+/// Chunks are put in an order the spec allows no matter the order they
+/// are added in, the header comes first and IEND is added by `build`.
+pub struct PngBuilder {
+    ihdr: Ihdr,
+    palette: Option<Chunk>,
+    ancillary: Vec<Chunk>,
+    trailing: Vec<Chunk>,
+    idat: Vec<Chunk>,
+}
+
+impl PngBuilder {
+    pub fn new(ihdr: Ihdr) -> PngBuilder {
+        PngBuilder { ihdr, palette: None, ancillary: Vec::new(), trailing: Vec::new(), idat: Vec::new() }
+    }
+
+    /// Sets the PLTE chunk data, 3 bytes per entry.
+    pub fn palette(mut self, data: Vec<u8>) -> PngBuilder {
+        self.palette = Some(Chunk::new(ChunkType::PLTE, data));
+        self
+    }
+
+    /// Adds an IDAT chunk holding part of the compressed image data.
+    pub fn idat(mut self, data: Vec<u8>) -> PngBuilder {
+        self.idat.push(Chunk::new(ChunkType::IDAT, data));
+        self
+    }
+
+    /// Adds an ancillary chunk, it is placed before the image data.
+    ///
+    /// # Errors
+    /// returns an Error for critical chunks, use the other methods for those,
+    /// and for chunks that must come after the image data.
+    pub fn chunk(mut self, chunk: Chunk) -> Result<PngBuilder, Error> {
+        if chunk.chunk_type().is_critical() {
+            bail!("critical chunk {} can not be added as ancillary", chunk.chunk_type());
+        }
+        if chunk.chunk_type().ordering_constraint() == OrderingConstraint::AfterIdat {
+            bail!("{} must come after the image data", chunk.chunk_type());
+        }
+        self.ancillary.push(chunk);
+        Ok(self)
+    }
+
+    /// Adds an ancillary chunk after the image data, only chunks
+    /// the spec allows there (like tEXt or tIME) can go there.
+    ///
+    /// # Errors
+    /// returns an Error for critical chunks and chunks that must
+    /// come before IDAT.
+    pub fn trailing_chunk(mut self, chunk: Chunk) -> Result<PngBuilder, Error> {
+        let ordering = chunk.chunk_type().ordering_constraint();
+        if chunk.chunk_type().is_critical()
+            || !matches!(ordering, OrderingConstraint::Anywhere | OrderingConstraint::AfterIdat)
+        {
+            bail!("{} can not come after the image data", chunk.chunk_type());
+        }
+        self.trailing.push(chunk);
+        Ok(self)
+    }
+
+    /// Puts the chunks in order and appends IEND.
+    ///
+    /// # Errors
+    /// returns an Error if there is no IDAT, or the palette is missing
+    /// for an indexed image or present for a greyscale one.
+    pub fn build(self) -> Result<Png, Error> {
+        if self.idat.is_empty() {
+            return Err(Error::MissingChunk(ChunkType::IDAT));
+        }
+        match (self.ihdr.color_type(), &self.palette) {
+            (ColorType::Indexed, None) => bail!("indexed image without palette"),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(_)) => {
+                bail!("palette not allowed for {:?}", self.ihdr.color_type())
+            }
+            _ => {}
+        }
+
+        let mut ancillary = self.ancillary;
+        // stable, so chunks with the same placement keep their order
+        ancillary.sort_by_key(|c| c.chunk_type().ordering_constraint());
+        let split = ancillary
+            .partition_point(|c| c.chunk_type().ordering_constraint() == OrderingConstraint::BeforePlte);
+        let after_plte = ancillary.split_off(split);
+
+        let mut chunks = vec![self.ihdr.to_chunk()];
+        chunks.extend(ancillary);
+        chunks.extend(self.palette);
+        chunks.extend(after_plte);
+        chunks.extend(self.idat);
+        chunks.extend(self.trailing);
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+/// Builds a `Chunk`, checking what `Chunk::new` does not:
+/// the length limit and the data layout of known chunk types.
+pub struct ChunkBuilder {
+    chunk_type: ChunkType,
+    data: Vec<u8>,
+    max_size: u32,
+}
+
+impl ChunkBuilder {
+    pub fn new(chunk_type: ChunkType) -> ChunkBuilder {
+        ChunkBuilder { chunk_type, data: Vec::new(), max_size: MAX_CHUNK_LENGTH }
+    }
+
+    pub fn data(mut self, data: Vec<u8>) -> ChunkBuilder {
+        self.data = data;
+        self
+    }
+
+    /// Sets the data to the bytes of `text`.
+    pub fn text(mut self, text: &str) -> ChunkBuilder {
+        self.data = text.as_bytes().to_vec();
+        self
+    }
+
+    /// Lowers the data length limit, it can not go over `MAX_CHUNK_LENGTH`.
+    pub fn max_size(mut self, max_size: u32) -> ChunkBuilder {
+        self.max_size = max_size.min(MAX_CHUNK_LENGTH);
+        self
+    }
+
+    /// # Errors
+    /// returns an Error if the data is longer than the limit or does not
+    /// fit the format of a known chunk type.
+    pub fn build(self) -> Result<Chunk, Error> {
+        if self.data.len() as u64 > self.max_size as u64 {
+            bail!("{} data is {} bytes, limit is {}", self.chunk_type, self.data.len(), self.max_size);
+        }
+        check_format(&self.chunk_type, &self.data)?;
+        Ok(Chunk::new(self.chunk_type, self.data))
+    }
+}
+
+impl Chunk {
+    /// Starts building a `Chunk` of type `chunk_type`.
+    /// See `ChunkBuilder`.
+    pub fn builder(chunk_type: ChunkType) -> ChunkBuilder {
+        ChunkBuilder::new(chunk_type)
+    }
+}
+
+/// Checks `data` against the layout the spec gives `chunk_type`.
+/// Unknown types and types without a simple layout always pass.
+pub(crate) fn check_format(chunk_type: &ChunkType, data: &[u8]) -> Result<(), Error> {
+    let fixed = match *chunk_type {
+        ChunkType::IHDR => Some(13),
+        ChunkType::IEND => Some(0),
+        ChunkType::GAMA => Some(4),
+        ChunkType::CHRM => Some(32),
+        ChunkType::SRGB => Some(1),
+        ChunkType::PHYS => Some(9),
+        ChunkType::TIME => Some(7),
+        ChunkType::CICP => Some(4),
+        ChunkType::MDCV => Some(24),
+        ChunkType::CLLI => Some(8),
+        ChunkType::ACTL => Some(8),
+        ChunkType::FCTL => Some(26),
+        ChunkType::OFFS => Some(9),
+        _ => None,
+    };
+    if let Some(len) = fixed {
+        if data.len() != len {
+            bail!("{} data must be {} bytes, got {}", chunk_type, len, data.len());
+        }
+    }
+
+    match *chunk_type {
+        ChunkType::PLTE if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 => {
+            bail!("invalid PLTE length: {}", data.len())
+        }
+        ChunkType::SBIT if data.is_empty() || data.len() > 4 => {
+            bail!("invalid sBIT length: {}", data.len())
+        }
+        ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT => {
+            match data.iter().position(|b| *b == 0) {
+                Some(1..=79) => {}
+                _ => bail!("{} needs a 1 to 79 byte keyword followed by a null byte", chunk_type),
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl Png {
+    /// Starts building a new `Png` with `ihdr` as its header.
+    /// See `PngBuilder`.
+    pub fn builder(ihdr: Ihdr) -> PngBuilder {
+        PngBuilder::new(ihdr)
+    }
+
+    /// Sorts the chunks into an order the spec allows: IHDR, the
+    /// ancillary chunks that must precede PLTE, PLTE, the ones that must
+    /// follow it, the IDAT run and IEND last. Chunks that may go anywhere
+    /// stay on the side of the image data they were on, and chunks with
+    /// the same placement keep their relative order.
+    pub fn normalize_chunk_order(&mut self) {
+        let chunks = std::mem::take(self.chunks_mut());
+        let first_idat = chunks.iter().position(|c| c.chunk_type().bytes() == *b"IDAT");
+        let mut keyed: Vec<(u8, Chunk)> = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(n, c)| (rank(&c, first_idat.is_some_and(|i| n > i)), c))
+            .collect();
+        keyed.sort_by_key(|(rank, _)| *rank);
+        *self.chunks_mut() = keyed.into_iter().map(|(_, c)| c).collect();
+    }
+}
+
+/// Sort key used by `normalize_chunk_order`.
+fn rank(chunk: &Chunk, after_idat: bool) -> u8 {
+    if chunk.chunk_type().bytes() == *b"PLTE" {
+        return 2;
+    }
+    match chunk.chunk_type().ordering_constraint() {
+        OrderingConstraint::First => 0,
+        OrderingConstraint::BeforePlte => 1,
+        OrderingConstraint::AfterPlte => 3,
+        OrderingConstraint::BeforeIdat => 4,
+        OrderingConstraint::Anywhere if after_idat => 7,
+        OrderingConstraint::Anywhere => 5,
+        OrderingConstraint::Consecutive => 6,
+        OrderingConstraint::AfterIdat => 7,
+        OrderingConstraint::Last => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_builder_orders_chunks() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        let png = Png::builder(ihdr)
+            .idat(vec![1])
+            .chunk(chunk("tRNS", &[0])).unwrap()
+            .chunk(chunk("tEXt", b"a\0b")).unwrap()
+            .chunk(chunk("gAMA", &[0, 0, 0, 1])).unwrap()
+            .palette(vec![0, 0, 0])
+            .trailing_chunk(chunk("tIME", &[0; 7])).unwrap()
+            .idat(vec![2])
+            .build()
+            .unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "gAMA", "PLTE", "tRNS", "tEXt", "IDAT", "IDAT", "tIME", "IEND"]);
+        assert!(png.validate().is_ok());
+    }
+
+    #[test]
+    fn test_normalize_chunk_order() {
+        let mut png = Png::from_chunks(vec![
+            chunk("tEXt", b"a\0b"),
+            chunk("IDAT", &[1]),
+            chunk("IEND", &[]),
+            chunk("tRNS", &[0]),
+            chunk("PLTE", &[0, 0, 0]),
+            Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap().to_chunk(),
+            chunk("ruSt", b"private"),
+            chunk("gAMA", &[0, 0, 0, 1]),
+            chunk("IDAT", &[2]),
+        ]);
+        png.normalize_chunk_order();
+        assert_eq!(
+            types(&png),
+            vec!["IHDR", "gAMA", "PLTE", "tRNS", "tEXt", "IDAT", "IDAT", "ruSt", "IEND"]
+        );
+        assert!(png.validate().is_ok());
+    }
+
+    #[test]
+    fn test_chunk_builder() {
+        let built = Chunk::builder(ChunkType::TEXT).text("Title\0Dice").build().unwrap();
+        assert_eq!(built, chunk("tEXt", b"Title\0Dice"));
+        assert!(Chunk::builder(ChunkType::TIME).data(vec![0; 7]).build().is_ok());
+        assert!(Chunk::builder(ChunkType::from_str("RuSt").unwrap()).text("anything").build().is_ok());
+    }
+
+    #[test]
+    fn test_chunk_builder_rejects() {
+        assert!(Chunk::builder(ChunkType::TIME).data(vec![0; 6]).build().is_err());
+        assert!(Chunk::builder(ChunkType::IEND).data(vec![0]).build().is_err());
+        assert!(Chunk::builder(ChunkType::PLTE).data(vec![0; 4]).build().is_err());
+        assert!(Chunk::builder(ChunkType::TEXT).text("no keyword").build().is_err());
+        assert!(Chunk::builder(ChunkType::TEXT).text("\0empty keyword").build().is_err());
+        let rust = ChunkType::from_str("RuSt").unwrap();
+        assert!(Chunk::builder(rust).text("12345").max_size(4).build().is_err());
+        assert!(Chunk::builder(rust).text("1234").max_size(4).build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_rejects() {
+        let rgb = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap();
+        assert!(Png::builder(rgb.clone()).build().is_err());
+        assert!(Png::builder(rgb.clone()).chunk(chunk("IDAT", &[])).is_err());
+        assert!(Png::builder(rgb).trailing_chunk(chunk("pHYs", &[0; 9])).is_err());
+
+        let indexed = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        assert!(Png::builder(indexed).idat(vec![1]).build().is_err());
+
+        let gray = Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap();
+        assert!(Png::builder(gray).idat(vec![1]).palette(vec![0, 0, 0]).build().is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::ihdr::Ihdr;
+use crate::known;
+use crate::png::Png;
+use crate::standard;
+use crate::validate::{ValidationError, MAX_CHUNK_LENGTH};
+
+/// Something wrong with a single chunk, see `Png::check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// The stored crc does not match the type and data.
+    BadCrc { found: u32, expected: u32 },
+    /// The length field does not match the data, only possible when
+    /// parsed leniently.
+    LengthMismatch { declared: u32, actual: usize },
+    /// The length is over `MAX_CHUNK_LENGTH`.
+    TooLong,
+    /// A critical chunk type no decoder is required to know, so one
+    /// that does not must refuse the file.
+    UnknownCritical,
+    /// The data does not parse as its type, with the reason.
+    Malformed(String),
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finding::BadCrc { found, expected } => {
+                write!(f, "CRC error (computed {:08x}, expected {:08x})", expected, found)
+            }
+            Finding::LengthMismatch { declared, actual } => {
+                write!(f, "declared length {} but {} bytes of data", declared, actual)
+            }
+            Finding::TooLong => write!(f, "length over the 2^31 - 1 limit"),
+            Finding::UnknownCritical => write!(f, "unknown critical chunk"),
+            Finding::Malformed(e) => write!(f, "invalid data: {}", e),
+        }
+    }
+}
+
+/// One chunk as `Png::check` saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkCheck {
+    pub index: usize,
+    /// Byte offset of the length field, counting the signature.
+    pub offset: u64,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+    pub findings: Vec<Finding>,
+}
+
+impl ChunkCheck {
+    /// True if the stored crc matches.
+    pub fn crc_ok(&self) -> bool {
+        !self.findings.iter().any(|f| matches!(f, Finding::BadCrc { .. }))
+    }
+}
+
+/// Everything `Png::check` found, per chunk and for the file as a whole.
+/// `Display` gives a report in the style of `pngcheck -v`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    chunks: Vec<ChunkCheck>,
+    errors: Vec<ValidationError>,
+    header: Option<Ihdr>,
+    file_size: u64,
+}
+
+impl CheckReport {
+    /// Every chunk in file order, with or without findings.
+    pub fn chunks(&self) -> &[ChunkCheck] {
+        &self.chunks
+    }
+
+    /// The structural problems of `Png::validate`.
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.errors
+    }
+
+    /// Count of chunk findings and structural problems.
+    pub fn error_count(&self) -> usize {
+        self.chunks.iter().map(|c| c.findings.len()).sum::<usize>() + self.errors.len()
+    }
+
+    /// True if nothing was found, what pngcheck reports as OK.
+    pub fn is_ok(&self) -> bool {
+        self.error_count() == 0
+    }
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "File: ({} bytes)", self.file_size)?;
+        for c in &self.chunks {
+            writeln!(f, "  chunk {} at offset 0x{:05x}, length {}", c.chunk_type, c.offset, c.length)?;
+            for finding in &c.findings {
+                writeln!(f, "    {}: {}", c.chunk_type, finding)?;
+            }
+        }
+        for e in &self.errors {
+            writeln!(f, "  {}", e)?;
+        }
+        if !self.is_ok() {
+            return write!(f, "ERRORS DETECTED: {}", self.error_count());
+        }
+        write!(f, "No errors detected ({} chunks", self.chunks.len())?;
+        if let Some(h) = &self.header {
+            let interlace = if h.is_interlaced() { "interlaced" } else { "non-interlaced" };
+            write!(f, ", {}x{} image, {}-bit {:?}, {}", h.width(), h.height(), h.bit_depth(), h.color_type(), interlace)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Png {
+    /// Checks every chunk, its crc, length and typed contents as far as
+    /// IHDR and the types in the global registry go, plus the structural
+    /// rules of `validate`. Meant as a stand-in for pngcheck: print the
+    /// report and fail on `!is_ok()`.
+    pub fn check(&self) -> CheckReport {
+        let registry = known::global().read().unwrap_or_else(|e| e.into_inner());
+        let mut offset = 8;
+        let mut chunks = Vec::with_capacity(self.chunks().len());
+        for (index, c) in self.iter().enumerate() {
+            let mut findings = Vec::new();
+            if let Err(Error::BadCrc { found, expected }) = c.verify_crc() {
+                findings.push(Finding::BadCrc { found, expected });
+            }
+            if c.length() as usize != c.data().len() {
+                findings.push(Finding::LengthMismatch { declared: c.length(), actual: c.data().len() });
+            }
+            if c.length() > MAX_CHUNK_LENGTH {
+                findings.push(Finding::TooLong);
+            }
+            if c.chunk_type().is_critical() && standard::lookup(c.chunk_type()).is_none() {
+                findings.push(Finding::UnknownCritical);
+            }
+            if let Some(Err(e)) = parse_typed(c, &registry) {
+                findings.push(Finding::Malformed(e.to_string()));
+            }
+            chunks.push(ChunkCheck {
+                index,
+                offset,
+                chunk_type: *c.chunk_type(),
+                length: c.length(),
+                crc: c.crc(),
+                findings,
+            });
+            offset += c.total_size();
+        }
+
+        CheckReport {
+            chunks,
+            errors: self.validate().err().unwrap_or_default(),
+            header: self.header().ok(),
+            file_size: self.file_size(),
+        }
+    }
+}
+
+/// Parses `chunk` as its typed form, `None` if it has none to check.
+fn parse_typed(chunk: &Chunk, registry: &known::ChunkRegistry) -> Option<Result<(), Error>> {
+    if *chunk.chunk_type() == ChunkType::IHDR {
+        return Some(Ihdr::try_from(chunk).map(|_| ()));
+    }
+    registry.parse(chunk).map(|r| r.map(|_| ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ParseOptions;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_clean_check() {
+        let png = Png::try_from(IMG).unwrap();
+        let report = png.check();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.chunks().len(), png.chunks().len());
+        assert_eq!(report.chunks()[0].offset, 8);
+        assert_eq!(report.chunks()[1].offset, 8 + 12 + 13);
+        assert!(report.to_string().lines().last().unwrap().starts_with("No errors detected"));
+    }
+
+    #[test]
+    fn test_check_findings() {
+        let mut bytes = Png::try_from(IMG).unwrap().as_bytes();
+        let first_crc = 8 + 12 + 13 - 1;
+        bytes[first_crc] ^= 1;
+        let mut png = Png::from_bytes_with(&bytes, &ParseOptions::lenient()).unwrap();
+        png.insert_before_type("IEND", Chunk::new(ChunkType::TIME, vec![0; 3])).unwrap();
+        png.insert_before_type("IEND", Chunk::new(ChunkType::from_bytes_const(*b"ABCD"), vec![])).unwrap();
+        png.remove_chunks("IDAT");
+
+        let report = png.check();
+        assert!(!report.is_ok());
+        assert!(!report.chunks()[0].crc_ok());
+        let iend = report.chunks().len() - 1;
+        assert!(matches!(report.chunks()[iend - 2].findings[..], [Finding::Malformed(_)]));
+        assert_eq!(report.chunks()[iend - 1].findings, [Finding::UnknownCritical]);
+        assert_eq!(report.errors(), [ValidationError::MissingChunk(ChunkType::IDAT)]);
+        assert_eq!(report.error_count(), 4);
+        assert_eq!(report.to_string().lines().last().unwrap(), "ERRORS DETECTED: 4");
+    }
+}
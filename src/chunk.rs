@@ -1,10 +1,16 @@
-use std::fmt::Display;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Display;
+use core::hash::{Hash, Hasher};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+#[cfg(feature = "std")]
+use std::io::Write;
 
 use crate::chunk_type::ChunkType;
-use crate::crc;
-use anyhow::bail;
-
-type Error = anyhow::Error;
+use crate::crc::Crc32;
+use crate::error::{bail, Error};
+use crate::png_ref::ChunkRef;
 
 /// Png files are made of chunks of varying sizes
 /// each chunk has a length, Type, Data and a CRC
@@ -14,11 +20,15 @@ type Error = anyhow::Error;
 /// Then comes the data which is a `Vec<u8>` of bytes.
 /// The last 4 bytes make up the CRC `u32` wich was a
 /// pain to calculate.
+#[derive(Debug, Clone)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
     data: Vec<u8>,
-    crc: u32,
+    /// Unknown until computed, see `new_unchecked`.
+    crc: LazyCrc,
+    /// The bytes the chunk was read from, see `raw_bytes`.
+    raw: Option<Vec<u8>>,
 }
 
 impl Chunk {
@@ -26,33 +36,174 @@ impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         let crc = Chunk::calculate_crc(&chunk_type, &data);
         let length: u32 = data.len() as u32;
-        Chunk { length, chunk_type, data, crc }
+        Chunk { length, chunk_type, data, crc: LazyCrc::known(crc), raw: None }
+    }
+
+    /// Like `new` but the crc is only computed, once, when needed, by `crc`,
+    /// `as_bytes` or `write_to`. Saves computing it twice for chunks
+    /// that are written out right away.
+    pub fn new_unchecked(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        let length: u32 = data.len() as u32;
+        Chunk { length, chunk_type, data, crc: LazyCrc::default(), raw: None }
+    }
+
+    /// Calculates the 32 bit CRC over the type and data fields.
+    /// See `crc::Crc32`.
+    pub(crate) fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(&chunk_type.bytes());
+        crc.update(data);
+        crc.finalize()
+    }
+
+    /// Like `TryFrom<&[u8]>` but keeps the crc as it was read
+    /// even when it does not match the data.
+    /// See `has_valid_crc`.
+    ///
+    /// The declared length is kept as well, see `Png::repair`.
+    ///
+    /// # Errors
+    /// returns an Error if chunk type is invalid or there are
+    /// less than 12 bytes
+    pub fn from_bytes_unchecked(value: &[u8]) -> Result<Chunk, Error> {
+        if value.len() < 12 {
+            return Err(Error::TruncatedChunk);
+        }
+        let length = u32::from_be_bytes(value[0..4].try_into()?);
+        let chunk_type: [u8; 4] = value[4..8].try_into()?;
+        let chunk_type = ChunkType::try_from(chunk_type)?;
+        let data = value[8..value.len()-4].to_vec();
+        let crc = u32::from_be_bytes(value[value.len()-4..].try_into()?);
+
+        Ok(Chunk { length, chunk_type, data, crc: LazyCrc::known(crc), raw: None })
+    }
+
+    /// Parses the chunk at the start of `bytes`, reading only as much
+    /// as its declared length says. Returns the chunk and the bytes after it.
+    ///
+    /// # Errors
+    /// returns an Error if the input is truncated, the chunk type
+    /// is invalid or the crc is wrong
+    pub fn parse_prefix(bytes: &[u8]) -> Result<(Chunk, &[u8]), Error> {
+        let (chunk, rest) = ChunkRef::parse_prefix(bytes)?;
+        Ok((chunk.to_owned(), rest))
+    }
+
+    /// Creates a `Chunk` from fields as read, without any checks.
+    pub(crate) fn from_parts(length: u32, chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Chunk {
+        Chunk { length, chunk_type, data, crc: LazyCrc::known(crc), raw: None }
+    }
+
+    /// Keeps `raw` as the bytes the chunk was read from.
+    pub(crate) fn with_raw(mut self, raw: Vec<u8>) -> Chunk {
+        self.raw = Some(raw);
+        self
+    }
+
+    /// The exact bytes the chunk was read from, kept when parsed with
+    /// `ParseOptions::preserve_raw` and until the chunk is changed.
+    /// `as_bytes` and `write_to` write these when present, so quirks
+    /// like a truncated chunk survive a round trip.
+    pub fn raw_bytes(&self) -> Option<&[u8]> {
+        self.raw.as_deref()
+    }
+
+    /// Sets the length field to the length of the data.
+    /// Returns true if it was wrong.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn fix_length(&mut self) -> bool {
+        let length = self.data.len() as u32;
+        let changed = self.length != length;
+        self.length = length;
+        if changed {
+            self.raw = None;
+        }
+        changed
+    }
+
+    /// Recalculates the crc. Returns true if it was wrong.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn fix_crc(&mut self) -> bool {
+        let crc = Chunk::calculate_crc(&self.chunk_type, &self.data);
+        let changed = self.crc.get().is_some_and(|c| c != crc);
+        self.crc = LazyCrc::known(crc);
+        if changed {
+            self.raw = None;
+        }
+        changed
+    }
+
+    /// Returns true if the stored crc matches the type and data.
+    /// Always true unless the chunk was read with `from_bytes_unchecked`.
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc.get().is_none_or(|c| c == Chunk::calculate_crc(&self.chunk_type, &self.data))
     }
 
-    /// Calculates a 32 bit CRC by calling another function :)
-    /// See `crc::crc32`.
-    fn calculate_crc(chunk_type: &ChunkType, data: &Vec<u8>) -> u32 {
-        let mut buf = chunk_type.bytes().to_vec();
-        buf.extend(data);
-        crc::crc32(buf.as_ref(), buf.len())
+    /// Checks the stored crc against the type and data.
+    ///
+    /// # Errors
+    /// returns an Error if the crc is wrong
+    pub fn verify_crc(&self) -> Result<(), Error> {
+        let calc_crc = Chunk::calculate_crc(&self.chunk_type, &self.data);
+        match self.crc.get() {
+            Some(crc) if crc != calc_crc => Err(Error::BadCrc { found: crc, expected: calc_crc }),
+            _ => Ok(()),
+        }
     }
 
     /// Returns a `Vec<u8>` of the chunk.
     /// containing all fields as `u8`
     pub fn as_bytes(&self) -> Vec<u8> {
+        if let Some(raw) = &self.raw {
+            return raw.clone();
+        }
         let mut bytes = self.length.to_be_bytes().to_vec();
         bytes.extend(self.chunk_type.bytes().iter());
         bytes.extend(self.data.iter());
-        bytes.extend(self.crc.to_be_bytes().iter());
+        bytes.extend(self.crc().to_be_bytes().iter());
         bytes
     }
 
+    /// Writes the chunk to `writer` field by field,
+    /// without building the `Vec<u8>` that `as_bytes` does.
+    ///
+    /// # Errors
+    /// returns an Error if writing fails.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        if let Some(raw) = &self.raw {
+            writer.write_all(raw)?;
+            return Ok(());
+        }
+        writer.write_all(&self.length.to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.data)?;
+        writer.write_all(&self.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Replaces the data, recalculating the length and crc.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.crc = LazyCrc::known(Chunk::calculate_crc(&self.chunk_type, &data));
+        self.length = data.len() as u32;
+        self.data = data;
+        self.raw = None;
+    }
+
+    /// Bytes the chunk takes up in a file, the data plus 12 bytes for
+    /// the length, type and crc fields.
+    pub fn total_size(&self) -> u64 {
+        self.data.len() as u64 + 12
+    }
+
     pub fn length(&self) -> u32 {
         self.length
     }
 
+    /// The stored crc, or the computed one for `new_unchecked` chunks,
+    /// which is computed on the first call and kept.
     pub fn crc(&self) -> u32 {
-        self.crc
+        self.crc.get_or_init(|| Chunk::calculate_crc(&self.chunk_type, &self.data))
     }
 
     pub fn chunk_type(&self) -> &ChunkType {
@@ -77,6 +228,39 @@ impl Chunk {
 
         Ok(format!("{}", String::from_utf8_lossy(data)))
     }
+
+    /// Returns data as `String`, allowing any valid UTF-8.
+    ///
+    /// # Errors
+    /// returns an Error if the data is not valid UTF-8
+    pub fn data_as_utf8(&self) -> Result<String, Error> {
+        Ok(String::from_utf8(self.data.clone())?)
+    }
+
+    /// Splits tEXt style data into keyword and value, both decoded
+    /// as Latin-1 like the spec says.
+    ///
+    /// # Errors
+    /// returns an Error if there is no null byte after the keyword
+    pub fn data_as_text(&self) -> Result<(String, String), Error> {
+        let split = match self.data.iter().position(|b| *b == 0) {
+            Some(n) => n,
+            None => bail!("no keyword separator"),
+        };
+        let latin1 = |bytes: &[u8]| bytes.iter().map(|b| *b as char).collect::<String>();
+        Ok((latin1(&self.data[..split]), latin1(&self.data[split + 1..])))
+    }
+
+    /// Returns data as lowercase hex bytes separated by spaces.
+    pub fn data_as_hex(&self) -> String {
+        self.data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+    }
+
+    /// One line describing the chunk without its data,
+    /// e.g. `IDAT length: 8192 crc: 0x1a2b3c4d`.
+    pub fn display_summary(&self) -> String {
+        format!("{} length: {} crc: {:#010x}", self.chunk_type, self.length, self.crc())
+    }
 }
 
 impl TryFrom<&[u8]> for Chunk {
@@ -85,25 +269,92 @@ impl TryFrom<&[u8]> for Chunk {
     /// Tries to create a `Chunk` from `&[u8]`
     ///
     /// # Errors
-    /// returns an Error if chunk type is invalid or crc is wrong
+    /// returns an Error if chunk type is invalid, the declared length
+    /// does not match the data or crc is wrong
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let length = u32::from_be_bytes(value[0..4].try_into()?);
-        let chunk_type: [u8; 4] = value[4..8].try_into()?;
-        let chunk_type = ChunkType::try_from(chunk_type)?;
-        let data = value[8..value.len()-4].try_into()?;
-        let crc = u32::from_be_bytes(value[value.len()-4..].try_into()?);
-        let calc_crc = Chunk::calculate_crc(&chunk_type, &data);
-        if crc != calc_crc {
-            bail!("invalid crc: {}, should be: {}", crc, calc_crc);
+        let chunk = Chunk::from_bytes_unchecked(value)?;
+        chunk.verify_crc()?;
+        if chunk.length as usize != chunk.data.len() {
+            bail!(
+                "chunk {} declares length {} but has {} bytes of data",
+                chunk.chunk_type,
+                chunk.length,
+                chunk.data.len()
+            );
         }
+        Ok(chunk)
+    }
+}
+
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.length == other.length
+            && self.chunk_type == other.chunk_type
+            && self.data == other.data
+            && self.crc() == other.crc()
+    }
+}
+
+impl Eq for Chunk {}
 
-        Ok(Chunk { length, chunk_type, data, crc })
+impl Hash for Chunk {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // leaving out the crc keeps this consistent with `eq`
+        // without computing it for `new_unchecked` chunks
+        self.length.hash(state);
+        self.chunk_type.hash(state);
+        self.data.hash(state);
     }
 }
 
 impl Display for Chunk {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.data_as_string().unwrap())
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // printable ASCII as is, anything else escaped, so binary data can't panic
+        write!(f, "{}", self.data.escape_ascii())
+    }
+}
+
+/// A crc that is either stored or computed once when first needed.
+/// Atomics rather than a `OnceCell` so `Chunk` stays `Sync`, two
+/// threads racing to fill it in compute the same value.
+#[derive(Default)]
+struct LazyCrc {
+    value: AtomicU32,
+    known: AtomicBool,
+}
+
+impl LazyCrc {
+    fn known(crc: u32) -> LazyCrc {
+        LazyCrc { value: AtomicU32::new(crc), known: AtomicBool::new(true) }
+    }
+
+    fn get(&self) -> Option<u32> {
+        self.known.load(Ordering::Acquire).then(|| self.value.load(Ordering::Relaxed))
+    }
+
+    fn get_or_init(&self, f: impl FnOnce() -> u32) -> u32 {
+        if let Some(crc) = self.get() {
+            return crc;
+        }
+        let crc = f();
+        self.value.store(crc, Ordering::Relaxed);
+        self.known.store(true, Ordering::Release);
+        crc
+    }
+}
+
+impl Clone for LazyCrc {
+    fn clone(&self) -> LazyCrc {
+        match self.get() {
+            Some(crc) => LazyCrc::known(crc),
+            None => LazyCrc::default(),
+        }
+    }
+}
+
+impl core::fmt::Debug for LazyCrc {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.get().fmt(f)
     }
 }
 
@@ -111,7 +362,7 @@ impl Display for Chunk {
 mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
-    use std::str::FromStr;
+    use core::str::FromStr;
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -140,12 +391,36 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_chunk_write_to() {
+        let chunk = testing_chunk();
+        let mut out = Vec::new();
+        chunk.write_to(&mut out).unwrap();
+        assert_eq!(out, chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_chunk_set_data() {
+        let mut chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), Vec::new());
+        chunk.set_data("This is where your secret message will be!".as_bytes().to_vec());
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
         assert_eq!(chunk.length(), 42);
     }
 
+    #[test]
+    fn test_chunk_total_size() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.total_size(), 54);
+        assert_eq!(chunk.total_size(), chunk.as_bytes().len() as u64);
+    }
+
     #[test]
     fn test_chunk_type() {
         let chunk = testing_chunk();
@@ -160,6 +435,43 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_utf8() {
+        let data = "Titel\0\0\0de\0Titel\0Würfel".as_bytes().to_vec();
+        let chunk = Chunk::new(ChunkType::from_str("iTXt").unwrap(), data);
+        assert!(chunk.data_as_string().is_err());
+        assert!(chunk.data_as_utf8().unwrap().ends_with("Würfel"));
+        let binary = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0xff, 0xfe]);
+        assert!(binary.data_as_utf8().is_err());
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Author\0J\xf6rg".to_vec());
+        let (keyword, value) = chunk.data_as_text().unwrap();
+        assert_eq!(keyword, "Author");
+        assert_eq!(value, "Jörg");
+        assert!(testing_chunk().data_as_text().is_err());
+    }
+
+    #[test]
+    fn test_chunk_hex() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0, 0xab, 16]);
+        assert_eq!(chunk.data_as_hex(), "00 ab 10");
+    }
+
+    #[test]
+    fn test_chunk_display() {
+        assert_eq!(testing_chunk().to_string(), "This is where your secret message will be!");
+        let binary = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![b'a', 0, 0xff, b'\n']);
+        assert_eq!(binary.to_string(), "a\\x00\\xff\\n");
+    }
+
+    #[test]
+    fn test_chunk_display_summary() {
+        assert_eq!(testing_chunk().display_summary(), "RuSt length: 42 crc: 0xabd1d84e");
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -214,6 +526,87 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_from_bytes_unchecked() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+
+        let chunk = Chunk::from_bytes_unchecked(bytes.as_ref()).unwrap();
+        assert!(!chunk.has_valid_crc());
+        assert_eq!(chunk.as_bytes(), bytes);
+        assert!(testing_chunk().has_valid_crc());
+    }
+
+    #[test]
+    fn test_chunk_short_input() {
+        let bytes = testing_chunk().as_bytes();
+        for n in 0..12 {
+            assert!(Chunk::try_from(&bytes[..n]).is_err());
+            assert!(Chunk::from_bytes_unchecked(&bytes[..n]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_mismatch() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[3] = 41;
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+        bytes[3] = 43;
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_parse_prefix() {
+        let first = testing_chunk();
+        let second = Chunk::new(ChunkType::from_str("RuSt").unwrap(), Vec::new());
+        let mut bytes = first.as_bytes();
+        bytes.extend(second.as_bytes());
+
+        let (chunk, rest) = Chunk::parse_prefix(&bytes).unwrap();
+        assert_eq!(chunk, first);
+        let (chunk, rest) = Chunk::parse_prefix(rest).unwrap();
+        assert_eq!(chunk, second);
+        assert!(rest.is_empty());
+        assert!(Chunk::parse_prefix(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_new_unchecked() {
+        let data = "This is where your secret message will be!".as_bytes().to_vec();
+        let chunk = Chunk::new_unchecked(ChunkType::from_str("RuSt").unwrap(), data);
+        assert_eq!(chunk.crc.get(), None);
+        assert_eq!(chunk.crc(), 2882656334);
+        // computed once and kept, clones included
+        assert_eq!(chunk.crc.get(), Some(2882656334));
+        assert_eq!(chunk.clone().crc.get(), Some(2882656334));
+        assert_eq!(chunk, testing_chunk());
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+        assert!(chunk.has_valid_crc());
+        assert!(chunk.verify_crc().is_ok());
+    }
+
+    #[test]
+    fn test_chunk_verify_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let chunk = Chunk::from_bytes_unchecked(bytes.as_ref()).unwrap();
+        let expected = testing_chunk().crc();
+        assert!(matches!(chunk.verify_crc(), Err(Error::BadCrc { found, .. }) if found == expected ^ 1));
+        assert!(testing_chunk().verify_crc().is_ok());
+    }
+
+    #[test]
+    fn test_chunk_clone_eq() {
+        let chunk = testing_chunk();
+        let mut copy = chunk.clone();
+        assert_eq!(chunk, copy);
+        copy.set_data(Vec::new());
+        assert_ne!(chunk, copy);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
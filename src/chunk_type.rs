@@ -1,21 +1,124 @@
-use std::{str::FromStr, fmt::{Display, write}};
-use std::convert::TryFrom;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::{str::FromStr, fmt::Display};
+use core::convert::TryFrom;
 
-use anyhow::{bail, Result};
-
-type Error = anyhow::Error;
+use crate::error::{bail, Error};
 
 /// A chunk type is a 4 byte array of valid ASCII chars
 /// however they should not be treated as chars.
 /// This type code is only relevent to software that uses it
 /// the only thing that matters to the file is the case of the chars
 /// or the 5 bit of each byte.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
 
 impl ChunkType {
+    // The 18 chunk types defined by the PNG spec.
+
+    /// `IHDR`, image header.
+    pub const IHDR: ChunkType = ChunkType::from_bytes_const(*b"IHDR");
+    /// `PLTE`, palette.
+    pub const PLTE: ChunkType = ChunkType::from_bytes_const(*b"PLTE");
+    /// `IDAT`, image data.
+    pub const IDAT: ChunkType = ChunkType::from_bytes_const(*b"IDAT");
+    /// `IEND`, image trailer.
+    pub const IEND: ChunkType = ChunkType::from_bytes_const(*b"IEND");
+    /// `cHRM`, primary chromaticities and white point.
+    pub const CHRM: ChunkType = ChunkType::from_bytes_const(*b"cHRM");
+    /// `gAMA`, image gamma.
+    pub const GAMA: ChunkType = ChunkType::from_bytes_const(*b"gAMA");
+    /// `iCCP`, embedded ICC profile.
+    pub const ICCP: ChunkType = ChunkType::from_bytes_const(*b"iCCP");
+    /// `sBIT`, significant bits.
+    pub const SBIT: ChunkType = ChunkType::from_bytes_const(*b"sBIT");
+    /// `sRGB`, standard RGB colour space.
+    pub const SRGB: ChunkType = ChunkType::from_bytes_const(*b"sRGB");
+    /// `bKGD`, background colour.
+    pub const BKGD: ChunkType = ChunkType::from_bytes_const(*b"bKGD");
+    /// `hIST`, palette histogram.
+    pub const HIST: ChunkType = ChunkType::from_bytes_const(*b"hIST");
+    /// `tRNS`, transparency.
+    pub const TRNS: ChunkType = ChunkType::from_bytes_const(*b"tRNS");
+    /// `pHYs`, physical pixel dimensions.
+    pub const PHYS: ChunkType = ChunkType::from_bytes_const(*b"pHYs");
+    /// `sPLT`, suggested palette.
+    pub const SPLT: ChunkType = ChunkType::from_bytes_const(*b"sPLT");
+    /// `tIME`, last modification time.
+    pub const TIME: ChunkType = ChunkType::from_bytes_const(*b"tIME");
+    /// `iTXt`, international text.
+    pub const ITXT: ChunkType = ChunkType::from_bytes_const(*b"iTXt");
+    /// `tEXt`, Latin-1 text.
+    pub const TEXT: ChunkType = ChunkType::from_bytes_const(*b"tEXt");
+    /// `zTXt`, compressed Latin-1 text.
+    pub const ZTXT: ChunkType = ChunkType::from_bytes_const(*b"zTXt");
+
+    // Registered extension chunk types.
+
+    /// `eXIf`, Exif data.
+    pub const EXIF: ChunkType = ChunkType::from_bytes_const(*b"eXIf");
+    /// `cICP`, coding independent code points.
+    pub const CICP: ChunkType = ChunkType::from_bytes_const(*b"cICP");
+    /// `mDCv`, mastering display colour volume.
+    pub const MDCV: ChunkType = ChunkType::from_bytes_const(*b"mDCv");
+    /// `cLLi`, content light level.
+    pub const CLLI: ChunkType = ChunkType::from_bytes_const(*b"cLLi");
+    /// `acTL`, APNG animation control.
+    pub const ACTL: ChunkType = ChunkType::from_bytes_const(*b"acTL");
+    /// `fcTL`, APNG frame control.
+    pub const FCTL: ChunkType = ChunkType::from_bytes_const(*b"fcTL");
+    /// `fdAT`, APNG frame data.
+    pub const FDAT: ChunkType = ChunkType::from_bytes_const(*b"fdAT");
+    /// `oFFs`, image offset.
+    pub const OFFS: ChunkType = ChunkType::from_bytes_const(*b"oFFs");
+    /// `pCAL`, pixel calibration.
+    pub const PCAL: ChunkType = ChunkType::from_bytes_const(*b"pCAL");
+    /// `sCAL`, physical scale.
+    pub const SCAL: ChunkType = ChunkType::from_bytes_const(*b"sCAL");
+    /// `sTER`, stereo indicator.
+    pub const STER: ChunkType = ChunkType::from_bytes_const(*b"sTER");
+    /// `gIFg`, GIF graphic control extension.
+    pub const GIFG: ChunkType = ChunkType::from_bytes_const(*b"gIFg");
+    /// `gIFx`, GIF application extension.
+    pub const GIFX: ChunkType = ChunkType::from_bytes_const(*b"gIFx");
+
+    /// Creates a `ChunkType` in a const context.
+    /// Returns `None` if any byte is not an ASCII letter.
+    pub const fn new(bytes: [u8; 4]) -> Option<ChunkType> {
+        if ChunkType::is_ascii_valid(&bytes) {
+            Some(ChunkType { bytes })
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if every byte is an ASCII letter, A-Z or a-z.
+    pub const fn is_ascii_valid(bytes: &[u8; 4]) -> bool {
+        bytes[0].is_ascii_alphabetic()
+            && bytes[1].is_ascii_alphabetic()
+            && bytes[2].is_ascii_alphabetic()
+            && bytes[3].is_ascii_alphabetic()
+    }
+
+    /// Like `new` but panics on invalid bytes, which is a compile
+    /// error when used to define a constant:
+    ///
+    /// ```
+    /// use nice_pics::chunk_type::ChunkType;
+    /// const RUST: ChunkType = ChunkType::from_bytes_const(*b"RuSt");
+    /// ```
+    ///
+    /// # Panics
+    /// panics if any byte is not an ASCII letter.
+    pub const fn from_bytes_const(bytes: [u8; 4]) -> ChunkType {
+        match ChunkType::new(bytes) {
+            Some(chunk_type) => chunk_type,
+            None => panic!("chunk type bytes must be ASCII letters"),
+        }
+    }
+
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
@@ -47,7 +150,59 @@ impl ChunkType {
         ChunkType::get_bit_at(self.bytes[3], 5).unwrap()
     }
 
-    /// See `is_reserved_bit_valid`
+    /// Sets or clears bit 5 of byte `n`, `upper` means cleared.
+    fn set_case(&mut self, n: usize, upper: bool) {
+        if upper {
+            self.bytes[n] &= !0x20;
+        } else {
+            self.bytes[n] |= 0x20;
+        }
+    }
+
+    /// Makes the chunk critical (uppercase first letter) or ancillary.
+    pub fn set_critical(&mut self, critical: bool) {
+        self.set_case(0, critical);
+    }
+
+    /// Makes the chunk public (uppercase second letter) or private.
+    pub fn set_public(&mut self, public: bool) {
+        self.set_case(1, public);
+    }
+
+    /// Makes the chunk safe to copy (lowercase fourth letter) or unsafe.
+    pub fn set_safe_to_copy(&mut self, safe: bool) {
+        self.set_case(3, !safe);
+    }
+
+    /// Returns a copy with `set_critical` applied.
+    pub fn with_critical(mut self, critical: bool) -> ChunkType {
+        self.set_critical(critical);
+        self
+    }
+
+    /// Returns a copy with `set_public` applied.
+    pub fn with_public(mut self, public: bool) -> ChunkType {
+        self.set_public(public);
+        self
+    }
+
+    /// Returns a copy with `set_safe_to_copy` applied.
+    pub fn with_safe_to_copy(mut self, safe: bool) -> ChunkType {
+        self.set_safe_to_copy(safe);
+        self
+    }
+
+    /// Reads all four property bits at once.
+    pub fn classify(&self) -> ChunkProperties {
+        ChunkProperties {
+            critical: self.bytes[0] & 0x20 == 0,
+            public: self.bytes[1] & 0x20 == 0,
+            reserved_bit_valid: self.bytes[2] & 0x20 == 0,
+            safe_to_copy: self.bytes[3] & 0x20 != 0,
+        }
+    }
+
+    /// See `is_reserved_bit_valid`, and `validate` for the other rules.
     pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
@@ -66,6 +221,69 @@ impl ChunkType {
     }
 }
 
+/// A chunk type to look for, given as a `&str`, `[u8; 4]` or `ChunkType`.
+/// Strings and arrays are only checked by `resolve`, so methods taking
+/// a query can report an invalid type instead of just finding nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkTypeQuery<'a> {
+    Type(ChunkType),
+    Str(&'a str),
+    Bytes([u8; 4]),
+}
+
+impl ChunkTypeQuery<'_> {
+    /// Turns the query into a `ChunkType`.
+    ///
+    /// # Errors
+    /// returns an Error if the string or bytes are not a valid chunk type.
+    pub fn resolve(&self) -> Result<ChunkType, Error> {
+        match self {
+            ChunkTypeQuery::Type(t) => Ok(*t),
+            ChunkTypeQuery::Str(s) => ChunkType::from_str(s),
+            ChunkTypeQuery::Bytes(b) => ChunkType::try_from(*b),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for ChunkTypeQuery<'a> {
+    fn from(value: &'a str) -> Self {
+        ChunkTypeQuery::Str(value)
+    }
+}
+
+impl From<ChunkType> for ChunkTypeQuery<'_> {
+    fn from(value: ChunkType) -> Self {
+        ChunkTypeQuery::Type(value)
+    }
+}
+
+impl From<&ChunkType> for ChunkTypeQuery<'_> {
+    fn from(value: &ChunkType) -> Self {
+        ChunkTypeQuery::Type(*value)
+    }
+}
+
+impl From<[u8; 4]> for ChunkTypeQuery<'_> {
+    fn from(value: [u8; 4]) -> Self {
+        ChunkTypeQuery::Bytes(value)
+    }
+}
+
+impl From<&[u8; 4]> for ChunkTypeQuery<'_> {
+    fn from(value: &[u8; 4]) -> Self {
+        ChunkTypeQuery::Bytes(*value)
+    }
+}
+
+/// The property bits of a `ChunkType`, see `ChunkType::classify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkProperties {
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+}
+
 impl TryFrom<[u8; 4]> for ChunkType {
     type Error = Error;
 
@@ -76,10 +294,9 @@ impl TryFrom<[u8; 4]> for ChunkType {
     /// returns an Error if a byte is invalid ASCII
     ///
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        let range: Vec<u8> = (b'A'..=b'Z').chain(b'a'..=b'z').collect();
         for n in value {
-            if !range.contains(&n) {
-                bail!("invalid byte: {}/{}", n, n.to_string());
+            if !n.is_ascii_alphabetic() {
+                return Err(Error::InvalidChunkType(value.to_vec()));
             }
         }
 
@@ -96,19 +313,17 @@ impl FromStr for ChunkType {
     /// # Errors
     /// returns an Error if len != 4 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-       if s.len() != 4 { bail!("invalid len of: {}", s.len()); }
+       if s.len() != 4 { return Err(Error::InvalidChunkType(s.as_bytes().to_vec())); }
        let s: Vec<u8> = s.bytes().take(4).collect();
        let mut bytes = [0u8; 4];
-       for i in 0..4 {
-           bytes[i] = s[i];
-       }
+       bytes.copy_from_slice(&s[..4]);
 
        ChunkType::try_from(bytes)
     }
 }
 
 impl Display for ChunkType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", String::from_utf8_lossy(&self.bytes()))
     }
 }
@@ -116,8 +331,8 @@ impl Display for ChunkType {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::convert::TryFrom;
-    use std::str::FromStr;
+    use core::convert::TryFrom;
+    use core::str::FromStr;
 
     #[test]
     pub fn test_chunk_type_from_bytes() {
@@ -203,6 +418,113 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_clone_hash() {
+        use std::collections::HashSet;
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        let mut set = HashSet::new();
+        set.insert(chunk);
+        assert!(set.contains(&chunk));
+        assert!(!set.contains(&ChunkType::from_str("RuST").unwrap()));
+    }
+
+    #[test]
+    pub fn test_chunk_type_constants() {
+        assert_eq!(ChunkType::IHDR, ChunkType::from_str("IHDR").unwrap());
+        assert_eq!(ChunkType::TEXT, ChunkType::from_str("tEXt").unwrap());
+        assert_eq!(ChunkType::FDAT, ChunkType::from_str("fdAT").unwrap());
+        assert!(ChunkType::IEND.is_critical());
+        assert!(!ChunkType::ZTXT.is_critical());
+        assert!(ChunkType::ITXT.is_valid());
+    }
+
+    #[test]
+    pub fn test_chunk_type_const_new() {
+        const RUST: Option<ChunkType> = ChunkType::new(*b"RuSt");
+        assert_eq!(RUST, Some(ChunkType::from_str("RuSt").unwrap()));
+        assert_eq!(ChunkType::new(*b"Ru1t"), None);
+        assert_eq!(ChunkType::from_bytes_const(*b"RuSt").bytes(), *b"RuSt");
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_chunk_type_const_panics() {
+        ChunkType::from_bytes_const(*b"Ru t");
+    }
+
+    #[test]
+    pub fn test_chunk_type_classify() {
+        let props = ChunkType::from_str("RuSt").unwrap().classify();
+        assert_eq!(props, ChunkProperties {
+            critical: true,
+            public: false,
+            reserved_bit_valid: true,
+            safe_to_copy: true,
+        });
+        for s in ["RuSt", "rust", "RUST", "rUsT"] {
+            let chunk = ChunkType::from_str(s).unwrap();
+            let props = chunk.classify();
+            assert_eq!(props.critical, chunk.is_critical());
+            assert_eq!(props.public, chunk.is_public());
+            assert_eq!(props.reserved_bit_valid, chunk.is_reserved_bit_valid());
+            assert_eq!(props.safe_to_copy, chunk.is_safe_to_copy());
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_ascii_valid() {
+        assert!(ChunkType::is_ascii_valid(b"RuSt"));
+        assert!(!ChunkType::is_ascii_valid(b"Ru[t"));
+        assert!(!ChunkType::is_ascii_valid(b"Ru@t"));
+        assert!(ChunkType::try_from(*b"Ru`t").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_setters() {
+        let chunk = ChunkType::from_str("RuSt").unwrap()
+            .with_critical(false)
+            .with_public(true)
+            .with_safe_to_copy(false);
+        assert_eq!(&chunk.to_string(), "rUST");
+        assert!(!chunk.is_critical());
+        assert!(chunk.is_public());
+        assert!(!chunk.is_safe_to_copy());
+        assert!(chunk.is_valid());
+
+        let mut chunk = chunk;
+        chunk.set_critical(true);
+        chunk.set_public(false);
+        chunk.set_safe_to_copy(true);
+        assert_eq!(&chunk.to_string(), "RuSt");
+    }
+
+    #[test]
+    pub fn test_chunk_type_copy_ord() {
+        use std::collections::BTreeMap;
+        let chunk = ChunkType::IDAT;
+        let copy = chunk;
+        assert_eq!(chunk, copy);
+
+        let mut types = vec![ChunkType::TEXT, ChunkType::IEND, ChunkType::IDAT];
+        types.sort();
+        assert_eq!(types, vec![ChunkType::IDAT, ChunkType::IEND, ChunkType::TEXT]);
+
+        let mut index = BTreeMap::new();
+        *index.entry(ChunkType::IDAT).or_insert(0) += 1;
+        *index.entry(ChunkType::IDAT).or_insert(0) += 1;
+        assert_eq!(index[&ChunkType::IDAT], 2);
+    }
+
+    #[test]
+    pub fn test_chunk_type_query() {
+        let expected = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(ChunkTypeQuery::from("RuSt").resolve().unwrap(), expected);
+        assert_eq!(ChunkTypeQuery::from(*b"RuSt").resolve().unwrap(), expected);
+        assert_eq!(ChunkTypeQuery::from(expected).resolve().unwrap(), expected);
+        assert!(ChunkTypeQuery::from("Rust!").resolve().is_err());
+        assert!(ChunkTypeQuery::from(b"Ru1t").resolve().is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -0,0 +1,231 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+/// gAMA and cHRM store values times 100000.
+const SCALE: f64 = 100_000.0;
+
+/// The gamma written with sRGB, 1/2.2 rounded like the spec says.
+pub const SRGB_GAMMA: f64 = 0.45455;
+
+/// Colour space chunks go before the palette and image data.
+const BEFORE: [ChunkType; 2] = [ChunkType::PLTE, ChunkType::IDAT];
+
+/// The CIE 1931 x, y of the white point and primaries, from cHRM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chromaticities {
+    pub white: (f64, f64),
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+}
+
+impl Chromaticities {
+    /// The values the spec gives for images with sRGB.
+    pub const SRGB: Chromaticities = Chromaticities {
+        white: (0.3127, 0.329),
+        red: (0.64, 0.33),
+        green: (0.3, 0.6),
+        blue: (0.15, 0.06),
+    };
+
+    /// # Errors
+    /// returns an Error if a value is negative or too large to store.
+    pub fn to_chunk(&self) -> Result<Chunk, Error> {
+        let mut data = Vec::with_capacity(32);
+        for (x, y) in [self.white, self.red, self.green, self.blue] {
+            data.extend(to_fixed(x)?.to_be_bytes());
+            data.extend(to_fixed(y)?.to_be_bytes());
+        }
+        Ok(Chunk::new(ChunkType::CHRM, data))
+    }
+}
+
+impl TryFrom<&Chunk> for Chromaticities {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the chunk is not a 32 byte cHRM.
+    fn try_from(chunk: &Chunk) -> Result<Chromaticities, Error> {
+        if *chunk.chunk_type() != ChunkType::CHRM {
+            bail!("expected cHRM, got {}", chunk.chunk_type());
+        }
+        if chunk.length() != 32 {
+            bail!("cHRM data must be 32 bytes, got {}", chunk.length());
+        }
+        let values: Vec<f64> = chunk.data().chunks(4).map(from_fixed).collect();
+        Ok(Chromaticities {
+            white: (values[0], values[1]),
+            red: (values[2], values[3]),
+            green: (values[4], values[5]),
+            blue: (values[6], values[7]),
+        })
+    }
+}
+
+/// How colours outside the output gamut are mapped, from sRGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+impl RenderingIntent {
+    pub fn to_chunk(self) -> Chunk {
+        Chunk::new(ChunkType::SRGB, vec![self as u8])
+    }
+}
+
+impl TryFrom<&Chunk> for RenderingIntent {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the chunk is not a 1 byte sRGB with a known intent.
+    fn try_from(chunk: &Chunk) -> Result<RenderingIntent, Error> {
+        if *chunk.chunk_type() != ChunkType::SRGB {
+            bail!("expected sRGB, got {}", chunk.chunk_type());
+        }
+        Ok(match *chunk.data() {
+            [0] => RenderingIntent::Perceptual,
+            [1] => RenderingIntent::RelativeColorimetric,
+            [2] => RenderingIntent::Saturation,
+            [3] => RenderingIntent::AbsoluteColorimetric,
+            [n] => bail!("unknown rendering intent: {}", n),
+            _ => bail!("sRGB data must be 1 byte, got {}", chunk.length()),
+        })
+    }
+}
+
+impl Png {
+    /// The gamma from gAMA, like 0.45455 for 1/2.2.
+    ///
+    /// # Errors
+    /// returns an Error if the gAMA chunk is not 4 bytes.
+    pub fn gamma(&self) -> Result<Option<f64>, Error> {
+        match self.chunk_by_type("gAMA") {
+            Some(c) if c.length() == 4 => Ok(Some(from_fixed(c.data()))),
+            Some(c) => bail!("gAMA data must be 4 bytes, got {}", c.length()),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces gAMA, or adds it before the palette and image data.
+    ///
+    /// # Errors
+    /// returns an Error if `gamma` is not positive or too large to store.
+    pub fn set_gamma(&mut self, gamma: f64) -> Result<(), Error> {
+        let fixed = to_fixed(gamma)?;
+        if fixed == 0 {
+            bail!("gamma must be positive: {}", gamma);
+        }
+        self.replace_unique(Chunk::new(ChunkType::GAMA, fixed.to_be_bytes().to_vec()), &BEFORE);
+        Ok(())
+    }
+
+    /// The white point and primaries from cHRM.
+    ///
+    /// # Errors
+    /// returns an Error if the cHRM chunk is malformed.
+    pub fn chromaticities(&self) -> Result<Option<Chromaticities>, Error> {
+        self.chunk_by_type("cHRM").map(Chromaticities::try_from).transpose()
+    }
+
+    /// Replaces cHRM, or adds it before the palette and image data.
+    ///
+    /// # Errors
+    /// returns an Error if a value is negative or too large to store.
+    pub fn set_chromaticities(&mut self, chromaticities: &Chromaticities) -> Result<(), Error> {
+        self.replace_unique(chromaticities.to_chunk()?, &BEFORE);
+        Ok(())
+    }
+
+    /// The rendering intent from sRGB, which also means the image is sRGB.
+    ///
+    /// # Errors
+    /// returns an Error if the sRGB chunk is malformed.
+    pub fn srgb(&self) -> Result<Option<RenderingIntent>, Error> {
+        self.chunk_by_type("sRGB").map(RenderingIntent::try_from).transpose()
+    }
+
+    /// Marks the image as sRGB. Like the spec recommends, this also
+    /// writes the matching gAMA and cHRM for decoders without sRGB
+    /// support, and removes iCCP, which must not appear with sRGB.
+    pub fn set_srgb(&mut self, intent: RenderingIntent) {
+        self.chunks_mut().retain(|c| *c.chunk_type() != ChunkType::ICCP);
+        self.replace_unique(intent.to_chunk(), &BEFORE);
+        let gamma = (SRGB_GAMMA * SCALE).round() as u32;
+        self.replace_unique(Chunk::new(ChunkType::GAMA, gamma.to_be_bytes().to_vec()), &BEFORE);
+        let chrm = Chromaticities::SRGB.to_chunk().expect("sRGB chromaticities are in range");
+        self.replace_unique(chrm, &BEFORE);
+    }
+}
+
+fn to_fixed(value: f64) -> Result<u32, Error> {
+    let fixed = (value * SCALE).round();
+    if !(0.0..=i32::MAX as f64).contains(&fixed) {
+        bail!("{} is out of range", value);
+    }
+    Ok(fixed as u32)
+}
+
+fn from_fixed(bytes: &[u8]) -> f64 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64 / SCALE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn position(png: &Png, chunk_type: ChunkType) -> usize {
+        png.chunks().iter().position(|c| *c.chunk_type() == chunk_type).unwrap()
+    }
+
+    #[test]
+    fn test_gamma() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_chunks("gAMA");
+        assert_eq!(png.gamma().unwrap(), None);
+        png.set_gamma(1.0 / 2.2).unwrap();
+        assert_eq!(png.chunk_by_type("gAMA").unwrap().data(), 45455u32.to_be_bytes());
+        assert_eq!(png.gamma().unwrap(), Some(0.45455));
+        assert!(position(&png, ChunkType::GAMA) < position(&png, ChunkType::PLTE));
+        assert!(png.set_gamma(0.0).is_err());
+        assert!(png.set_gamma(-1.0).is_err());
+    }
+
+    #[test]
+    fn test_chromaticities() {
+        let chunk = Chromaticities::SRGB.to_chunk().unwrap();
+        assert_eq!(chunk.data()[..8], [0, 0, 0x7a, 0x26, 0, 0, 0x80, 0x84]);
+        assert_eq!(Chromaticities::try_from(&chunk).unwrap(), Chromaticities::SRGB);
+        assert!(Chromaticities::try_from(&Chunk::new(ChunkType::CHRM, vec![0; 31])).is_err());
+
+        let mut png = Png::try_from(IMG).unwrap();
+        let custom = Chromaticities { white: (0.3457, 0.3585), ..Chromaticities::SRGB };
+        png.set_chromaticities(&custom).unwrap();
+        assert_eq!(png.chromaticities().unwrap(), Some(custom));
+    }
+
+    #[test]
+    fn test_srgb() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.insert_after_type("IHDR", Chunk::new(ChunkType::ICCP, b"p\0\0".to_vec())).unwrap();
+        png.set_gamma(1.0).unwrap();
+        png.set_srgb(RenderingIntent::Perceptual);
+        assert_eq!(png.srgb().unwrap(), Some(RenderingIntent::Perceptual));
+        assert_eq!(png.gamma().unwrap(), Some(SRGB_GAMMA));
+        assert_eq!(png.chromaticities().unwrap(), Some(Chromaticities::SRGB));
+        assert!(png.chunk_by_type("iCCP").is_none());
+        assert!(position(&png, ChunkType::SRGB) < position(&png, ChunkType::PLTE));
+
+        png.set_srgb(RenderingIntent::AbsoluteColorimetric);
+        assert_eq!(png.srgb().unwrap(), Some(RenderingIntent::AbsoluteColorimetric));
+        assert_eq!(png.chunks_of_type("sRGB").count(), 1);
+        assert!(RenderingIntent::try_from(&Chunk::new(ChunkType::SRGB, vec![4])).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::chunk_type::ChunkType;
+use crate::encode::EncodeOptions;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::quantize::pack_rows;
+
+impl Png {
+    /// Encodes the image again in another colour type and bit depth.
+    /// Palettes are expanded, greyscale is spread over RGB and RGB turns
+    /// into greyscale by its luma. Samples are scaled to the new bit
+    /// depth, rounding to the nearest value. Transparency from tRNS
+    /// becomes the alpha channel, alpha is dropped if the new colour type
+    /// has none. Interlacing is kept.
+    ///
+    /// The header and image data are replaced, PLTE, tRNS, bKGD, hIST
+    /// and sBIT describe the old pixel format, so they are removed.
+    /// Nothing changes if the image already has that format.
+    ///
+    /// # Errors
+    /// returns an Error if `bit_depth` is not allowed for `color_type`,
+    /// the image can not be decoded, or `color_type` is `Indexed`:
+    /// that needs a palette to be picked, which `Png::quantize` does.
+    pub fn convert(&mut self, color_type: ColorType, bit_depth: u8) -> Result<(), Error> {
+        if color_type == ColorType::Indexed {
+            bail!("converting to indexed colour needs a palette, use Png::quantize");
+        }
+        let old = self.header()?;
+        let ihdr =
+            Ihdr::new(old.width(), old.height(), bit_depth, color_type)?.with_interlace(old.is_interlaced());
+        if old == ihdr {
+            return Ok(());
+        }
+        let image = self.decode_pixels()?;
+
+        let max = (1u32 << bit_depth) - 1;
+        let scale = |v: u16| ((v as u32 * max + 32767) / 65535) as u16;
+        let channels = color_type.channels() as usize;
+        let mut samples = Vec::with_capacity(image.width() as usize * image.height() as usize * channels);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let [r, g, b, a] = image.pixel_rgba16(x, y)?;
+                // Rec. 601 luma, exact for grey pixels
+                let luma = ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u16;
+                match color_type {
+                    ColorType::Grayscale => samples.push(luma),
+                    ColorType::GrayscaleAlpha => samples.extend([luma, a]),
+                    ColorType::Rgb => samples.extend([r, g, b]),
+                    _ => samples.extend([r, g, b, a]),
+                }
+            }
+        }
+        let pixels: Vec<u8> = match bit_depth {
+            16 => samples.into_iter().flat_map(|s| s.to_be_bytes()).collect(),
+            8 => samples.into_iter().map(|s| scale(s) as u8).collect(),
+            _ => pack_rows(&ihdr, &samples.into_iter().map(|s| scale(s) as u8).collect::<Vec<u8>>()),
+        };
+
+        for t in [ChunkType::PLTE, ChunkType::TRNS, ChunkType::BKGD, ChunkType::HIST, ChunkType::SBIT] {
+            self.chunks_mut().retain(|c| *c.chunk_type() != t);
+        }
+        self.replace_pixels(&ihdr, &pixels, &EncodeOptions { bit_depth, ..Default::default() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_convert_to_rgba8() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let expected = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        png.convert(ColorType::Rgba, 8).unwrap();
+        assert!(png.chunk_by_type("PLTE").is_none());
+        assert!(png.validate().is_ok());
+        let image = png.decode_pixels().unwrap();
+        assert_eq!((image.color_type(), image.bit_depth()), (ColorType::Rgba, 8));
+        assert_eq!(image.data(), expected.data());
+    }
+
+    #[test]
+    fn test_convert_depths() {
+        let pixels = [0x12, 0x34, 0xff, 0x80, 0x00, 0x7f];
+        let options = EncodeOptions { bit_depth: 16, ..Default::default() };
+        let mut png = Png::from_pixels(3, 1, ColorType::Grayscale, &pixels, options).unwrap();
+        png.convert(ColorType::Grayscale, 8).unwrap();
+        assert_eq!(png.decode_pixels().unwrap().data(), &[0x12, 0xff, 0x00]);
+
+        png.convert(ColorType::Grayscale, 1).unwrap();
+        assert_eq!(png.decode_pixels().unwrap().data(), &[0b0100_0000]);
+        png.convert(ColorType::Grayscale, 8).unwrap();
+        assert_eq!(png.decode_pixels().unwrap().data(), &[0, 255, 0]);
+
+        png.convert(ColorType::GrayscaleAlpha, 16).unwrap();
+        let expected = [0, 0, 255, 255, 255, 255, 255, 255, 0, 0, 255, 255];
+        assert_eq!(png.decode_pixels().unwrap().data(), &expected);
+    }
+
+    #[test]
+    fn test_convert_gray_rgb_alpha() {
+        let pixels = [10, 20, 30, 0, 200, 200, 200, 255];
+        let mut png = Png::from_pixels(2, 1, ColorType::Rgba, &pixels, EncodeOptions::default()).unwrap();
+        png.convert(ColorType::Rgb, 8).unwrap();
+        assert_eq!(png.decode_pixels().unwrap().data(), &[10, 20, 30, 200, 200, 200]);
+        png.convert(ColorType::Grayscale, 8).unwrap();
+        assert_eq!(png.decode_pixels().unwrap().data(), &[18, 200]);
+
+        // the tRNS key turns into alpha
+        png.insert_before_type("IDAT", Chunk::new(ChunkType::TRNS, vec![0, 200])).unwrap();
+        png.convert(ColorType::Rgba, 8).unwrap();
+        assert!(png.chunk_by_type("tRNS").is_none());
+        assert_eq!(png.decode_pixels().unwrap().data(), &[18, 18, 18, 255, 200, 200, 200, 0]);
+    }
+
+    #[test]
+    fn test_convert_errors() {
+        let mut png = Png::try_from(IMG).unwrap();
+        assert!(png.convert(ColorType::Indexed, 8).is_err());
+        assert!(png.convert(ColorType::Rgb, 4).is_err());
+
+        // unchanged, so the suggested palette stays
+        let mut rgb = Png::from_pixels(1, 1, ColorType::Rgb, &[1, 2, 3], EncodeOptions::default()).unwrap();
+        rgb.insert_before_type("IDAT", Chunk::new(ChunkType::PLTE, vec![0; 3])).unwrap();
+        let before = rgb.clone();
+        rgb.convert(ColorType::Rgb, 8).unwrap();
+        assert_eq!(rgb, before);
+    }
+}
@@ -1,61 +1,323 @@
 
+/// Describes a CRC algorithm, in the usual Rocksoft model terms.
+/// `width` can be anything from 8 to 32 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CrcParams {
+    pub width: u8,
+    pub poly: u32,
+    pub init: u32,
+    /// Process bits least significant first, for both input and output.
+    pub reflect: bool,
+    pub xor_out: u32,
+}
+
+/// The CRC-32 used by PNG, zlib and Ethernet.
+pub const PNG_CRC32: CrcParams = CrcParams {
+    width: 32,
+    poly: 0x04c11db7,
+    init: 0xffffffff,
+    reflect: true,
+    xor_out: 0xffffffff,
+};
+
+/// CRC-16/CCITT-FALSE, as used by X.25 framing and many file formats.
+pub const CRC16_CCITT: CrcParams = CrcParams {
+    width: 16,
+    poly: 0x1021,
+    init: 0xffff,
+    reflect: false,
+    xor_out: 0,
+};
+
+/// A table driven CRC for any `CrcParams`.
+/// The table is built at compile time when used in a `const` or `static`.
+#[derive(Debug, Clone)]
+pub struct CrcEngine {
+    params: CrcParams,
+    table: [u32; 256],
+}
+
+/// The engine behind `crc32` and `Crc32`.
+pub static CRC32_ENGINE: CrcEngine = CrcEngine::new(PNG_CRC32);
+
+const fn reflect_bits(mut value: u32, width: u8) -> u32 {
+    let mut out = 0;
+    let mut n = 0;
+    while n < width {
+        out = (out << 1) | (value & 1);
+        value >>= 1;
+        n += 1;
+    }
+    out
+}
+
+const fn mask(width: u8) -> u32 {
+    if width == 32 { u32::MAX } else { (1 << width) - 1 }
+}
+
+impl CrcEngine {
+    /// # Panics
+    /// panics if `params.width` is not between 8 and 32.
+    pub const fn new(params: CrcParams) -> CrcEngine {
+        assert!(params.width >= 8 && params.width <= 32, "crc width must be 8 to 32 bits");
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c;
+            let mut bit = 0;
+            if params.reflect {
+                let poly = reflect_bits(params.poly, params.width);
+                c = i as u32;
+                while bit < 8 {
+                    c = if c & 1 == 1 { (c >> 1) ^ poly } else { c >> 1 };
+                    bit += 1;
+                }
+            } else {
+                let top = 1 << (params.width - 1);
+                c = (i as u32) << (params.width - 8);
+                while bit < 8 {
+                    c = if c & top != 0 { (c << 1) ^ params.poly } else { c << 1 };
+                    bit += 1;
+                }
+                c &= mask(params.width);
+            }
+            table[i] = c;
+            i += 1;
+        }
+        CrcEngine { params, table }
+    }
+
+    pub fn params(&self) -> &CrcParams {
+        &self.params
+    }
+
+    /// Starts an incremental computation.
+    pub fn digest(&self) -> Digest<'_> {
+        let init = if self.params.reflect {
+            reflect_bits(self.params.init, self.params.width)
+        } else {
+            self.params.init
+        };
+        Digest { engine: self, c: init }
+    }
+
+    /// The crc of `data` in one go.
+    pub fn checksum(&self, data: &[u8]) -> u32 {
+        let mut digest = self.digest();
+        digest.update(data);
+        digest.finalize()
+    }
+}
+
+/// An incremental computation started by `CrcEngine::digest`.
+#[derive(Debug, Clone, Copy)]
+pub struct Digest<'a> {
+    engine: &'a CrcEngine,
+    c: u32,
+}
+
+impl Digest<'_> {
+    pub fn update(&mut self, data: &[u8]) {
+        let table = &self.engine.table;
+        let width = self.engine.params.width;
+        if self.engine.params.reflect {
+            for b in data {
+                self.c = (self.c >> 8) ^ table[(self.c as u8 ^ b) as usize];
+            }
+        } else {
+            let shift = width - 8;
+            for b in data {
+                let index = ((self.c >> shift) as u8 ^ b) as usize;
+                self.c = ((self.c << 8) & mask(width)) ^ table[index];
+            }
+        }
+    }
+
+    /// The crc of everything passed to `update` so far.
+    pub fn finalize(&self) -> u32 {
+        (self.c ^ self.engine.params.xor_out) & mask(self.engine.params.width)
+    }
+}
+
+/// CRC-32 of the first `len` bytes of `data`, as used by PNG chunks.
 pub fn crc32(data: &[u8], len: usize) -> u32 {
-    let mut c = 0xffffffff;
-    let table = get_table();
-
-    for n in 0..len {
-        let index = ((c as u8 ^ data[n]) & 0xff) as usize;
-        c = (c >> 8) ^ table[index];
-    }
-    c = !c;
-    c
-}
-
-fn get_table() -> [u32; 256] {
-    [
-    0x00000000, 0x77073096, 0xee0e612c, 0x990951ba, 0x076dc419, 0x706af48f,
-	0xe963a535, 0x9e6495a3,	0x0edb8832, 0x79dcb8a4, 0xe0d5e91e, 0x97d2d988,
-	0x09b64c2b, 0x7eb17cbd, 0xe7b82d07, 0x90bf1d91, 0x1db71064, 0x6ab020f2,
-	0xf3b97148, 0x84be41de,	0x1adad47d, 0x6ddde4eb, 0xf4d4b551, 0x83d385c7,
-	0x136c9856, 0x646ba8c0, 0xfd62f97a, 0x8a65c9ec,	0x14015c4f, 0x63066cd9,
-	0xfa0f3d63, 0x8d080df5,	0x3b6e20c8, 0x4c69105e, 0xd56041e4, 0xa2677172,
-	0x3c03e4d1, 0x4b04d447, 0xd20d85fd, 0xa50ab56b,	0x35b5a8fa, 0x42b2986c,
-	0xdbbbc9d6, 0xacbcf940,	0x32d86ce3, 0x45df5c75, 0xdcd60dcf, 0xabd13d59,
-	0x26d930ac, 0x51de003a, 0xc8d75180, 0xbfd06116, 0x21b4f4b5, 0x56b3c423,
-	0xcfba9599, 0xb8bda50f, 0x2802b89e, 0x5f058808, 0xc60cd9b2, 0xb10be924,
-	0x2f6f7c87, 0x58684c11, 0xc1611dab, 0xb6662d3d,	0x76dc4190, 0x01db7106,
-	0x98d220bc, 0xefd5102a, 0x71b18589, 0x06b6b51f, 0x9fbfe4a5, 0xe8b8d433,
-	0x7807c9a2, 0x0f00f934, 0x9609a88e, 0xe10e9818, 0x7f6a0dbb, 0x086d3d2d,
-	0x91646c97, 0xe6635c01, 0x6b6b51f4, 0x1c6c6162, 0x856530d8, 0xf262004e,
-	0x6c0695ed, 0x1b01a57b, 0x8208f4c1, 0xf50fc457, 0x65b0d9c6, 0x12b7e950,
-	0x8bbeb8ea, 0xfcb9887c, 0x62dd1ddf, 0x15da2d49, 0x8cd37cf3, 0xfbd44c65,
-	0x4db26158, 0x3ab551ce, 0xa3bc0074, 0xd4bb30e2, 0x4adfa541, 0x3dd895d7,
-	0xa4d1c46d, 0xd3d6f4fb, 0x4369e96a, 0x346ed9fc, 0xad678846, 0xda60b8d0,
-	0x44042d73, 0x33031de5, 0xaa0a4c5f, 0xdd0d7cc9, 0x5005713c, 0x270241aa,
-	0xbe0b1010, 0xc90c2086, 0x5768b525, 0x206f85b3, 0xb966d409, 0xce61e49f,
-	0x5edef90e, 0x29d9c998, 0xb0d09822, 0xc7d7a8b4, 0x59b33d17, 0x2eb40d81,
-	0xb7bd5c3b, 0xc0ba6cad, 0xedb88320, 0x9abfb3b6, 0x03b6e20c, 0x74b1d29a,
-	0xead54739, 0x9dd277af, 0x04db2615, 0x73dc1683, 0xe3630b12, 0x94643b84,
-	0x0d6d6a3e, 0x7a6a5aa8, 0xe40ecf0b, 0x9309ff9d, 0x0a00ae27, 0x7d079eb1,
-	0xf00f9344, 0x8708a3d2, 0x1e01f268, 0x6906c2fe, 0xf762575d, 0x806567cb,
-	0x196c3671, 0x6e6b06e7, 0xfed41b76, 0x89d32be0, 0x10da7a5a, 0x67dd4acc,
-	0xf9b9df6f, 0x8ebeeff9, 0x17b7be43, 0x60b08ed5, 0xd6d6a3e8, 0xa1d1937e,
-	0x38d8c2c4, 0x4fdff252, 0xd1bb67f1, 0xa6bc5767, 0x3fb506dd, 0x48b2364b,
-	0xd80d2bda, 0xaf0a1b4c, 0x36034af6, 0x41047a60, 0xdf60efc3, 0xa867df55,
-	0x316e8eef, 0x4669be79, 0xcb61b38c, 0xbc66831a, 0x256fd2a0, 0x5268e236,
-	0xcc0c7795, 0xbb0b4703, 0x220216b9, 0x5505262f, 0xc5ba3bbe, 0xb2bd0b28,
-	0x2bb45a92, 0x5cb36a04, 0xc2d7ffa7, 0xb5d0cf31, 0x2cd99e8b, 0x5bdeae1d,
-	0x9b64c2b0, 0xec63f226, 0x756aa39c, 0x026d930a, 0x9c0906a9, 0xeb0e363f,
-	0x72076785, 0x05005713, 0x95bf4a82, 0xe2b87a14, 0x7bb12bae, 0x0cb61b38,
-	0x92d28e9b, 0xe5d5be0d, 0x7cdcefb7, 0x0bdbdf21, 0x86d3d2d4, 0xf1d4e242,
-	0x68ddb3f8, 0x1fda836e, 0x81be16cd, 0xf6b9265b, 0x6fb077e1, 0x18b74777,
-	0x88085ae6, 0xff0f6a70, 0x66063bca, 0x11010b5c, 0x8f659eff, 0xf862ae69,
-	0x616bffd3, 0x166ccf45, 0xa00ae278, 0xd70dd2ee, 0x4e048354, 0x3903b3c2,
-	0xa7672661, 0xd06016f7, 0x4969474d, 0x3e6e77db, 0xaed16a4a, 0xd9d65adc,
-	0x40df0b66, 0x37d83bf0, 0xa9bcae53, 0xdebb9ec5, 0x47b2cf7f, 0x30b5ffe9,
-	0xbdbdf21c, 0xcabac28a, 0x53b39330, 0x24b4a3a6, 0xbad03605, 0xcdd70693,
-	0x54de5729, 0x23d967bf, 0xb3667a2e, 0xc4614ab8, 0x5d681b02, 0x2a6f2b94,
-	0xb40bbe37, 0xc30c8ea1, 0x5a05df1b, 0x2d02ef8d
-    ]
+    let mut crc = Crc32::new();
+    crc.update(&data[..len]);
+    crc.finalize()
+}
 
+/// Incremental CRC-32, for data that does not sit in one buffer,
+/// like the type and data fields of a chunk.
+/// With the `fast-crc` feature this uses `crc32fast`, which picks
+/// SSE4.2/PCLMULQDQ or ARM CRC instructions at runtime when available,
+/// otherwise it is `CRC32_ENGINE`.
+#[derive(Debug, Clone)]
+pub struct Crc32 {
+    #[cfg(not(feature = "fast-crc"))]
+    digest: Digest<'static>,
+    #[cfg(feature = "fast-crc")]
+    hasher: crc32fast::Hasher,
+}
+
+impl Crc32 {
+    #[cfg(not(feature = "fast-crc"))]
+    pub fn new() -> Crc32 {
+        Crc32 { digest: CRC32_ENGINE.digest() }
+    }
+
+    #[cfg(feature = "fast-crc")]
+    pub fn new() -> Crc32 {
+        Crc32 { hasher: crc32fast::Hasher::new() }
+    }
+
+    #[cfg(not(feature = "fast-crc"))]
+    pub fn update(&mut self, data: &[u8]) {
+        self.digest.update(data);
+    }
+
+    #[cfg(feature = "fast-crc")]
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    /// The crc of everything passed to `update` so far.
+    #[cfg(not(feature = "fast-crc"))]
+    pub fn finalize(&self) -> u32 {
+        self.digest.finalize()
+    }
+
+    /// The crc of everything passed to `update` so far.
+    #[cfg(feature = "fast-crc")]
+    pub fn finalize(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+/// Adler-32 of `data`, the checksum at the end of a zlib stream
+/// like the one split across IDAT chunks.
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.finalize()
+}
+
+/// Incremental Adler-32, see `adler32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    const MOD: u32 = 65521;
+    // most bytes that can be summed before `b` could overflow a u32
+    const NMAX: usize = 5552;
+
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for block in data.chunks(Adler32::NMAX) {
+            for b in block {
+                self.a += *b as u32;
+                self.b += self.a;
+            }
+            self.a %= Adler32::MOD;
+            self.b %= Adler32::MOD;
+        }
+    }
+
+    /// The checksum of everything passed to `update` so far.
+    pub fn finalize(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Adler32::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"", 0), 0);
+        assert_eq!(crc32(b"123456789", 9), 0xcbf43926);
+        assert_eq!(crc32(b"123456789xyz", 9), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_crc32_incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"");
+        crc.update(b"56789");
+        assert_eq!(crc.finalize(), 0xcbf43926);
+        assert_eq!(Crc32::default().finalize(), 0);
+    }
+
+    #[test]
+    fn test_crc_engine_presets() {
+        // the "check" values of the standard catalogue, crc of "123456789"
+        assert_eq!(CRC32_ENGINE.checksum(b"123456789"), 0xcbf43926);
+        assert_eq!(CRC32_ENGINE.table[1], 0x77073096);
+        assert_eq!(CRC32_ENGINE.table[255], 0x2d02ef8d);
+        let ccitt = CrcEngine::new(CRC16_CCITT);
+        assert_eq!(ccitt.checksum(b"123456789"), 0x29b1);
+        assert_eq!(ccitt.checksum(b""), 0xffff);
+    }
+
+    #[test]
+    fn test_crc_engine_custom() {
+        // CRC-16/ARC, reflected
+        let arc = CrcEngine::new(CrcParams { width: 16, poly: 0x8005, init: 0, reflect: true, xor_out: 0 });
+        assert_eq!(arc.checksum(b"123456789"), 0xbb3d);
+        // CRC-8/SMBUS
+        let smbus = CrcEngine::new(CrcParams { width: 8, poly: 0x07, init: 0, reflect: false, xor_out: 0 });
+        assert_eq!(smbus.checksum(b"123456789"), 0xf4);
+        // CRC-32/BZIP2, not reflected
+        let bzip2 = CrcEngine::new(CrcParams { reflect: false, ..PNG_CRC32 });
+        let mut digest = bzip2.digest();
+        digest.update(b"1234");
+        digest.update(b"56789");
+        assert_eq!(digest.finalize(), 0xfc891918);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let data = vec![0xff; 100_000];
+        let mut adler = Adler32::new();
+        for part in data.chunks(777) {
+            adler.update(part);
+        }
+        assert_eq!(adler.finalize(), adler32(&data));
+        // computed without blocking, all in u64
+        let (mut a, mut b) = (1u64, 0u64);
+        for x in &data {
+            a = (a + *x as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&data), ((b << 16) | a) as u32);
+    }
+
+    #[test]
+    fn test_crc32_long_input() {
+        // long enough for the accelerated paths to kick in
+        let data: Vec<u8> = (0..10_000u32).map(|n| (n * 7) as u8).collect();
+        assert_eq!(crc32(&data, data.len()), CRC32_ENGINE.checksum(&data));
+    }
 }
@@ -0,0 +1,406 @@
+use std::convert::TryFrom;
+
+use crate::error::{bail, Error};
+use crate::filter::{unfilter, FilterType};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::Image;
+use crate::parse::Limits;
+use crate::png::Png;
+
+/// The Adam7 passes: first column, first row, column step and row step.
+pub(crate) const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Width and height of an Adam7 pass of a `width` x `height` image.
+pub(crate) fn pass_size(pass: (usize, usize, usize, usize), width: usize, height: usize) -> (usize, usize) {
+    let (x0, y0, dx, dy) = pass;
+    let w = if width > x0 { (width - x0).div_ceil(dx) } else { 0 };
+    let h = if height > y0 { (height - y0).div_ceil(dy) } else { 0 };
+    (w, h)
+}
+
+/// Bytes of filtered data in `height` rows of `width` pixels, each row
+/// with its filter byte.
+///
+/// # Errors
+/// returns `LimitExceeded` if that is over `max`, or does not fit a `usize`.
+pub(crate) fn filtered_len(ihdr: &Ihdr, width: usize, height: usize, max: u64) -> Result<usize, Error> {
+    let stride = width.checked_mul(ihdr.bits_per_pixel()).map(|bits| bits.div_ceil(8));
+    match stride.and_then(|s| s.checked_add(1)).and_then(|s| s.checked_mul(height)) {
+        Some(len) if len as u64 <= max => Ok(len),
+        _ => Err(Error::LimitExceeded("max_decompressed")),
+    }
+}
+
+/// Bytes of filtered data the whole image takes up, over all seven
+/// passes when it is interlaced. Known from the header alone, so a
+/// crafted one can be refused before anything is allocated for it.
+///
+/// # Errors
+/// returns `LimitExceeded` if that is over `max`.
+pub(crate) fn image_data_len(ihdr: &Ihdr, max: u64) -> Result<usize, Error> {
+    let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+    if !ihdr.is_interlaced() {
+        return filtered_len(ihdr, width, height, max);
+    }
+    let mut total: usize = 0;
+    for pass in ADAM7 {
+        let (pw, ph) = pass_size(pass, width, height);
+        if pw == 0 || ph == 0 {
+            continue;
+        }
+        total = match total.checked_add(filtered_len(ihdr, pw, ph, max)?) {
+            Some(total) if total as u64 <= max => total,
+            _ => return Err(Error::LimitExceeded("max_decompressed")),
+        };
+    }
+    Ok(total)
+}
+
+impl Png {
+    /// Inflates and unfilters the image data, undoing Adam7 interlacing,
+    /// giving the pixels in the bit depth and colour type of the file.
+    ///
+    /// # Errors
+    /// returns an Error if the header is invalid, the image data is
+    /// broken or too short, a filter type is invalid or an indexed
+    /// image has no palette.
+    pub fn decode_pixels(&self) -> Result<Image, Error> {
+        self.decode_pixels_with(&Limits::default())
+    }
+
+    /// Like `decode_pixels` but the image data may inflate to
+    /// `limits.max_decompressed` instead of the default. The header is
+    /// checked against it before the data is inflated.
+    ///
+    /// # Errors
+    /// as `decode_pixels`, and `LimitExceeded` if the header asks for
+    /// more than the limit.
+    pub fn decode_pixels_with(&self, limits: &Limits) -> Result<Image, Error> {
+        let ihdr = self.header()?;
+        let needed = image_data_len(&ihdr, limits.max_decompressed)?;
+        let data = self.idat_data_with(limits)?;
+        // every buffer below is at most about the size of the data
+        if data.len() < needed {
+            bail!("image data too short: {} bytes, need {}", data.len(), needed);
+        }
+        let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+        let pixels = if ihdr.is_interlaced() {
+            deinterlace(&ihdr, &data)?
+        } else {
+            unfilter_rows(&ihdr, width, height, &data)?.0
+        };
+
+        self.image_with(ihdr, pixels)
+    }
+
+    /// What the image looks like as bytes: dimensions, bit depth and colour
+    /// type, the palette of indexed images, tRNS and the decoded pixels.
+    /// Metadata, the compression and the interlacing make no difference.
+    #[cfg(any(feature = "sign", feature = "digest"))]
+    pub(crate) fn pixel_content(&self) -> Result<Vec<u8>, Error> {
+        let image = self.decode_pixels()?;
+        let ihdr = self.header()?;
+        let mut content = ihdr.width().to_be_bytes().to_vec();
+        content.extend(ihdr.height().to_be_bytes());
+        content.extend([ihdr.bit_depth(), ihdr.color_type() as u8]);
+        // the palette only gives colours to indexed images,
+        // transparency is part of how any image looks
+        let plte = self.chunk_by_type("PLTE").filter(|_| ihdr.color_type() == ColorType::Indexed);
+        for c in [plte, self.chunk_by_type("tRNS")] {
+            let data = c.map_or(&[][..], |c| c.data());
+            content.extend((data.len() as u32).to_be_bytes());
+            content.extend(data);
+        }
+        content.extend(image.data());
+        Ok(content)
+    }
+
+    /// Wraps `pixels` in an `Image` with the PLTE and tRNS of this file.
+    fn image_with(&self, ihdr: Ihdr, pixels: Vec<u8>) -> Result<Image, Error> {
+        let palette = self.chunk_by_type("PLTE").map(|c| c.data().to_vec());
+        if ihdr.color_type() == ColorType::Indexed && palette.is_none() {
+            bail!("indexed image without palette");
+        }
+        let trns = self.chunk_by_type("tRNS").map(|c| c.data().to_vec());
+        Ok(Image::new(ihdr, pixels, palette, trns))
+    }
+
+    /// Decodes the `width` x `height` rectangle at `x`, `y`. Rows below
+    /// it are neither inflated nor unfiltered, the ones above have to
+    /// be as each row depends on the one before. Interlaced images are
+    /// decoded in full and cropped.
+    ///
+    /// # Errors
+    /// as `decode_pixels`, and if the rectangle is empty or not
+    /// inside the image.
+    pub fn decode_region(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Image, Error> {
+        let ihdr = self.header()?;
+        if width == 0
+            || height == 0
+            || x as u64 + width as u64 > ihdr.width() as u64
+            || y as u64 + height as u64 > ihdr.height() as u64
+        {
+            bail!("region {}x{} at {},{} is not inside the image", width, height, x, y);
+        }
+        let pixels = if ihdr.is_interlaced() {
+            image_data_len(&ihdr, Limits::default().max_decompressed)?;
+            let data = self.idat_data()?;
+            deinterlace(&ihdr, &data)?
+        } else {
+            let rows = (y + height) as usize;
+            let len = filtered_len(&ihdr, ihdr.width() as usize, rows, Limits::default().max_decompressed)?;
+            let data = self.idat_data_prefix(len)?;
+            unfilter_rows(&ihdr, ihdr.width() as usize, rows, &data)?.0
+        };
+        let pixels = crop(&ihdr, &pixels, x, y, width, height);
+        let region = Ihdr::new(width, height, ihdr.bit_depth(), ihdr.color_type())?;
+        self.image_with(region, pixels)
+    }
+}
+
+/// Copies the rectangle at `x`, `y` out of the rows in `pixels`.
+pub(crate) fn crop(ihdr: &Ihdr, pixels: &[u8], x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+    let stride = ihdr.row_bytes(ihdr.width());
+    let out_stride = ihdr.row_bytes(width);
+    let bits = ihdr.bits_per_pixel();
+    let mut out = vec![0u8; out_stride * height as usize];
+    for row in 0..height as usize {
+        let src = &pixels[(y as usize + row) * stride..][..stride];
+        let dest = &mut out[row * out_stride..][..out_stride];
+        if bits >= 8 {
+            let bytes = bits / 8;
+            dest.copy_from_slice(&src[x as usize * bytes..][..out_stride]);
+        } else {
+            for px in 0..width as usize {
+                copy_pixel(src, x as usize + px, dest, px, bits);
+            }
+        }
+    }
+    out
+}
+
+/// Bytes per complete pixel as used by the filters, at least 1.
+pub(crate) fn filter_bpp(ihdr: &Ihdr) -> usize {
+    (ihdr.bits_per_pixel() / 8).max(1)
+}
+
+/// Unfilters `height` rows of `width` pixels from the start of `data`.
+/// Returns the rows and how many bytes of `data` they took up.
+pub(crate) fn unfilter_rows(
+    ihdr: &Ihdr,
+    width: usize,
+    height: usize,
+    data: &[u8],
+) -> Result<(Vec<u8>, usize), Error> {
+    let stride = ihdr.row_bytes(width as u32);
+    let used = match filtered_len(ihdr, width, height, u64::MAX) {
+        Ok(used) if used <= data.len() => used,
+        _ => bail!("image data too short: {} bytes for {} rows of {} pixels", data.len(), height, width),
+    };
+
+    let bpp = filter_bpp(ihdr);
+    let mut out = vec![0u8; stride * height];
+    let zeros = vec![0u8; stride];
+    for (y, line) in data[..used].chunks_exact(stride + 1).enumerate() {
+        let filter = FilterType::try_from(line[0])?;
+        let (done, rest) = out.split_at_mut(y * stride);
+        let prev = if y == 0 { &zeros[..] } else { &done[(y - 1) * stride..] };
+        let row = &mut rest[..stride];
+        row.copy_from_slice(&line[1..]);
+        unfilter(filter, bpp, prev, row);
+    }
+    Ok((out, used))
+}
+
+/// Unfilters each Adam7 pass and puts its pixels in place.
+fn deinterlace(ihdr: &Ihdr, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+    let stride = ihdr.row_bytes(ihdr.width());
+    let bits = ihdr.bits_per_pixel();
+
+    // where each pass starts in `data`, known up front from the sizes
+    let mut passes = Vec::new();
+    let mut offset: usize = 0;
+    for pass in ADAM7 {
+        let (pw, ph) = pass_size(pass, width, height);
+        if pw > 0 && ph > 0 {
+            passes.push((pass, pw, ph, offset));
+            let len = filtered_len(ihdr, pw, ph, u64::MAX).ok();
+            offset = match len.and_then(|len| offset.checked_add(len)) {
+                Some(end) if end <= data.len() => end,
+                _ => bail!("image data too short: {} bytes for {}x{} interlaced", data.len(), width, height),
+            };
+        }
+    }
+    // each row is in a pass with its own filter byte, and the passes
+    // hold every pixel, so this is at most about twice `data`
+    let mut out = vec![0u8; stride * height];
+
+    #[cfg(feature = "rayon")]
+    let unfiltered: Vec<_> = {
+        use rayon::prelude::*;
+        passes.par_iter().map(|&(_, pw, ph, start)| unfilter_rows(ihdr, pw, ph, &data[start..])).collect()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let unfiltered: Vec<_> =
+        passes.iter().map(|&(_, pw, ph, start)| unfilter_rows(ihdr, pw, ph, &data[start..])).collect();
+
+    for ((pass, pw, ph, _), result) in passes.into_iter().zip(unfiltered) {
+        let (pixels, _) = result?;
+        let pass_stride = ihdr.row_bytes(pw as u32);
+        let (x0, y0, dx, dy) = pass;
+        for py in 0..ph {
+            let src = &pixels[py * pass_stride..(py + 1) * pass_stride];
+            let dest = &mut out[(y0 + py * dy) * stride..][..stride];
+            for px in 0..pw {
+                copy_pixel(src, px, dest, x0 + px * dx, bits);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Copies pixel `from` of row `src` to pixel `to` of row `dest`,
+/// which must still be zero there when pixels are under 8 bits.
+pub(crate) fn copy_pixel(src: &[u8], from: usize, dest: &mut [u8], to: usize, bits: usize) {
+    if bits >= 8 {
+        let bytes = bits / 8;
+        dest[to * bytes..(to + 1) * bytes].copy_from_slice(&src[from * bytes..(from + 1) * bytes]);
+    } else {
+        let value = Image::packed_sample(src, from, bits as u8);
+        dest[to * bits / 8] |= value << (8 - bits - (to * bits % 8));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::io::Write;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn png_with(ihdr: &Ihdr, raw: &[u8], extra: Vec<Chunk>) -> Png {
+        let mut chunks = vec![ihdr.to_chunk()];
+        chunks.extend(extra);
+        chunks.push(Chunk::new(ChunkType::IDAT, zlib(raw)));
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_decode_filters() {
+        // 2x3 gray, one row each with None, Sub and Up
+        let ihdr = Ihdr::new(2, 3, 8, ColorType::Grayscale).unwrap();
+        let raw = [0, 10, 20, 1, 5, 5, 2, 1, 1];
+        let image = png_with(&ihdr, &raw, Vec::new()).decode_pixels().unwrap();
+        assert_eq!(image.data(), &[10, 20, 5, 10, 6, 11]);
+        assert_eq!(image.row(2), &[6, 11]);
+    }
+
+    #[test]
+    fn test_decode_adam7() {
+        // 3x3 gray, pixel value = index; passes 1, 4, 5, 6 and 7 are not empty
+        let ihdr = Ihdr::new(3, 3, 8, ColorType::Grayscale).unwrap().with_interlace(true);
+        let raw = [
+            0, 0, // pass 1: (0,0)
+            0, 2, // pass 4: (2,0)
+            0, 6, 8, // pass 5: (0,2) (2,2)
+            0, 1, // pass 6: (1,0)
+            0, 7, // pass 6: (1,2)
+            0, 3, 4, 5, // pass 7: row 1
+        ];
+        let image = png_with(&ihdr, &raw, Vec::new()).decode_pixels().unwrap();
+        assert_eq!(image.data(), &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_decode_adam7_packed() {
+        // 3x1 1-bit gray: pass 1 has x=0, pass 4 x=2, pass 6 x=1
+        let ihdr = Ihdr::new(3, 1, 1, ColorType::Grayscale).unwrap().with_interlace(true);
+        let raw = [0, 0b1000_0000, 0, 0b1000_0000, 0, 0];
+        let image = png_with(&ihdr, &raw, Vec::new()).decode_pixels().unwrap();
+        assert_eq!(image.data(), &[0b1010_0000]);
+    }
+
+    #[test]
+    fn test_decode_file() {
+        let png = Png::try_from(IMG).unwrap();
+        let image = png.decode_pixels().unwrap();
+        assert_eq!(image.color_type(), ColorType::Indexed);
+        assert_eq!(image.rows().count(), image.height() as usize);
+        assert_eq!(image.data().len(), image.stride() * image.height() as usize);
+        assert!(image.palette().is_some());
+    }
+
+    #[test]
+    fn test_decode_region() {
+        let png = Png::try_from(IMG).unwrap();
+        let full = png.decode_pixels().unwrap();
+        let region = png.decode_region(10, 20, 30, 5).unwrap();
+        assert_eq!((region.width(), region.height()), (30, 5));
+        for row in 0..5 {
+            assert_eq!(region.row(row), &full.row(20 + row)[10..40]);
+        }
+        assert_eq!(region.palette(), full.palette());
+
+        assert!(png.decode_region(0, 0, 0, 1).is_err());
+        assert!(png.decode_region(200, 0, 26, 1).is_err());
+        assert!(png.decode_region(0, 0, full.width(), full.height()).is_ok());
+    }
+
+    #[test]
+    fn test_decode_region_packed_interlaced() {
+        // 4x2 2-bit gray, interlaced, values 0 1 2 3 / 3 2 1 0
+        let ihdr = Ihdr::new(4, 2, 2, ColorType::Grayscale).unwrap().with_interlace(true);
+        let raw = [
+            0, 0b0000_0000, // pass 1: (0,0)
+            0, 0b1000_0000, // pass 4: (2,0)
+            0, 0b0111_0000, // pass 6: (1,0) (3,0)
+            0, 0b11_10_01_00, // pass 7: row 1
+        ];
+        let png = png_with(&ihdr, &raw, Vec::new());
+        assert_eq!(png.decode_pixels().unwrap().data(), &[0b00_01_10_11, 0b11_10_01_00]);
+        let region = png.decode_region(1, 0, 2, 2).unwrap();
+        assert_eq!(region.data(), &[0b0110_0000, 0b1001_0000]);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+        assert!(png_with(&ihdr, &[0, 1, 2], Vec::new()).decode_pixels().is_err());
+        assert!(png_with(&ihdr, &[5, 1, 2, 0, 1, 2], Vec::new()).decode_pixels().is_err());
+
+        let indexed = Ihdr::new(2, 1, 8, ColorType::Indexed).unwrap();
+        assert!(png_with(&indexed, &[0, 0, 0], Vec::new()).decode_pixels().is_err());
+        let plte = Chunk::new(ChunkType::PLTE, vec![0, 0, 0]);
+        assert!(png_with(&indexed, &[0, 0, 0], vec![plte]).decode_pixels().is_ok());
+    }
+
+    #[test]
+    fn test_decode_huge_header() {
+        // a few bytes claiming to be 100000x100000 RGBA16, interlaced or not
+        for interlace in [false, true] {
+            let ihdr = Ihdr::new(100_000, 100_000, 16, ColorType::Rgba).unwrap().with_interlace(interlace);
+            let png = png_with(&ihdr, &[0; 16], Vec::new());
+            assert!(matches!(png.decode_pixels(), Err(Error::LimitExceeded("max_decompressed"))));
+            assert!(png.decode_pixels_with(&Limits::unlimited()).is_err());
+            assert!(png.decode_region(0, 0, 1, 1).is_err());
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use sha2::Digest;
+
+use crate::chunk::Chunk;
+use crate::error::Error;
+use crate::png::Png;
+
+/// What `Png::digest` hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestScope {
+    /// The whole file as `Png::as_bytes` writes it.
+    File,
+    /// The decoded pixels with their dimensions, format, palette and
+    /// transparency, so files that differ only in metadata or
+    /// compression hash the same. Needs the `flate` feature.
+    #[cfg(feature = "flate")]
+    Pixels,
+    /// The length, type and data of the critical chunks in order.
+    CriticalChunks,
+}
+
+/// The hash function `Png::digest` uses, both give 32 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum Algo {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+/// One running hash of either algorithm.
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algo: Algo) -> Hasher {
+        match algo {
+            Algo::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            Algo::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    /// Hashes the length, type and data of `chunk`, not its crc.
+    fn update_chunk(&mut self, chunk: &Chunk) {
+        self.update(&chunk.length().to_be_bytes());
+        self.update(&chunk.chunk_type().bytes());
+        self.update(chunk.data());
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        match self {
+            Hasher::Sha256(h) => h.finalize().into(),
+            Hasher::Blake3(h) => h.finalize().into(),
+        }
+    }
+}
+
+/// Hashes `data` on its own with `algo`.
+pub(crate) fn hash(algo: Algo, data: &[u8]) -> [u8; 32] {
+    let mut hasher = Hasher::new(algo);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+impl Png {
+    /// Hashes the part of the file picked by `scope` with `algo`.
+    /// Comparing pixel digests finds images that only differ in tIME,
+    /// text or other metadata.
+    ///
+    /// # Errors
+    /// returns an Error if `scope` is `Pixels` and the image can not be decoded.
+    pub fn digest(&self, scope: DigestScope, algo: Algo) -> Result<[u8; 32], Error> {
+        let mut hasher = Hasher::new(algo);
+        match scope {
+            DigestScope::File => {
+                hasher.update(&Png::STANDARD_HEADER);
+                for c in self.iter() {
+                    hasher.update_chunk(c);
+                    hasher.update(&c.crc().to_be_bytes());
+                }
+                hasher.update(self.trailer());
+            }
+            #[cfg(feature = "flate")]
+            DigestScope::Pixels => hasher.update(&self.pixel_content()?),
+            DigestScope::CriticalChunks => {
+                for c in self.critical_chunks() {
+                    hasher.update_chunk(c);
+                }
+            }
+        }
+        Ok(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn hex(digest: [u8; 32]) -> String {
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_file_digest() {
+        let png = Png::try_from(IMG).unwrap();
+        let sha = png.digest(DigestScope::File, Algo::Sha256).unwrap();
+        assert_eq!(sha, <[u8; 32]>::from(sha2::Sha256::digest(IMG)));
+        let blake = png.digest(DigestScope::File, Algo::Blake3).unwrap();
+        assert_eq!(blake, *blake3::hash(IMG).as_bytes());
+        assert_ne!(sha, blake);
+
+        let empty = Png::from_chunks(Vec::new());
+        let expected = "4c4b6a3be1314ab86138bef4314dde022e600960d8689a2c8f8631802d20dab6";
+        assert_eq!(hex(empty.digest(DigestScope::File, Algo::Sha256).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_critical_digest() {
+        let png = Png::try_from(IMG).unwrap();
+        let mut edited = png.clone();
+        edited.insert_before_type("IEND", Chunk::new(ChunkType::TIME, vec![7; 7])).unwrap();
+        for algo in [Algo::Sha256, Algo::Blake3] {
+            let critical = png.digest(DigestScope::CriticalChunks, algo).unwrap();
+            assert_eq!(edited.digest(DigestScope::CriticalChunks, algo).unwrap(), critical);
+            let file = png.digest(DigestScope::File, algo).unwrap();
+            assert_ne!(edited.digest(DigestScope::File, algo).unwrap(), file);
+        }
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_pixel_digest() {
+        let png = Png::try_from(IMG).unwrap();
+        let pixels = png.digest(DigestScope::Pixels, Algo::Blake3).unwrap();
+        let mut recompressed = png.clone();
+        recompressed.insert_before_type("IEND", Chunk::new(ChunkType::TEXT, b"a\0b".to_vec())).unwrap();
+        recompressed.interlace_adam7().unwrap();
+        assert_ne!(
+            recompressed.digest(DigestScope::CriticalChunks, Algo::Blake3).unwrap(),
+            png.digest(DigestScope::CriticalChunks, Algo::Blake3).unwrap()
+        );
+        assert_eq!(recompressed.digest(DigestScope::Pixels, Algo::Blake3).unwrap(), pixels);
+
+        let image = png.decode_pixels().unwrap();
+        let mut changed = image.data().to_vec();
+        changed[0] ^= 1;
+        let mut other = png.clone();
+        other.replace_pixels(&png.header().unwrap(), &changed, &Default::default()).unwrap();
+        assert_ne!(other.digest(DigestScope::Pixels, Algo::Blake3).unwrap(), pixels);
+    }
+}
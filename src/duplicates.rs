@@ -0,0 +1,111 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Returns true if `chunk_type` may only appear once in a file.
+/// See `ChunkType::allows_multiple`.
+pub fn is_unique_type(chunk_type: &ChunkType) -> bool {
+    !chunk_type.allows_multiple()
+}
+
+/// Which chunk `Png::dedupe_chunks` keeps out of a set of duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeStrategy {
+    KeepFirst,
+    KeepLast,
+}
+
+impl Png {
+    /// Lists the chunk types that appear more often than the spec allows,
+    /// with how many times they appear, in order of first appearance.
+    pub fn find_duplicates(&self) -> Vec<(ChunkType, usize)> {
+        let mut found: Vec<(ChunkType, usize)> = Vec::new();
+        for c in self.iter().filter(|c| is_unique_type(c.chunk_type())) {
+            match found.iter_mut().find(|(t, _)| t == c.chunk_type()) {
+                Some((_, count)) => *count += 1,
+                None => found.push((*c.chunk_type(), 1)),
+            }
+        }
+        found.retain(|(_, count)| *count > 1);
+        found
+    }
+
+    /// Removes duplicates of chunk types that may only appear once,
+    /// keeping one of each as picked by `strategy`. The kept chunk stays
+    /// where it was. Returns the removed chunks in file order.
+    pub fn dedupe_chunks(&mut self, strategy: DedupeStrategy) -> Vec<Chunk> {
+        let chunks = std::mem::take(self.chunks_mut());
+        let len = chunks.len();
+        let mut keep = vec![true; len];
+        let mut seen: Vec<ChunkType> = Vec::new();
+
+        let order: Box<dyn Iterator<Item = usize>> = match strategy {
+            DedupeStrategy::KeepFirst => Box::new(0..len),
+            DedupeStrategy::KeepLast => Box::new((0..len).rev()),
+        };
+        for n in order {
+            let t = chunks[n].chunk_type();
+            if is_unique_type(t) {
+                if seen.contains(t) {
+                    keep[n] = false;
+                } else {
+                    seen.push(*t);
+                }
+            }
+        }
+
+        let (kept, removed): (Vec<_>, Vec<_>) = chunks
+            .into_iter()
+            .zip(keep)
+            .partition(|(_, keep)| *keep);
+        *self.chunks_mut() = kept.into_iter().map(|(c, _)| c).collect();
+        removed.into_iter().map(|(c, _)| c).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tIME", &[1; 7]),
+            chunk("tEXt", b"a\0b"),
+            chunk("tEXt", b"c\0d"),
+            chunk("IDAT", &[]),
+            chunk("tIME", &[2; 7]),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let dups = testing_png().find_duplicates();
+        assert_eq!(dups, vec![(ChunkType::from_str("tIME").unwrap(), 2)]);
+    }
+
+    #[test]
+    fn test_dedupe_keep_first() {
+        let mut png = testing_png();
+        let removed = png.dedupe_chunks(DedupeStrategy::KeepFirst);
+        assert_eq!(removed, vec![chunk("tIME", &[2; 7])]);
+        assert_eq!(png.chunks()[1], chunk("tIME", &[1; 7]));
+        assert_eq!(png.chunks_of_type("tEXt").count(), 2);
+        assert!(png.find_duplicates().is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_keep_last() {
+        let mut png = testing_png();
+        let removed = png.dedupe_chunks(DedupeStrategy::KeepLast);
+        assert_eq!(removed, vec![chunk("tIME", &[1; 7])]);
+        assert_eq!(png.chunks()[4], chunk("tIME", &[2; 7]));
+        assert_eq!(png.chunks().len(), 6);
+    }
+}
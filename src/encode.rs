@@ -0,0 +1,418 @@
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::decode::{copy_pixel, filter_bpp, pass_size, ADAM7};
+use crate::error::{bail, Error};
+use crate::filter::FilterStrategy;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::Image;
+use crate::png::Png;
+
+/// Deflate effort, trading speed for size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CompressionLevel {
+    Fastest,
+    #[default]
+    Default,
+    Best,
+    /// zlib style level, 0 stores without compressing and 9 is the
+    /// same as `Best`. Values over 9 count as 9.
+    Level(u8),
+}
+
+impl CompressionLevel {
+    fn to_flate(self) -> Compression {
+        match self {
+            CompressionLevel::Fastest => Compression::fast(),
+            CompressionLevel::Default => Compression::default(),
+            CompressionLevel::Best => Compression::best(),
+            CompressionLevel::Level(n) => Compression::new(n.min(9) as u32),
+        }
+    }
+}
+
+/// How `Png::from_pixels` encodes an image.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// Bits per sample of the pixel buffer, 8 by default.
+    pub bit_depth: u8,
+    /// PLTE data for indexed images, 3 bytes per entry.
+    pub palette: Option<Vec<u8>>,
+    /// How rows are filtered before compression.
+    pub filter: FilterStrategy,
+    pub level: CompressionLevel,
+    /// Largest data size of one IDAT chunk, the stream is split over
+    /// as many as needed. 64 KiB by default, 0 means no limit.
+    pub max_idat_size: usize,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            bit_depth: 8,
+            palette: None,
+            filter: FilterStrategy::default(),
+            level: CompressionLevel::default(),
+            max_idat_size: 1 << 16,
+        }
+    }
+}
+
+impl Png {
+    /// Encodes a new image from rows of pixels, packed like `Image::data`:
+    /// `Ihdr::row_bytes` bytes per row with no padding between rows.
+    /// The result has IHDR, PLTE if given, IDAT and IEND.
+    ///
+    /// # Errors
+    /// returns an Error if the size, bit depth or palette is invalid
+    /// for `color_type`, or `pixels` is not exactly the size of the image.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        pixels: &[u8],
+        options: EncodeOptions,
+    ) -> Result<Png, Error> {
+        let ihdr = Ihdr::new(width, height, options.bit_depth, color_type)?;
+        let stride = ihdr.row_bytes(width);
+        if pixels.len() != stride * height as usize {
+            bail!("pixel buffer is {} bytes, should be {}", pixels.len(), stride * height as usize);
+        }
+
+        let filtered = filter_rows(&ihdr, pixels, options.filter);
+        let mut builder = Png::builder(ihdr);
+        for idat in deflate_idat(&filtered, &options)? {
+            builder = builder.idat(idat);
+        }
+        if let Some(palette) = options.palette {
+            builder = builder.palette(palette);
+        }
+        builder.build()
+    }
+
+    /// Inflates the image data and deflates it again following the
+    /// level and IDAT size of `options`. The new IDAT chunks replace the
+    /// old ones where they were, nothing else is touched, not even the
+    /// filtering of the rows.
+    ///
+    /// # Errors
+    /// returns an Error if there are no IDAT chunks or they can not be
+    /// inflated.
+    pub fn recompress_idat(&mut self, options: &EncodeOptions) -> Result<(), Error> {
+        let data = self.idat_data()?;
+        self.replace_idat(deflate_idat(&data, options)?);
+        Ok(())
+    }
+
+    /// Encodes the image again without interlacing.
+    /// Does nothing if it is not interlaced.
+    ///
+    /// # Errors
+    /// returns an Error if the image can not be decoded.
+    pub fn deinterlace(&mut self) -> Result<(), Error> {
+        self.set_interlace(false)
+    }
+
+    /// Encodes the image again with Adam7 interlacing, so viewers can
+    /// show a coarse version before all of it has loaded. This usually
+    /// makes the file bigger. Does nothing if it is already interlaced.
+    ///
+    /// # Errors
+    /// returns an Error if the image can not be decoded.
+    pub fn interlace_adam7(&mut self) -> Result<(), Error> {
+        self.set_interlace(true)
+    }
+
+    fn set_interlace(&mut self, interlaced: bool) -> Result<(), Error> {
+        let ihdr = self.header()?;
+        if ihdr.is_interlaced() == interlaced {
+            return Ok(());
+        }
+        let image = self.decode_pixels()?;
+        let options = EncodeOptions { bit_depth: ihdr.bit_depth(), ..Default::default() };
+        self.replace_pixels(&ihdr.with_interlace(interlaced), image.data(), &options)
+    }
+
+    /// Replaces the header and image data with `pixels` encoded following
+    /// `options`, keeping every other chunk. Callers deal with chunks that
+    /// depend on the pixel format, like PLTE and tRNS.
+    ///
+    /// # Errors
+    /// returns an Error if the image data can not be compressed.
+    pub(crate) fn replace_pixels(
+        &mut self,
+        ihdr: &Ihdr,
+        pixels: &[u8],
+        options: &EncodeOptions,
+    ) -> Result<(), Error> {
+        let filtered = filter_image(ihdr, pixels, options.filter);
+        let idat = deflate_idat(&filtered, options)?;
+        self.set_header(ihdr);
+        self.replace_idat(idat);
+        Ok(())
+    }
+
+    /// Puts IDAT chunks holding `idat` where the first IDAT was,
+    /// or before IEND if there was none.
+    pub(crate) fn replace_idat(&mut self, idat: Vec<Vec<u8>>) {
+        let chunks = self.chunks_mut();
+        let at = chunks
+            .iter()
+            .position(|c| *c.chunk_type() == ChunkType::IDAT || *c.chunk_type() == ChunkType::IEND)
+            .unwrap_or(chunks.len());
+        chunks.retain(|c| *c.chunk_type() != ChunkType::IDAT);
+        chunks.splice(at..at, idat.into_iter().map(|d| Chunk::new(ChunkType::IDAT, d)));
+    }
+}
+
+impl Image {
+    /// Encodes the image as a new `Png` with default options, keeping its
+    /// interlacing, palette and tRNS data.
+    ///
+    /// # Errors
+    /// returns an Error if an indexed image has no palette or the image
+    /// data can not be compressed.
+    pub fn to_png(&self) -> Result<Png, Error> {
+        let ihdr = self.header();
+        let options = EncodeOptions::default();
+        let mut builder = Png::builder(ihdr.clone());
+        for idat in deflate_idat(&filter_image(ihdr, self.data(), options.filter), &options)? {
+            builder = builder.idat(idat);
+        }
+        // PLTE is a suggestion for truecolour and not allowed for greyscale
+        if let (Some(palette), ColorType::Indexed | ColorType::Rgb | ColorType::Rgba) =
+            (self.palette(), self.color_type())
+        {
+            builder = builder.palette(palette.to_vec());
+        }
+        if let Some(trns) = self.trns() {
+            builder = builder.chunk(Chunk::new(ChunkType::TRNS, trns.to_vec()))?;
+        }
+        builder.build()
+    }
+}
+
+/// Compresses `data` and splits the stream into IDAT sized pieces.
+pub(crate) fn deflate_idat(data: &[u8], options: &EncodeOptions) -> Result<Vec<Vec<u8>>, Error> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), options.level.to_flate());
+    encoder.write_all(data)?;
+    let stream = encoder.finish()?;
+    if options.max_idat_size == 0 {
+        return Ok(vec![stream]);
+    }
+    Ok(stream.chunks(options.max_idat_size).map(|c| c.to_vec()).collect())
+}
+
+/// Filters the image like `filter_rows`, pass by pass if `ihdr`
+/// is Adam7 interlaced.
+pub(crate) fn filter_image(ihdr: &Ihdr, pixels: &[u8], strategy: FilterStrategy) -> Vec<u8> {
+    if !ihdr.is_interlaced() {
+        return filter_rows(ihdr, pixels, strategy);
+    }
+    let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+    let stride = ihdr.row_bytes(ihdr.width());
+    let bits = ihdr.bits_per_pixel();
+    let mut out = Vec::new();
+    for pass in ADAM7 {
+        let (pw, ph) = pass_size(pass, width, height);
+        if pw == 0 || ph == 0 {
+            continue;
+        }
+        // never bigger than the image, so always valid
+        let pass_ihdr = Ihdr::new(pw as u32, ph as u32, ihdr.bit_depth(), ihdr.color_type()).unwrap();
+        let pass_stride = pass_ihdr.row_bytes(pw as u32);
+        let (x0, y0, dx, dy) = pass;
+        let mut reduced = vec![0u8; pass_stride * ph];
+        for py in 0..ph {
+            let src = &pixels[(y0 + py * dy) * stride..][..stride];
+            let dest = &mut reduced[py * pass_stride..][..pass_stride];
+            for px in 0..pw {
+                copy_pixel(src, x0 + px * dx, dest, px, bits);
+            }
+        }
+        out.extend(filter_rows(&pass_ihdr, &reduced, strategy));
+    }
+    out
+}
+
+/// Filters every row, each prefixed with its filter type.
+/// Rows only depend on the unfiltered row above, so with the `rayon`
+/// feature they are filtered in parallel.
+pub(crate) fn filter_rows(ihdr: &Ihdr, pixels: &[u8], strategy: FilterStrategy) -> Vec<u8> {
+    let stride = ihdr.row_bytes(ihdr.width());
+    let bpp = filter_bpp(ihdr);
+    let zeros = vec![0u8; stride];
+    let mut out = vec![0u8; (stride + 1) * ihdr.height() as usize];
+
+    let filter_row = |scratch: &mut Vec<u8>, (y, line): (usize, &mut [u8])| {
+        let row = &pixels[y * stride..(y + 1) * stride];
+        let prev = if y == 0 { &zeros[..] } else { &pixels[(y - 1) * stride..y * stride] };
+        let (filter_type, line) = line.split_first_mut().unwrap();
+        *filter_type = strategy.apply(bpp, prev, row, line, scratch) as u8;
+    };
+
+    // one scratch row per thread, not per row
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        out.par_chunks_exact_mut(stride + 1).enumerate().for_each_init(|| vec![0u8; stride], filter_row);
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        let mut scratch = vec![0u8; stride];
+        out.chunks_exact_mut(stride + 1).enumerate().for_each(|row| filter_row(&mut scratch, row));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pixels_roundtrip() {
+        let pixels: Vec<u8> = (0..5 * 4 * 3).map(|n| (n * 37 % 256) as u8).collect();
+        let png = Png::from_pixels(5, 4, ColorType::Rgb, &pixels, EncodeOptions::default()).unwrap();
+        assert!(png.validate().is_ok());
+
+        let parsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        let image = parsed.decode_pixels().unwrap();
+        assert_eq!((image.width(), image.height()), (5, 4));
+        assert_eq!(image.data(), pixels.as_slice());
+    }
+
+    #[test]
+    fn test_from_pixels_indexed() {
+        let palette = Some(vec![0, 0, 0, 255, 0, 0, 0, 255, 0]);
+        let options = EncodeOptions { bit_depth: 2, palette, ..Default::default() };
+        let pixels = [0b00_01_10_00, 0b10_01_00_00];
+        let png = Png::from_pixels(3, 2, ColorType::Indexed, &pixels, options).unwrap();
+        assert!(png.validate().is_ok());
+        let rgba = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        assert_eq!(rgba.pixel(1, 0), [255, 0, 0, 255]);
+        assert_eq!(rgba.pixel(0, 1), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_filter_strategies() {
+        // a smooth gradient, which filtering helps a lot
+        let pixels: Vec<u8> = (0..64 * 64).map(|n| ((n % 64) * 2 + (n / 64) * 3) as u8).collect();
+        let size = |filter| {
+            let options = EncodeOptions { filter, ..Default::default() };
+            let png = Png::from_pixels(64, 64, ColorType::Grayscale, &pixels, options).unwrap();
+            assert_eq!(png.decode_pixels().unwrap().data(), pixels.as_slice());
+            png.file_size()
+        };
+        let adaptive = size(FilterStrategy::default());
+        for fixed in [FilterStrategy::None, FilterStrategy::Sub, FilterStrategy::Up] {
+            size(fixed);
+        }
+        size(FilterStrategy::Average);
+        size(FilterStrategy::Paeth);
+        assert!(adaptive < size(FilterStrategy::None));
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x = 12345u32;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_idat_size() {
+        let pixels = noise(100 * 100 * 3);
+        let options = EncodeOptions { max_idat_size: 8192, ..Default::default() };
+        let png = Png::from_pixels(100, 100, ColorType::Rgb, &pixels, options).unwrap();
+        let idats: Vec<_> = png.chunks_of_type("IDAT").collect();
+        assert!(idats.len() > 1);
+        assert!(idats.iter().all(|c| c.length() <= 8192));
+        assert!(png.validate().is_ok());
+        assert_eq!(png.decode_pixels().unwrap().data(), pixels.as_slice());
+    }
+
+    #[test]
+    fn test_compression_level() {
+        let pixels: Vec<u8> = (0..64 * 64).map(|n| (n / 7 % 256) as u8).collect();
+        let size = |level| {
+            let options = EncodeOptions { level, ..Default::default() };
+            Png::from_pixels(64, 64, ColorType::Grayscale, &pixels, options).unwrap().file_size()
+        };
+        assert!(size(CompressionLevel::Level(0)) > size(CompressionLevel::Best));
+        assert!(size(CompressionLevel::Level(0)) > size(CompressionLevel::Fastest));
+    }
+
+    #[test]
+    fn test_recompress_idat() {
+        let pixels = noise(40 * 40);
+        let stored =
+            EncodeOptions { level: CompressionLevel::Level(0), max_idat_size: 100, ..Default::default() };
+        let mut png = Png::from_pixels(40, 40, ColorType::Grayscale, &pixels, stored).unwrap();
+        let text = Chunk::new(ChunkType::TEXT, b"a\0b".to_vec());
+        png.insert_after_type("IDAT", text.clone()).unwrap();
+        let before = png.file_size();
+
+        let best = EncodeOptions { level: CompressionLevel::Best, max_idat_size: 0, ..Default::default() };
+        png.recompress_idat(&best).unwrap();
+        assert!(png.file_size() < before);
+        assert_eq!(png.chunks_of_type("IDAT").count(), 1);
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.decode_pixels().unwrap().data(), pixels.as_slice());
+        assert!(Png::from_chunks(Vec::new()).recompress_idat(&EncodeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_interlace() {
+        for (color_type, bit_depth, width) in [(ColorType::Rgb, 8, 7), (ColorType::Grayscale, 1, 11)] {
+            let ihdr = Ihdr::new(width, 5, bit_depth, color_type).unwrap();
+            let stride = ihdr.row_bytes(width);
+            let mut pixels = noise(stride * 5);
+            // padding bits at the end of rows do not survive interlacing
+            let padding = stride * 8 - width as usize * ihdr.bits_per_pixel();
+            pixels.chunks_exact_mut(stride).for_each(|row| row[stride - 1] &= 0xff << padding);
+            let options = EncodeOptions { bit_depth, ..Default::default() };
+            let mut png = Png::from_pixels(width, 5, color_type, &pixels, options).unwrap();
+            let expected = png.decode_pixels().unwrap();
+
+            png.interlace_adam7().unwrap();
+            assert!(png.header().unwrap().is_interlaced());
+            assert!(png.validate().is_ok());
+            assert_eq!(png.decode_pixels().unwrap().data(), expected.data());
+
+            png.deinterlace().unwrap();
+            assert!(!png.header().unwrap().is_interlaced());
+            assert_eq!(png.decode_pixels().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_deinterlace_keeps_chunks() {
+        let mut png = Png::from_pixels(3, 3, ColorType::Grayscale, &[7; 9], EncodeOptions::default()).unwrap();
+        png.insert_after_type("IDAT", Chunk::new(ChunkType::TEXT, b"a\0b".to_vec())).unwrap();
+        png.deinterlace().unwrap();
+        png.interlace_adam7().unwrap();
+        png.interlace_adam7().unwrap();
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.decode_pixels().unwrap().data(), &[7; 9]);
+    }
+
+    #[test]
+    fn test_from_pixels_errors() {
+        let opts = EncodeOptions::default;
+        assert!(Png::from_pixels(2, 2, ColorType::Rgb, &[0; 11], opts()).is_err());
+        assert!(Png::from_pixels(0, 2, ColorType::Rgb, &[], opts()).is_err());
+        assert!(Png::from_pixels(1, 1, ColorType::Indexed, &[0], opts()).is_err());
+        let deep = EncodeOptions { bit_depth: 4, ..opts() };
+        assert!(Png::from_pixels(2, 1, ColorType::Rgb, &[0; 3], deep).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::chunk_type::ChunkType;
+
+/// What went wrong in a call into this crate. Failures without a
+/// variant of their own are `Invalid`, with a message saying what.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input does not start with the PNG signature.
+    InvalidSignature,
+    /// The crc stored in a chunk does not match its type and data.
+    BadCrc { found: u32, expected: u32 },
+    /// The input ends inside a chunk.
+    TruncatedChunk,
+    /// Bytes that are not a valid chunk type, as given.
+    InvalidChunkType(Vec<u8>),
+    /// A chunk the operation needs is not there.
+    MissingChunk(ChunkType),
+    /// Reading or writing failed.
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// Any other malformed input or argument.
+    Invalid(String),
+    /// The input went over the named field of `Limits`.
+    LimitExceeded(&'static str),
+    /// A chunk failed to parse, with where it is in the input.
+    Parse(ParseError),
+}
+
+/// Where in the input a chunk failed to parse and why.
+#[derive(Debug)]
+pub struct ParseError {
+    /// Byte offset of the chunk's length field, counting the signature.
+    pub offset: u64,
+    /// Index of the chunk in the input, starting at 0.
+    pub chunk_index: usize,
+    pub kind: Box<Error>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "chunk {} at byte {}: {}", self.chunk_index, self.offset, self.kind)
+    }
+}
+
+impl Error {
+    /// The error without its location, looks through `Parse`.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Parse(e) => &e.kind,
+            e => e,
+        }
+    }
+
+    /// Wraps the error in `Parse` with the location of the chunk.
+    pub(crate) fn at(self, offset: u64, chunk_index: usize) -> Error {
+        match self {
+            Error::Parse(_) => self,
+            kind => Error::Parse(ParseError { offset, chunk_index, kind: Box::new(kind) }),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::InvalidSignature => write!(f, "invalid PNG signature"),
+            Error::BadCrc { found, expected } => write!(f, "invalid crc: {}, should be: {}", found, expected),
+            Error::TruncatedChunk => write!(f, "unexpected end of input in a chunk"),
+            Error::InvalidChunkType(bytes) => {
+                write!(f, "invalid chunk type: {:?}", String::from_utf8_lossy(bytes))
+            }
+            Error::MissingChunk(chunk_type) => write!(f, "no {} chunk", chunk_type),
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Invalid(message) => write!(f, "{}", message),
+            Error::LimitExceeded(limit) => write!(f, "input exceeds the {} limit", limit),
+            Error::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e.kind.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Errors from the standard library that only mean the input is
+/// malformed, kept as their message.
+macro_rules! invalid_from {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Error {
+            fn from(e: $t) -> Error {
+                Error::Invalid(e.to_string())
+            }
+        })*
+    };
+}
+
+invalid_from!(
+    core::array::TryFromSliceError,
+    core::num::TryFromIntError,
+    core::str::Utf8Error,
+    alloc::string::FromUtf8Error
+);
+
+/// Returns early with an `Error::Invalid` formatted like `format!`.
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::Error::Invalid(alloc::format!($($arg)*)))
+    };
+}
+
+pub(crate) use bail;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fails() -> Result<(), Error> {
+        bail!("bad value: {}", 3)
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(fails().unwrap_err().to_string(), "bad value: 3");
+        let crc = Error::BadCrc { found: 1, expected: 2 };
+        assert_eq!(crc.to_string(), "invalid crc: 1, should be: 2");
+        assert_eq!(Error::MissingChunk(ChunkType::IHDR).to_string(), "no IHDR chunk");
+        assert_eq!(Error::InvalidChunkType(b"ab1d".to_vec()).to_string(), "invalid chunk type: \"ab1d\"");
+        #[cfg(feature = "std")]
+        {
+            let io = Error::from(std::io::Error::other("disk"));
+            assert!(std::error::Error::source(&io).is_some());
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let e = Error::TruncatedChunk.at(33, 1);
+        assert_eq!(e.to_string(), "chunk 1 at byte 33: unexpected end of input in a chunk");
+        assert!(matches!(e.kind(), Error::TruncatedChunk));
+        assert!(matches!(e.at(8, 0), Error::Parse(ParseError { offset: 33, chunk_index: 1, .. })));
+        assert!(matches!(Error::InvalidSignature.kind(), Error::InvalidSignature));
+    }
+}
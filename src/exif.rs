@@ -0,0 +1,344 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+const ORIENTATION: u16 = 0x0112;
+const MAKE: u16 = 0x010f;
+const MODEL: u16 = 0x0110;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const GPS_IFD: u16 = 0x8825;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+/// The TIFF structure of an eXIf chunk, with readers for a few common fields.
+/// Anything else is kept as is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Exif {
+    data: Vec<u8>,
+    big_endian: bool,
+}
+
+/// A directory entry, with where its value is.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    at: usize,
+}
+
+impl Exif {
+    /// # Errors
+    /// returns an Error if `data` does not start with a TIFF header.
+    pub fn new(data: Vec<u8>) -> Result<Exif, Error> {
+        let big_endian = match data.get(..4) {
+            Some(b"MM\0\x2a") => true,
+            Some(b"II\x2a\0") => false,
+            _ => bail!("eXIf data does not start with a TIFF header"),
+        };
+        if data.len() < 8 {
+            bail!("eXIf data is too short for a TIFF header");
+        }
+        Ok(Exif { data, big_endian })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::EXIF, self.data.clone())
+    }
+
+    /// How the image should be turned for display, from 1 (as stored) to 8.
+    pub fn orientation(&self) -> Option<u16> {
+        let entry = self.find(self.ifd0()?, ORIENTATION)?;
+        match entry.kind {
+            3 => self.u16_at(entry.at),
+            _ => None,
+        }
+    }
+
+    /// The camera maker.
+    pub fn make(&self) -> Option<String> {
+        self.ascii(self.ifd0()?, MAKE)
+    }
+
+    /// The camera model.
+    pub fn model(&self) -> Option<String> {
+        self.ascii(self.ifd0()?, MODEL)
+    }
+
+    /// When the file was last changed, as `YYYY:MM:DD HH:MM:SS`.
+    pub fn date_time(&self) -> Option<String> {
+        self.ascii(self.ifd0()?, DATE_TIME)
+    }
+
+    /// When the picture was taken, as `YYYY:MM:DD HH:MM:SS`.
+    pub fn date_time_original(&self) -> Option<String> {
+        let exif = self.find(self.ifd0()?, EXIF_IFD)?;
+        self.ascii(self.u32_at(exif.at)? as usize, DATE_TIME_ORIGINAL)
+    }
+
+    /// Whether there is a GPS directory, which often holds where the
+    /// picture was taken.
+    pub fn has_gps(&self) -> bool {
+        self.ifd0().and_then(|ifd| self.find(ifd, GPS_IFD)).is_some()
+    }
+
+    /// Removes the GPS directory: its entry in the first directory is
+    /// dropped and the directory and its values are zeroed. Other fields
+    /// stay where they are. Returns whether there was one.
+    pub fn strip_gps(&mut self) -> bool {
+        let ifd0 = match self.ifd0() {
+            Some(n) => n,
+            None => return false,
+        };
+        let entries = self.entries(ifd0).unwrap_or_default();
+        let index = match entries.iter().position(|e| e.tag == GPS_IFD) {
+            Some(n) => n,
+            None => return false,
+        };
+        if let Some(gps) = self.u32_at(entries[index].at).map(|n| n as usize) {
+            for entry in self.entries(gps).unwrap_or_default() {
+                let size = value_size(entry.kind, entry.count);
+                if size > 4 {
+                    self.zero(entry.at, size);
+                }
+            }
+            let count = self.u16_at(gps).unwrap_or(0) as usize;
+            self.zero(gps, 2 + count * 12 + 4);
+        }
+
+        // shift the later entries and the next directory offset down
+        let start = ifd0 + 2 + index * 12;
+        let end = ifd0 + 2 + entries.len() * 12 + 4;
+        self.data.copy_within(start + 12..end, start);
+        self.zero(end - 12, 12);
+        let count = (entries.len() - 1) as u16;
+        let count = if self.big_endian { count.to_be_bytes() } else { count.to_le_bytes() };
+        self.data[ifd0..ifd0 + 2].copy_from_slice(&count);
+        true
+    }
+
+    fn ifd0(&self) -> Option<usize> {
+        self.u32_at(4).map(|n| n as usize)
+    }
+
+    /// The entries of the directory at `offset`, `None` if it does not fit.
+    fn entries(&self, offset: usize) -> Option<Vec<Entry>> {
+        let count = self.u16_at(offset)? as usize;
+        self.data.get(offset..offset + 2 + count * 12 + 4)?;
+        let mut entries = Vec::with_capacity(count);
+        for n in 0..count {
+            let at = offset + 2 + n * 12;
+            let (kind, count) = (self.u16_at(at + 2)?, self.u32_at(at + 4)?);
+            let value_at = match value_size(kind, count) {
+                0..=4 => at + 8,
+                size => {
+                    let value_at = self.u32_at(at + 8)? as usize;
+                    self.data.get(value_at..value_at.checked_add(size)?)?;
+                    value_at
+                }
+            };
+            entries.push(Entry { tag: self.u16_at(at)?, kind, count, at: value_at });
+        }
+        Some(entries)
+    }
+
+    fn find(&self, ifd: usize, tag: u16) -> Option<Entry> {
+        self.entries(ifd)?.into_iter().find(|e| e.tag == tag)
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let entry = self.find(ifd, tag).filter(|e| e.kind == 2)?;
+        let bytes = self.data.get(entry.at..entry.at + entry.count as usize)?;
+        let text = bytes.split(|b| *b == 0).next().unwrap_or_default();
+        Some(String::from_utf8_lossy(text).trim_end().to_string())
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let bytes = [*self.data.get(at)?, *self.data.get(at + 1)?];
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn zero(&mut self, at: usize, len: usize) {
+        let end = (at + len).min(self.data.len());
+        if at < end {
+            self.data[at..end].fill(0);
+        }
+    }
+}
+
+/// The size in bytes of `count` values of TIFF type `kind`.
+fn value_size(kind: u16, count: u32) -> usize {
+    let size = match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    };
+    size * count as usize
+}
+
+impl TryFrom<&Chunk> for Exif {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the chunk is not eXIf or has no TIFF header.
+    fn try_from(chunk: &Chunk) -> Result<Exif, Error> {
+        if *chunk.chunk_type() != ChunkType::EXIF {
+            bail!("expected eXIf, got {}", chunk.chunk_type());
+        }
+        Exif::new(chunk.data().to_vec())
+    }
+}
+
+impl Png {
+    /// The Exif data from eXIf.
+    ///
+    /// # Errors
+    /// returns an Error if the eXIf chunk has no TIFF header.
+    pub fn exif(&self) -> Result<Option<Exif>, Error> {
+        self.chunk_by_type("eXIf").map(Exif::try_from).transpose()
+    }
+
+    /// Replaces eXIf, or adds it before the image data.
+    pub fn set_exif(&mut self, exif: &Exif) {
+        self.replace_unique(exif.to_chunk(), &[ChunkType::IDAT]);
+    }
+
+    /// Removes eXIf, returning whether there was one.
+    pub fn remove_exif(&mut self) -> bool {
+        !self.remove_chunks("eXIf").is_empty()
+    }
+
+    /// Removes the GPS location from eXIf, see `Exif::strip_gps`.
+    /// Returns whether there was one.
+    ///
+    /// # Errors
+    /// returns an Error if the eXIf chunk has no TIFF header.
+    pub fn strip_gps(&mut self) -> Result<bool, Error> {
+        let mut exif = match self.exif()? {
+            Some(exif) => exif,
+            None => return Ok(false),
+        };
+        if !exif.strip_gps() {
+            return Ok(false);
+        }
+        self.set_exif(&exif);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    /// A little TIFF with make, model, orientation, date, an Exif
+    /// directory with the original date and a GPS directory.
+    fn sample(big_endian: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let u16b = |n: u16| if big_endian { n.to_be_bytes() } else { n.to_le_bytes() };
+        let u32b = |n: u32| if big_endian { n.to_be_bytes() } else { n.to_le_bytes() };
+        let entry = |out: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: u32| {
+            out.extend(u16b(tag));
+            out.extend(u16b(kind));
+            out.extend(u32b(count));
+            match kind {
+                3 if count == 1 => out.extend([u16b(value as u16), [0, 0]].concat()),
+                _ => out.extend(u32b(value)),
+            }
+        };
+        out.extend(if big_endian { b"MM\0\x2a" } else { b"II\x2a\0" });
+        out.extend(u32b(8));
+        // IFD0 at 8 with 6 entries, ends at 8 + 2 + 72 + 4 = 86
+        out.extend(u16b(6));
+        entry(&mut out, MAKE, 2, 6, 86);
+        entry(&mut out, MODEL, 2, 6, 92);
+        entry(&mut out, ORIENTATION, 3, 1, 6);
+        entry(&mut out, DATE_TIME, 2, 20, 98);
+        entry(&mut out, EXIF_IFD, 4, 1, 118);
+        entry(&mut out, GPS_IFD, 4, 1, 156);
+        out.extend(u32b(0));
+        out.extend(b"Canon\0");
+        out.extend(b"R5  \0\0");
+        out.extend(b"2024:02:29 13:05:09\0");
+        // Exif IFD at 118, 1 entry, value at 136
+        out.extend(u16b(1));
+        entry(&mut out, DATE_TIME_ORIGINAL, 2, 20, 136);
+        out.extend(u32b(0));
+        out.extend(b"2024:02:28 10:00:00\0");
+        // GPS IFD at 156, latitude ref and a rational latitude at 186
+        out.extend(u16b(2));
+        entry(&mut out, 1, 2, 2, u32::from_be_bytes(*b"N\0\0\0"));
+        entry(&mut out, 2, 5, 3, 186);
+        out.extend(u32b(0));
+        for n in [52, 1, 31, 1, 0, 1] {
+            out.extend(u32b(n));
+        }
+        out
+    }
+
+    #[test]
+    fn test_exif_fields() {
+        for big_endian in [true, false] {
+            let exif = Exif::new(sample(big_endian)).unwrap();
+            assert_eq!(exif.make().unwrap(), "Canon");
+            assert_eq!(exif.model().unwrap(), "R5");
+            assert_eq!(exif.orientation(), Some(6));
+            assert_eq!(exif.date_time().unwrap(), "2024:02:29 13:05:09");
+            assert_eq!(exif.date_time_original().unwrap(), "2024:02:28 10:00:00");
+            assert!(exif.has_gps());
+        }
+        assert!(Exif::new(b"Exif\0\0MM\0\x2a".to_vec()).is_err());
+
+        let empty = Exif::new(b"MM\0\x2a\0\0\0\x08\0\0\0\0\0\0".to_vec()).unwrap();
+        assert_eq!((empty.make(), empty.orientation()), (None, None));
+        assert!(!empty.has_gps());
+        // a directory offset past the end
+        let broken = Exif::new(b"II\x2a\0\xff\0\0\0".to_vec()).unwrap();
+        assert_eq!(broken.model(), None);
+    }
+
+    #[test]
+    fn test_strip_gps() {
+        for big_endian in [true, false] {
+            let mut exif = Exif::new(sample(big_endian)).unwrap();
+            assert!(exif.strip_gps());
+            assert!(!exif.has_gps());
+            assert!(exif.as_bytes()[156..].iter().all(|b| *b == 0));
+            assert_eq!(exif.make().unwrap(), "Canon");
+            assert_eq!(exif.date_time_original().unwrap(), "2024:02:28 10:00:00");
+            assert_eq!(exif.as_bytes().len(), sample(big_endian).len());
+            assert!(!exif.strip_gps());
+        }
+    }
+
+    #[test]
+    fn test_png_exif() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_exif();
+        assert_eq!(png.exif().unwrap(), None);
+        assert!(!png.strip_gps().unwrap());
+
+        png.set_exif(&Exif::new(sample(true)).unwrap());
+        let exif = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::EXIF).unwrap();
+        let idat = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IDAT).unwrap();
+        assert!(exif < idat);
+        assert!(png.exif().unwrap().unwrap().has_gps());
+        assert!(png.strip_gps().unwrap());
+        assert!(!png.exif().unwrap().unwrap().has_gps());
+        assert_eq!(png.exif().unwrap().unwrap().model().unwrap(), "R5");
+        assert!(png.remove_exif());
+    }
+}
@@ -0,0 +1,425 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::known::KnownChunk;
+use crate::text::check_keyword;
+
+/// The unit of an oFFs offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OffsetUnit {
+    Pixel,
+    Micrometer,
+}
+
+/// Where the image sits on a page, from oFFs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageOffset {
+    pub x: i32,
+    pub y: i32,
+    pub unit: OffsetUnit,
+}
+
+impl KnownChunk for ImageOffset {
+    const TYPE: ChunkType = ChunkType::OFFS;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<ImageOffset, Error> {
+        let data = expect(chunk, ChunkType::OFFS)?;
+        if data.len() != 9 {
+            bail!("oFFs data must be 9 bytes, got {}", data.len());
+        }
+        let unit = match data[8] {
+            0 => OffsetUnit::Pixel,
+            1 => OffsetUnit::Micrometer,
+            n => bail!("unknown oFFs unit: {}", n),
+        };
+        Ok(ImageOffset { x: be_i32(&data[..4]), y: be_i32(&data[4..8]), unit })
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data = self.x.to_be_bytes().to_vec();
+        data.extend(self.y.to_be_bytes());
+        data.push(self.unit as u8);
+        Chunk::new(ChunkType::OFFS, data)
+    }
+}
+
+/// How pCAL maps samples to physical values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Equation {
+    /// `p0 + p1 * x`
+    Linear,
+    /// `p0 + p1 * e^(p2 * x)`
+    Exponential,
+    /// `p0 + p1 * p2^x`
+    ArbitraryBase,
+    /// `p0 + p1 * sinh(p2 * (x - p3))`
+    Hyperbolic,
+}
+
+impl Equation {
+    /// How many parameters the equation takes.
+    pub fn parameter_count(&self) -> usize {
+        match self {
+            Equation::Linear => 2,
+            Equation::Exponential | Equation::ArbitraryBase => 3,
+            Equation::Hyperbolic => 4,
+        }
+    }
+}
+
+/// Maps samples to physical values like temperature or density, from pCAL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelCalibration {
+    name: String,
+    x0: i32,
+    x1: i32,
+    equation: Equation,
+    unit: String,
+    parameters: Vec<f64>,
+}
+
+impl PixelCalibration {
+    /// Samples from 0 to the largest value are spread over `x0` to `x1`,
+    /// then `equation` turns them into values in `unit`, with `x` the
+    /// position divided by `x1 - x0`.
+    ///
+    /// # Errors
+    /// returns an Error if `name` is not a valid keyword, `x0` equals
+    /// `x1`, the number of parameters does not match the equation, or one
+    /// is not finite.
+    pub fn new(
+        name: &str,
+        x0: i32,
+        x1: i32,
+        equation: Equation,
+        unit: &str,
+        parameters: Vec<f64>,
+    ) -> Result<PixelCalibration, Error> {
+        check_keyword(name)?;
+        if x0 == x1 {
+            bail!("pCAL x0 and x1 must differ");
+        }
+        if parameters.len() != equation.parameter_count() {
+            bail!("{:?} takes {} parameters, got {}", equation, equation.parameter_count(), parameters.len());
+        }
+        if parameters.iter().any(|p| !p.is_finite()) {
+            bail!("pCAL parameters must be finite: {:?}", parameters);
+        }
+        let (name, unit) = (name.to_string(), unit.to_string());
+        Ok(PixelCalibration { name, x0, x1, equation, unit, parameters })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn x0(&self) -> i32 {
+        self.x0
+    }
+
+    pub fn x1(&self) -> i32 {
+        self.x1
+    }
+
+    pub fn equation(&self) -> Equation {
+        self.equation
+    }
+
+    /// The unit of the physical values, like `K` or `g/cm^3`.
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    pub fn parameters(&self) -> &[f64] {
+        &self.parameters
+    }
+
+    /// The physical value of `sample` in an image with `bit_depth`.
+    pub fn physical_value(&self, sample: u16, bit_depth: u8) -> f64 {
+        let max = ((1u64 << bit_depth) - 1).max(1) as i64;
+        let range = self.x1 as i64 - self.x0 as i64;
+        let x = (sample as i64 * range + max / 2).div_euclid(max) + self.x0 as i64;
+        let x = x as f64 / range as f64;
+        let p = &self.parameters;
+        match self.equation {
+            Equation::Linear => p[0] + p[1] * x,
+            Equation::Exponential => p[0] + p[1] * (p[2] * x).exp(),
+            Equation::ArbitraryBase => p[0] + p[1] * p[2].powf(x),
+            Equation::Hyperbolic => p[0] + p[1] * (p[2] * (x - p[3])).sinh(),
+        }
+    }
+}
+
+impl KnownChunk for PixelCalibration {
+    const TYPE: ChunkType = ChunkType::PCAL;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<PixelCalibration, Error> {
+        let data = expect(chunk, ChunkType::PCAL)?;
+        let split = match data.iter().position(|b| *b == 0) {
+            Some(n) => n,
+            None => bail!("pCAL has no name separator"),
+        };
+        let rest = &data[split + 1..];
+        if rest.len() < 11 {
+            bail!("pCAL is too short");
+        }
+        let equation = match rest[8] {
+            0 => Equation::Linear,
+            1 => Equation::Exponential,
+            2 => Equation::ArbitraryBase,
+            3 => Equation::Hyperbolic,
+            n => bail!("unknown pCAL equation: {}", n),
+        };
+        let mut strings = rest[10..].split(|b| *b == 0);
+        let unit = latin1(strings.next().unwrap_or_default());
+        let parameters = strings.map(parse_float).collect::<Result<Vec<f64>, Error>>()?;
+        if parameters.len() != rest[9] as usize {
+            bail!("pCAL says {} parameters, has {}", rest[9], parameters.len());
+        }
+        let (x0, x1) = (be_i32(&rest[..4]), be_i32(&rest[4..8]));
+        PixelCalibration::new(&latin1(&data[..split]), x0, x1, equation, &unit, parameters)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data: Vec<u8> = self.name.chars().map(|c| c as u8).collect();
+        data.push(0);
+        data.extend(self.x0.to_be_bytes());
+        data.extend(self.x1.to_be_bytes());
+        data.extend([self.equation as u8, self.parameters.len() as u8]);
+        data.extend(self.unit.chars().map(|c| u8::try_from(c).unwrap_or(b'?')));
+        for p in &self.parameters {
+            data.push(0);
+            data.extend(p.to_string().bytes());
+        }
+        Chunk::new(ChunkType::PCAL, data)
+    }
+}
+
+/// The unit of sCAL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScaleUnit {
+    Meter = 1,
+    Radian = 2,
+}
+
+/// The size of a pixel of the subject, from sCAL. Unlike pHYs this
+/// describes what was imaged, not how to display it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalScale {
+    unit: ScaleUnit,
+    width: f64,
+    height: f64,
+}
+
+impl PhysicalScale {
+    /// # Errors
+    /// returns an Error if `width` or `height` is not positive and finite.
+    pub fn new(unit: ScaleUnit, width: f64, height: f64) -> Result<PhysicalScale, Error> {
+        if !(width.is_finite() && width > 0.0 && height.is_finite() && height > 0.0) {
+            bail!("sCAL sizes must be positive: {} by {}", width, height);
+        }
+        Ok(PhysicalScale { unit, width, height })
+    }
+
+    pub fn unit(&self) -> ScaleUnit {
+        self.unit
+    }
+
+    /// The width of a pixel in units.
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// The height of a pixel in units.
+    pub fn height(&self) -> f64 {
+        self.height
+    }
+}
+
+impl KnownChunk for PhysicalScale {
+    const TYPE: ChunkType = ChunkType::SCAL;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<PhysicalScale, Error> {
+        let data = expect(chunk, ChunkType::SCAL)?;
+        let unit = match data.first() {
+            Some(1) => ScaleUnit::Meter,
+            Some(2) => ScaleUnit::Radian,
+            Some(n) => bail!("unknown sCAL unit: {}", n),
+            None => bail!("sCAL is empty"),
+        };
+        match *data[1..].split(|b| *b == 0).collect::<Vec<_>>() {
+            [width, height] => PhysicalScale::new(unit, parse_float(width)?, parse_float(height)?),
+            _ => bail!("sCAL needs a width and height separated by a null byte"),
+        }
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let data = format!("{}{}\0{}", self.unit as u8 as char, self.width, self.height);
+        Chunk::new(ChunkType::SCAL, data.into_bytes())
+    }
+}
+
+/// How the two halves of a stereo pair are laid out side by side, from sTER.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StereoMode {
+    /// The right eye image is on the left.
+    CrossFuse,
+    /// The left eye image is on the left.
+    DivergingFuse,
+}
+
+impl KnownChunk for StereoMode {
+    const TYPE: ChunkType = ChunkType::STER;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<StereoMode, Error> {
+        Ok(match *expect(chunk, ChunkType::STER)? {
+            [0] => StereoMode::CrossFuse,
+            [1] => StereoMode::DivergingFuse,
+            [n] => bail!("unknown sTER mode: {}", n),
+            _ => bail!("sTER data must be 1 byte, got {}", chunk.length()),
+        })
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::STER, vec![*self as u8])
+    }
+}
+
+/// The GIF graphic control extension of an image converted from GIF, from gIFg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GifControl {
+    /// What to do with the frame before the next one, 0 to 7.
+    pub disposal: u8,
+    /// Whether the viewer should wait for input before going on.
+    pub user_input: bool,
+    /// How long to wait, in hundredths of a second.
+    pub delay: u16,
+}
+
+impl KnownChunk for GifControl {
+    const TYPE: ChunkType = ChunkType::GIFG;
+
+    fn parse(chunk: &Chunk) -> Result<GifControl, Error> {
+        match *expect(chunk, ChunkType::GIFG)? {
+            [disposal, input, d1, d2] if input <= 1 => {
+                Ok(GifControl { disposal, user_input: input == 1, delay: u16::from_be_bytes([d1, d2]) })
+            }
+            [_, input, _, _] => bail!("invalid gIFg user input flag: {}", input),
+            _ => bail!("gIFg data must be 4 bytes, got {}", chunk.length()),
+        }
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data = vec![self.disposal, self.user_input as u8];
+        data.extend(self.delay.to_be_bytes());
+        Chunk::new(ChunkType::GIFG, data)
+    }
+}
+
+/// The data of `chunk`, if it has the right type.
+pub(crate) fn expect(chunk: &Chunk, chunk_type: ChunkType) -> Result<&[u8], Error> {
+    if *chunk.chunk_type() != chunk_type {
+        bail!("expected {}, got {}", chunk_type, chunk.chunk_type());
+    }
+    Ok(chunk.data())
+}
+
+fn be_i32(bytes: &[u8]) -> i32 {
+    i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+/// Parses the ASCII floating point syntax pCAL and sCAL use, like `1.5E-3`.
+fn parse_float(bytes: &[u8]) -> Result<f64, Error> {
+    let text = std::str::from_utf8(bytes)?;
+    let valid = !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit() || b".+-eE".contains(&b));
+    match text.parse::<f64>() {
+        Ok(value) if valid => Ok(value),
+        _ => bail!("invalid floating point value: {:?}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_image_offset() {
+        let offset = ImageOffset { x: -5, y: 300, unit: OffsetUnit::Micrometer };
+        let chunk = offset.to_chunk();
+        assert_eq!(chunk.data(), [0xff, 0xff, 0xff, 0xfb, 0, 0, 1, 0x2c, 1]);
+        assert_eq!(ImageOffset::parse(&chunk).unwrap(), offset);
+        assert!(ImageOffset::parse(&Chunk::new(ChunkType::OFFS, vec![0; 8])).is_err());
+    }
+
+    #[test]
+    fn test_pixel_calibration() {
+        let parameters = vec![200.0, 150.0];
+        let pcal = PixelCalibration::new("temp", 0, 65535, Equation::Linear, "K", parameters).unwrap();
+        let chunk = pcal.to_chunk();
+        assert_eq!(chunk.data(), b"temp\0\0\0\0\0\0\0\xff\xff\0\x02K\x00200\x00150");
+        assert_eq!(PixelCalibration::parse(&chunk).unwrap(), pcal);
+        assert_eq!(pcal.physical_value(0, 16), 200.0);
+        assert_eq!(pcal.physical_value(65535, 16), 350.0);
+        assert_eq!(pcal.physical_value(255, 8), 350.0);
+
+        let data = b"depth\0\0\0\0\0\0\0\0\x64\x03\x04m\x000\x001.5E+1\x002\x000".to_vec();
+        let parsed = PixelCalibration::parse(&Chunk::new(ChunkType::PCAL, data)).unwrap();
+        assert_eq!(parsed.equation(), Equation::Hyperbolic);
+        assert_eq!(parsed.parameters(), [0.0, 15.0, 2.0, 0.0]);
+        assert_eq!(parsed.unit(), "m");
+
+        assert!(PixelCalibration::new("t", 0, 0, Equation::Linear, "", vec![0.0, 1.0]).is_err());
+        assert!(PixelCalibration::new("t", 0, 1, Equation::Exponential, "", vec![0.0, 1.0]).is_err());
+        let bad = b"t\0\0\0\0\0\0\0\0\x01\x00\x02\x000\x00inf".to_vec();
+        assert!(PixelCalibration::parse(&Chunk::new(ChunkType::PCAL, bad)).is_err());
+    }
+
+    #[test]
+    fn test_physical_scale() {
+        let scale = PhysicalScale::new(ScaleUnit::Meter, 0.0001, 2.5).unwrap();
+        let chunk = scale.to_chunk();
+        assert_eq!(chunk.data(), b"\x010.0001\x002.5");
+        assert_eq!(PhysicalScale::parse(&chunk).unwrap(), scale);
+        let parsed = PhysicalScale::parse(&Chunk::new(ChunkType::SCAL, b"\x021e-3\x004".to_vec())).unwrap();
+        assert_eq!((parsed.unit(), parsed.width(), parsed.height()), (ScaleUnit::Radian, 0.001, 4.0));
+        assert!(PhysicalScale::new(ScaleUnit::Meter, 0.0, 1.0).is_err());
+        assert!(PhysicalScale::parse(&Chunk::new(ChunkType::SCAL, b"\x011".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_stereo_and_gif() {
+        let stereo = StereoMode::DivergingFuse;
+        assert_eq!(StereoMode::parse(&stereo.to_chunk()).unwrap(), stereo);
+        assert!(StereoMode::parse(&Chunk::new(ChunkType::STER, vec![2])).is_err());
+
+        let gif = GifControl { disposal: 2, user_input: true, delay: 500 };
+        assert_eq!(gif.to_chunk().data(), [2, 1, 1, 0xf4]);
+        assert_eq!(GifControl::parse(&gif.to_chunk()).unwrap(), gif);
+        assert!(GifControl::parse(&Chunk::new(ChunkType::GIFG, vec![0, 2, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_png_extensions() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_typed_chunk(&StereoMode::CrossFuse);
+        png.set_typed_chunk(&PhysicalScale::new(ScaleUnit::Meter, 1.0, 1.0).unwrap());
+        png.set_typed_chunk(&GifControl { disposal: 0, user_input: false, delay: 10 });
+        png.set_typed_chunk(&GifControl { disposal: 1, user_input: false, delay: 20 });
+        assert_eq!(png.typed_chunk::<StereoMode>().unwrap(), Some(StereoMode::CrossFuse));
+        assert_eq!(png.typed_chunk::<PhysicalScale>().unwrap().unwrap().width(), 1.0);
+        assert_eq!(png.typed_chunks_of::<GifControl>().unwrap().len(), 2);
+        let at = |t| png.chunks().iter().position(|c| *c.chunk_type() == t).unwrap();
+        assert!(at(ChunkType::STER) < at(ChunkType::IDAT));
+    }
+}
@@ -0,0 +1,237 @@
+use std::convert::TryFrom;
+
+use crate::error::{bail, Error};
+
+/// The filter type byte at the start of each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => FilterType::None,
+            1 => FilterType::Sub,
+            2 => FilterType::Up,
+            3 => FilterType::Average,
+            4 => FilterType::Paeth,
+            _ => bail!("invalid filter type: {}", value),
+        })
+    }
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] =
+        [FilterType::None, FilterType::Sub, FilterType::Up, FilterType::Average, FilterType::Paeth];
+}
+
+/// How an adaptive strategy scores a filtered row, lower is better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Heuristic {
+    /// Sum of the filtered bytes taken as signed, the method
+    /// suggested by the spec.
+    MinSumOfAbsDiff,
+}
+
+impl Heuristic {
+    pub fn score(&self, filtered: &[u8]) -> u64 {
+        match self {
+            Heuristic::MinSumOfAbsDiff => filtered.iter().map(|b| (*b as i8).unsigned_abs() as u64).sum(),
+        }
+    }
+}
+
+/// Which filter the encoder uses for each row.
+/// A fixed filter is fastest, `Adaptive` tries all five on every
+/// row and keeps the best, which usually gives smaller files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterStrategy {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+    Adaptive(Heuristic),
+}
+
+impl Default for FilterStrategy {
+    fn default() -> Self {
+        FilterStrategy::Adaptive(Heuristic::MinSumOfAbsDiff)
+    }
+}
+
+impl FilterStrategy {
+    /// Filters `row` into `out` following the strategy and returns
+    /// the filter type used. `scratch` must be as long as `row`.
+    pub fn apply(
+        &self,
+        bpp: usize,
+        prev: &[u8],
+        row: &[u8],
+        out: &mut [u8],
+        scratch: &mut [u8],
+    ) -> FilterType {
+        let fixed = match self {
+            FilterStrategy::None => FilterType::None,
+            FilterStrategy::Sub => FilterType::Sub,
+            FilterStrategy::Up => FilterType::Up,
+            FilterStrategy::Average => FilterType::Average,
+            FilterStrategy::Paeth => FilterType::Paeth,
+            FilterStrategy::Adaptive(heuristic) => {
+                let mut best = (u64::MAX, FilterType::None);
+                for f in FilterType::ALL {
+                    filter(f, bpp, prev, row, scratch);
+                    let score = heuristic.score(scratch);
+                    if score < best.0 {
+                        best = (score, f);
+                        out.copy_from_slice(scratch);
+                    }
+                }
+                return best.1;
+            }
+        };
+        filter(fixed, bpp, prev, row, out);
+        fixed
+    }
+}
+
+/// The Paeth predictor: whichever of left, above and upper left
+/// is closest to `a + b - c`.
+pub fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses `filter` on `row` in place. `prev` is the previous
+/// unfiltered row, all zeros for the first one, and `bpp` the bytes
+/// per complete pixel, at least 1.
+pub fn unfilter(filter: FilterType, bpp: usize, prev: &[u8], row: &mut [u8]) {
+    match filter {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for n in bpp..row.len() {
+                row[n] = row[n].wrapping_add(row[n - bpp]);
+            }
+        }
+        FilterType::Up => {
+            for (x, b) in row.iter_mut().zip(prev) {
+                *x = x.wrapping_add(*b);
+            }
+        }
+        FilterType::Average => {
+            for n in 0..row.len() {
+                let left = if n >= bpp { row[n - bpp] } else { 0 };
+                let avg = ((left as u16 + prev[n] as u16) / 2) as u8;
+                row[n] = row[n].wrapping_add(avg);
+            }
+        }
+        FilterType::Paeth => {
+            for n in 0..row.len() {
+                let (left, upper_left) = if n >= bpp { (row[n - bpp], prev[n - bpp]) } else { (0, 0) };
+                row[n] = row[n].wrapping_add(paeth(left, prev[n], upper_left));
+            }
+        }
+    }
+}
+
+/// Applies `filter` to `row`, writing the filtered bytes to `out`,
+/// which must be as long as `row`. `prev` and `bpp` are as for `unfilter`.
+pub fn filter(filter: FilterType, bpp: usize, prev: &[u8], row: &[u8], out: &mut [u8]) {
+    for n in 0..row.len() {
+        let left = if n >= bpp { row[n - bpp] } else { 0 };
+        let upper_left = if n >= bpp { prev[n - bpp] } else { 0 };
+        let predicted = match filter {
+            FilterType::None => 0,
+            FilterType::Sub => left,
+            FilterType::Up => prev[n],
+            FilterType::Average => ((left as u16 + prev[n] as u16) / 2) as u8,
+            FilterType::Paeth => paeth(left, prev[n], upper_left),
+        };
+        out[n] = row[n].wrapping_sub(predicted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_type() {
+        assert_eq!(FilterType::try_from(4).unwrap(), FilterType::Paeth);
+        assert!(FilterType::try_from(5).is_err());
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+        assert_eq!(paeth(0, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let prev = [10, 20, 30, 40];
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Sub, 2, &prev, &mut row);
+        assert_eq!(row, [1, 2, 4, 6]);
+
+        let mut row = [1, 2, 3, 250];
+        unfilter(FilterType::Up, 2, &prev, &mut row);
+        assert_eq!(row, [11, 22, 33, 34]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Average, 2, &prev, &mut row);
+        assert_eq!(row, [6, 12, 21, 30]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Paeth, 2, &prev, &mut row);
+        assert_eq!(row, [11, 22, 33, 44]);
+    }
+
+    #[test]
+    fn test_filter_strategy() {
+        let prev = [0; 6];
+        let row = [10, 20, 30, 40, 50, 60];
+        let (mut out, mut scratch) = ([0; 6], [0; 6]);
+
+        let used = FilterStrategy::Up.apply(1, &prev, &row, &mut out, &mut scratch);
+        assert_eq!(used, FilterType::Up);
+        assert_eq!(out, row);
+
+        // a ramp filters best with Sub, leaving small steps
+        let used = FilterStrategy::default().apply(1, &prev, &row, &mut out, &mut scratch);
+        assert_eq!(used, FilterType::Sub);
+        assert_eq!(out, [10, 10, 10, 10, 10, 10]);
+        assert_eq!(Heuristic::MinSumOfAbsDiff.score(&[1, 255, 128]), 1 + 1 + 128);
+    }
+
+    #[test]
+    fn test_filter_roundtrip() {
+        let prev = [200, 3, 90, 7, 255, 0];
+        let row = [1, 250, 33, 4, 128, 64];
+        for f in FilterType::ALL {
+            let mut out = [0; 6];
+            filter(f, 3, &prev, &row, &mut out);
+            unfilter(f, 3, &prev, &mut out);
+            assert_eq!(out, row, "{:?}", f);
+        }
+    }
+}
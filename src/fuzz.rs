@@ -0,0 +1,104 @@
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+
+#[cfg(feature = "arbitrary")]
+use crate::chunk::Chunk;
+#[cfg(feature = "arbitrary")]
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::png::Png;
+#[cfg(feature = "arbitrary")]
+use crate::standard::STANDARD_CHUNKS;
+
+/// Half of the time a type from the spec, so generated files reach
+/// the code for known chunks, otherwise four random letters.
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for ChunkType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<ChunkType> {
+        if u.arbitrary()? {
+            return Ok(u.choose(&STANDARD_CHUNKS)?.chunk_type);
+        }
+        let bytes = <[u8; 4]>::arbitrary(u)?.map(|b| (b'A' + b % 26) | (b & 0x20));
+        Ok(ChunkType::from_bytes_const(bytes))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (1, Some(5))
+    }
+}
+
+/// A chunk with a correct length and crc.
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Chunk {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Chunk> {
+        Ok(Chunk::new(u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+/// Chunks in any order, then a trailer that is usually empty.
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Png {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Png> {
+        let mut png = Png::from_chunks(u.arbitrary()?);
+        if u.ratio(1, 8)? {
+            png.set_trailer(u.arbitrary()?);
+        }
+        Ok(png)
+    }
+}
+
+impl Png {
+    /// Parses `bytes`, writes the result back out and parses that again,
+    /// for use as a fuzz target. Returns the parsed `Png`.
+    ///
+    /// # Errors
+    /// returns an Error if `bytes` is not a valid PNG.
+    ///
+    /// # Panics
+    /// panics if writing a parsed file does not give back `bytes`,
+    /// or the written file parses to something else.
+    pub fn roundtrip_check(bytes: &[u8]) -> Result<Png, Error> {
+        let png = Png::try_from(bytes)?;
+        let written = png.as_bytes();
+        assert_eq!(written, bytes, "writing a parsed png changed its bytes");
+        let reparsed = Png::try_from(written.as_slice()).expect("a written png failed to parse");
+        assert_eq!(reparsed, png, "a written png parsed to different chunks");
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_roundtrip_check() {
+        let png = Png::roundtrip_check(IMG).unwrap();
+        assert_eq!(png.as_bytes(), IMG);
+        assert!(Png::roundtrip_check(&IMG[..IMG.len() - 1]).is_err());
+        assert!(Png::roundtrip_check(b"not a png").is_err());
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_png_roundtrips() {
+        let seed: Vec<u8> = (0..4096u32).map(|n| (n.wrapping_mul(2654435761) >> 13) as u8).collect();
+        for start in (0..seed.len()).step_by(97) {
+            let mut u = Unstructured::new(&seed[start..]);
+            let png = Png::arbitrary(&mut u).unwrap();
+            for chunk in png.iter() {
+                assert!(ChunkType::is_ascii_valid(&chunk.chunk_type().bytes()));
+                assert!(chunk.has_valid_crc());
+            }
+            // a generated IEND ends the file early, the rest is parsed as trailer,
+            // and without one a trailer is parsed as chunks
+            let early_end = png.iter().any(|c| *c.chunk_type() == ChunkType::IEND);
+            match Png::roundtrip_check(&png.as_bytes()) {
+                Ok(parsed) => assert!(early_end || parsed == png),
+                Err(_) => assert!(!png.trailer().is_empty()),
+            }
+        }
+    }
+}
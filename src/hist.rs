@@ -0,0 +1,111 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::known::KnownChunk;
+use crate::png::Png;
+
+/// How often each palette entry is used, from hIST. The values are
+/// relative, encoders scale them to fit 16 bits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Histogram {
+    frequencies: Vec<u16>,
+}
+
+impl Histogram {
+    /// # Errors
+    /// returns an Error if there are not 1 to 256 frequencies.
+    pub fn new(frequencies: Vec<u16>) -> Result<Histogram, Error> {
+        if frequencies.is_empty() || frequencies.len() > 256 {
+            bail!("hIST needs 1 to 256 entries, got {}", frequencies.len());
+        }
+        Ok(Histogram { frequencies })
+    }
+
+    pub fn frequencies(&self) -> &[u16] {
+        &self.frequencies
+    }
+}
+
+impl KnownChunk for Histogram {
+    const TYPE: ChunkType = ChunkType::HIST;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<Histogram, Error> {
+        if *chunk.chunk_type() != ChunkType::HIST {
+            bail!("expected hIST, got {}", chunk.chunk_type());
+        }
+        if !chunk.data().len().is_multiple_of(2) {
+            bail!("hIST length must be even, got {}", chunk.length());
+        }
+        Histogram::new(chunk.data().chunks(2).map(|f| u16::from_be_bytes([f[0], f[1]])).collect())
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::HIST, self.frequencies.iter().flat_map(|f| f.to_be_bytes()).collect())
+    }
+}
+
+impl Png {
+    /// Counts how often each palette entry is used and stores it as hIST,
+    /// scaled so the most used entry is 65535 and used entries are at
+    /// least 1. Returns the histogram.
+    ///
+    /// # Errors
+    /// returns an Error if the image is not indexed, has no palette, or
+    /// can not be decoded.
+    #[cfg(feature = "flate")]
+    pub fn compute_histogram(&mut self) -> Result<Histogram, Error> {
+        use crate::ihdr::ColorType;
+
+        let image = self.decode_pixels()?;
+        if image.color_type() != ColorType::Indexed {
+            bail!("hIST needs an indexed image");
+        }
+        let len = match self.chunk_by_type("PLTE") {
+            Some(c) => c.data().len() / 3,
+            None => bail!("hIST needs a palette"),
+        };
+        let mut counts = vec![0u64; len];
+        for index in image.samples_u16() {
+            if let Some(count) = counts.get_mut(index as usize) {
+                *count += 1;
+            }
+        }
+        let max = counts.iter().copied().max().unwrap_or(0).max(1);
+        let scale = |c: u64| match c {
+            0 => 0,
+            c => (c * 65535 / max).max(1) as u16,
+        };
+        let histogram = Histogram::new(counts.into_iter().map(scale).collect())?;
+        self.set_typed_chunk(&histogram);
+        Ok(histogram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let hist = Histogram::new(vec![1, 0, 65535]).unwrap();
+        let chunk = hist.to_chunk();
+        assert_eq!(chunk.data(), [0, 1, 0, 0, 255, 255]);
+        assert_eq!(Histogram::parse(&chunk).unwrap(), hist);
+        assert!(Histogram::parse(&Chunk::new(ChunkType::HIST, vec![0; 3])).is_err());
+        assert!(Histogram::new(vec![0; 257]).is_err());
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_compute_histogram() {
+        let mut png = Png::try_from(include_bytes!("../img.png").as_slice()).unwrap();
+        let hist = png.compute_histogram().unwrap();
+        let plte = png.chunk_by_type("PLTE").unwrap().data().len() / 3;
+        assert_eq!(hist.frequencies().len(), plte);
+        assert!(hist.frequencies().contains(&65535));
+        assert_eq!(png.typed_chunk::<Histogram>().unwrap(), Some(hist));
+        let at = |t| png.chunks().iter().position(|c| *c.chunk_type() == t).unwrap();
+        assert!(at(ChunkType::PLTE) < at(ChunkType::HIST) && at(ChunkType::HIST) < at(ChunkType::IDAT));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+use crate::text::{check_keyword, deflate, inflate};
+
+impl Png {
+    /// The decompressed ICC profile from iCCP, `None` if there is none
+    /// or it is malformed.
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        let (_, compressed) = split_iccp(self.chunk_by_type("iCCP")?)?;
+        inflate(compressed).ok()
+    }
+
+    /// The name the profile is stored under.
+    pub fn icc_profile_name(&self) -> Option<String> {
+        let (name, _) = split_iccp(self.chunk_by_type("iCCP")?)?;
+        Some(name.iter().map(|b| *b as char).collect())
+    }
+
+    /// Replaces iCCP with `profile` under `name`, or adds it before the
+    /// palette and image data. sRGB is removed, since the spec does not
+    /// allow both.
+    ///
+    /// # Errors
+    /// returns an Error if `name` is not a valid keyword, see `TextChunk::to_chunk`,
+    /// or the profile is too short to have an ICC header.
+    pub fn set_icc_profile(&mut self, name: &str, profile: &[u8]) -> Result<(), Error> {
+        check_keyword(name)?;
+        if profile.len() < 128 {
+            bail!("ICC profile must have a 128 byte header, got {} bytes", profile.len());
+        }
+        let mut data: Vec<u8> = name.chars().map(|c| c as u8).collect();
+        data.extend([0, 0]);
+        data.extend(deflate(profile)?);
+        self.chunks_mut().retain(|c| *c.chunk_type() != ChunkType::SRGB);
+        self.replace_unique(Chunk::new(ChunkType::ICCP, data), &[ChunkType::PLTE, ChunkType::IDAT]);
+        Ok(())
+    }
+
+    /// Removes iCCP, returning whether there was one.
+    pub fn remove_icc_profile(&mut self) -> bool {
+        !self.remove_chunks("iCCP").is_empty()
+    }
+}
+
+/// Splits iCCP data into the name and compressed profile, `None` if there
+/// is no separator or the compression method is not zlib.
+fn split_iccp(chunk: &Chunk) -> Option<(&[u8], &[u8])> {
+    let data = chunk.data();
+    let split = data.iter().position(|b| *b == 0)?;
+    match data.get(split + 1) {
+        Some(0) => Some((&data[..split], &data[split + 2..])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn profile() -> Vec<u8> {
+        let mut profile = vec![0; 128];
+        profile[..4].copy_from_slice(&128u32.to_be_bytes());
+        profile[36..40].copy_from_slice(b"acsp");
+        profile
+    }
+
+    #[test]
+    fn test_icc_profile() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_icc_profile();
+        assert_eq!(png.icc_profile(), None);
+
+        png.set_srgb(crate::colorspace::RenderingIntent::Perceptual);
+        png.set_icc_profile("Display P3", &profile()).unwrap();
+        assert_eq!(png.icc_profile().unwrap(), profile());
+        assert_eq!(png.icc_profile_name().unwrap(), "Display P3");
+        assert!(png.chunk_by_type("sRGB").is_none());
+        let iccp = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::ICCP).unwrap();
+        let plte = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::PLTE).unwrap();
+        assert!(iccp < plte);
+
+        // moving a profile between images
+        let mut other = Png::try_from(IMG).unwrap();
+        other.set_icc_profile(&png.icc_profile_name().unwrap(), &png.icc_profile().unwrap()).unwrap();
+        assert_eq!(other.icc_profile(), png.icc_profile());
+
+        assert!(png.remove_icc_profile());
+        assert!(!png.remove_icc_profile());
+    }
+
+    #[test]
+    fn test_invalid_icc_profile() {
+        let mut png = Png::try_from(IMG).unwrap();
+        assert!(png.set_icc_profile("", &profile()).is_err());
+        assert!(png.set_icc_profile("short", &[0; 10]).is_err());
+
+        png.remove_icc_profile();
+        png.insert_after_type("IHDR", Chunk::new(ChunkType::ICCP, b"name\0\x01abc".to_vec())).unwrap();
+        assert_eq!(png.icc_profile(), None);
+        assert_eq!(png.icc_profile_name(), None);
+    }
+}
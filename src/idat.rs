@@ -0,0 +1,139 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::parse::Limits;
+use crate::png::Png;
+use crate::text::inflate_limited;
+
+impl Png {
+    /// The zlib stream of the image, like `combined_idat` but failing
+    /// when there is no image data.
+    ///
+    /// # Errors
+    /// returns an Error if there are no IDAT chunks.
+    pub fn idat_compressed(&self) -> Result<Vec<u8>, Error> {
+        if self.idat_chunks().is_empty() {
+            return Err(Error::MissingChunk(ChunkType::IDAT));
+        }
+        Ok(self.combined_idat())
+    }
+
+    /// Inflates the image data, giving the filtered scanlines:
+    /// each row starts with its filter type byte.
+    ///
+    /// # Errors
+    /// returns an Error if there are no IDAT chunks or the zlib stream
+    /// is broken, including a wrong Adler-32 checksum.
+    pub fn idat_data(&self) -> Result<Vec<u8>, Error> {
+        self.idat_data_with(&Limits::default())
+    }
+
+    /// Like `idat_data` but the inflated size is capped by
+    /// `limits.max_decompressed` instead of the default.
+    pub fn idat_data_with(&self, limits: &Limits) -> Result<Vec<u8>, Error> {
+        inflate_limited(&self.idat_compressed()?, limits.max_decompressed)
+    }
+
+    /// Inflates only the first `len` bytes of the image data,
+    /// or less if the stream is shorter.
+    pub(crate) fn idat_data_prefix(&self, len: usize) -> Result<Vec<u8>, Error> {
+        let stream = self.idat_compressed()?;
+        // grows with what the stream holds, not with what was asked for
+        let mut data = Vec::new();
+        ZlibDecoder::new(stream.as_slice()).take(len as u64).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::ihdr::{ColorType, Ihdr};
+    use std::convert::TryFrom;
+    use std::io::Write;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_idat_data() {
+        let png = Png::try_from(IMG).unwrap();
+        let ihdr = png.header().unwrap();
+        let data = png.idat_data().unwrap();
+        assert_eq!(data.len(), (ihdr.row_bytes(ihdr.width()) + 1) * ihdr.height() as usize);
+    }
+
+    #[test]
+    fn test_idat_data_split() {
+        let raw: Vec<u8> = (0..1000u32).map(|n| (n % 251) as u8).collect();
+        let stream = zlib(&raw);
+        let (a, b) = stream.split_at(stream.len() / 3);
+        let png = Png::from_chunks(vec![
+            Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap().to_chunk(),
+            Chunk::new(ChunkType::IDAT, a.to_vec()),
+            Chunk::new(ChunkType::IDAT, b.to_vec()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        assert_eq!(png.idat_compressed().unwrap(), stream);
+        assert_eq!(png.idat_data().unwrap(), raw);
+    }
+
+    #[test]
+    fn test_idat_data_errors() {
+        assert!(Png::from_chunks(Vec::new()).idat_data().is_err());
+
+        let mut stream = zlib(b"some data");
+        let last = stream.len() - 1;
+        stream[last] ^= 1; // breaks the Adler-32
+        let png = Png::from_chunks(vec![Chunk::new(ChunkType::IDAT, stream)]);
+        assert!(png.idat_data().is_err());
+    }
+
+    #[test]
+    fn test_idat_data_limit() {
+        let png = Png::from_chunks(vec![Chunk::new(ChunkType::IDAT, zlib(&[0u8; 100_000]))]);
+        assert_eq!(png.idat_data().unwrap().len(), 100_000);
+        let limits = Limits { max_decompressed: 100_000, ..Default::default() };
+        assert_eq!(png.idat_data_with(&limits).unwrap().len(), 100_000);
+        let limits = Limits { max_decompressed: 99_999, ..Default::default() };
+        assert!(matches!(png.idat_data_with(&limits), Err(Error::LimitExceeded("max_decompressed"))));
+    }
+
+    #[test]
+    fn test_crafted_header_limits() {
+        // a file of under 80 bytes claiming 100000x100000 RGBA16, which
+        // decoding must refuse instead of allocating 80 GB for
+        for interlace in [false, true] {
+            let ihdr = Ihdr::new(100_000, 100_000, 16, ColorType::Rgba).unwrap().with_interlace(interlace);
+            let bytes = Png::from_chunks(vec![
+                ihdr.to_chunk(),
+                Chunk::new(ChunkType::IDAT, zlib(&[])),
+                Chunk::new(ChunkType::IEND, Vec::new()),
+            ])
+            .as_bytes();
+            assert!(bytes.len() < 80);
+            let png = Png::try_from(bytes.as_slice()).unwrap();
+            assert!(matches!(png.decode_pixels(), Err(Error::LimitExceeded("max_decompressed"))));
+            assert!(png.decode_pixels_with(&Limits::unlimited()).is_err());
+            assert!(png.scanlines().is_err());
+            assert!(png.scan_anomalies().is_clean());
+        }
+
+        let png = Png::try_from(IMG).unwrap();
+        let ihdr = png.header().unwrap();
+        let needed = (ihdr.row_bytes(ihdr.width()) as u64 + 1) * ihdr.height() as u64;
+        let limits = Limits { max_decompressed: needed, ..Default::default() };
+        assert!(png.decode_pixels_with(&limits).is_ok());
+        let limits = Limits { max_decompressed: needed - 1, ..Default::default() };
+        assert!(matches!(png.decode_pixels_with(&limits), Err(Error::LimitExceeded("max_decompressed"))));
+    }
+}
@@ -0,0 +1,254 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+
+/// The colour type byte of an IHDR chunk.
+/// Describes how each pixel is made up from samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// The number of samples that make up one pixel.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// The bit depths the spec allows for this colour type.
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            _ => &[8, 16],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => ColorType::Grayscale,
+            2 => ColorType::Rgb,
+            3 => ColorType::Indexed,
+            4 => ColorType::GrayscaleAlpha,
+            6 => ColorType::Rgba,
+            _ => bail!("invalid color type: {}", value),
+        })
+    }
+}
+
+/// The image header, the first chunk of every PNG.
+/// 13 bytes: width and height as big endian `u32`s followed by
+/// bit depth, colour type, compression method, filter method
+/// and interlace method, one byte each.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    compression: u8,
+    filter: u8,
+    interlace: u8,
+}
+
+impl Ihdr {
+    /// Length of the IHDR data field.
+    pub const LENGTH: usize = 13;
+
+    /// Creates an `Ihdr` using the only defined compression and
+    /// filter methods and no interlacing.
+    ///
+    /// # Errors
+    /// returns an Error if a dimension is 0 or over 2^31 - 1, or the bit
+    /// depth is not allowed for the colour type.
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Result<Ihdr, Error> {
+        let ihdr = Ihdr { width, height, bit_depth, color_type, compression: 0, filter: 0, interlace: 0 };
+        ihdr.check()?;
+        Ok(ihdr)
+    }
+
+    /// Returns a copy using Adam7 interlacing if `interlaced`.
+    pub fn with_interlace(mut self, interlaced: bool) -> Ihdr {
+        self.interlace = interlaced as u8;
+        self
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.width == 0 || self.width > i32::MAX as u32 {
+            bail!("invalid width: {}", self.width);
+        }
+        if self.height == 0 || self.height > i32::MAX as u32 {
+            bail!("invalid height: {}", self.height);
+        }
+        if !self.color_type.allowed_bit_depths().contains(&self.bit_depth) {
+            bail!("invalid bit depth {} for {:?}", self.bit_depth, self.color_type);
+        }
+        if self.compression != 0 {
+            bail!("invalid compression method: {}", self.compression);
+        }
+        if self.filter != 0 {
+            bail!("invalid filter method: {}", self.filter);
+        }
+        if self.interlace > 1 {
+            bail!("invalid interlace method: {}", self.interlace);
+        }
+        Ok(())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn compression(&self) -> u8 {
+        self.compression
+    }
+
+    pub fn filter(&self) -> u8 {
+        self.filter
+    }
+
+    pub fn interlace(&self) -> u8 {
+        self.interlace
+    }
+
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace == 1
+    }
+
+    /// Bits used by one pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    /// Bytes in one row of `width` pixels, without the filter byte.
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Returns the 13 data bytes of the chunk.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.width.to_be_bytes().to_vec();
+        bytes.extend(self.height.to_be_bytes());
+        bytes.extend([
+            self.bit_depth,
+            self.color_type as u8,
+            self.compression,
+            self.filter,
+            self.interlace,
+        ]);
+        bytes
+    }
+
+    /// Creates an IHDR `Chunk` from this header.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::IHDR, self.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = Error;
+
+    /// Tries to create an `Ihdr` from the data field of an IHDR chunk.
+    ///
+    /// # Errors
+    /// returns an Error if the data is not 13 bytes or any field is invalid.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() != Ihdr::LENGTH {
+            bail!("invalid IHDR length: {}", value.len());
+        }
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes(value[0..4].try_into()?),
+            height: u32::from_be_bytes(value[4..8].try_into()?),
+            bit_depth: value[8],
+            color_type: ColorType::try_from(value[9])?,
+            compression: value[10],
+            filter: value[11],
+            interlace: value[12],
+        };
+        ihdr.check()?;
+        Ok(ihdr)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the chunk is not an IHDR or is invalid.
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        if value.chunk_type().bytes() != *b"IHDR" {
+            bail!("not an IHDR chunk: {}", value.chunk_type());
+        }
+        Ihdr::try_from(value.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let bytes = [0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0];
+        let ihdr = Ihdr::try_from(&bytes[..]).unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 40);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+        assert!(!ihdr.is_interlaced());
+        assert_eq!(ihdr.as_bytes(), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_ihdr_invalid() {
+        assert!(Ihdr::try_from(&[0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0][..]).is_err());
+        assert!(Ihdr::try_from(&[0, 0, 0, 0, 0, 0, 0, 40, 8, 6, 0, 0, 0][..]).is_err());
+        assert!(Ihdr::try_from(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 5, 0, 0, 0][..]).is_err());
+        assert!(Ihdr::try_from(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 2][..]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_new() {
+        assert!(Ihdr::new(1, 1, 4, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed).is_err());
+        let ihdr = Ihdr::new(3, 2, 1, ColorType::Grayscale).unwrap().with_interlace(true);
+        assert!(ihdr.is_interlaced());
+        assert_eq!(ihdr.row_bytes(ihdr.width()), 1);
+    }
+
+    #[test]
+    fn test_ihdr_chunk_roundtrip() {
+        let ihdr = Ihdr::new(640, 480, 16, ColorType::Rgb).unwrap();
+        let chunk = ihdr.to_chunk();
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), ihdr);
+    }
+}
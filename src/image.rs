@@ -0,0 +1,337 @@
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+
+/// Decoded pixels in the bit depth and colour type of the file.
+/// Rows are unfiltered and packed like in the file, `stride` bytes
+/// each, so pixels under 8 bits share bytes. Indexed images carry
+/// their palette, and `trns` holds the tRNS data if there was one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Image {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
+}
+
+impl Image {
+    pub(crate) fn new(ihdr: Ihdr, data: Vec<u8>, palette: Option<Vec<u8>>, trns: Option<Vec<u8>>) -> Image {
+        Image { ihdr, data, palette, trns }
+    }
+
+    /// The header the pixels are laid out by.
+    pub fn header(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn width(&self) -> u32 {
+        self.ihdr.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.ihdr.height()
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.ihdr.bit_depth()
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.ihdr.color_type()
+    }
+
+    /// Bytes in one row.
+    pub fn stride(&self) -> usize {
+        self.ihdr.row_bytes(self.ihdr.width())
+    }
+
+    /// All rows, one after the other.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// The row at `y`.
+    ///
+    /// # Panics
+    /// panics if `y` is not less than the height.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let stride = self.stride();
+        &self.data[y as usize * stride..(y as usize + 1) * stride]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        // chunks_exact panics on 0, a row is never empty anyway
+        self.data.chunks_exact(self.stride().max(1))
+    }
+
+    /// The PLTE data, 3 bytes per entry.
+    pub fn palette(&self) -> Option<&[u8]> {
+        self.palette.as_deref()
+    }
+
+    pub fn trns(&self) -> Option<&[u8]> {
+        self.trns.as_deref()
+    }
+
+    /// The value of sample `channel` of the pixel at `x`, `y` at the
+    /// bit depth of the image, unpacking 1, 2 and 4 bit rows.
+    /// For indexed images this is the palette index.
+    ///
+    /// # Panics
+    /// panics if the position or channel is outside the image.
+    pub fn sample(&self, x: u32, y: u32, channel: usize) -> u16 {
+        let channels = self.color_type().channels() as usize;
+        assert!(x < self.width() && channel < channels, "sample out of bounds");
+        let row = self.row(y);
+        let n = x as usize * channels + channel;
+        match self.bit_depth() {
+            16 => u16::from_be_bytes([row[n * 2], row[n * 2 + 1]]),
+            8 => row[n] as u16,
+            depth => Image::packed_sample(row, x as usize, depth) as u16,
+        }
+    }
+
+    /// Every sample of every pixel, row by row, at the bit depth of the image.
+    pub fn samples_u16(&self) -> Vec<u16> {
+        let channels = self.color_type().channels() as usize;
+        let mut out = Vec::with_capacity(self.width() as usize * self.height() as usize * channels);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                for c in 0..channels {
+                    out.push(self.sample(x, y, c));
+                }
+            }
+        }
+        out
+    }
+
+    /// The grey level of the pixel, at the bit depth of the image.
+    /// `None` unless the image is greyscale.
+    pub fn pixel_gray(&self, x: u32, y: u32) -> Option<u16> {
+        match self.color_type() {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => Some(self.sample(x, y, 0)),
+            _ => None,
+        }
+    }
+
+    /// Grey level and alpha of the pixel, at the bit depth of the image.
+    /// Greyscale images without alpha get it from tRNS or are opaque.
+    /// `None` unless the image is greyscale.
+    pub fn pixel_gray_alpha(&self, x: u32, y: u32) -> Option<(u16, u16)> {
+        let gray = self.pixel_gray(x, y)?;
+        let alpha = match self.color_type() {
+            ColorType::GrayscaleAlpha => self.sample(x, y, 1),
+            _ if self.is_trns_key(&[gray]) => 0,
+            _ => self.max_sample(),
+        };
+        Some((gray, alpha))
+    }
+
+    /// The pixel as 16 bit RGBA, whatever the colour type and bit depth.
+    /// Lower bit depths are scaled so the highest value becomes 65535.
+    ///
+    /// # Errors
+    /// returns an Error if a palette index is out of range.
+    pub fn pixel_rgba16(&self, x: u32, y: u32) -> Result<[u16; 4], Error> {
+        let max = self.max_sample() as u32;
+        let scale = |v: u16| (v as u32 * 65535 / max) as u16;
+        let opaque = |key: &[u16]| if self.is_trns_key(key) { 0 } else { 65535 };
+        let s = |c| self.sample(x, y, c);
+        Ok(match self.color_type() {
+            ColorType::Grayscale => {
+                let g = s(0);
+                [scale(g), scale(g), scale(g), opaque(&[g])]
+            }
+            ColorType::GrayscaleAlpha => {
+                let g = scale(s(0));
+                [g, g, g, scale(s(1))]
+            }
+            ColorType::Rgb => {
+                let (r, g, b) = (s(0), s(1), s(2));
+                [scale(r), scale(g), scale(b), opaque(&[r, g, b])]
+            }
+            ColorType::Rgba => [scale(s(0)), scale(s(1)), scale(s(2)), scale(s(3))],
+            ColorType::Indexed => {
+                let index = s(0) as usize;
+                let palette = self.palette.as_deref().unwrap_or_default();
+                let entry = match palette.get(index * 3..index * 3 + 3) {
+                    Some(e) => e,
+                    None => bail!("palette index out of range: {}", index),
+                };
+                // entries past the end of tRNS are opaque
+                let a = self.trns.as_ref().and_then(|t| t.get(index)).copied().unwrap_or(255);
+                // palette entries are 8 bit
+                [entry[0] as u16 * 257, entry[1] as u16 * 257, entry[2] as u16 * 257, a as u16 * 257]
+            }
+        })
+    }
+
+    /// Converts to 8 bit RGBA. Palettes are looked up, greyscale is
+    /// spread over RGB, 16 bit samples keep their high byte and low
+    /// bit depths are scaled up. Alpha comes from the image, from tRNS
+    /// or is opaque.
+    ///
+    /// # Errors
+    /// returns an Error if a palette index is out of range.
+    pub fn to_rgba8(&self) -> Result<Rgba8Image, Error> {
+        let mut out = Vec::with_capacity(self.width() as usize * self.height() as usize * 4);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                out.extend(self.pixel_rgba16(x, y)?.map(|v| (v >> 8) as u8));
+            }
+        }
+        Ok(Rgba8Image { width: self.width(), height: self.height(), data: out })
+    }
+
+    /// Highest sample value at the bit depth of the image.
+    fn max_sample(&self) -> u16 {
+        ((1u32 << self.bit_depth()) - 1) as u16
+    }
+
+    /// Returns true if `samples` are the transparent colour of tRNS,
+    /// for greyscale and RGB images.
+    fn is_trns_key(&self, samples: &[u16]) -> bool {
+        match &self.trns {
+            Some(t) if t.len() == samples.len() * 2 => {
+                t.chunks_exact(2).zip(samples).all(|(k, s)| u16::from_be_bytes([k[0], k[1]]) == *s)
+            }
+            _ => false,
+        }
+    }
+
+    /// The sample value of the pixel at `x` in `row`, for bit depths
+    /// under 8, where samples are packed most significant bits first.
+    pub(crate) fn packed_sample(row: &[u8], x: usize, bit_depth: u8) -> u8 {
+        let bits = bit_depth as usize;
+        let byte = row[x * bits / 8];
+        let shift = 8 - bits - (x * bits % 8);
+        (byte >> shift) & ((1u16 << bits) - 1) as u8
+    }
+}
+
+/// 8 bit RGBA pixels, 4 bytes per pixel with no padding.
+/// See `Image::to_rgba8`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rgba8Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Rgba8Image {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The pixel at `x`, `y`.
+    ///
+    /// # Panics
+    /// panics if the position is outside the image.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        let n = (y as usize * self.width as usize + x as usize) * 4;
+        [self.data[n], self.data[n + 1], self.data[n + 2], self.data[n + 3]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_rows() {
+        let ihdr = Ihdr::new(3, 2, 8, ColorType::Rgb).unwrap();
+        let image = Image::new(ihdr, (0..18).collect(), None, None);
+        assert_eq!(image.stride(), 9);
+        assert_eq!(image.row(1), &(9..18).collect::<Vec<u8>>()[..]);
+        assert_eq!(image.rows().count(), 2);
+    }
+
+    fn image(width: u32, depth: u8, color_type: ColorType, data: Vec<u8>) -> Image {
+        Image::new(Ihdr::new(width, 1, depth, color_type).unwrap(), data, None, None)
+    }
+
+    #[test]
+    fn test_rgba8_gray() {
+        let rgba = image(4, 2, ColorType::Grayscale, vec![0b00_01_10_11]).to_rgba8().unwrap();
+        assert_eq!(rgba.pixel(0, 0), [0, 0, 0, 255]);
+        assert_eq!(rgba.pixel(1, 0), [85, 85, 85, 255]);
+        assert_eq!(rgba.pixel(3, 0), [255, 255, 255, 255]);
+
+        let mut gray16 = image(2, 16, ColorType::Grayscale, vec![0x12, 0x34, 0xab, 0xcd]);
+        gray16.trns = Some(vec![0xab, 0xcd]);
+        let rgba = gray16.to_rgba8().unwrap();
+        assert_eq!(rgba.data(), &[0x12, 0x12, 0x12, 255, 0xab, 0xab, 0xab, 0]);
+
+        let rgba = image(1, 8, ColorType::GrayscaleAlpha, vec![7, 9]).to_rgba8().unwrap();
+        assert_eq!(rgba.pixel(0, 0), [7, 7, 7, 9]);
+    }
+
+    #[test]
+    fn test_rgba8_rgb() {
+        let mut rgb = image(2, 8, ColorType::Rgb, vec![1, 2, 3, 4, 5, 6]);
+        rgb.trns = Some(vec![0, 4, 0, 5, 0, 6]);
+        assert_eq!(rgb.to_rgba8().unwrap().data(), &[1, 2, 3, 255, 4, 5, 6, 0]);
+
+        let rgba = image(1, 8, ColorType::Rgba, vec![1, 2, 3, 4]).to_rgba8().unwrap();
+        assert_eq!(rgba.into_data(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_rgba8_indexed() {
+        let mut indexed = image(3, 4, ColorType::Indexed, vec![0x01, 0x20]);
+        indexed.palette = Some(vec![10, 11, 12, 20, 21, 22, 30, 31, 32]);
+        indexed.trns = Some(vec![128]);
+        let rgba = indexed.to_rgba8().unwrap();
+        assert_eq!((rgba.width(), rgba.height()), (3, 1));
+        assert_eq!(rgba.pixel(0, 0), [10, 11, 12, 128]);
+        assert_eq!(rgba.pixel(1, 0), [20, 21, 22, 255]);
+        assert_eq!(rgba.pixel(2, 0), [30, 31, 32, 255]);
+
+        indexed.palette = Some(vec![10, 11, 12]);
+        assert!(indexed.to_rgba8().is_err());
+    }
+
+    #[test]
+    fn test_sample_accessors() {
+        let packed = image(4, 2, ColorType::Grayscale, vec![0b0001_1011]);
+        assert_eq!(packed.samples_u16(), vec![0, 1, 2, 3]);
+        assert_eq!(packed.pixel_gray(2, 0), Some(2));
+        assert_eq!(packed.pixel_gray_alpha(3, 0), Some((3, 3)));
+        assert_eq!(packed.pixel_rgba16(1, 0).unwrap(), [21845, 21845, 21845, 65535]);
+
+        let rgba16 = image(1, 16, ColorType::Rgba, vec![0x12, 0x34, 0, 1, 0xff, 0xff, 0x80, 0]);
+        assert_eq!(rgba16.sample(0, 0, 0), 0x1234);
+        assert_eq!(rgba16.pixel_rgba16(0, 0).unwrap(), [0x1234, 1, 0xffff, 0x8000]);
+        assert_eq!(rgba16.pixel_gray(0, 0), None);
+
+        let mut gray_alpha = image(1, 16, ColorType::GrayscaleAlpha, vec![0xab, 0xcd, 0x01, 0x02]);
+        assert_eq!(gray_alpha.pixel_gray_alpha(0, 0), Some((0xabcd, 0x0102)));
+        gray_alpha.ihdr = Ihdr::new(2, 1, 16, ColorType::Grayscale).unwrap();
+        gray_alpha.trns = Some(vec![0x01, 0x02]);
+        assert_eq!(gray_alpha.pixel_gray_alpha(1, 0), Some((0x0102, 0)));
+        assert_eq!(gray_alpha.pixel_gray_alpha(0, 0), Some((0xabcd, 0xffff)));
+    }
+
+    #[test]
+    fn test_packed_sample() {
+        let row = [0b1001_1100, 0b0100_0000];
+        assert_eq!(Image::packed_sample(&row, 0, 1), 1);
+        assert_eq!(Image::packed_sample(&row, 1, 1), 0);
+        assert_eq!(Image::packed_sample(&row, 1, 2), 0b01);
+        assert_eq!(Image::packed_sample(&row, 2, 2), 0b11);
+        assert_eq!(Image::packed_sample(&row, 1, 4), 0b1100);
+        assert_eq!(Image::packed_sample(&row, 2, 4), 0b0100);
+    }
+}
@@ -0,0 +1,131 @@
+use image::{DynamicImage, ImageBuffer};
+
+use crate::encode::EncodeOptions;
+use crate::error::{bail, Error};
+use crate::ihdr::ColorType;
+use crate::image::Image;
+use crate::png::Png;
+
+impl Png {
+    /// Decodes the pixels into an `image::DynamicImage`. 8 and 16 bit
+    /// images keep their colour type. Indexed images and any image with
+    /// tRNS become RGBA with the transparency applied, 16 bit if the
+    /// file is. Greyscale under 8 bits is scaled up to 8.
+    ///
+    /// # Errors
+    /// returns an Error if the image can not be decoded.
+    pub fn to_dynamic_image(&self) -> Result<DynamicImage, Error> {
+        use DynamicImage::*;
+
+        let image = self.decode_pixels()?;
+        let (width, height) = (image.width(), image.height());
+        let bytes = || image.data().to_vec();
+        let wide = || image.samples_u16();
+        let dynamic = match (image.color_type(), image.bit_depth(), image.trns().is_some()) {
+            (ColorType::Indexed, ..) | (_, _, true) => return rgba(&image),
+            (ColorType::Grayscale, 8, _) => ImageBuffer::from_raw(width, height, bytes()).map(ImageLuma8),
+            (ColorType::Grayscale, 16, _) => ImageBuffer::from_raw(width, height, wide()).map(ImageLuma16),
+            (ColorType::Grayscale, depth, _) => {
+                let max = (1u16 << depth) - 1;
+                let samples = wide().into_iter().map(|s| (s * 255 / max) as u8).collect();
+                ImageBuffer::from_raw(width, height, samples).map(ImageLuma8)
+            }
+            (ColorType::GrayscaleAlpha, 8, _) => ImageBuffer::from_raw(width, height, bytes()).map(ImageLumaA8),
+            (ColorType::GrayscaleAlpha, ..) => ImageBuffer::from_raw(width, height, wide()).map(ImageLumaA16),
+            (ColorType::Rgb, 8, _) => ImageBuffer::from_raw(width, height, bytes()).map(ImageRgb8),
+            (ColorType::Rgb, ..) => ImageBuffer::from_raw(width, height, wide()).map(ImageRgb16),
+            (ColorType::Rgba, 8, _) => ImageBuffer::from_raw(width, height, bytes()).map(ImageRgba8),
+            (ColorType::Rgba, ..) => ImageBuffer::from_raw(width, height, wide()).map(ImageRgba16),
+        };
+        match dynamic {
+            Some(d) => Ok(d),
+            None => bail!("decoded pixels do not fit a {}x{} image", width, height),
+        }
+    }
+
+    /// Encodes an `image::DynamicImage`. Luma, luma alpha, RGB and RGBA
+    /// at 8 and 16 bits map onto the matching colour type, 32 bit float
+    /// images are stored as 16 bit. The bit depth and palette of
+    /// `options` are ignored as the image decides them.
+    ///
+    /// # Errors
+    /// returns an Error if the image is empty or encoding fails.
+    pub fn from_dynamic_image(image: &DynamicImage, options: EncodeOptions) -> Result<Png, Error> {
+        let (width, height) = (image.width(), image.height());
+        let wide = |samples: &[u16]| samples.iter().flat_map(|s| s.to_be_bytes()).collect::<Vec<u8>>();
+        let (color_type, bit_depth, pixels) = match image {
+            DynamicImage::ImageLuma8(b) => (ColorType::Grayscale, 8, b.as_raw().clone()),
+            DynamicImage::ImageLumaA8(b) => (ColorType::GrayscaleAlpha, 8, b.as_raw().clone()),
+            DynamicImage::ImageRgb8(b) => (ColorType::Rgb, 8, b.as_raw().clone()),
+            DynamicImage::ImageRgba8(b) => (ColorType::Rgba, 8, b.as_raw().clone()),
+            DynamicImage::ImageLuma16(b) => (ColorType::Grayscale, 16, wide(b.as_raw())),
+            DynamicImage::ImageLumaA16(b) => (ColorType::GrayscaleAlpha, 16, wide(b.as_raw())),
+            DynamicImage::ImageRgb16(b) => (ColorType::Rgb, 16, wide(b.as_raw())),
+            DynamicImage::ImageRgba16(b) => (ColorType::Rgba, 16, wide(b.as_raw())),
+            DynamicImage::ImageRgb32F(_) => (ColorType::Rgb, 16, wide(image.to_rgb16().as_raw())),
+            _ => (ColorType::Rgba, 16, wide(image.to_rgba16().as_raw())),
+        };
+        let options = EncodeOptions { bit_depth, palette: None, ..options };
+        Png::from_pixels(width, height, color_type, &pixels, options)
+    }
+}
+
+/// `image` as RGBA at 8 bits, or 16 if it has them.
+fn rgba(image: &Image) -> Result<DynamicImage, Error> {
+    let (width, height) = (image.width(), image.height());
+    let dynamic = if image.bit_depth() == 16 {
+        let mut samples = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                samples.extend(image.pixel_rgba16(x, y)?);
+            }
+        }
+        ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+    } else {
+        ImageBuffer::from_raw(width, height, image.to_rgba8()?.into_data()).map(DynamicImage::ImageRgba8)
+    };
+    match dynamic {
+        Some(d) => Ok(d),
+        None => bail!("decoded pixels do not fit a {}x{} image", width, height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_dynamic_roundtrip() {
+        let pixels: Vec<u8> = (0..4 * 3 * 3).map(|n| n as u8 * 7).collect();
+        let png = Png::from_pixels(4, 3, ColorType::Rgb, &pixels, EncodeOptions::default()).unwrap();
+        let dynamic = png.to_dynamic_image().unwrap();
+        assert_eq!(dynamic.as_rgb8().unwrap().as_raw(), &pixels);
+
+        let back = Png::from_dynamic_image(&dynamic, EncodeOptions::default()).unwrap();
+        assert_eq!(back.decode_pixels().unwrap().data(), pixels);
+
+        let options = EncodeOptions { bit_depth: 16, ..Default::default() };
+        let wide: Vec<u8> = (0..2 * 2 * 2 * 2).map(|n| n as u8 * 13).collect();
+        let png = Png::from_pixels(2, 2, ColorType::GrayscaleAlpha, &wide, options).unwrap();
+        let dynamic = png.to_dynamic_image().unwrap();
+        assert_eq!(dynamic.as_luma_alpha16().unwrap().get_pixel(0, 0).0, [13, 26 * 256 + 39]);
+        let back = Png::from_dynamic_image(&dynamic, EncodeOptions::default()).unwrap();
+        assert_eq!(back.header().unwrap().bit_depth(), 16);
+        assert_eq!(back.decode_pixels().unwrap().data(), wide);
+    }
+
+    #[test]
+    fn test_indexed_to_rgba() {
+        let png = Png::try_from(IMG).unwrap();
+        let rgba = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        let dynamic = png.to_dynamic_image().unwrap();
+        assert_eq!(dynamic.as_rgba8().unwrap().as_raw(), rgba.data());
+
+        let options = EncodeOptions { bit_depth: 1, ..Default::default() };
+        let gray = Png::from_pixels(8, 1, ColorType::Grayscale, &[0b1010_0101], options).unwrap();
+        let dynamic = gray.to_dynamic_image().unwrap();
+        assert_eq!(dynamic.as_luma8().unwrap().as_raw(), &[255, 0, 255, 0, 0, 255, 0, 255]);
+    }
+}
@@ -0,0 +1,268 @@
+use std::io::{self, Read, Write};
+
+use crate::chunk::Chunk;
+use crate::error::Error;
+use crate::parse::ParseOptions;
+use crate::png::Png;
+use crate::validate::MAX_CHUNK_LENGTH;
+
+/// Reads a PNG one chunk at a time without building a `Png`.
+/// Also an `Iterator` over the chunks.
+pub struct ChunkReader<R> {
+    reader: R,
+    options: ParseOptions,
+    index: usize,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks the signature, chunks are read on demand.
+    ///
+    /// # Errors
+    /// returns an Error if the signature is invalid or reading fails.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        ChunkReader::with_options(reader, ParseOptions::default())
+    }
+
+    /// Like `new` but `options` decides how to deal with broken chunks.
+    pub fn with_options(mut reader: R, options: ParseOptions) -> Result<Self, Error> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        if header != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(ChunkReader { reader, options, index: 0, offset: 8, done: false })
+    }
+
+    /// Reads the next chunk, `None` at the end of the input.
+    ///
+    /// # Errors
+    /// returns an Error if a chunk is invalid, or the reader ends
+    /// part way through a chunk.
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>, Error> {
+        let mut lenb = [0u8; 4];
+        while !self.done {
+            let (offset, index) = (self.offset, self.index);
+            // a clean end of input between chunks is the end of the file
+            let read = self.reader.read(&mut lenb).map_err(|e| Error::from(e).at(offset, index))?;
+            if read == 0 {
+                self.done = true;
+                break;
+            }
+            self.reader.read_exact(&mut lenb[read..]).map_err(|e| Error::from(e).at(offset, index))?;
+
+            let len = u32::from_be_bytes(lenb);
+            self.options.limits.check_chunk(index, len).map_err(|e| e.at(offset, index))?;
+            let len = len as u64;
+            let mut bytes = lenb.to_vec();
+            // type + data + crc, read through `take` so a bogus length
+            // can not make us allocate more than the reader holds
+            let rest = 4 + len + 4;
+            let read = self.reader.by_ref().take(rest).read_to_end(&mut bytes)
+                .map_err(|e| Error::from(e).at(offset, index))?;
+            let complete = read as u64 == rest;
+            self.done = !complete;
+            let chunk = Png::chunk_from_read(&bytes, complete, &self.options).map_err(|e| e.at(offset, index))?;
+            self.index += 1;
+            self.offset += bytes.len() as u64;
+            if chunk.is_some() {
+                return Ok(chunk);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_chunk() {
+            Ok(chunk) => chunk.map(Ok),
+            Err(e) => {
+                // don't keep reading from the middle of a broken chunk
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes a PNG one chunk at a time.
+pub struct ChunkWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes the signature, chunks follow with `write_chunk`.
+    ///
+    /// # Errors
+    /// returns an Error if writing fails.
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(ChunkWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        chunk.write_to(&mut self.writer)
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl Chunk {
+    /// The data as a `Read`, for decompressors and parsers that take one.
+    pub fn reader(&self) -> impl Read + '_ {
+        self.data()
+    }
+
+    /// A `Write` that replaces the data with what is written to it.
+    /// See `ChunkDataWriter`.
+    pub fn data_writer(&mut self) -> ChunkDataWriter<'_> {
+        ChunkDataWriter { chunk: Some(self), data: Vec::new() }
+    }
+}
+
+/// Collects the data of a chunk through `Write`, so a compressor can
+/// write straight into one. The chunk gets the data, length and crc on
+/// `finish`, or when the writer is dropped.
+pub struct ChunkDataWriter<'a> {
+    chunk: Option<&'a mut Chunk>,
+    data: Vec<u8>,
+}
+
+impl<'a> ChunkDataWriter<'a> {
+    /// Hands the data to the chunk and returns it.
+    pub fn finish(mut self) -> &'a mut Chunk {
+        let chunk = self.chunk.take().expect("chunk is only taken once");
+        chunk.set_data(std::mem::take(&mut self.data));
+        chunk
+    }
+}
+
+impl Write for ChunkDataWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if (self.data.len() + buf.len()) as u64 > MAX_CHUNK_LENGTH as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk data over the length limit"));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ChunkDataWriter<'_> {
+    fn drop(&mut self) {
+        if let Some(chunk) = self.chunk.take() {
+            chunk.set_data(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::parse::{CrcMode, Limits};
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a\0b".to_vec()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_chunk_reader() {
+        let png = testing_png();
+        let bytes = png.as_bytes();
+        let chunks: Vec<Chunk> = ChunkReader::new(bytes.as_slice()).unwrap().map(|c| c.unwrap()).collect();
+        assert_eq!(chunks, png.chunks());
+    }
+
+    #[test]
+    fn test_chunk_reader_errors() {
+        assert!(ChunkReader::new(&b"not a png"[..]).is_err());
+
+        let bytes = testing_png().as_bytes();
+        let mut reader = ChunkReader::new(&bytes[..bytes.len() - 4]).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_chunk_reader_skip() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[8 + 8] ^= 1;
+        let options = ParseOptions { crc: CrcMode::Skip, ..Default::default() };
+        let reader = ChunkReader::with_options(bytes.as_slice(), options).unwrap();
+        assert_eq!(reader.count(), 2);
+    }
+
+    #[test]
+    fn test_chunk_reader_limits() {
+        let bytes = testing_png().as_bytes();
+        let read = |limits: Limits| {
+            let options = ParseOptions { limits, ..Default::default() };
+            ChunkReader::with_options(bytes.as_slice(), options).unwrap().collect::<Result<Vec<_>, _>>()
+        };
+        assert_eq!(read(Limits::unlimited()).unwrap().len(), 3);
+        let err = read(Limits { max_chunk_size: 4, ..Default::default() }).unwrap_err();
+        assert!(matches!(err.kind(), Error::LimitExceeded("max_chunk_size")));
+        let err = read(Limits { max_chunks: 2, ..Default::default() }).unwrap_err();
+        assert!(matches!(err.kind(), Error::LimitExceeded("max_chunks")));
+    }
+
+    #[test]
+    fn test_strip_with_reader_and_writer() {
+        let bytes = testing_png().as_bytes();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        for chunk in ChunkReader::new(bytes.as_slice()).unwrap() {
+            let chunk = chunk.unwrap();
+            if *chunk.chunk_type() != ChunkType::TEXT {
+                writer.write_chunk(&chunk).unwrap();
+            }
+        }
+        let out = Png::try_from(writer.finish().unwrap().as_slice()).unwrap();
+        assert_eq!(out.chunks().len(), 2);
+        assert!(out.chunk_by_type("tEXt").is_none());
+    }
+
+    #[test]
+    fn test_chunk_data_adapters() {
+        let mut chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"old".to_vec());
+        {
+            let mut writer = chunk.data_writer();
+            write!(writer, "key=").unwrap();
+            io::copy(&mut &b"value"[..], &mut writer).unwrap();
+        }
+        assert_eq!(chunk.data(), b"key=value");
+        assert_eq!(chunk.length(), 9);
+        assert!(chunk.has_valid_crc());
+
+        let mut read = String::new();
+        chunk.reader().read_to_string(&mut read).unwrap();
+        assert_eq!(read, "key=value");
+
+        let mut writer = chunk.data_writer();
+        writer.write_all(b"new").unwrap();
+        let chunk = writer.finish();
+        assert_eq!(*chunk, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"new".to_vec()));
+    }
+}
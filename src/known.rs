@@ -0,0 +1,309 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::sync::{OnceLock, RwLock};
+
+use crate::apng::{AnimationControl, FrameControl, FrameData};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::colorspace::RenderingIntent;
+use crate::error::Error;
+use crate::exif::Exif;
+use crate::extensions::{GifControl, ImageOffset, PhysicalScale, PixelCalibration, StereoMode};
+use crate::hist::Histogram;
+use crate::phys::PixelDims;
+use crate::png::Png;
+use crate::sbit::SignificantBits;
+use crate::splt::SuggestedPalette;
+use crate::time::Timestamp;
+
+/// A chunk type with a typed form that can be read without the rest of the file.
+pub trait KnownChunk: Sized {
+    const TYPE: ChunkType;
+
+    /// Where a new chunk goes if the file has none yet: before the first
+    /// of these, or IEND.
+    const BEFORE: &'static [ChunkType] = &[];
+
+    /// # Errors
+    /// returns an Error if the chunk is another type or malformed.
+    fn parse(chunk: &Chunk) -> Result<Self, Error>;
+
+    fn to_chunk(&self) -> Chunk;
+}
+
+/// A parsed chunk of a type only known at runtime, from `Png::typed_chunks`.
+/// Use `downcast_ref` to get the `KnownChunk` back.
+pub trait TypedChunk: Debug + Send + Sync {
+    fn chunk_type(&self) -> ChunkType;
+
+    /// `KnownChunk::to_chunk` of the value, named apart so the two traits
+    /// can be in scope together.
+    fn encode(&self) -> Chunk;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: KnownChunk + Debug + Send + Sync + 'static> TypedChunk for T {
+    fn chunk_type(&self) -> ChunkType {
+        T::TYPE
+    }
+
+    fn encode(&self) -> Chunk {
+        self.to_chunk()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl dyn TypedChunk {
+    /// The chunk as `T`, `None` if it is another type.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+type Parser = fn(&Chunk) -> Result<Box<dyn TypedChunk>, Error>;
+
+fn parse_boxed<T>(chunk: &Chunk) -> Result<Box<dyn TypedChunk>, Error>
+where
+    T: KnownChunk + Debug + Send + Sync + 'static,
+{
+    Ok(Box::new(T::parse(chunk)?))
+}
+
+/// Which `KnownChunk` parses each chunk type.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkRegistry {
+    parsers: Vec<(ChunkType, Parser)>,
+}
+
+impl ChunkRegistry {
+    /// A registry with no types.
+    pub fn new() -> ChunkRegistry {
+        ChunkRegistry::default()
+    }
+
+    /// A registry with every `KnownChunk` of this crate.
+    pub fn with_standard() -> ChunkRegistry {
+        let mut registry = ChunkRegistry::new();
+        registry.register::<Timestamp>();
+        registry.register::<PixelDims>();
+        registry.register::<RenderingIntent>();
+        registry.register::<Exif>();
+        registry.register::<SignificantBits>();
+        registry.register::<Histogram>();
+        registry.register::<SuggestedPalette>();
+        registry.register::<ImageOffset>();
+        registry.register::<PixelCalibration>();
+        registry.register::<PhysicalScale>();
+        registry.register::<StereoMode>();
+        registry.register::<GifControl>();
+        registry.register::<AnimationControl>();
+        registry.register::<FrameControl>();
+        registry.register::<FrameData>();
+        registry
+    }
+
+    /// Parses chunks of type `T::TYPE` as `T`, in place of whatever did before.
+    pub fn register<T: KnownChunk + Debug + Send + Sync + 'static>(&mut self) {
+        self.parsers.retain(|(t, _)| *t != T::TYPE);
+        self.parsers.push((T::TYPE, parse_boxed::<T>));
+    }
+
+    pub fn is_registered(&self, chunk_type: &ChunkType) -> bool {
+        self.parsers.iter().any(|(t, _)| t == chunk_type)
+    }
+
+    /// Parses `chunk` with the type registered for it, `None` if there is none.
+    pub fn parse(&self, chunk: &Chunk) -> Option<Result<Box<dyn TypedChunk>, Error>> {
+        let (_, parser) = self.parsers.iter().find(|(t, _)| t == chunk.chunk_type())?;
+        Some(parser(chunk))
+    }
+}
+
+pub(crate) fn global() -> &'static RwLock<ChunkRegistry> {
+    static REGISTRY: OnceLock<RwLock<ChunkRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(ChunkRegistry::with_standard()))
+}
+
+/// Adds `T` to the registry `Png::typed_chunks` uses, which starts with
+/// every `KnownChunk` of this crate. Replaces any type registered for `T::TYPE`.
+pub fn register<T: KnownChunk + Debug + Send + Sync + 'static>() {
+    global().write().unwrap_or_else(|e| e.into_inner()).register::<T>();
+}
+
+impl Png {
+    /// Parses every chunk with a type in the global registry, see
+    /// `register`, in file order. Other chunks are left out.
+    pub fn typed_chunks(&self) -> Vec<Result<Box<dyn TypedChunk>, Error>> {
+        self.typed_chunks_with(&global().read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Like `typed_chunks` with `registry` in place of the global one.
+    pub fn typed_chunks_with(&self, registry: &ChunkRegistry) -> Vec<Result<Box<dyn TypedChunk>, Error>> {
+        self.iter().filter_map(|c| registry.parse(c)).collect()
+    }
+
+    /// The first chunk of type `T::TYPE`, parsed.
+    ///
+    /// # Errors
+    /// returns an Error if it is malformed.
+    pub fn typed_chunk<T: KnownChunk>(&self) -> Result<Option<T>, Error> {
+        self.iter().find(|c| *c.chunk_type() == T::TYPE).map(T::parse).transpose()
+    }
+
+    /// Every chunk of type `T::TYPE`, parsed, for types that can appear
+    /// more than once.
+    ///
+    /// # Errors
+    /// returns an Error if any of them is malformed.
+    pub fn typed_chunks_of<T: KnownChunk>(&self) -> Result<Vec<T>, Error> {
+        self.iter().filter(|c| *c.chunk_type() == T::TYPE).map(T::parse).collect()
+    }
+
+    /// Replaces the chunk of type `T::TYPE`, or adds it before `T::BEFORE`.
+    /// Types that can appear more than once are added next to the others.
+    pub fn set_typed_chunk<T: KnownChunk>(&mut self, value: &T) {
+        let chunk = value.to_chunk();
+        if !T::TYPE.allows_multiple() {
+            return self.replace_unique(chunk, T::BEFORE);
+        }
+        let at = self
+            .chunks()
+            .iter()
+            .position(|c| T::BEFORE.contains(c.chunk_type()) || *c.chunk_type() == ChunkType::IEND)
+            .unwrap_or(self.chunks().len());
+        self.chunks_mut().insert(at, chunk);
+    }
+
+    /// Removes every chunk of type `T::TYPE`, returning how many there were.
+    pub fn remove_typed_chunks<T: KnownChunk>(&mut self) -> usize {
+        let before = self.chunks().len();
+        self.chunks_mut().retain(|c| *c.chunk_type() != T::TYPE);
+        before - self.chunks().len()
+    }
+}
+
+impl KnownChunk for Timestamp {
+    const TYPE: ChunkType = ChunkType::TIME;
+
+    fn parse(chunk: &Chunk) -> Result<Timestamp, Error> {
+        Timestamp::try_from(chunk)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        Timestamp::to_chunk(self)
+    }
+}
+
+impl KnownChunk for PixelDims {
+    const TYPE: ChunkType = ChunkType::PHYS;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<PixelDims, Error> {
+        PixelDims::try_from(chunk)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        PixelDims::to_chunk(self)
+    }
+}
+
+impl KnownChunk for RenderingIntent {
+    const TYPE: ChunkType = ChunkType::SRGB;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::PLTE, ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<RenderingIntent, Error> {
+        RenderingIntent::try_from(chunk)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        RenderingIntent::to_chunk(*self)
+    }
+}
+
+impl KnownChunk for Exif {
+    const TYPE: ChunkType = ChunkType::EXIF;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<Exif, Error> {
+        Exif::try_from(chunk)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        Exif::to_chunk(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phys::Unit;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_typed_chunk() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_typed_chunks::<PixelDims>();
+        assert_eq!(png.typed_chunk::<PixelDims>().unwrap(), None);
+
+        let dims = PixelDims::new(3, 4, Unit::Unknown);
+        png.set_typed_chunk(&dims);
+        png.set_typed_chunk(&dims);
+        assert_eq!(png.typed_chunk::<PixelDims>().unwrap(), Some(dims));
+        assert_eq!(png.typed_chunks_of::<PixelDims>().unwrap(), vec![dims]);
+        assert_eq!(png.physical_dims().unwrap(), Some(dims));
+        assert_eq!(png.remove_typed_chunks::<PixelDims>(), 1);
+
+        png.append_chunk(Chunk::new(ChunkType::TIME, vec![0; 3]));
+        assert!(png.typed_chunk::<Timestamp>().is_err());
+    }
+
+    /// A chunk a downstream crate could define.
+    #[derive(Debug, PartialEq)]
+    struct Note(String);
+
+    impl KnownChunk for Note {
+        const TYPE: ChunkType = ChunkType::from_bytes_const(*b"noTe");
+
+        fn parse(chunk: &Chunk) -> Result<Note, Error> {
+            Ok(Note(String::from_utf8(chunk.data().to_vec())?))
+        }
+
+        fn to_chunk(&self) -> Chunk {
+            Chunk::new(Note::TYPE, self.0.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_typed_chunk(&StereoMode::CrossFuse);
+        png.set_typed_chunk(&Note("hello".to_string()));
+
+        let mut registry = ChunkRegistry::new();
+        assert!(png.typed_chunks_with(&registry).is_empty());
+        registry.register::<Note>();
+        assert!(registry.is_registered(&Note::TYPE));
+        let typed = png.typed_chunks_with(&registry);
+        assert_eq!(typed.len(), 1);
+        let note = typed[0].as_ref().unwrap();
+        assert_eq!(note.chunk_type(), Note::TYPE);
+        assert_eq!(note.downcast_ref::<Note>(), Some(&Note("hello".to_string())));
+        assert_eq!(note.downcast_ref::<StereoMode>(), None);
+        assert_eq!(note.encode().data(), b"hello");
+
+        let standard = ChunkRegistry::with_standard();
+        let typed = png.typed_chunks_with(&standard);
+        assert!(typed.iter().any(|t| t.as_ref().unwrap().downcast_ref() == Some(&StereoMode::CrossFuse)));
+        assert!(!standard.is_registered(&Note::TYPE));
+
+        register::<Note>();
+        let global = png.typed_chunks();
+        assert!(global.iter().any(|t| t.as_ref().unwrap().chunk_type() == Note::TYPE));
+        assert!(global.iter().any(|t| t.as_ref().unwrap().chunk_type() == ChunkType::STER));
+    }
+}
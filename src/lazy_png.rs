@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+/// Where a chunk lives in the underlying reader.
+/// `offset` points at the length field of the chunk,
+/// `length` is the length of the data field only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    offset: u64,
+    length: u32,
+    chunk_type: ChunkType,
+}
+
+impl ChunkInfo {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+}
+
+/// A PNG that only remembers where its chunks are.
+/// Parsing reads the length and type of each chunk and seeks
+/// past the data, chunks are only read when asked for.
+pub struct LazyPng<R> {
+    reader: R,
+    infos: Vec<ChunkInfo>,
+}
+
+impl LazyPng<BufReader<File>> {
+    /// Opens the file at `path` and indexes its chunks.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        LazyPng::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> LazyPng<R> {
+    /// Indexes the chunks in `reader` without reading their data.
+    ///
+    /// # Errors
+    /// returns an Error if the header or a chunk type is invalid,
+    /// or a chunk runs past the end of the reader.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        if header != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut infos = Vec::new();
+        let mut offset = 8u64;
+        let mut buf = [0u8; 8];
+
+        while offset < end {
+            let index = infos.len();
+            if end - offset < 12 {
+                return Err(Error::TruncatedChunk.at(offset, index));
+            }
+            reader.read_exact(&mut buf)?;
+            let length = u32::from_be_bytes(buf[..4].try_into()?);
+            let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&buf[4..])?)
+                .map_err(|e| e.at(offset, index))?;
+            let next = offset + 12 + length as u64;
+            if next > end {
+                return Err(Error::TruncatedChunk.at(offset, index));
+            }
+            infos.push(ChunkInfo { offset, length, chunk_type });
+            offset = reader.seek(SeekFrom::Start(next))?;
+        }
+
+        Ok(LazyPng { reader, infos })
+    }
+
+    pub fn chunk_infos(&self) -> &[ChunkInfo] {
+        self.infos.as_ref()
+    }
+
+    /// Reads the chunk at `index` from the underlying reader.
+    ///
+    /// # Errors
+    /// returns an Error if `index` is out of range, reading fails
+    /// or the crc of the chunk is wrong.
+    pub fn load_chunk(&mut self, index: usize) -> Result<Chunk, Error> {
+        let info = match self.infos.get(index) {
+            Some(info) => info,
+            None => bail!("no chunk at index: {}", index),
+        };
+        let mut bytes = vec![0u8; 12 + info.length as usize];
+        self.reader.seek(SeekFrom::Start(info.offset))?;
+        self.reader.read_exact(&mut bytes)?;
+
+        Chunk::try_from(bytes.as_ref()).map_err(|e| e.at(info.offset, index))
+    }
+
+    /// Reads the first `Chunk` of specified type, if there is one.
+    pub fn chunk_by_type(&mut self, chunk_type: &str) -> Result<Option<Chunk>, Error> {
+        let index = self.infos.iter()
+            .position(|i| i.chunk_type.bytes() == chunk_type.as_bytes());
+        match index {
+            Some(n) => Ok(Some(self.load_chunk(n)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads every chunk, turning this into a regular `Png`.
+    pub fn into_png(mut self) -> Result<Png, Error> {
+        let mut chunks = Vec::with_capacity(self.infos.len());
+        for n in 0..self.infos.len() {
+            chunks.push(self.load_chunk(n)?);
+        }
+
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ParseError;
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_lazy_chunk_infos() {
+        let lazy = LazyPng::new(Cursor::new(testing_bytes())).unwrap();
+        let infos = lazy.chunk_infos();
+        assert_eq!(infos.len(), 3);
+        assert_eq!(infos[0].offset(), 8);
+        assert_eq!(infos[0].length(), 20);
+        assert_eq!(&infos[1].chunk_type().to_string(), "miDl");
+        assert_eq!(infos[1].offset(), 8 + 12 + 20);
+    }
+
+    #[test]
+    fn test_lazy_chunk_by_type() {
+        let mut lazy = LazyPng::new(Cursor::new(testing_bytes())).unwrap();
+        let chunk = lazy.chunk_by_type("LASt").unwrap().unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the last chunk");
+        assert!(lazy.chunk_by_type("TeSt").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_lazy_into_png() {
+        let bytes = testing_bytes();
+        let lazy = LazyPng::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(lazy.into_png().unwrap().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_lazy_truncated() {
+        let mut bytes = testing_bytes();
+        bytes.truncate(bytes.len() - 1);
+        assert!(LazyPng::new(Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_lazy_bad_crc_on_load() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let mut lazy = LazyPng::new(Cursor::new(bytes)).unwrap();
+        assert!(lazy.load_chunk(0).is_ok());
+        let err = lazy.load_chunk(2).unwrap_err();
+        assert!(matches!(err, Error::Parse(ParseError { offset: 70, chunk_index: 2, .. })));
+        assert!(matches!(err.kind(), Error::BadCrc { .. }));
+    }
+}
@@ -1,10 +1,170 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod error;
 pub mod chunk_type;
+pub mod standard;
 pub mod chunk;
 pub mod crc;
+pub mod ihdr;
 pub mod png;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod parse;
+#[cfg(feature = "std")]
+pub mod lazy_png;
+pub mod png_ref;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "flate")]
+pub mod idat;
+#[cfg(feature = "flate")]
+pub mod image;
+#[cfg(feature = "flate")]
+pub mod decode;
+#[cfg(feature = "flate")]
+pub mod encode;
+#[cfg(feature = "flate")]
+pub mod scanline;
+#[cfg(feature = "flate")]
+pub mod quantize;
+#[cfg(feature = "flate")]
+pub mod optimize;
+#[cfg(feature = "flate")]
+pub mod convert;
+#[cfg(feature = "flate")]
+pub mod transform;
+#[cfg(feature = "flate")]
+pub mod watermark;
+#[cfg(feature = "image")]
+pub mod interop;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod repair;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod duplicates;
+#[cfg(feature = "std")]
+pub mod palette;
+#[cfg(feature = "std")]
+pub mod text;
+#[cfg(feature = "std")]
+pub mod xmp;
+#[cfg(feature = "std")]
+pub mod bkgd;
+#[cfg(feature = "std")]
+pub mod trns;
+#[cfg(feature = "std")]
+pub mod known;
+#[cfg(feature = "std")]
+pub mod sbit;
+#[cfg(feature = "std")]
+pub mod hist;
+#[cfg(feature = "std")]
+pub mod splt;
+#[cfg(feature = "std")]
+pub mod extensions;
+#[cfg(feature = "std")]
+pub mod metadata;
+#[cfg(feature = "std")]
+pub mod apng;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "std")]
+pub mod phys;
+#[cfg(feature = "std")]
+pub mod colorspace;
+#[cfg(feature = "flate")]
+pub mod iccp;
+#[cfg(feature = "std")]
+pub mod exif;
+#[cfg(feature = "std")]
+pub mod stego;
+#[cfg(feature = "std")]
+pub mod scan;
+#[cfg(feature = "sign")]
+pub mod sign;
+#[cfg(feature = "digest")]
+pub mod digest;
+#[cfg(feature = "digest")]
+pub mod manifest;
+#[cfg(feature = "std")]
+pub mod fuzz;
+#[cfg(feature = "serde")]
+pub mod serialize;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+pub use crate::error::{Error, ParseError};
 
 pub mod prelude {
     pub use crate::png::Png;
+    pub use crate::error::Error;
     pub use crate::chunk::Chunk;
-    pub use crate::chunk_type::ChunkType;
+    pub use crate::chunk_type::{ChunkType, ChunkTypeQuery};
+    pub use crate::standard::{Strictness, TypeViolation};
+    pub use crate::png_ref::{ChunkRef, PngRef};
+    pub use crate::ihdr::{ColorType, Ihdr};
+    pub use crate::parse::{CrcMode, Limits, ParseOptions};
+    #[cfg(feature = "std")]
+    pub use self::with_std::*;
+
+    #[cfg(feature = "std")]
+    mod with_std {
+        pub use crate::io::{ChunkDataWriter, ChunkReader, ChunkWriter};
+        pub use crate::lazy_png::LazyPng;
+        pub use crate::repair::RepairOptions;
+        pub use crate::builder::{ChunkBuilder, PngBuilder};
+        pub use crate::duplicates::DedupeStrategy;
+        pub use crate::palette::{Palette, Rgba};
+        pub use crate::text::TextChunk;
+        pub use crate::time::Timestamp;
+        pub use crate::phys::{PixelDims, Unit};
+        pub use crate::colorspace::{Chromaticities, RenderingIntent};
+        pub use crate::exif::Exif;
+        pub use crate::bkgd::Background;
+        pub use crate::trns::Transparency;
+        pub use crate::known::{ChunkRegistry, KnownChunk, TypedChunk};
+        pub use crate::metadata::{CopyPolicy, StripPolicy};
+        pub use crate::apng::{AnimationControl, Apng, BlendOp, DisposeOp, Frame, FrameControl, FrameData};
+        pub use crate::sbit::SignificantBits;
+        pub use crate::hist::Histogram;
+        pub use crate::splt::{SuggestedColor, SuggestedPalette};
+        pub use crate::extensions::{
+            Equation, GifControl, ImageOffset, OffsetUnit, PhysicalScale, PixelCalibration, ScaleUnit, StereoMode,
+        };
+        pub use crate::stego::{Compression, CoverChunk, EmbedMethod, EmbedOptions, FileInfo};
+        pub use crate::scan::{Anomaly, Report};
+        pub use crate::check::{CheckReport, ChunkCheck, Finding};
+        #[cfg(feature = "flate")]
+        pub use crate::image::{Image, Rgba8Image};
+        #[cfg(feature = "flate")]
+        pub use crate::encode::{CompressionLevel, EncodeOptions};
+        #[cfg(feature = "flate")]
+        pub use crate::scanline::Scanline;
+        #[cfg(feature = "flate")]
+        pub use crate::quantize::DitherMode;
+        #[cfg(feature = "flate")]
+        pub use crate::optimize::OptLevel;
+        #[cfg(feature = "flate")]
+        pub use crate::transform::ResizeFilter;
+        #[cfg(feature = "flate")]
+        pub use crate::watermark::Corner;
+        #[cfg(feature = "sign")]
+        pub use crate::sign::SignScope;
+        #[cfg(feature = "digest")]
+        pub use crate::digest::{Algo, DigestScope};
+        #[cfg(feature = "digest")]
+        pub use crate::manifest::{DataStore, Manifest, ManifestEntry};
+        pub use crate::filter::{FilterStrategy, FilterType, Heuristic};
+    }
 }
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::digest::{self, Algo};
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+/// Where `Png::from_manifest` gets chunk data from, keyed by the hex
+/// hash a `Manifest` records for it.
+pub trait DataStore {
+    fn get(&self, hash: &str) -> Option<Vec<u8>>;
+
+    fn put(&mut self, hash: String, data: Vec<u8>);
+}
+
+impl DataStore for HashMap<String, Vec<u8>> {
+    fn get(&self, hash: &str) -> Option<Vec<u8>> {
+        HashMap::get(self, hash).cloned()
+    }
+
+    fn put(&mut self, hash: String, data: Vec<u8>) {
+        self.insert(hash, data);
+    }
+}
+
+/// One chunk of a `Manifest`. `length` and `crc` are the fields as
+/// stored, so a chunk with a wrong one comes back the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManifestEntry {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+    /// Hex hash of the data.
+    pub hash: String,
+}
+
+/// The layout of a `Png` without its data: every chunk in order with
+/// the hash of its data, and the trailer's hash if there is one.
+/// Two files can be diffed by their manifests, and a file rebuilt from
+/// its manifest and a `DataStore`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+    pub algo: Algo,
+    pub chunks: Vec<ManifestEntry>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trailer: Option<String>,
+}
+
+impl Manifest {
+    /// Indices of the chunks that differ from `other` at the same place,
+    /// and of those only one of them has.
+    pub fn diff(&self, other: &Manifest) -> Vec<usize> {
+        let len = self.chunks.len().max(other.chunks.len());
+        (0..len).filter(|&n| self.chunks.get(n) != other.chunks.get(n)).collect()
+    }
+
+    /// # Errors
+    /// returns an Error if serializing fails.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Invalid(e.to_string()))
+    }
+
+    /// # Errors
+    /// returns an Error if `json` is not a manifest.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Manifest, Error> {
+        serde_json::from_str(json).map_err(|e| Error::Invalid(e.to_string()))
+    }
+}
+
+fn hex_hash(algo: Algo, data: &[u8]) -> String {
+    digest::hash(algo, data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Gets the data for `hash` and checks it hashes to that.
+fn fetch(store: &impl DataStore, algo: Algo, hash: &str) -> Result<Vec<u8>, Error> {
+    let Some(data) = store.get(hash) else {
+        bail!("no data for hash {}", hash);
+    };
+    if hex_hash(algo, &data) != hash {
+        bail!("data for hash {} does not match it", hash);
+    }
+    Ok(data)
+}
+
+impl Png {
+    /// The manifest of the file, hashing with sha256.
+    pub fn to_manifest(&self) -> Manifest {
+        self.to_manifest_with(Algo::default())
+    }
+
+    /// Like `to_manifest` with `algo` as the hash.
+    pub fn to_manifest_with(&self, algo: Algo) -> Manifest {
+        let chunks = self
+            .iter()
+            .map(|c| ManifestEntry {
+                chunk_type: *c.chunk_type(),
+                length: c.length(),
+                crc: c.crc(),
+                hash: hex_hash(algo, c.data()),
+            })
+            .collect();
+        let trailer = Some(self.trailer()).filter(|t| !t.is_empty()).map(|t| hex_hash(algo, t));
+        Manifest { algo, chunks, trailer }
+    }
+
+    /// Puts the data of every chunk and the trailer in `store`,
+    /// returning the manifest to get them back with.
+    pub fn export_to(&self, store: &mut impl DataStore) -> Manifest {
+        let manifest = self.to_manifest();
+        for (c, e) in self.iter().zip(&manifest.chunks) {
+            store.put(e.hash.clone(), c.data().to_vec());
+        }
+        if let Some(hash) = &manifest.trailer {
+            store.put(hash.clone(), self.trailer().to_vec());
+        }
+        manifest
+    }
+
+    /// Rebuilds a file from `manifest` with the data in `store`.
+    ///
+    /// # Errors
+    /// returns an Error if `store` has no data for a hash, or data that
+    /// does not match it.
+    pub fn from_manifest(manifest: &Manifest, store: &impl DataStore) -> Result<Png, Error> {
+        let mut chunks = Vec::with_capacity(manifest.chunks.len());
+        for e in &manifest.chunks {
+            let data = fetch(store, manifest.algo, &e.hash)?;
+            chunks.push(Chunk::from_parts(e.length, e.chunk_type, data, e.crc));
+        }
+        let mut png = Png::from_chunks(chunks);
+        if let Some(hash) = &manifest.trailer {
+            png.set_trailer(fetch(store, manifest.algo, hash)?);
+        }
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_trailer(b"after".to_vec());
+        let mut store = HashMap::new();
+        let manifest = png.export_to(&mut store);
+        assert_eq!(manifest, png.to_manifest());
+        assert_eq!(manifest.chunks.len(), png.chunks().len());
+        assert_eq!(manifest.chunks[0].chunk_type, ChunkType::IHDR);
+        assert_eq!(manifest.chunks[0].length, 13);
+        assert_eq!(store.len(), png.chunks().len() + 1);
+
+        let back = Png::from_manifest(&manifest, &store).unwrap();
+        assert_eq!(back.as_bytes(), png.as_bytes());
+
+        let blake = png.to_manifest_with(Algo::Blake3);
+        assert_ne!(blake.chunks[0].hash, manifest.chunks[0].hash);
+        assert!(Png::from_manifest(&blake, &store).is_err());
+    }
+
+    #[test]
+    fn test_manifest_errors_and_diff() {
+        let png = Png::try_from(IMG).unwrap();
+        let mut store = HashMap::new();
+        let manifest = png.export_to(&mut store);
+
+        let mut edited = png.clone();
+        edited.insert_before_type("IEND", Chunk::new(ChunkType::TIME, vec![0; 7])).unwrap();
+        let iend = png.chunks().len() - 1;
+        assert_eq!(manifest.diff(&edited.to_manifest()), vec![iend, iend + 1]);
+        assert!(manifest.diff(&manifest).is_empty());
+
+        let missing = edited.to_manifest();
+        assert!(Png::from_manifest(&missing, &store).is_err());
+
+        let idat = &manifest.chunks[1].hash;
+        store.insert(idat.clone(), vec![1, 2, 3]);
+        assert!(Png::from_manifest(&manifest, &store).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_manifest_json() {
+        let manifest = Png::try_from(IMG).unwrap().to_manifest();
+        let json = manifest.to_json().unwrap();
+        assert!(json.contains(r#""algo": "sha256""#));
+        assert!(json.contains(r#""type": "IHDR""#));
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+        assert!(Manifest::from_json("{}").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::png::Png;
+use crate::xmp::XMP_KEYWORD;
+
+/// Which kinds of metadata `Png::strip_metadata` removes. Critical
+/// chunks and tRNS are always kept, since the image depends on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StripPolicy {
+    /// tEXt, zTXt and iTXt, apart from XMP.
+    pub text: bool,
+    /// tIME.
+    pub time: bool,
+    /// eXIf and the XMP iTXt.
+    pub exif_xmp: bool,
+    /// iCCP, sRGB, gAMA, cHRM, cICP, mDCv and cLLi. Removing these can
+    /// change how colours are shown.
+    pub color_profile: bool,
+    /// Ancillary chunks this crate does not know, like private ones.
+    pub unknown_ancillary: bool,
+    /// Unknown ancillary chunks without the safe to copy bit, which
+    /// depend on the image data. Like for editors in the spec, the bit
+    /// only counts for chunks this crate does not know.
+    pub unsafe_to_copy: bool,
+}
+
+impl StripPolicy {
+    /// Removes every category, colour profiles too.
+    pub fn all() -> StripPolicy {
+        StripPolicy { color_profile: true, ..StripPolicy::default() }
+    }
+
+    /// Removes nothing, to build on with struct update syntax.
+    pub fn none() -> StripPolicy {
+        StripPolicy {
+            text: false,
+            time: false,
+            exif_xmp: false,
+            color_profile: false,
+            unknown_ancillary: false,
+            unsafe_to_copy: false,
+        }
+    }
+
+    /// Whether `chunk` falls in a category this policy removes.
+    fn strips(&self, chunk: &Chunk) -> bool {
+        match Category::of(chunk) {
+            Some(Category::Text) => self.text,
+            Some(Category::Time) => self.time,
+            Some(Category::ExifXmp) => self.exif_xmp,
+            Some(Category::ColorProfile) => self.color_profile,
+            Some(Category::Unknown) => {
+                self.unknown_ancillary || (self.unsafe_to_copy && !chunk.chunk_type().is_safe_to_copy())
+            }
+            Some(Category::Other) | None => false,
+        }
+    }
+}
+
+impl Default for StripPolicy {
+    /// Removes everything that can say who made the image, where or when,
+    /// and keeps colour profiles so it looks the same.
+    fn default() -> Self {
+        StripPolicy {
+            text: true,
+            time: true,
+            exif_xmp: true,
+            color_profile: false,
+            unknown_ancillary: true,
+            unsafe_to_copy: true,
+        }
+    }
+}
+
+/// Which kinds of metadata `Png::copy_metadata_from` copies. Critical
+/// chunks and APNG frame chunks are never copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyPolicy {
+    /// tEXt, zTXt and iTXt, apart from XMP.
+    pub text: bool,
+    /// tIME.
+    pub time: bool,
+    /// eXIf and the XMP iTXt.
+    pub exif_xmp: bool,
+    /// iCCP, sRGB, gAMA, cHRM, cICP, mDCv and cLLi.
+    pub color_profile: bool,
+    /// Other standard ancillary chunks, like pHYs, bKGD and tRNS. The ones
+    /// tied to the sample format or palette are only copied if both images
+    /// share them.
+    pub other: bool,
+    /// Unknown ancillary chunks with the safe to copy bit.
+    pub unknown_ancillary: bool,
+    /// Unknown ancillary chunks without the safe to copy bit. Even then
+    /// they are only copied if both images have the same IHDR and PLTE.
+    pub unsafe_to_copy: bool,
+}
+
+impl CopyPolicy {
+    /// Copies every category, unsafe to copy chunks too.
+    pub fn all() -> CopyPolicy {
+        CopyPolicy { unsafe_to_copy: true, ..CopyPolicy::default() }
+    }
+
+    /// Copies nothing, to build on with struct update syntax.
+    pub fn none() -> CopyPolicy {
+        CopyPolicy {
+            text: false,
+            time: false,
+            exif_xmp: false,
+            color_profile: false,
+            other: false,
+            unknown_ancillary: false,
+            unsafe_to_copy: false,
+        }
+    }
+
+    /// Whether `chunk` falls in a category this policy copies, not yet
+    /// looking at the headers.
+    fn copies(&self, chunk: &Chunk) -> bool {
+        match Category::of(chunk) {
+            Some(Category::Text) => self.text,
+            Some(Category::Time) => self.time,
+            Some(Category::ExifXmp) => self.exif_xmp,
+            Some(Category::ColorProfile) => self.color_profile,
+            Some(Category::Other) => self.other && !is_animation(chunk.chunk_type()),
+            Some(Category::Unknown) if chunk.chunk_type().is_safe_to_copy() => self.unknown_ancillary,
+            Some(Category::Unknown) => self.unsafe_to_copy,
+            None => false,
+        }
+    }
+}
+
+impl Default for CopyPolicy {
+    /// Copies everything the spec lets an editor copy after changing the
+    /// image data.
+    fn default() -> Self {
+        CopyPolicy {
+            text: true,
+            time: true,
+            exif_xmp: true,
+            color_profile: true,
+            other: true,
+            unknown_ancillary: true,
+            unsafe_to_copy: false,
+        }
+    }
+}
+
+fn is_animation(chunk_type: &ChunkType) -> bool {
+    matches!(*chunk_type, ChunkType::ACTL | ChunkType::FCTL | ChunkType::FDAT)
+}
+
+/// Standard chunks whose values are samples, palette indices or a
+/// colour space, so only hold for the same colour type, bit depth and
+/// palette.
+fn depends_on_format(chunk_type: &ChunkType) -> bool {
+    matches!(
+        *chunk_type,
+        ChunkType::TRNS
+            | ChunkType::BKGD
+            | ChunkType::SBIT
+            | ChunkType::HIST
+            | ChunkType::ICCP
+            | ChunkType::PCAL
+    )
+}
+
+/// Where the chunk at `index` of `source` goes in another file: before
+/// the first of these, so ordering rules keep holding.
+fn anchors(source: &Png, index: usize) -> &'static [ChunkType] {
+    let mut next = source.chunks()[index..].iter().map(|c| *c.chunk_type());
+    match next.find(|t| *t == ChunkType::PLTE || *t == ChunkType::IDAT) {
+        Some(ChunkType::PLTE) => &[ChunkType::PLTE, ChunkType::IDAT, ChunkType::IEND],
+        Some(_) => &[ChunkType::IDAT, ChunkType::IEND],
+        None => &[ChunkType::IEND],
+    }
+}
+
+/// The metadata categories shared by `StripPolicy` and `CopyPolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Text,
+    Time,
+    ExifXmp,
+    ColorProfile,
+    /// Other standard ancillary chunks, like pHYs, bKGD and tRNS.
+    Other,
+    Unknown,
+}
+
+impl Category {
+    /// `None` for critical chunks.
+    fn of(chunk: &Chunk) -> Option<Category> {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical() {
+            return None;
+        }
+        Some(match *chunk_type {
+            ChunkType::ITXT if is_xmp(chunk) => Category::ExifXmp,
+            ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT => Category::Text,
+            ChunkType::TIME => Category::Time,
+            ChunkType::EXIF => Category::ExifXmp,
+            ChunkType::ICCP
+            | ChunkType::SRGB
+            | ChunkType::GAMA
+            | ChunkType::CHRM
+            | ChunkType::CICP
+            | ChunkType::MDCV
+            | ChunkType::CLLI => Category::ColorProfile,
+            _ if chunk_type.is_standard() => Category::Other,
+            _ => Category::Unknown,
+        })
+    }
+}
+
+fn is_xmp(chunk: &Chunk) -> bool {
+    chunk.data().strip_prefix(XMP_KEYWORD.as_bytes()).is_some_and(|rest| rest.first() == Some(&0))
+}
+
+impl Png {
+    /// Removes the metadata `policy` selects, returning the removed
+    /// chunks in file order.
+    pub fn strip_metadata(&mut self, policy: StripPolicy) -> Vec<Chunk> {
+        let (removed, kept) = std::mem::take(self.chunks_mut()).into_iter().partition(|c| policy.strips(c));
+        *self.chunks_mut() = kept;
+        removed
+    }
+
+    /// Copies the metadata `policy` selects from `other`, for example
+    /// after the pixels were re-encoded by another tool. Chunks of a type
+    /// that is copied replace this image's ones, and any copied colour
+    /// profile chunk replaces the whole profile, so two never get mixed.
+    /// Returns how many chunks were copied.
+    ///
+    /// Chunks tied to the sample format, like tRNS, bKGD and iCCP, are
+    /// skipped unless both images have the same colour type, bit depth
+    /// and palette.
+    ///
+    /// # Errors
+    /// returns an Error if either image has no valid IHDR.
+    pub fn copy_metadata_from(
+        &mut self,
+        other: &Png,
+        policy: CopyPolicy,
+    ) -> Result<usize, Error> {
+        let (ours, theirs) = (self.header()?, other.header()?);
+        let palette = |png: &Png| png.chunk_by_type("PLTE").map(|c| c.data().to_vec());
+        let same_palette = palette(self) == palette(other);
+        let same_format = same_palette
+            && ours.color_type() == theirs.color_type()
+            && ours.bit_depth() == theirs.bit_depth();
+        let same_header = same_palette && ours == theirs;
+
+        let copied: Vec<(usize, &Chunk)> = other
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| policy.copies(c))
+            .filter(|(_, c)| !depends_on_format(c.chunk_type()) || same_format)
+            .filter(|(_, c)| c.chunk_type().is_standard() || c.chunk_type().is_safe_to_copy() || same_header)
+            .collect();
+
+        let replaced: Vec<ChunkType> = copied.iter().map(|(_, c)| *c.chunk_type()).collect();
+        let profile = |c: &Chunk| Category::of(c) == Some(Category::ColorProfile);
+        let new_profile = copied.iter().any(|(_, c)| profile(c));
+        self.chunks_mut().retain(|c| !(replaced.contains(c.chunk_type()) || new_profile && profile(c)));
+
+        for (index, chunk) in &copied {
+            let before = anchors(other, *index);
+            let at = self
+                .chunks()
+                .iter()
+                .position(|c| before.contains(c.chunk_type()))
+                .unwrap_or(self.chunks().len());
+            self.chunks_mut().insert(at, (*chunk).clone());
+        }
+        Ok(copied.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorspace::RenderingIntent;
+    use crate::exif::Exif;
+    use crate::ihdr::{ColorType, Ihdr};
+    use crate::time::Timestamp;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn tagged() -> Png {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_text("Author", "someone").unwrap();
+        png.set_xmp("<x/>");
+        png.set_modified_time(Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap());
+        png.set_exif(&Exif::new(b"MM\0\x2a\0\0\0\x08\0\0\0\0\0\0".to_vec()).unwrap());
+        png.set_gamma(0.45455).unwrap();
+        png.insert_before_type("IEND", Chunk::new("prIv".parse().unwrap(), vec![1])).unwrap();
+        png.insert_before_type("IDAT", Chunk::new(ChunkType::TRNS, vec![0])).unwrap();
+        png
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_strip_default() {
+        let mut png = tagged();
+        let removed = png.strip_metadata(StripPolicy::default());
+        assert!(removed.iter().any(|c| *c.chunk_type() == ChunkType::EXIF));
+        assert!(png.text_entries().is_empty());
+        assert_eq!(png.xmp(), None);
+        assert_eq!(png.modified_time().unwrap(), None);
+        assert!(png.chunk_by_type("prIv").is_none());
+        assert_eq!(types(&png), ["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_strip_selected() {
+        let mut png = tagged();
+        png.strip_metadata(StripPolicy { text: true, ..StripPolicy::none() });
+        assert!(png.text("Author").is_none());
+        assert_eq!(png.xmp().unwrap(), "<x/>");
+        assert!(png.exif().unwrap().is_some());
+
+        png.strip_metadata(StripPolicy { exif_xmp: true, ..StripPolicy::none() });
+        assert_eq!(png.xmp(), None);
+        assert!(png.exif().unwrap().is_none());
+
+        let mut all = tagged();
+        all.strip_metadata(StripPolicy::all());
+        assert_eq!(types(&all), ["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+        assert!(tagged().strip_metadata(StripPolicy::none()).is_empty());
+
+        let mut private = tagged();
+        private.insert_before_type("IEND", Chunk::new("prIV".parse().unwrap(), vec![2])).unwrap();
+        let removed = private.strip_metadata(StripPolicy { unsafe_to_copy: true, ..StripPolicy::none() });
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "prIV");
+        assert!(private.chunk_by_type("prIv").is_some());
+    }
+
+    #[test]
+    fn test_copy_metadata() {
+        let source = tagged();
+        let mut target = Png::try_from(IMG).unwrap();
+        target.set_text("Author", "nobody").unwrap();
+        target.set_srgb(RenderingIntent::Perceptual);
+
+        let copied = target.copy_metadata_from(&source, CopyPolicy::default()).unwrap();
+        assert_eq!(copied, 7);
+        assert_eq!(target.text("Author"), Some("someone".to_string()));
+        assert_eq!(target.chunks_of_type("tEXt").count(), 1);
+        assert_eq!(target.xmp().unwrap(), "<x/>");
+        assert!(target.exif().unwrap().is_some());
+        assert_eq!(target.srgb().unwrap(), None);
+        assert_eq!(target.chromaticities().unwrap(), None);
+        assert_eq!(types(&target), types(&source));
+
+        let mut unsafe_private = tagged();
+        unsafe_private.insert_before_type("IEND", Chunk::new("prIV".parse().unwrap(), vec![2])).unwrap();
+        let mut target = Png::try_from(IMG).unwrap();
+        target.copy_metadata_from(&unsafe_private, CopyPolicy::default()).unwrap();
+        assert!(target.chunk_by_type("prIV").is_none());
+        target.copy_metadata_from(&unsafe_private, CopyPolicy::all()).unwrap();
+        assert!(target.chunk_by_type("prIV").is_some());
+    }
+
+    #[test]
+    fn test_copy_metadata_other_format() {
+        let source = tagged();
+        let mut rgb = Png::try_from(IMG).unwrap();
+        rgb.remove_chunks("PLTE");
+        rgb.set_header(&Ihdr::new(225, 225, 8, ColorType::Rgb).unwrap());
+        rgb.copy_metadata_from(&source, CopyPolicy::all()).unwrap();
+        assert!(rgb.chunk_by_type("tRNS").is_none());
+        assert!(rgb.chunk_by_type("prIv").is_some());
+        assert!(rgb.gamma().unwrap().is_some());
+
+        let mut target = Png::try_from(IMG).unwrap();
+        let copied = target.copy_metadata_from(&source, CopyPolicy { text: true, ..CopyPolicy::none() });
+        assert_eq!(copied.unwrap(), 1);
+        assert!(target.copy_metadata_from(&Png::from_chunks(vec![]), CopyPolicy::all()).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::error::Error;
+use crate::png::Png;
+use crate::png_ref::ChunkRef;
+
+/// A PNG file mapped into memory. Chunks are checked once when the
+/// file is opened and handed out as `ChunkRef`s pointing into the map,
+/// so their data is only paged in when it is touched.
+pub struct MmapPng {
+    map: Mmap,
+    spans: Vec<Range<usize>>,
+}
+
+impl MmapPng {
+    /// Maps the file at `path` and checks every chunk.
+    ///
+    /// # Errors
+    /// returns an Error if the file can not be mapped, or the header
+    /// or any chunk is invalid.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MmapPng, Error> {
+        let file = File::open(path)?;
+        // SAFETY: the map is read only. Like any mmap, another process
+        // truncating the file while it is mapped is undefined behaviour,
+        // that is the price of not reading the file.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < 8 || map[..8] != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut spans = Vec::new();
+        let mut start = 8;
+        while start < map.len() {
+            let (_, rest) = ChunkRef::parse_prefix(&map[start..])
+                .map_err(|e| e.at(start as u64, spans.len()))?;
+            let end = map.len() - rest.len();
+            spans.push(start..end);
+            start = end;
+        }
+
+        Ok(MmapPng { map, spans })
+    }
+
+    /// Iterates over the chunks in file order.
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkRef<'_>> {
+        self.spans.iter().map(|r| ChunkRef::from_checked(&self.map[r.clone()]))
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<ChunkRef<'_>> {
+        self.chunks().find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+    }
+
+    /// Copies every chunk into an owned `Png`.
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks().map(|c| c.to_owned()).collect())
+    }
+}
+
+impl Png {
+    /// Memory maps the file at `path`, see `MmapPng`.
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<MmapPng, Error> {
+        MmapPng::open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_open_mmap() {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ];
+        let bytes = Png::from_chunks(chunks).as_bytes();
+        let path = std::env::temp_dir().join(format!("nice_pics_mmap_{}.png", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let png = Png::open_mmap(&path).unwrap();
+        assert_eq!(png.chunks().count(), 2);
+        assert_eq!(png.chunk_by_type("LASt").unwrap().data(), b"I am the last chunk");
+        assert_eq!(png.to_png().as_bytes(), bytes);
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Png::open_mmap(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
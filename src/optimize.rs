@@ -0,0 +1,210 @@
+use crate::chunk::Chunk;
+use crate::duplicates::DedupeStrategy;
+use crate::encode::{deflate_idat, filter_image, CompressionLevel, EncodeOptions};
+use crate::error::Error;
+use crate::filter::{FilterStrategy, Heuristic};
+use crate::ihdr::ColorType;
+use crate::png::Png;
+
+/// How hard `Png::optimize` tries, trading time for size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OptLevel {
+    /// Keeps the filtering and only deflates again at the best level.
+    Fast,
+    /// Also tries adaptive filtering and no filtering.
+    #[default]
+    Default,
+    /// Tries every filter strategy.
+    Max,
+}
+
+impl OptLevel {
+    /// The filter strategies tried, `None` keeps the rows as filtered.
+    fn strategies(&self) -> Option<&'static [FilterStrategy]> {
+        match self {
+            OptLevel::Fast => None,
+            OptLevel::Default => {
+                Some(&[FilterStrategy::Adaptive(Heuristic::MinSumOfAbsDiff), FilterStrategy::None])
+            }
+            OptLevel::Max => Some(&[
+                FilterStrategy::Adaptive(Heuristic::MinSumOfAbsDiff),
+                FilterStrategy::None,
+                FilterStrategy::Sub,
+                FilterStrategy::Up,
+                FilterStrategy::Average,
+                FilterStrategy::Paeth,
+            ]),
+        }
+    }
+}
+
+impl Png {
+    /// Makes the file smaller without changing the image or its metadata:
+    /// removes duplicate chunks, a PLTE or tRNS no decoder uses, and
+    /// compresses the image data again, trying the filter strategies of
+    /// `level` and keeping whichever is smallest. The image data is only
+    /// replaced if it got smaller.
+    /// Returns how many bytes were saved.
+    ///
+    /// # Errors
+    /// returns an Error if the header or image data is invalid.
+    pub fn optimize(&mut self, level: OptLevel) -> Result<u64, Error> {
+        let before = self.file_size();
+        let ihdr = self.header()?;
+
+        self.dedupe_chunks(DedupeStrategy::KeepFirst);
+        remove_copies(self);
+        self.remove_unused_palette(ihdr.color_type());
+        if self.chunk_by_type("tRNS").is_some() {
+            self.remove_unused_trns(ihdr.color_type())?;
+        }
+
+        let options = EncodeOptions { level: CompressionLevel::Best, ..Default::default() };
+        let mut best = deflate_idat(&self.idat_data()?, &options)?;
+        if let Some(strategies) = level.strategies() {
+            let pixels = self.decode_pixels()?;
+            for strategy in strategies {
+                let idat = deflate_idat(&filter_image(&ihdr, pixels.data(), *strategy), &options)?;
+                if size(&idat) < size(&best) {
+                    best = idat;
+                }
+            }
+        }
+        let old: Vec<Vec<u8>> = self.chunks_of_type("IDAT").map(|c| c.data().to_vec()).collect();
+        if size(&best) < size(&old) {
+            self.replace_idat(best);
+        }
+        Ok(before - self.file_size())
+    }
+
+    /// PLTE is only used by indexed images, for truecolour it is a
+    /// suggestion decoders do not need and greyscale does not allow it.
+    fn remove_unused_palette(&mut self, color_type: ColorType) {
+        if color_type != ColorType::Indexed {
+            self.remove_chunks("PLTE");
+        }
+    }
+
+    /// Drops tRNS entries that change nothing: opaque palette entries at
+    /// the end, a colour key no pixel has, or any tRNS for colour types
+    /// that carry alpha themselves.
+    fn remove_unused_trns(&mut self, color_type: ColorType) -> Result<(), Error> {
+        let unused = match color_type {
+            ColorType::GrayscaleAlpha | ColorType::Rgba => true,
+            ColorType::Indexed => {
+                let trns = self.chunk_by_type_mut("tRNS").unwrap();
+                let len = trns.data().iter().rposition(|a| *a != 255).map_or(0, |n| n + 1);
+                if len > 0 && len < trns.length() as usize {
+                    let data = trns.data()[..len].to_vec();
+                    trns.set_data(data);
+                }
+                len == 0
+            }
+            ColorType::Grayscale | ColorType::Rgb => {
+                let image = self.decode_pixels()?;
+                let keyed = (0..image.height())
+                    .any(|y| (0..image.width()).any(|x| image.pixel_rgba16(x, y).is_ok_and(|p| p[3] == 0)));
+                !keyed
+            }
+        };
+        if unused {
+            self.remove_chunks("tRNS");
+        }
+        Ok(())
+    }
+}
+
+/// Removes ancillary chunks that are exact copies of an earlier one.
+fn remove_copies(png: &mut Png) {
+    let chunks = std::mem::take(png.chunks_mut());
+    let mut kept: Vec<Chunk> = Vec::with_capacity(chunks.len());
+    for c in chunks {
+        let copy = !c.chunk_type().is_critical()
+            && kept.iter().any(|k| k.chunk_type() == c.chunk_type() && k.data() == c.data());
+        if !copy {
+            kept.push(c);
+        }
+    }
+    *png.chunks_mut() = kept;
+}
+
+/// Total data size of a set of IDAT chunks.
+fn size(idat: &[Vec<u8>]) -> usize {
+    idat.iter().map(|d| d.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn encode(pixels: &[u8], width: u32, color_type: ColorType, options: EncodeOptions) -> Png {
+        let height = (pixels.len() / (width as usize * color_type.channels() as usize)) as u32;
+        Png::from_pixels(width, height, color_type, pixels, options).unwrap()
+    }
+
+    #[test]
+    fn test_optimize_shrinks() {
+        let pixels: Vec<u8> = (0..64 * 64).map(|n| ((n % 64) * 2 + (n / 64) * 3) as u8).collect();
+        let stored = EncodeOptions {
+            level: CompressionLevel::Level(0),
+            filter: FilterStrategy::None,
+            ..Default::default()
+        };
+        let mut png = encode(&pixels, 64, ColorType::Grayscale, stored);
+        let text = Chunk::new(ChunkType::TEXT, b"a\0b".to_vec());
+        png.insert_after_type("IDAT", text.clone()).unwrap();
+        png.insert_after_type("IDAT", text).unwrap();
+        let before = png.file_size();
+
+        let saved = png.optimize(OptLevel::Default).unwrap();
+        assert_eq!(png.file_size(), before - saved);
+        assert!(png.file_size() * 4 < before);
+        assert_eq!(png.chunks_of_type("tEXt").count(), 1);
+        assert!(png.validate().is_ok());
+        assert_eq!(png.decode_pixels().unwrap().data(), pixels.as_slice());
+    }
+
+    #[test]
+    fn test_optimize_levels() {
+        let mut fast = Png::try_from(IMG).unwrap();
+        let image = fast.decode_pixels().unwrap();
+        let mut max = fast.clone();
+        fast.optimize(OptLevel::Fast).unwrap();
+        max.optimize(OptLevel::Max).unwrap();
+        assert!(max.file_size() <= fast.file_size());
+        assert_eq!(max.decode_pixels().unwrap(), image);
+
+        // already optimal, nothing left to save
+        assert_eq!(max.optimize(OptLevel::Max).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_optimize_unused_palette_and_trns() {
+        let mut rgb = encode(&[1, 2, 3, 4, 5, 6], 2, ColorType::Rgb, EncodeOptions::default());
+        rgb.insert_before_type("IDAT", Chunk::new(ChunkType::PLTE, vec![0; 3])).unwrap();
+        // the key colour is not used by any pixel
+        rgb.insert_before_type("IDAT", Chunk::new(ChunkType::TRNS, vec![0, 9, 0, 9, 0, 9])).unwrap();
+        rgb.optimize(OptLevel::Fast).unwrap();
+        assert!(rgb.chunk_by_type("PLTE").is_none());
+        assert!(rgb.chunk_by_type("tRNS").is_none());
+
+        let mut keyed = encode(&[1, 2, 3, 4, 5, 6], 2, ColorType::Rgb, EncodeOptions::default());
+        keyed.insert_before_type("IDAT", Chunk::new(ChunkType::TRNS, vec![0, 4, 0, 5, 0, 6])).unwrap();
+        keyed.optimize(OptLevel::Fast).unwrap();
+        assert!(keyed.chunk_by_type("tRNS").is_some());
+
+        let palette = Some(vec![0, 0, 0, 255, 255, 255]);
+        let options = EncodeOptions { palette, ..Default::default() };
+        let mut indexed = encode(&[0, 1], 2, ColorType::Indexed, options);
+        indexed.insert_after_type("PLTE", Chunk::new(ChunkType::TRNS, vec![0, 255])).unwrap();
+        indexed.optimize(OptLevel::Fast).unwrap();
+        assert_eq!(indexed.chunk_by_type("tRNS").unwrap().data(), &[0]);
+        indexed.chunk_by_type_mut("tRNS").unwrap().set_data(vec![255]);
+        indexed.optimize(OptLevel::Fast).unwrap();
+        assert!(indexed.chunk_by_type("tRNS").is_none());
+        assert!(indexed.chunk_by_type("PLTE").is_some());
+    }
+}
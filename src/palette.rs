@@ -0,0 +1,291 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::ihdr::ColorType;
+use crate::png::Png;
+
+/// An 8 bit colour with alpha, as stored in PLTE and tRNS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    /// An opaque colour.
+    pub fn rgb(r: u8, g: u8, b: u8) -> Rgba {
+        Rgba { r, g, b, a: 255 }
+    }
+}
+
+impl From<[u8; 4]> for Rgba {
+    fn from(value: [u8; 4]) -> Self {
+        Rgba::new(value[0], value[1], value[2], value[3])
+    }
+}
+
+impl From<Rgba> for [u8; 4] {
+    fn from(value: Rgba) -> Self {
+        [value.r, value.g, value.b, value.a]
+    }
+}
+
+/// The palette of an image, PLTE entries with the alpha from tRNS.
+/// Entries without a tRNS value are opaque.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Palette {
+    entries: Vec<Rgba>,
+}
+
+impl Palette {
+    /// Most entries a palette can have.
+    pub const MAX_ENTRIES: usize = 256;
+
+    /// Creates an empty palette.
+    pub fn new() -> Palette {
+        Palette::default()
+    }
+
+    /// Parses PLTE data and the tRNS data of an indexed image.
+    ///
+    /// # Errors
+    /// returns an Error if `plte` is empty, not a multiple of 3 or over 256
+    /// entries, or `trns` has more values than there are entries.
+    pub fn from_data(plte: &[u8], trns: Option<&[u8]>) -> Result<Palette, Error> {
+        if plte.is_empty() || !plte.len().is_multiple_of(3) || plte.len() > Palette::MAX_ENTRIES * 3 {
+            bail!("invalid PLTE length: {}", plte.len());
+        }
+        let alpha = trns.unwrap_or_default();
+        if alpha.len() > plte.len() / 3 {
+            bail!("tRNS has {} values for {} palette entries", alpha.len(), plte.len() / 3);
+        }
+        let entries = plte
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(n, c)| Rgba::new(c[0], c[1], c[2], alpha.get(n).copied().unwrap_or(255)))
+            .collect();
+        Ok(Palette { entries })
+    }
+
+    pub fn entries(&self) -> &[Rgba] {
+        self.entries.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry at `index`, `None` if there is no such entry.
+    pub fn get(&self, index: u8) -> Option<Rgba> {
+        self.entries.get(index as usize).copied()
+    }
+
+    /// Replaces the entry at `index`.
+    ///
+    /// # Errors
+    /// returns an Error if there is no entry at `index`.
+    pub fn set(&mut self, index: u8, color: Rgba) -> Result<(), Error> {
+        match self.entries.get_mut(index as usize) {
+            Some(e) => *e = color,
+            None => bail!("palette index out of range: {} >= {}", index, self.entries.len()),
+        }
+        Ok(())
+    }
+
+    /// Inserts an entry at `index`, shifting all entries after it.
+    /// Pixels refer to entries by index, so this changes the colour of
+    /// every pixel using a shifted entry.
+    ///
+    /// # Errors
+    /// returns an Error if `index` > number of entries or the palette is full.
+    pub fn insert(&mut self, index: usize, color: Rgba) -> Result<(), Error> {
+        if self.entries.len() >= Palette::MAX_ENTRIES {
+            bail!("palette is full");
+        }
+        if index > self.entries.len() {
+            bail!("index out of range: {} > {}", index, self.entries.len());
+        }
+        self.entries.insert(index, color);
+        Ok(())
+    }
+
+    /// Appends an entry, returning its index.
+    ///
+    /// # Errors
+    /// returns an Error if the palette is full.
+    pub fn push(&mut self, color: Rgba) -> Result<u8, Error> {
+        self.insert(self.entries.len(), color)?;
+        Ok((self.entries.len() - 1) as u8)
+    }
+
+    /// Returns true if any entry is not fully opaque.
+    pub fn has_alpha(&self) -> bool {
+        self.entries.iter().any(|e| e.a != 255)
+    }
+
+    /// The PLTE chunk for this palette.
+    pub fn to_plte(&self) -> Chunk {
+        let data = self.entries.iter().flat_map(|e| [e.r, e.g, e.b]).collect();
+        Chunk::new(ChunkType::PLTE, data)
+    }
+
+    /// The tRNS chunk for this palette, `None` if every entry is opaque.
+    /// Opaque entries at the end are left out, as the spec allows.
+    pub fn to_trns(&self) -> Option<Chunk> {
+        let len = self.entries.iter().rposition(|e| e.a != 255)? + 1;
+        let data = self.entries[..len].iter().map(|e| e.a).collect();
+        Some(Chunk::new(ChunkType::TRNS, data))
+    }
+}
+
+impl Png {
+    /// Parses the PLTE chunk, with alpha from tRNS for indexed images.
+    /// Returns `None` if there is no PLTE chunk.
+    ///
+    /// # Errors
+    /// returns an Error if PLTE or tRNS is invalid.
+    pub fn palette(&self) -> Result<Option<Palette>, Error> {
+        let plte = match self.find_chunk(ChunkType::PLTE)? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        // tRNS is a colour key for the other colour types
+        let trns = match self.header() {
+            Ok(ihdr) if ihdr.color_type() != ColorType::Indexed => None,
+            _ => self.find_chunk(ChunkType::TRNS)?.map(|c| c.data()),
+        };
+        Palette::from_data(plte.data(), trns).map(Some)
+    }
+
+    /// Replaces PLTE, and tRNS for indexed images, with `palette`.
+    /// PLTE stays where it was or goes before the image data,
+    /// tRNS goes directly after it.
+    ///
+    /// # Errors
+    /// returns an Error if the palette is empty, the image is greyscale,
+    /// or the palette has alpha and the image is not indexed.
+    pub fn set_palette(&mut self, palette: &Palette) -> Result<(), Error> {
+        if palette.is_empty() {
+            bail!("empty palette");
+        }
+        let indexed = match self.header().map(|h| h.color_type()) {
+            Ok(ColorType::Grayscale | ColorType::GrayscaleAlpha) => bail!("palette not allowed for greyscale"),
+            Ok(ColorType::Indexed) => true,
+            _ => false,
+        };
+        if !indexed && palette.has_alpha() {
+            bail!("palette alpha needs an indexed image");
+        }
+
+        let chunks = self.chunks_mut();
+        if indexed {
+            chunks.retain(|c| *c.chunk_type() != ChunkType::TRNS);
+        }
+        let at = match chunks.iter().position(|c| *c.chunk_type() == ChunkType::PLTE) {
+            Some(n) => {
+                chunks.retain(|c| *c.chunk_type() != ChunkType::PLTE);
+                n
+            }
+            None => chunks
+                .iter()
+                .position(|c| *c.chunk_type() == ChunkType::IDAT || *c.chunk_type() == ChunkType::IEND)
+                .unwrap_or(chunks.len()),
+        };
+        let new = std::iter::once(palette.to_plte()).chain(palette.to_trns().filter(|_| indexed));
+        chunks.splice(at..at, new);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Ihdr;
+
+    fn indexed_png(plte: &[u8], trns: Option<&[u8]>) -> Png {
+        let mut chunks = vec![Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap().to_chunk()];
+        chunks.push(Chunk::new(ChunkType::PLTE, plte.to_vec()));
+        chunks.extend(trns.map(|t| Chunk::new(ChunkType::TRNS, t.to_vec())));
+        chunks.push(Chunk::new(ChunkType::IDAT, vec![0]));
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_palette_from_png() {
+        let png = indexed_png(&[1, 2, 3, 4, 5, 6], Some(&[128]));
+        let palette = png.palette().unwrap().unwrap();
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette.get(0), Some(Rgba::new(1, 2, 3, 128)));
+        assert_eq!(palette.get(1), Some(Rgba::rgb(4, 5, 6)));
+        assert_eq!(palette.get(2), None);
+
+        assert!(indexed_png(&[1, 2], None).palette().is_err());
+        assert!(indexed_png(&[1, 2, 3], Some(&[0, 0])).palette().is_err());
+        let mut none = indexed_png(&[1, 2, 3], None);
+        none.remove_chunk("PLTE").unwrap();
+        assert_eq!(none.palette().unwrap(), None);
+    }
+
+    #[test]
+    fn test_palette_edit() {
+        let mut palette = Palette::new();
+        assert_eq!(palette.push(Rgba::rgb(0, 0, 0)).unwrap(), 0);
+        palette.insert(0, Rgba::new(9, 9, 9, 0)).unwrap();
+        palette.set(1, Rgba::rgb(255, 0, 0)).unwrap();
+        assert!(palette.set(2, Rgba::rgb(0, 0, 0)).is_err());
+        assert!(palette.insert(3, Rgba::rgb(0, 0, 0)).is_err());
+        assert_eq!(palette.entries(), &[Rgba::new(9, 9, 9, 0), Rgba::rgb(255, 0, 0)]);
+        assert_eq!(palette.to_plte().data(), &[9, 9, 9, 255, 0, 0]);
+        assert_eq!(palette.to_trns().unwrap().data(), &[0]);
+
+        let mut full = Palette::from_data(&[0; 768], None).unwrap();
+        assert!(full.push(Rgba::rgb(0, 0, 0)).is_err());
+        assert!(full.to_trns().is_none());
+        assert_eq!(<[u8; 4]>::from(Rgba::from([1, 2, 3, 4])), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_set_palette() {
+        let mut png = indexed_png(&[1, 2, 3], Some(&[7]));
+        let mut palette = png.palette().unwrap().unwrap();
+        palette.set(0, Rgba::rgb(1, 2, 3)).unwrap();
+        palette.push(Rgba::new(4, 5, 6, 10)).unwrap();
+        png.set_palette(&palette).unwrap();
+        assert_eq!(types(&png), vec!["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+        assert_eq!(png.palette().unwrap().unwrap(), palette);
+        assert_eq!(png.chunk_by_type("tRNS").unwrap().data(), &[255, 10]);
+
+        // opaque again, so tRNS goes away
+        palette.set(1, Rgba::rgb(4, 5, 6)).unwrap();
+        png.remove_chunk("PLTE").unwrap();
+        png.set_palette(&palette).unwrap();
+        assert_eq!(types(&png), vec!["IHDR", "PLTE", "IDAT", "IEND"]);
+
+        assert!(png.set_palette(&Palette::new()).is_err());
+        let mut gray = png.clone();
+        gray.set_header(&Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap());
+        assert!(gray.set_palette(&palette).is_err());
+
+        // a suggested palette for truecolour can not carry alpha
+        let mut rgb = png.clone();
+        rgb.set_header(&Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap());
+        rgb.set_palette(&palette).unwrap();
+        palette.set(0, Rgba::new(0, 0, 0, 0)).unwrap();
+        assert!(rgb.set_palette(&palette).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::error::Error;
+
+/// What to do with a chunk whose crc does not match its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrcMode {
+    /// Fail the whole parse.
+    #[default]
+    Strict,
+    /// Keep the chunk with its crc as read,
+    /// `Chunk::has_valid_crc` returns false for it.
+    Warn,
+    /// Drop the chunk and carry on.
+    Skip,
+}
+
+/// Caps on what parsing and decoding accept, so a crafted length
+/// field, image header or zlib bomb can not exhaust memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest data length of a single chunk.
+    pub max_chunk_size: u32,
+    /// Most chunks in one file.
+    pub max_chunks: usize,
+    /// Largest inflated size of the image data, a compressed text chunk
+    /// or an embedded payload. Decoding also checks the image data the
+    /// header asks for against it before allocating any pixel buffer,
+    /// and so do the APNG canvas and sprite sheet.
+    pub max_decompressed: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { max_chunk_size: 1 << 28, max_chunks: 1 << 20, max_decompressed: 1 << 30 }
+    }
+}
+
+impl Limits {
+    /// No caps beyond what the format itself allows.
+    pub fn unlimited() -> Limits {
+        Limits { max_chunk_size: u32::MAX, max_chunks: usize::MAX, max_decompressed: u64::MAX }
+    }
+
+    /// Checks the chunk at `index` declaring `length` bytes of data
+    /// before any of it is read.
+    pub(crate) fn check_chunk(&self, index: usize, length: u32) -> Result<(), Error> {
+        if index >= self.max_chunks {
+            return Err(Error::LimitExceeded("max_chunks"));
+        }
+        if length > self.max_chunk_size {
+            return Err(Error::LimitExceeded("max_chunk_size"));
+        }
+        Ok(())
+    }
+}
+
+/// Controls how forgiving parsing is.
+/// The default is as strict as `Png::try_from`.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub crc: CrcMode,
+    /// Keep a chunk that is cut off by the end of the input instead of
+    /// failing. The chunk keeps its declared length, holds whatever data
+    /// was read and a crc of 0. `Png::repair` can drop it later.
+    pub keep_truncated: bool,
+    pub limits: Limits,
+    /// Keep the bytes each chunk was read from, see `Chunk::raw_bytes`.
+    /// Costs a second copy of every chunk.
+    pub preserve_raw: bool,
+}
+
+impl ParseOptions {
+    /// Options that keep every chunk no matter its crc, and keep
+    /// a truncated last chunk.
+    pub fn lenient() -> ParseOptions {
+        ParseOptions { crc: CrcMode::Warn, keep_truncated: true, ..Default::default() }
+    }
+
+    /// Like `lenient` but keeping the raw bytes of every chunk, so
+    /// `Png::as_bytes` gives back the input byte for byte as long as
+    /// nothing is changed. Input ending less than 8 bytes into a chunk
+    /// still fails.
+    pub fn exact() -> ParseOptions {
+        ParseOptions { preserve_raw: true, ..ParseOptions::lenient() }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// The unit of a pHYs chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Only the aspect ratio is known.
+    Unknown,
+    Meter,
+}
+
+/// Pixels per unit on each axis, from pHYs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PixelDims {
+    x: u32,
+    y: u32,
+    unit: Unit,
+}
+
+impl PixelDims {
+    pub fn new(x: u32, y: u32, unit: Unit) -> PixelDims {
+        PixelDims { x, y, unit }
+    }
+
+    /// Dots per inch, rounded to the nearest whole pixels per meter.
+    ///
+    /// # Errors
+    /// returns an Error if either value is not positive or is too large.
+    pub fn from_dpi(x: f64, y: f64) -> Result<PixelDims, Error> {
+        let per_meter = |dpi: f64| {
+            let ppm = (dpi / METERS_PER_INCH).round();
+            if !(1.0..=i32::MAX as f64).contains(&ppm) {
+                bail!("invalid dpi: {}", dpi);
+            }
+            Ok(ppm as u32)
+        };
+        Ok(PixelDims::new(per_meter(x)?, per_meter(y)?, Unit::Meter))
+    }
+
+    /// Pixels per unit on the x axis.
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    /// Pixels per unit on the y axis.
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    /// Dots per inch on each axis, `None` if the unit is unknown.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            Unit::Meter => Some((self.x as f64 * METERS_PER_INCH, self.y as f64 * METERS_PER_INCH)),
+            Unit::Unknown => None,
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.x.to_be_bytes().to_vec();
+        data.extend(self.y.to_be_bytes());
+        data.push(match self.unit {
+            Unit::Unknown => 0,
+            Unit::Meter => 1,
+        });
+        Chunk::new(ChunkType::PHYS, data)
+    }
+}
+
+impl TryFrom<&Chunk> for PixelDims {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the chunk is not a 9 byte pHYs or has an unknown unit.
+    fn try_from(chunk: &Chunk) -> Result<PixelDims, Error> {
+        if *chunk.chunk_type() != ChunkType::PHYS {
+            bail!("expected pHYs, got {}", chunk.chunk_type());
+        }
+        let data = chunk.data();
+        if data.len() != 9 {
+            bail!("pHYs data must be 9 bytes, got {}", data.len());
+        }
+        let unit = match data[8] {
+            0 => Unit::Unknown,
+            1 => Unit::Meter,
+            n => bail!("unknown pHYs unit: {}", n),
+        };
+        let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        Ok(PixelDims::new(x, y, unit))
+    }
+}
+
+impl Png {
+    /// The pixel size or aspect ratio from pHYs.
+    ///
+    /// # Errors
+    /// returns an Error if the pHYs chunk is malformed.
+    pub fn physical_dims(&self) -> Result<Option<PixelDims>, Error> {
+        self.chunk_by_type("pHYs").map(PixelDims::try_from).transpose()
+    }
+
+    /// Replaces pHYs, or adds it before the image data.
+    pub fn set_physical_dims(&mut self, dims: PixelDims) {
+        self.replace_unique(dims.to_chunk(), &[ChunkType::IDAT]);
+    }
+
+    /// Dots per inch on each axis, `None` if there is no pHYs or it
+    /// only gives the aspect ratio.
+    ///
+    /// # Errors
+    /// returns an Error if the pHYs chunk is malformed.
+    pub fn dpi(&self) -> Result<Option<(f64, f64)>, Error> {
+        Ok(self.physical_dims()?.and_then(|d| d.dpi()))
+    }
+
+    /// Sets pHYs to `x` by `y` dots per inch, stored as pixels per meter
+    /// so the value read back can be off by a little, 300 comes back as 299.9994.
+    ///
+    /// # Errors
+    /// returns an Error if either value is not positive or is too large.
+    pub fn set_dpi(&mut self, x: f64, y: f64) -> Result<(), Error> {
+        self.set_physical_dims(PixelDims::from_dpi(x, y)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_pixel_dims() {
+        let dims = PixelDims::from_dpi(300.0, 72.0).unwrap();
+        assert_eq!((dims.x(), dims.y(), dims.unit()), (11811, 2835, Unit::Meter));
+        let (x, y) = dims.dpi().unwrap();
+        assert!((x - 300.0).abs() < 0.01 && (y - 72.0).abs() < 0.01);
+
+        let chunk = dims.to_chunk();
+        assert_eq!(chunk.data(), [0, 0, 0x2e, 0x23, 0, 0, 0x0b, 0x13, 1]);
+        assert_eq!(PixelDims::try_from(&chunk).unwrap(), dims);
+
+        assert_eq!(PixelDims::new(1, 2, Unit::Unknown).dpi(), None);
+        assert!(PixelDims::from_dpi(0.0, 72.0).is_err());
+        assert!(PixelDims::from_dpi(72.0, f64::NAN).is_err());
+        assert!(PixelDims::try_from(&Chunk::new(ChunkType::PHYS, vec![0; 8])).is_err());
+        let bad_unit = [[0; 8].as_slice(), &[2]].concat();
+        assert!(PixelDims::try_from(&Chunk::new(ChunkType::PHYS, bad_unit)).is_err());
+    }
+
+    #[test]
+    fn test_png_dpi() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_chunks("pHYs");
+        assert_eq!(png.dpi().unwrap(), None);
+
+        png.set_dpi(300.0, 300.0).unwrap();
+        let (x, _) = png.dpi().unwrap().unwrap();
+        assert!((x - 300.0).abs() < 0.01);
+        let phys = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::PHYS).unwrap();
+        let idat = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IDAT).unwrap();
+        assert!(phys < idat);
+
+        png.set_physical_dims(PixelDims::new(2, 1, Unit::Unknown));
+        assert_eq!(png.dpi().unwrap(), None);
+        assert_eq!(png.physical_dims().unwrap().unwrap().x(), 2);
+        assert_eq!(png.chunks_of_type("pHYs").count(), 1);
+    }
+}
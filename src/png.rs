@@ -1,25 +1,148 @@
-use std::fmt::Display;
-
-use anyhow::Result;
-use anyhow::bail;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Display;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
 
 use crate::chunk::Chunk;
-
-type Error = anyhow::Error;
+use crate::chunk_type::{ChunkType, ChunkTypeQuery};
+use crate::error::{bail, Error};
+use crate::ihdr::Ihdr;
+#[cfg(feature = "std")]
+use crate::io::{ChunkReader, ChunkWriter};
+use crate::parse::{CrcMode, Limits, ParseOptions};
 
 /// Is a PNG image representation.
-/// stores only `Vec<Chunk>`.
+/// stores the chunks and any bytes after IEND.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Png {
     chunks: Vec<Chunk>,
+    trailer: Vec<u8>,
 }
 
 impl Png {
     /// The standard PNG header, should be at the start of all PNG files.
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+    /// The 8 byte PNG signature, the same bytes as `STANDARD_HEADER`.
+    pub const SIGNATURE: [u8; 8] = Png::STANDARD_HEADER;
+
+    /// Returns true if `bytes` starts with the PNG signature.
+    /// Nothing after the signature is looked at.
+    pub fn is_png(bytes: &[u8]) -> bool {
+        bytes.starts_with(&Png::SIGNATURE)
+    }
+
+    /// Reads up to 8 bytes from `reader` and returns true if they are
+    /// the PNG signature. The bytes are consumed, so rewind or chain
+    /// them back before parsing.
+    ///
+    /// # Errors
+    /// returns an Error if reading fails.
+    #[cfg(feature = "std")]
+    pub fn sniff_reader<R: Read>(reader: &mut R) -> Result<bool, Error> {
+        let mut buf = Vec::with_capacity(8);
+        reader.take(8).read_to_end(&mut buf)?;
+        Ok(Png::is_png(&buf))
+    }
+
     /// Creates a `Png` form `Vec<Chunk>`.
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Png { chunks }
+        Png { chunks, trailer: Vec::new() }
+    }
+
+    /// Creates a `Png` by reading the header and then one chunk at a
+    /// time from `reader`, so the whole file is never buffered at once.
+    /// Reading stops at the end of the reader, anything after IEND is
+    /// kept as the trailer, see `Png::trailer`.
+    ///
+    /// # Errors
+    /// returns an Error if the header is invalid, a chunk is invalid,
+    /// or the reader ends part way through a chunk.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png, Error> {
+        Png::from_reader_with(reader, &ParseOptions::default())
+    }
+
+    /// Like `from_reader` but `options` decides how to deal with
+    /// broken chunks instead of always failing.
+    #[cfg(feature = "std")]
+    pub fn from_reader_with<R: Read>(reader: R, options: &ParseOptions) -> Result<Png, Error> {
+        let mut reader = ChunkReader::with_options(reader, options.clone())?;
+        let mut chunks = Vec::new();
+        while let Some(c) = reader.read_chunk()? {
+            let end = *c.chunk_type() == ChunkType::IEND;
+            chunks.push(c);
+            if end {
+                break;
+            }
+        }
+        let mut trailer = Vec::new();
+        reader.into_inner().read_to_end(&mut trailer)?;
+        Ok(Png { chunks, trailer })
+    }
+
+    /// Turns the bytes read for one chunk into a `Chunk` following `options`.
+    /// `complete` is false if the input ended part way through the chunk.
+    /// Returns `None` if the chunk should be dropped.
+    pub(crate) fn chunk_from_read(
+        bytes: &[u8],
+        complete: bool,
+        options: &ParseOptions,
+    ) -> Result<Option<Chunk>, Error> {
+        let chunk = if !complete {
+            if !options.keep_truncated || bytes.len() < 8 {
+                return Err(Error::TruncatedChunk);
+            }
+            let len = u32::from_be_bytes(bytes[..4].try_into()?);
+            let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&bytes[4..8])?)?;
+            let end = bytes.len().min(8 + len as usize);
+            let data = bytes[8..end].to_vec();
+            Some(Chunk::from_parts(len, chunk_type, data, 0))
+        } else {
+            match options.crc {
+                CrcMode::Strict => Some(Chunk::try_from(bytes)?),
+                CrcMode::Warn => Some(Chunk::from_bytes_unchecked(bytes)?),
+                CrcMode::Skip => Some(Chunk::from_bytes_unchecked(bytes)?).filter(|c| c.has_valid_crc()),
+            }
+        };
+        Ok(chunk.map(|c| if options.preserve_raw { c.with_raw(bytes.to_vec()) } else { c }))
+    }
+
+    /// Creates a `Png` from `bytes`, `options` decides how to deal
+    /// with broken chunks. See `ParseOptions`. Works like
+    /// `from_reader_with` but without `std::io`, so it is there on `no_std`.
+    pub fn from_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<Png, Error> {
+        if !Png::is_png(bytes) {
+            return Err(Error::InvalidSignature);
+        }
+        let mut chunks = Vec::new();
+        let mut rest = &bytes[8..];
+        let mut index = 0;
+        while !rest.is_empty() {
+            let offset = (bytes.len() - rest.len()) as u64;
+            let Some(lenb) = rest.get(..4) else {
+                return Err(Error::TruncatedChunk.at(offset, index));
+            };
+            let len = u32::from_be_bytes(lenb.try_into()?);
+            options.limits.check_chunk(index, len).map_err(|e| e.at(offset, index))?;
+            // length, type, data and crc, or whatever is left of them
+            let want = 12 + len as u64;
+            let (read, next) = rest.split_at((rest.len() as u64).min(want) as usize);
+            let complete = read.len() as u64 == want;
+            let chunk = Png::chunk_from_read(read, complete, options).map_err(|e| e.at(offset, index))?;
+            index += 1;
+            rest = next;
+            if let Some(c) = chunk {
+                let end = *c.chunk_type() == ChunkType::IEND;
+                chunks.push(c);
+                if end {
+                    break;
+                }
+            }
+        }
+        Ok(Png { chunks, trailer: rest.to_vec() })
     }
 
     /// Appends a chunk to the end of the `Vec<Chunk>`.
@@ -27,30 +150,145 @@ impl Png {
         self.chunks.push(chunk);
     }
 
+    /// Inserts a chunk at `index`, shifting all chunks after it.
+    ///
+    /// # Errors
+    /// returns an Error if `index` > number of chunks.
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<(), Error> {
+        if index > self.chunks.len() {
+            bail!("index out of range: {} > {}", index, self.chunks.len());
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Inserts a chunk directly before the first `Chunk` of specified type.
+    ///
+    /// # Errors
+    /// returns an Error if no chunks of specified type exist.
+    pub fn insert_before_type(&mut self, chunk_type: &str, chunk: Chunk) -> Result<(), Error> {
+        match self.chunks.iter().position(|c| c.chunk_type().bytes() == chunk_type.as_bytes()) {
+            Some(n) => self.insert_chunk(n, chunk),
+            None => Err(Error::MissingChunk(chunk_type.parse()?)),
+        }
+    }
+
+    /// Inserts a chunk directly after the last `Chunk` of specified type,
+    /// so a run of chunks like IDAT is never split.
+    ///
+    /// # Errors
+    /// returns an Error if no chunks of specified type exist.
+    pub fn insert_after_type(&mut self, chunk_type: &str, chunk: Chunk) -> Result<(), Error> {
+        match self.chunks.iter().rposition(|c| c.chunk_type().bytes() == chunk_type.as_bytes()) {
+            Some(n) => self.insert_chunk(n + 1, chunk),
+            None => Err(Error::MissingChunk(chunk_type.parse()?)),
+        }
+    }
+
     /// Removes the first `Chunk` that apears of specified type.
     ///
     /// # Errors
     /// returns an Error if no chunks of specified type exist.
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
-        let chunk_type: Vec<u8> = chunk_type.bytes().collect();
+    /// Also returns an Error if the chunk type is invalid.
+    pub fn remove_chunk<'a>(
+        &mut self,
+        chunk_type: impl Into<ChunkTypeQuery<'a>>,
+    ) -> Result<Chunk, Error> {
+        let chunk_type = chunk_type.into().resolve()?;
         for n in 0..self.chunks.len() {
             let c = &self.chunks[n];
-            if c.chunk_type().bytes() == chunk_type.as_ref() {
+            if *c.chunk_type() == chunk_type {
                 return Ok(self.chunks.remove(n));
            }
         }
 
-        bail!("no such chunk")
+        Err(Error::MissingChunk(chunk_type))
+    }
+
+    /// Removes every `Chunk` of specified type, returning them in file order.
+    /// Returns an empty `Vec` if there were none.
+    pub fn remove_chunks(&mut self, chunk_type: &str) -> Vec<Chunk> {
+        let (removed, kept) = core::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|c| c.chunk_type().bytes() == chunk_type.as_bytes());
+        self.chunks = kept;
+        removed
+    }
+
+    /// Parses the IHDR chunk.
+    ///
+    /// # Errors
+    /// returns an Error if there is no IHDR chunk or it is invalid.
+    pub fn header(&self) -> Result<Ihdr, Error> {
+        match self.chunk_by_type("IHDR") {
+            Some(c) => Ihdr::try_from(c),
+            None => Err(Error::MissingChunk(ChunkType::IHDR)),
+        }
+    }
+
+    /// Replaces the IHDR chunk, or inserts it as the first chunk
+    /// if there was none.
+    pub fn set_header(&mut self, ihdr: &Ihdr) {
+        let chunk = ihdr.to_chunk();
+        match self.chunks.iter().position(|c| *c.chunk_type() == ChunkType::IHDR) {
+            Some(n) => self.chunks[n] = chunk,
+            None => self.chunks.insert(0, chunk),
+        }
     }
 
-    pub fn header(&self) -> [u8; 8] {
-        Png::STANDARD_HEADER
+    /// Replaces the chunk of this type, removing any duplicates, or
+    /// inserts it before the first chunk with a type in `before`, or IEND.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn replace_unique(&mut self, chunk: Chunk, before: &[ChunkType]) {
+        let chunk_type = *chunk.chunk_type();
+        match self.chunks.iter().position(|c| *c.chunk_type() == chunk_type) {
+            Some(n) => {
+                self.chunks[n] = chunk;
+                let rest = self.chunks.split_off(n + 1);
+                self.chunks.extend(rest.into_iter().filter(|c| *c.chunk_type() != chunk_type));
+            }
+            None => {
+                let at = self
+                    .chunks
+                    .iter()
+                    .position(|c| before.contains(c.chunk_type()) || *c.chunk_type() == ChunkType::IEND)
+                    .unwrap_or(self.chunks.len());
+                self.chunks.insert(at, chunk);
+            }
+        }
     }
 
     pub fn chunks(&self) -> &[Chunk] {
         self.chunks.as_ref()
     }
 
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn chunks_mut(&mut self) -> &mut Vec<Chunk> {
+        &mut self.chunks
+    }
+
+    /// Iterates over every chunk in file order.
+    pub fn iter(&self) -> core::slice::Iter<'_, Chunk> {
+        self.chunks.iter()
+    }
+
+    /// Iterates over every `Chunk` of specified type in file order.
+    pub fn chunks_of_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> + 'a {
+        self.chunks.iter().filter(move |c| c.chunk_type().bytes() == chunk_type.as_bytes())
+    }
+
+    /// Iterates over the chunks that are critical, see `ChunkType::is_critical`.
+    pub fn critical_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.iter().filter(|c| c.chunk_type().is_critical())
+    }
+
+    /// Iterates over the chunks that are not critical.
+    pub fn ancillary_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.iter().filter(|c| !c.chunk_type().is_critical())
+    }
+
+    /// The first chunk of type `chunk_type`. Image data is usually split
+    /// over many IDAT chunks, see `idat_chunks` and `combined_idat`.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         let chunk_type: Vec<u8> = chunk_type.bytes().collect();
         for n in 0..self.chunks.len() {
@@ -62,55 +300,160 @@ impl Png {
         None
     }
 
+    /// Like `chunk_by_type` but also takes a `ChunkType` or `[u8; 4]`.
+    ///
+    /// # Errors
+    /// returns an Error if the chunk type is invalid, rather than
+    /// quietly finding nothing.
+    pub fn find_chunk<'a>(
+        &self,
+        chunk_type: impl Into<ChunkTypeQuery<'a>>,
+    ) -> Result<Option<&Chunk>, Error> {
+        let chunk_type = chunk_type.into().resolve()?;
+        Ok(self.chunks.iter().find(|c| *c.chunk_type() == chunk_type))
+    }
+
+    /// Mutable version of `find_chunk`.
+    pub fn find_chunk_mut<'a>(
+        &mut self,
+        chunk_type: impl Into<ChunkTypeQuery<'a>>,
+    ) -> Result<Option<&mut Chunk>, Error> {
+        let chunk_type = chunk_type.into().resolve()?;
+        Ok(self.chunks.iter_mut().find(|c| *c.chunk_type() == chunk_type))
+    }
+
+    /// Every IDAT chunk in file order. Together they hold one zlib
+    /// stream, so no single one is the image data.
+    pub fn idat_chunks(&self) -> Vec<&Chunk> {
+        self.chunks.iter().filter(|c| *c.chunk_type() == ChunkType::IDAT).collect()
+    }
+
+    /// The data of every IDAT chunk joined, the compressed image data.
+    /// Empty if there are none.
+    pub fn combined_idat(&self) -> Vec<u8> {
+        self.idat_chunks().iter().flat_map(|c| c.data()).copied().collect()
+    }
+
+    /// Returns the first `Chunk` of specified type for editing in place.
+    pub fn chunk_by_type_mut(&mut self, chunk_type: &str) -> Option<&mut Chunk> {
+        self.chunks.iter_mut().find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+    }
+
+    /// Size of the file in bytes, the header plus every chunk and the trailer.
+    pub fn file_size(&self) -> u64 {
+        let chunks = self.chunks.iter().map(Chunk::total_size).sum::<u64>();
+        Png::STANDARD_HEADER.len() as u64 + chunks + self.trailer.len() as u64
+    }
+
+    /// The bytes after IEND, kept as they were read. Decoders never look
+    /// at them, which is why some tools hide data there.
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Replaces the bytes written after the last chunk, empty for none.
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    /// Removes the trailer and returns it, so it is no longer written.
+    pub fn take_trailer(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.trailer)
+    }
+
+    /// Total bytes taken up by each chunk type, see `Chunk::total_size`.
+    /// Types are listed in the order they first appear.
+    pub fn size_report(&self) -> Vec<(ChunkType, u64)> {
+        let mut report: Vec<(ChunkType, u64)> = Vec::new();
+        for c in &self.chunks {
+            match report.iter_mut().find(|(t, _)| t == c.chunk_type()) {
+                Some((_, size)) => *size += c.total_size(),
+                None => report.push((*c.chunk_type(), c.total_size())),
+            }
+        }
+        report
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Png::STANDARD_HEADER.to_vec();
         for n in 0..self.chunks.len() {
             let c = &self.chunks[n];
             bytes.extend(c.as_bytes());
         }
+        bytes.extend(&self.trailer);
 
         bytes
     }
+
+    /// Writes the header and every chunk to `writer` in order.
+    /// Unlike `as_bytes` the file is never held in memory as a whole.
+    ///
+    /// # Errors
+    /// returns an Error if writing fails.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut writer = ChunkWriter::new(writer)?;
+        for c in &self.chunks {
+            writer.write_chunk(c)?;
+        }
+        let mut writer = writer.finish()?;
+        writer.write_all(&self.trailer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = core::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Png {
+    type Item = Chunk;
+    type IntoIter = alloc::vec::IntoIter<Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.into_iter()
+    }
 }
 
 impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let header: [u8; 8] = value[..8].try_into().unwrap();
-        if header != Png::STANDARD_HEADER {
-            bail!("invalid header: {:?}", header);
+        if !Png::is_png(value) {
+            return Err(Error::InvalidSignature);
         }
 
+        let limits = Limits::default();
         let mut chunks = Vec::new();
-
-        let mut prev: usize = 8;
-
-        while prev < value.len() {
-            let lenb: [u8; 4] = value[prev..prev+4].try_into().unwrap();
-            prev += 4;
-            let len = u32::from_be_bytes(lenb);
-            let ctb: [u8; 4] = value[prev..prev+4].try_into().unwrap();
-            prev += 4;
-            let mut bytes = lenb.to_vec();
-            bytes.extend(ctb);
-            bytes.extend(&value[prev..prev+len as usize]);
-            prev += len as usize;
-            bytes.extend(&value[prev..prev+4]);
-            prev += 4;
-            let c = Chunk::try_from(bytes.as_ref())?;
+        let mut rest = &value[8..];
+        while !rest.is_empty() {
+            let offset = (value.len() - rest.len()) as u64;
+            let length = rest.get(..4).map_or(0, |b| u32::from_be_bytes(b.try_into().unwrap()));
+            let parsed = limits.check_chunk(chunks.len(), length).and_then(|_| Chunk::parse_prefix(rest));
+            let (c, next) = parsed.map_err(|e| e.at(offset, chunks.len()))?;
+            let end = *c.chunk_type() == ChunkType::IEND;
             chunks.push(c);
+            rest = next;
+            if end {
+                break;
+            }
         }
 
-        Ok(Png::from_chunks(chunks))
+        Ok(Png { chunks, trailer: rest.to_vec() })
     }
 }
 
 impl Display for Png {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let mut s = String::new();
         for c in &self.chunks {
-            s = format!("{} | {}", s, c.data_as_string().unwrap());
+            s = format!("{} | {}", s, c);
         }
         write!(f, "{}", s)
     }
@@ -121,17 +464,17 @@ mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
-    use std::str::FromStr;
-    use std::convert::TryFrom;
+    use crate::error::ParseError;
+    use crate::ihdr::ColorType;
+    use core::convert::TryFrom;
+    use core::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -140,7 +483,7 @@ mod tests {
     }
 
     fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, Error> {
-        use std::str::FromStr;
+        use core::str::FromStr;
 
         let chunk_type = ChunkType::from_str(chunk_type)?;
         let data: Vec<u8> = data.bytes().collect();
@@ -189,7 +532,34 @@ mod tests {
 
         let png = Png::try_from(bytes.as_ref());
 
-        assert!(png.is_err());
+        assert!(matches!(png, Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut png = testing_png();
+        assert!(matches!(png.header(), Err(Error::MissingChunk(ChunkType::IHDR))));
+        let chunk = chunk_from_strings("ruSt", "x").unwrap();
+        let missing = png.insert_before_type("teSt", chunk.clone());
+        assert!(matches!(missing, Err(Error::MissingChunk(t)) if t.to_string() == "teSt"));
+        assert!(matches!(png.insert_after_type("te5t", chunk), Err(Error::InvalidChunkType(_))));
+        assert!(matches!(png.remove_chunk("teSt"), Err(Error::MissingChunk(_))));
+
+        let mut bytes = Png::SIGNATURE.to_vec();
+        bytes.extend(&testing_chunks()[0].as_bytes()[..10]);
+        assert!(matches!(Png::try_from(bytes.as_ref()).unwrap_err().kind(), Error::TruncatedChunk));
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let mut bytes = testing_png().as_bytes();
+        let second = (8 + testing_chunks()[0].as_bytes().len()) as u64;
+        bytes[second as usize + 8] ^= 1;
+        let from_slice = Png::try_from(bytes.as_ref()).unwrap_err();
+        for err in [from_slice, Png::from_bytes_with(&bytes, &ParseOptions::default()).unwrap_err()] {
+            assert!(matches!(&err, Error::Parse(ParseError { chunk_index: 1, offset, .. }) if *offset == second));
+            assert!(matches!(err.kind(), Error::BadCrc { .. }));
+        }
     }
 
     #[test]
@@ -222,6 +592,35 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_iter() {
+        let png = testing_png();
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+        assert_eq!((&png).into_iter().count(), 3);
+        assert_eq!(png.into_iter().count(), 3);
+    }
+
+    #[test]
+    fn test_chunks_of_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Another").unwrap());
+        let data: Vec<String> = png.chunks_of_type("miDl")
+            .map(|c| c.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, vec!["I am another chunk", "Another"]);
+        assert_eq!(png.chunks_of_type("NoNe").count(), 0);
+    }
+
+    #[test]
+    fn test_critical_and_ancillary_chunks() {
+        let png = testing_png();
+        let critical: Vec<String> = png.critical_chunks().map(|c| c.chunk_type().to_string()).collect();
+        let ancillary: Vec<String> = png.ancillary_chunks().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(critical, vec!["FrSt", "LASt"]);
+        assert_eq!(ancillary, vec!["miDl"]);
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -231,6 +630,19 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_chunk_by_type_mut() {
+        let mut png = testing_png();
+        png.chunk_by_type_mut("miDl").unwrap().set_data(b"Edited".to_vec());
+        let chunk = &png.chunks()[1];
+        assert_eq!(&chunk.data_as_string().unwrap(), "Edited");
+        assert_eq!(chunk.length(), 6);
+        assert!(png.chunk_by_type_mut("NoNe").is_none());
+
+        let bytes = png.as_bytes();
+        assert!(Png::try_from(bytes.as_ref()).is_ok());
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -240,6 +652,35 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+        png.insert_chunk(4, chunk_from_strings("EnDs", "Message").unwrap()).unwrap();
+        assert_eq!(&png.chunks()[4].chunk_type().to_string(), "EnDs");
+        assert!(png.insert_chunk(6, chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_insert_before_type() {
+        let mut png = testing_png();
+        png.insert_before_type("LASt", chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(&png.chunks()[2].chunk_type().to_string(), "TeSt");
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "LASt");
+        assert!(png.insert_before_type("NoNe", chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_insert_after_type() {
+        let mut png = testing_png();
+        png.insert_after_type("miDl", chunk_from_strings("miDl", "Second").unwrap()).unwrap();
+        png.insert_after_type("miDl", chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(&png.chunks()[2].data_as_string().unwrap(), "Second");
+        assert_eq!(&png.chunks()[3].chunk_type().to_string(), "TeSt");
+        assert!(png.insert_after_type("NoNe", chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -249,20 +690,295 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Another").unwrap());
+        png.append_chunk(chunk_from_strings("EnDs", "Last").unwrap());
+        let removed = png.remove_chunks("miDl");
+        assert_eq!(removed.len(), 2);
+        assert_eq!(&removed[1].data_as_string().unwrap(), "Another");
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "LASt", "EnDs"]);
+        assert!(png.remove_chunks("miDl").is_empty());
+    }
+
+    #[test]
+    fn test_find_chunk() {
+        let png = testing_png();
+        let by_str = png.find_chunk("miDl").unwrap().unwrap();
+        let by_type = png.find_chunk(ChunkType::from_str("miDl").unwrap()).unwrap().unwrap();
+        let by_bytes = png.find_chunk(*b"miDl").unwrap().unwrap();
+        assert_eq!(by_str, by_type);
+        assert_eq!(by_str, by_bytes);
+        assert!(png.find_chunk("NoNe").unwrap().is_none());
+        assert!(png.find_chunk("miDl!").is_err());
+        assert!(png.find_chunk(*b"m1Dl").is_err());
+    }
+
+    #[test]
+    fn test_find_chunk_mut() {
+        let mut png = testing_png();
+        png.find_chunk_mut(*b"LASt").unwrap().unwrap().set_data(b"Edited".to_vec());
+        assert_eq!(&png.chunks()[2].data_as_string().unwrap(), "Edited");
+    }
+
+    #[test]
+    fn test_remove_chunk_typed() {
+        let mut png = testing_png();
+        assert!(png.remove_chunk(ChunkType::from_str("FrSt").unwrap()).is_ok());
+        assert!(png.remove_chunk(*b"LASt").is_ok());
+        assert!(png.remove_chunk("miD").is_err());
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_png_short_input() {
+        for n in [0, 4, 12, 20, PNG_FILE.len() - 1] {
+            assert!(Png::try_from(&PNG_FILE[..n]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_png_display_binary() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.to_string().starts_with(" | "));
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_png_from_reader() {
+        let png = Png::from_reader(&PNG_FILE[..]).unwrap();
+        let expected = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.as_bytes(), expected.as_bytes());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_reader_invalid_header() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[0] = 13;
+        assert!(Png::from_reader(bytes.as_slice()).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_reader_truncated() {
+        let png = Png::from_reader(&PNG_FILE[..PNG_FILE.len() - 2]);
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_header() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.header().unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 50);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+
+        assert!(testing_png().header().is_err());
+    }
+
+    #[test]
+    fn test_set_header() {
+        let mut png = testing_png();
+        let ihdr = Ihdr::new(2, 3, 8, ColorType::Rgb).unwrap();
+        png.set_header(&ihdr);
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(&png.chunks()[0].chunk_type().to_string(), "IHDR");
+        assert_eq!(png.header().unwrap(), ihdr);
+
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Rgb).unwrap();
+        png.set_header(&ihdr);
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.header().unwrap().width(), 4);
+    }
+
+    fn png_with_bad_crc() -> Vec<u8> {
+        let mut bytes = Png::from_chunks(testing_chunks()).as_bytes();
+        // last byte of the crc of the second chunk
+        let end = 8 + 12 + 20 + 12 + 18 - 1;
+        bytes[end] ^= 1;
+        bytes
+    }
+
+    #[test]
+    fn test_from_bytes_with_strict() {
+        let bytes = png_with_bad_crc();
+        assert!(Png::from_bytes_with(&bytes, &ParseOptions::default()).is_err());
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_with_warn() {
+        let bytes = png_with_bad_crc();
+        let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient()).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.chunks()[0].has_valid_crc());
+        assert!(!png.chunks()[1].has_valid_crc());
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_from_bytes_with_skip() {
+        let bytes = png_with_bad_crc();
+        let options = ParseOptions { crc: CrcMode::Skip, ..Default::default() };
+        let png = Png::from_bytes_with(&bytes, &options).unwrap();
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "LASt"]);
+    }
+
+    #[test]
+    fn test_from_bytes_with_keep_truncated() {
+        let bytes = Png::from_chunks(testing_chunks()).as_bytes();
+        let cut = &bytes[..bytes.len() - 10];
+        assert!(Png::from_bytes_with(cut, &ParseOptions::default()).is_err());
+
+        let png = Png::from_bytes_with(cut, &ParseOptions::lenient()).unwrap();
+        let last = png.chunks().last().unwrap();
+        assert_eq!(&last.chunk_type().to_string(), "LASt");
+        assert_eq!(last.length(), 19);
+        assert_eq!(last.data().len(), 13);
+    }
+
+    #[test]
+    fn test_from_bytes_with_exact() {
+        let bytes = Png::from_chunks(testing_chunks()).as_bytes();
+        let cut = &bytes[..bytes.len() - 10];
+        assert_ne!(Png::from_bytes_with(cut, &ParseOptions::lenient()).unwrap().as_bytes(), cut);
+
+        let mut png = Png::from_bytes_with(cut, &ParseOptions::exact()).unwrap();
+        assert_eq!(png.as_bytes(), cut);
+        #[cfg(feature = "std")]
+        {
+            let mut out = Vec::new();
+            png.write_to(&mut out).unwrap();
+            assert_eq!(out, cut);
+        }
+        assert!(png.chunks()[0].raw_bytes().is_some());
+
+        let last = png.chunks_mut().last_mut().unwrap();
+        last.set_data(b"changed".to_vec());
+        assert_eq!(last.raw_bytes(), None);
+        let expected = Chunk::new(*last.chunk_type(), b"changed".to_vec()).as_bytes();
+        assert!(png.as_bytes().ends_with(&expected));
+    }
+
+    #[test]
+    fn test_idat_chunks() {
+        let mut png = Png::from_chunks(testing_chunks());
+        assert!(png.idat_chunks().is_empty());
+        assert!(png.combined_idat().is_empty());
+
+        png.insert_chunk(1, Chunk::new(ChunkType::IDAT, vec![1, 2])).unwrap();
+        png.insert_chunk(2, Chunk::new(ChunkType::IDAT, vec![3])).unwrap();
+        png.insert_chunk(3, Chunk::new(ChunkType::IDAT, vec![])).unwrap();
+        let idats = png.idat_chunks();
+        assert_eq!(idats.len(), 3);
+        assert_eq!(idats[0], png.chunk_by_type("IDAT").unwrap());
+        assert_eq!(png.combined_idat(), vec![1, 2, 3]);
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_write_to() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut out = Vec::new();
+        png.write_to(&mut out).unwrap();
+        assert_eq!(out, PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(b"PK\x03\x04 not a chunk");
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailer(), b"PK\x03\x04 not a chunk");
+        assert_eq!(png.chunks().last().unwrap().chunk_type(), &ChunkType::IEND);
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.file_size(), bytes.len() as u64);
+        assert_eq!(Png::from_bytes_with(&bytes, &ParseOptions::default()).unwrap(), png);
+        #[cfg(feature = "std")]
+        {
+            let mut out = Vec::new();
+            png.write_to(&mut out).unwrap();
+            assert_eq!(out, bytes);
+        }
+
+        assert_eq!(png.take_trailer(), b"PK\x03\x04 not a chunk");
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+        png.set_trailer(b"more".to_vec());
+        assert!(png.as_bytes().ends_with(b"IEND\xaeB`\x82more"));
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().trailer().is_empty());
+    }
+
+    #[test]
+    fn test_is_png() {
+        assert!(Png::is_png(&PNG_FILE));
+        assert!(Png::is_png(&Png::SIGNATURE));
+        assert!(!Png::is_png(&PNG_FILE[..7]));
+        assert!(!Png::is_png(b"GIF89a.."));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sniff_reader() {
+        let mut reader = &PNG_FILE[..];
+        assert!(Png::sniff_reader(&mut reader).unwrap());
+        assert_eq!(reader.len(), PNG_FILE.len() - 8);
+
+        let mut reader = &b"PNG"[..];
+        assert!(!Png::sniff_reader(&mut reader).unwrap());
+    }
+
+    #[test]
+    fn test_file_size() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.file_size(), PNG_FILE.len() as u64);
+    }
+
+    #[test]
+    fn test_size_report() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "12345").unwrap());
+        let report: Vec<(String, u64)> = png.size_report()
+            .into_iter()
+            .map(|(t, s)| (t.to_string(), s))
+            .collect();
+        assert_eq!(report, vec![
+            ("FrSt".to_string(), 32 + 17),
+            ("miDl".to_string(), 30),
+            ("LASt".to_string(), 31),
+        ]);
+        let total: u64 = png.size_report().iter().map(|(_, s)| s).sum();
+        assert_eq!(total + 8, png.file_size());
+    }
+
+    #[test]
+    fn test_png_clone_eq() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut copy = png.clone();
+        assert_eq!(png, copy);
+        copy.remove_chunk("IEND").unwrap();
+        assert_ne!(png, copy);
+        assert_eq!(png, Png::try_from(&PNG_FILE[..]).unwrap());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::png::Png;
+
+/// A `Chunk` whose data is borrowed from the buffer it was parsed from.
+/// Use `to_owned` to get a `Chunk` that can outlive the buffer.
+#[derive(Debug)]
+pub struct ChunkRef<'a> {
+    length: u32,
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses one chunk from the start of `bytes`, returning it and the
+    /// bytes after it.
+    ///
+    /// # Errors
+    /// returns an Error if `bytes` is too short for the declared length,
+    /// the chunk type is invalid or the crc is wrong.
+    pub fn parse_prefix(bytes: &'a [u8]) -> Result<(ChunkRef<'a>, &'a [u8]), Error> {
+        if bytes.len() < 12 {
+            return Err(Error::TruncatedChunk);
+        }
+        let length = u32::from_be_bytes(bytes[0..4].try_into()?);
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&bytes[4..8])?)?;
+        let end = 8 + length as usize;
+        if bytes.len() < end + 4 {
+            return Err(Error::TruncatedChunk);
+        }
+        let data = &bytes[8..end];
+        let crc = u32::from_be_bytes(bytes[end..end + 4].try_into()?);
+        let chunk = ChunkRef { length, chunk_type, data, crc };
+        if !chunk.has_valid_crc() {
+            return Err(Error::BadCrc { found: crc, expected: Chunk::calculate_crc(&chunk.chunk_type, data) });
+        }
+
+        Ok((chunk, &bytes[end + 4..]))
+    }
+
+    /// Builds a `ChunkRef` from a chunk already checked by `parse_prefix`.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn from_checked(bytes: &'a [u8]) -> ChunkRef<'a> {
+        let length = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&bytes[4..8]).unwrap()).unwrap();
+        let end = bytes.len() - 4;
+        let crc = u32::from_be_bytes(bytes[end..].try_into().unwrap());
+        ChunkRef { length, chunk_type, data: &bytes[8..end], crc }
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == Chunk::calculate_crc(&self.chunk_type, self.data)
+    }
+
+    /// Copies the data into an owned `Chunk`.
+    pub fn to_owned(&self) -> Chunk {
+        Chunk::from_parts(self.length, self.chunk_type, self.data.to_vec(), self.crc)
+    }
+}
+
+/// A `Png` that borrows all chunk data from the parsed buffer,
+/// nothing is copied while parsing.
+#[derive(Debug)]
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        self.chunks.as_ref()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, ChunkRef<'a>> {
+        self.chunks.iter()
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>> {
+        self.chunks.iter().find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+    }
+
+    /// Copies every chunk into an owned `Png`.
+    pub fn to_owned(&self) -> Png {
+        Png::from_chunks(self.chunks.iter().map(ChunkRef::to_owned).collect())
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the header or any chunk is invalid.
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        if value.len() < 8 || value[..8] != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut chunks = Vec::new();
+        let mut rest = &value[8..];
+        while !rest.is_empty() {
+            let offset = (value.len() - rest.len()) as u64;
+            let (chunk, next) = ChunkRef::parse_prefix(rest).map_err(|e| e.at(offset, chunks.len()))?;
+            chunks.push(chunk);
+            rest = next;
+        }
+
+        Ok(PngRef { chunks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_png_ref_borrows() {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        let chunk = png.chunk_by_type("miDl").unwrap();
+        assert_eq!(chunk.data(), b"I am another chunk");
+        let offset = chunk.data().as_ptr() as usize - bytes.as_ptr() as usize;
+        assert_eq!(offset, 8 + 12 + 20 + 8);
+    }
+
+    #[test]
+    fn test_png_ref_to_owned() {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.to_owned().as_bytes(), bytes);
+        let chunk = png.iter().next().unwrap().to_owned();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+    }
+
+    #[test]
+    fn test_png_ref_invalid() {
+        let bytes = testing_bytes();
+        assert!(PngRef::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PngRef::try_from(&bytes[..4]).is_err());
+
+        let mut bytes = bytes;
+        bytes[20] ^= 1;
+        assert!(PngRef::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_ref_parse_prefix() {
+        let bytes = testing_bytes();
+        let (chunk, rest) = ChunkRef::parse_prefix(&bytes[8..]).unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+        assert_eq!(rest.len(), bytes.len() - 8 - 32);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use crate::chunk_type::ChunkType;
+use crate::encode::EncodeOptions;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::palette::{Palette, Rgba};
+use crate::png::Png;
+
+/// How `Png::quantize` spreads the error of mapping a pixel to
+/// the nearest palette colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DitherMode {
+    /// Every pixel becomes its nearest colour, best for flat colour art.
+    #[default]
+    None,
+    /// Floyd-Steinberg error diffusion, best for photos and gradients.
+    FloydSteinberg,
+}
+
+impl Png {
+    /// Converts the image to indexed colour with a palette of at most
+    /// `max_colors` entries, picked by median cut. Images that already
+    /// use few enough colours keep them exactly. The bit depth is the
+    /// smallest that fits the palette. Samples are reduced to 8 bits
+    /// first, so 16 bit images lose precision.
+    ///
+    /// The header, PLTE, tRNS and image data are replaced. bKGD, hIST and
+    /// sBIT describe the old pixel format, so they are removed.
+    ///
+    /// # Errors
+    /// returns an Error if `max_colors` is not 1 to 256, or the image
+    /// can not be decoded.
+    pub fn quantize(&mut self, max_colors: usize, dither: DitherMode) -> Result<(), Error> {
+        if max_colors == 0 || max_colors > Palette::MAX_ENTRIES {
+            bail!("max_colors must be 1 to {}, not {}", Palette::MAX_ENTRIES, max_colors);
+        }
+        let rgba = self.decode_pixels()?.to_rgba8()?;
+        let (width, height) = (rgba.width(), rgba.height());
+
+        let mut colors = median_cut(&histogram(rgba.data()), max_colors);
+        // opaque entries last keeps tRNS short
+        colors.sort_by_key(|c| c[3] == 255);
+        let indices = match dither {
+            DitherMode::None => map_nearest(rgba.data(), &colors),
+            DitherMode::FloydSteinberg => map_dithered(rgba.data(), width as usize, &colors),
+        };
+
+        let bit_depth = match colors.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        };
+        let ihdr = Ihdr::new(width, height, bit_depth, ColorType::Indexed)?;
+        let pixels = pack_rows(&ihdr, &indices);
+
+        for t in [ChunkType::PLTE, ChunkType::TRNS, ChunkType::BKGD, ChunkType::HIST, ChunkType::SBIT] {
+            self.chunks_mut().retain(|c| *c.chunk_type() != t);
+        }
+        self.replace_pixels(&ihdr, &pixels, &EncodeOptions { bit_depth, ..Default::default() })?;
+        let mut palette = Palette::new();
+        for c in colors {
+            palette.push(Rgba::from(c))?;
+        }
+        self.set_palette(&palette)
+    }
+}
+
+/// Every colour in `data` with how many pixels use it.
+fn histogram(data: &[u8]) -> Vec<([u8; 4], u32)> {
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    for p in data.chunks_exact(4) {
+        *counts.entry([p[0], p[1], p[2], p[3]]).or_default() += 1;
+    }
+    let mut colors: Vec<_> = counts.into_iter().collect();
+    // HashMap order is random, keep the palette reproducible
+    colors.sort_unstable();
+    colors
+}
+
+/// Splits the colours into at most `max` boxes, always splitting the box
+/// with the widest channel at its weighted median, and returns the
+/// weighted average colour of each box.
+fn median_cut(colors: &[([u8; 4], u32)], max: usize) -> Vec<[u8; 4]> {
+    if colors.len() <= max {
+        return colors.iter().map(|(c, _)| *c).collect();
+    }
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < max {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(n, b)| (widest_channel(b), n))
+            .max_by_key(|((_, range), _)| *range);
+        let ((channel, _), n) = match widest {
+            Some(w) => w,
+            None => break,
+        };
+        let mut b = boxes.swap_remove(n);
+        b.sort_unstable_by_key(|(c, _)| c[channel]);
+        let total: u64 = b.iter().map(|(_, count)| *count as u64).sum();
+        let mut seen = 0;
+        let median = b
+            .iter()
+            .position(|(_, count)| {
+                seen += *count as u64;
+                seen * 2 >= total
+            })
+            .unwrap();
+        // both halves need at least one colour
+        let split = (median + 1).clamp(1, b.len() - 1);
+        let upper = b.split_off(split);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|b| average(b)).collect()
+}
+
+/// The channel with the largest range in `colors`, and that range.
+fn widest_channel(colors: &[([u8; 4], u32)]) -> (usize, u8) {
+    (0..4)
+        .map(|ch| {
+            let (min, max) = colors
+                .iter()
+                .fold((255, 0), |(min, max), (c, _)| (c[ch].min(min), c[ch].max(max)));
+            (ch, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn average(colors: &[([u8; 4], u32)]) -> [u8; 4] {
+    let total: u64 = colors.iter().map(|(_, count)| *count as u64).sum();
+    let mut sum = [0u64; 4];
+    for (c, count) in colors {
+        for ch in 0..4 {
+            sum[ch] += c[ch] as u64 * *count as u64;
+        }
+    }
+    sum.map(|s| ((s + total / 2) / total) as u8)
+}
+
+/// Index of the palette colour closest to `color`.
+fn nearest(palette: &[[u8; 4]], color: [i32; 4]) -> u8 {
+    let distance = |p: &[u8; 4]| -> i32 { (0..4).map(|ch| (p[ch] as i32 - color[ch]).pow(2)).sum() };
+    let (n, _) = palette.iter().enumerate().min_by_key(|(_, p)| distance(p)).unwrap();
+    n as u8
+}
+
+fn map_nearest(data: &[u8], palette: &[[u8; 4]]) -> Vec<u8> {
+    let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+    data.chunks_exact(4)
+        .map(|p| {
+            let color = [p[0], p[1], p[2], p[3]];
+            *cache.entry(color).or_insert_with(|| nearest(palette, color.map(|v| v as i32)))
+        })
+        .collect()
+}
+
+/// Maps pixels to the palette, pushing the error of each pixel onto its
+/// right and lower neighbours with the Floyd-Steinberg weights.
+fn map_dithered(data: &[u8], width: usize, palette: &[[u8; 4]]) -> Vec<u8> {
+    // error for this row and the next, one pixel of padding either side
+    let mut errors = vec![[0i32; 4]; width + 2];
+    let mut next = vec![[0i32; 4]; width + 2];
+    let mut out = Vec::with_capacity(data.len() / 4);
+    for row in data.chunks_exact(width * 4) {
+        for (x, p) in row.chunks_exact(4).enumerate() {
+            let wanted: [i32; 4] =
+                std::array::from_fn(|ch| (p[ch] as i32 + errors[x + 1][ch] / 16).clamp(0, 255));
+            let index = nearest(palette, wanted);
+            out.push(index);
+            for ch in 0..4 {
+                let error = wanted[ch] - palette[index as usize][ch] as i32;
+                errors[x + 2][ch] += error * 7;
+                next[x][ch] += error * 3;
+                next[x + 1][ch] += error * 5;
+                next[x + 2][ch] += error;
+            }
+        }
+        std::mem::swap(&mut errors, &mut next);
+        next.iter_mut().for_each(|e| *e = [0; 4]);
+    }
+    out
+}
+
+/// Packs one palette index per pixel into rows at the bit depth of `ihdr`.
+pub(crate) fn pack_rows(ihdr: &Ihdr, indices: &[u8]) -> Vec<u8> {
+    let bits = ihdr.bit_depth() as usize;
+    let stride = ihdr.row_bytes(ihdr.width());
+    let mut out = vec![0u8; stride * ihdr.height() as usize];
+    for (row, line) in indices.chunks_exact(ihdr.width() as usize).zip(out.chunks_exact_mut(stride)) {
+        for (x, index) in row.iter().enumerate() {
+            let bit = x * bits;
+            line[bit / 8] |= index << (8 - bits - bit % 8);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn rgb_png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 3]) -> Png {
+        let pixel = &pixel;
+        let pixels: Vec<u8> = (0..height).flat_map(|y| (0..width).flat_map(move |x| pixel(x, y))).collect();
+        Png::from_pixels(width, height, ColorType::Rgb, &pixels, EncodeOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_quantize_exact() {
+        // four flat colours fit a 2 bit palette without loss
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [9, 9, 9]];
+        let mut png = rgb_png(16, 16, |x, y| colors[((x / 8) + (y / 8) * 2) as usize]);
+        let before = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        png.quantize(256, DitherMode::None).unwrap();
+
+        let ihdr = png.header().unwrap();
+        assert_eq!((ihdr.color_type(), ihdr.bit_depth()), (ColorType::Indexed, 2));
+        assert_eq!(png.palette().unwrap().unwrap().len(), 4);
+        assert!(png.chunk_by_type("tRNS").is_none());
+        assert!(png.validate().is_ok());
+        assert_eq!(png.decode_pixels().unwrap().to_rgba8().unwrap(), before);
+    }
+
+    #[test]
+    fn test_quantize_reduces() {
+        let mut png = rgb_png(32, 32, |x, y| [(x * 8) as u8, (y * 8) as u8, 128]);
+        png.quantize(16, DitherMode::None).unwrap();
+        let palette = png.palette().unwrap().unwrap();
+        assert_eq!(palette.len(), 16);
+        assert_eq!(png.header().unwrap().bit_depth(), 4);
+
+        let image = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        // every pixel is close to what it was
+        for (x, y) in [(0, 0), (31, 31), (10, 20)] {
+            let p = image.pixel(x, y);
+            assert!((p[0] as i32 - (x * 8) as i32).abs() < 48, "{:?}", p);
+            assert!((p[1] as i32 - (y * 8) as i32).abs() < 48, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_quantize_dither() {
+        // a ramp between two colours dithers into a mix of both
+        let mut png = rgb_png(64, 4, |x, _| [(x * 4) as u8; 3]);
+        png.quantize(2, DitherMode::FloydSteinberg).unwrap();
+        assert_eq!(png.header().unwrap().bit_depth(), 1);
+        let image = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        let middle: Vec<u8> = (28..36).map(|x| image.pixel(x, 1)[0]).collect();
+        assert!(middle.windows(2).any(|w| w[0] != w[1]), "{:?}", middle);
+    }
+
+    #[test]
+    fn test_quantize_alpha_and_chunks() {
+        let pixels: Vec<u8> = (0..8u8).flat_map(|n| [n * 30, 0, 0, if n < 4 { 0 } else { 255 }]).collect();
+        let mut png = Png::from_pixels(8, 1, ColorType::Rgba, &pixels, EncodeOptions::default()).unwrap();
+        png.insert_before_type("IDAT", crate::chunk::Chunk::new(ChunkType::BKGD, vec![0; 6])).unwrap();
+        png.quantize(8, DitherMode::None).unwrap();
+        assert!(png.chunk_by_type("bKGD").is_none());
+        assert_eq!(png.chunk_by_type("tRNS").unwrap().data(), &[0, 0, 0, 0]);
+        assert!(png.validate().is_ok());
+        let image = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        assert_eq!(image.data(), pixels.as_slice());
+    }
+
+    #[test]
+    fn test_quantize_file() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.quantize(4, DitherMode::FloydSteinberg).unwrap();
+        assert!(png.validate().is_ok());
+        assert_eq!(png.palette().unwrap().unwrap().len(), 4);
+        assert!(png.quantize(0, DitherMode::None).is_err());
+        assert!(png.quantize(257, DitherMode::None).is_err());
+    }
+}
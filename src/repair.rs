@@ -0,0 +1,132 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Which fixes `Png::repair` is allowed to make.
+/// The default makes all of them.
+#[derive(Debug, Clone)]
+pub struct RepairOptions {
+    /// Recalculate crcs that do not match their chunk.
+    pub fix_crc: bool,
+    /// Set length fields to the length of the data actually held.
+    pub fix_length: bool,
+    /// Append an IEND chunk if there is none.
+    pub add_iend: bool,
+    /// Drop chunks at the end of the file that hold less data than
+    /// their length says, as left by `ParseOptions::keep_truncated`.
+    pub drop_truncated: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        RepairOptions { fix_crc: true, fix_length: true, add_iend: true, drop_truncated: true }
+    }
+}
+
+/// What `Png::repair` changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub crcs_fixed: usize,
+    pub lengths_fixed: usize,
+    pub truncated_dropped: usize,
+    pub iend_added: bool,
+}
+
+impl RepairReport {
+    /// Returns true if nothing needed fixing.
+    pub fn is_clean(&self) -> bool {
+        *self == RepairReport::default()
+    }
+}
+
+impl Png {
+    /// Salvages a damaged `Png`, usually one parsed with
+    /// `ParseOptions::lenient`. Truncated trailing chunks are dropped
+    /// first, then lengths and crcs are fixed and finally IEND is
+    /// added if it is missing.
+    pub fn repair(&mut self, options: RepairOptions) -> RepairReport {
+        let mut report = RepairReport::default();
+        let chunks = self.chunks_mut();
+
+        if options.drop_truncated {
+            while chunks.last().is_some_and(|c| (c.length() as usize) > c.data().len()) {
+                chunks.pop();
+                report.truncated_dropped += 1;
+            }
+        }
+
+        for c in chunks.iter_mut() {
+            if options.fix_length && c.fix_length() {
+                report.lengths_fixed += 1;
+            }
+            if options.fix_crc && c.fix_crc() {
+                report.crcs_fixed += 1;
+            }
+        }
+
+        if options.add_iend && self.chunk_by_type("IEND").is_none() {
+            self.append_chunk(Chunk::new(ChunkType::IEND, Vec::new()));
+            report.iend_added = true;
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::parse::ParseOptions;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_repair_clean() {
+        let mut png = Png::try_from(testing_bytes().as_ref()).unwrap();
+        assert!(png.repair(RepairOptions::default()).is_clean());
+    }
+
+    #[test]
+    fn test_repair_crc() {
+        let mut bytes = testing_bytes();
+        bytes[8 + 8] ^= 1; // first byte of data of the first chunk
+        let mut png = Png::from_bytes_with(&bytes, &ParseOptions::lenient()).unwrap();
+        let report = png.repair(RepairOptions::default());
+        assert_eq!(report.crcs_fixed, 1);
+        assert!(png.iter().all(|c| c.has_valid_crc()));
+        assert!(Png::try_from(png.as_bytes().as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_repair_length() {
+        let mut bytes = Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"data".to_vec()).as_bytes();
+        bytes[3] = 2;
+        let chunk = Chunk::from_bytes_unchecked(&bytes).unwrap();
+        let mut png = Png::from_chunks(vec![chunk]);
+        let report = png.repair(RepairOptions { add_iend: false, ..Default::default() });
+        assert_eq!(report.lengths_fixed, 1);
+        assert_eq!(report.crcs_fixed, 0);
+        assert_eq!(png.chunks()[0].length(), 4);
+    }
+
+    #[test]
+    fn test_repair_truncated_and_iend() {
+        let bytes = testing_bytes();
+        // cut off IEND and half of the second chunk
+        let cut = &bytes[..bytes.len() - 12 - 10];
+        let mut png = Png::from_bytes_with(cut, &ParseOptions::lenient()).unwrap();
+        let report = png.repair(RepairOptions::default());
+        assert_eq!(report.truncated_dropped, 1);
+        assert!(report.iend_added);
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "IEND"]);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::known::KnownChunk;
+
+/// How many bits of each sample were significant in the source, from sBIT.
+/// One value per channel, with red, green and blue for indexed images.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignificantBits {
+    bits: Vec<u8>,
+}
+
+impl SignificantBits {
+    /// # Errors
+    /// returns an Error if there are not 1 to 4 values or one is 0.
+    pub fn new(bits: Vec<u8>) -> Result<SignificantBits, Error> {
+        if bits.is_empty() || bits.len() > 4 {
+            bail!("sBIT needs 1 to 4 values, got {}", bits.len());
+        }
+        if bits.contains(&0) {
+            bail!("sBIT values must be positive: {:?}", bits);
+        }
+        Ok(SignificantBits { bits })
+    }
+
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Checks the values match `ihdr`: one per channel, or three for
+    /// indexed, each at most the bit depth, or 8 for indexed.
+    ///
+    /// # Errors
+    /// returns an Error if they do not.
+    pub fn check(&self, ihdr: &Ihdr) -> Result<(), Error> {
+        let (count, max) = match ihdr.color_type() {
+            ColorType::Indexed => (3, 8),
+            color_type => (color_type.channels() as usize, ihdr.bit_depth()),
+        };
+        if self.bits.len() != count {
+            bail!("sBIT has {} values, a {:?} image needs {}", self.bits.len(), ihdr.color_type(), count);
+        }
+        if self.bits.iter().any(|b| *b > max) {
+            bail!("sBIT values {:?} are more than {} bits", self.bits, max);
+        }
+        Ok(())
+    }
+}
+
+impl KnownChunk for SignificantBits {
+    const TYPE: ChunkType = ChunkType::SBIT;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::PLTE, ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<SignificantBits, Error> {
+        if *chunk.chunk_type() != ChunkType::SBIT {
+            bail!("expected sBIT, got {}", chunk.chunk_type());
+        }
+        SignificantBits::new(chunk.data().to_vec())
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::SBIT, self.bits.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_significant_bits() {
+        let sbit = SignificantBits::new(vec![5, 6, 5]).unwrap();
+        assert_eq!(SignificantBits::parse(&sbit.to_chunk()).unwrap(), sbit);
+        assert!(sbit.check(&Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap()).is_ok());
+        assert!(sbit.check(&Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap()).is_ok());
+        assert!(sbit.check(&Ihdr::new(1, 1, 8, ColorType::Rgba).unwrap()).is_err());
+        let gray = Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap();
+        assert!(SignificantBits::new(vec![9]).unwrap().check(&gray).is_err());
+        assert!(SignificantBits::new(vec![]).is_err());
+        assert!(SignificantBits::new(vec![0, 1]).is_err());
+
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_typed_chunk(&sbit);
+        assert_eq!(png.typed_chunk::<SignificantBits>().unwrap(), Some(sbit));
+        let at = |t| png.chunks().iter().position(|c| *c.chunk_type() == t).unwrap();
+        assert!(at(ChunkType::SBIT) < at(ChunkType::PLTE));
+    }
+}
@@ -0,0 +1,433 @@
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "flate")]
+use crate::decode::image_data_len;
+#[cfg(feature = "flate")]
+use crate::parse::Limits;
+use crate::png::Png;
+use crate::standard;
+use crate::text::inflate;
+
+/// Text chunks with more data than this are flagged as oversized.
+/// Real metadata, XMP included, is rarely anywhere near it.
+pub const TEXT_SIZE_LIMIT: u32 = 16 * 1024;
+/// Ancillary data with more bits of entropy per byte than this looks
+/// compressed or encrypted.
+pub const ENTROPY_LIMIT: f64 = 7.5;
+/// Below this many bytes the measured entropy is too low to mean anything.
+const MIN_ENTROPY_LEN: usize = 256;
+/// Chi-square probability over which the sample LSBs look embedded.
+pub const LSB_PROBABILITY_LIMIT: f64 = 0.95;
+/// Fewest samples the chi-square test is run on.
+#[cfg(feature = "flate")]
+const MIN_LSB_SAMPLES: usize = 1024;
+/// Most bytes deflate can inflate one compressed byte to.
+#[cfg(feature = "flate")]
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+/// Something about a `Png` that hints at hidden data, see `Png::scan_anomalies`.
+/// Chunk indexes count from 0 and do not include the header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    /// A chunk type that is neither in the spec nor a registered extension.
+    NonStandardChunk { index: usize, chunk_type: ChunkType },
+    /// Chunks or a trailer after IEND, which decoders never read.
+    /// `bytes` counts both.
+    DataAfterIend { chunks: usize, bytes: u64 },
+    /// An ancillary chunk whose data, decompressed if the type is
+    /// compressed, is close to random: `entropy` is in bits per byte.
+    HighEntropy { index: usize, chunk_type: ChunkType, entropy: f64 },
+    /// A text chunk over `TEXT_SIZE_LIMIT`.
+    OversizedText { index: usize, chunk_type: ChunkType, length: u32 },
+    /// The least significant bits of the samples are spread as evenly as
+    /// random data embedded in them would: `probability` is the result of
+    /// the chi-square attack of Westfeld and Pfitzmann.
+    LsbEmbedding { probability: f64 },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::NonStandardChunk { index, chunk_type } => {
+                write!(f, "chunk {} has non-standard type {}", index, chunk_type)
+            }
+            Anomaly::DataAfterIend { chunks, bytes } => {
+                write!(f, "{} chunks, {} bytes after IEND", chunks, bytes)
+            }
+            Anomaly::HighEntropy { index, chunk_type, entropy } => {
+                write!(f, "{} at chunk {} looks random, {:.2} bits per byte", chunk_type, index, entropy)
+            }
+            Anomaly::OversizedText { index, chunk_type, length } => {
+                write!(f, "{} at chunk {} is {} bytes", chunk_type, index, length)
+            }
+            Anomaly::LsbEmbedding { probability } => {
+                write!(f, "sample LSBs look embedded, chi-square p = {:.3}", probability)
+            }
+        }
+    }
+}
+
+/// Every anomaly `Png::scan_anomalies` found, in the order of the checks.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    anomalies: Vec<Anomaly>,
+}
+
+impl Report {
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+
+    /// True if nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_clean() {
+            return write!(f, "no anomalies");
+        }
+        for a in &self.anomalies {
+            writeln!(f, "{}", a)?;
+        }
+        Ok(())
+    }
+}
+
+impl Png {
+    /// Looks for the ways data is commonly hidden in a PNG, this crate's
+    /// included: non-standard chunks, chunks after IEND, ancillary data
+    /// that looks compressed or encrypted, oversized text, and with the
+    /// `flate` feature LSB embedding in 8-bit samples.
+    /// These are hints, a clean report does not prove nothing is hidden
+    /// and odd files can be flagged without hiding anything.
+    pub fn scan_anomalies(&self) -> Report {
+        let mut anomalies = Vec::new();
+        let chunks = self.chunks();
+
+        for (index, c) in chunks.iter().enumerate() {
+            let chunk_type = *c.chunk_type();
+            if standard::lookup(&chunk_type).is_none() {
+                anomalies.push(Anomaly::NonStandardChunk { index, chunk_type });
+            }
+        }
+
+        let end = chunks.iter().position(|c| *c.chunk_type() == ChunkType::IEND);
+        let after = end.map_or(&[][..], |end| &chunks[end + 1..]);
+        if !after.is_empty() || !self.trailer().is_empty() {
+            let bytes = after.iter().map(|c| c.total_size()).sum::<u64>() + self.trailer().len() as u64;
+            anomalies.push(Anomaly::DataAfterIend { chunks: after.len(), bytes });
+        }
+
+        for (index, c) in chunks.iter().enumerate() {
+            if c.chunk_type().is_critical() {
+                continue;
+            }
+            if let Some(data) = plain_data(c) {
+                let entropy = entropy(&data);
+                if data.len() >= MIN_ENTROPY_LEN && entropy > ENTROPY_LIMIT {
+                    anomalies.push(Anomaly::HighEntropy { index, chunk_type: *c.chunk_type(), entropy });
+                }
+            }
+        }
+
+        for (index, c) in chunks.iter().enumerate() {
+            let text = [ChunkType::TEXT, ChunkType::ZTXT, ChunkType::ITXT].contains(c.chunk_type());
+            if text && c.length() > TEXT_SIZE_LIMIT {
+                let (chunk_type, length) = (*c.chunk_type(), c.length());
+                anomalies.push(Anomaly::OversizedText { index, chunk_type, length });
+            }
+        }
+
+        #[cfg(feature = "flate")]
+        if let Some(probability) = self.lsb_probability() {
+            if probability > LSB_PROBABILITY_LIMIT {
+                anomalies.push(Anomaly::LsbEmbedding { probability });
+            }
+        }
+
+        Report { anomalies }
+    }
+
+    /// Chi-square probability that the LSBs of the samples were replaced,
+    /// `None` if the image is not 8-bit, can not be decoded or is too small.
+    /// Also `None` if the header asks for more image data than the limits
+    /// allow or the IDAT chunks could ever inflate to, which is checked
+    /// before anything is inflated or allocated.
+    #[cfg(feature = "flate")]
+    fn lsb_probability(&self) -> Option<f64> {
+        let ihdr = self.header().ok()?;
+        if ihdr.bit_depth() != 8 {
+            return None;
+        }
+        let needed = image_data_len(&ihdr, Limits::default().max_decompressed).ok()?;
+        let compressed: u64 = self.idat_chunks().iter().map(|c| c.data().len() as u64).sum();
+        if needed as u64 > compressed.saturating_mul(MAX_DEFLATE_RATIO) {
+            return None;
+        }
+        let image = self.decode_pixels().ok()?;
+        if image.data().len() < MIN_LSB_SAMPLES {
+            return None;
+        }
+        let mut histogram = [0u64; 256];
+        for s in image.data() {
+            histogram[*s as usize] += 1;
+        }
+        // embedding evens out each pair of values that differ in the LSB
+        let mut chi = 0.0;
+        let mut pairs = 0;
+        for pair in histogram.chunks(2) {
+            let expected = (pair[0] + pair[1]) as f64 / 2.0;
+            if expected > 0.0 {
+                chi += (pair[0] as f64 - expected).powi(2) / expected;
+                pairs += 1;
+            }
+        }
+        if pairs < 2 {
+            return None;
+        }
+        Some(1.0 - chi_square_cdf(chi, (pairs - 1) as f64))
+    }
+}
+
+/// The data of an ancillary chunk as it would be read, inflated for the
+/// compressed types. `None` if it is compressed and can not be inflated.
+fn plain_data(chunk: &Chunk) -> Option<Vec<u8>> {
+    let data = chunk.data();
+    let compressed = match *chunk.chunk_type() {
+        // keyword, null and compression method
+        ChunkType::ZTXT | ChunkType::ICCP => {
+            let start = data.iter().position(|b| *b == 0)? + 2;
+            data.get(start..)?
+        }
+        // keyword, null, compression flag and method, language and translated keyword
+        ChunkType::ITXT => {
+            let keyword = data.iter().position(|b| *b == 0)?;
+            if *data.get(keyword + 1)? == 0 {
+                return Some(data.to_vec());
+            }
+            let rest = data.get(keyword + 3..)?;
+            let language = rest.iter().position(|b| *b == 0)?;
+            let translated = rest[language + 1..].iter().position(|b| *b == 0)?;
+            &rest[language + translated + 2..]
+        }
+        _ => return Some(data.to_vec()),
+    };
+    inflate(compressed).ok()
+}
+
+/// Shannon entropy of `data` in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for b in data {
+        counts[*b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts.iter().filter(|n| **n > 0).map(|n| *n as f64 / len).map(|p| -p * p.log2()).sum()
+}
+
+/// Chance a chi-square variable with `df` degrees of freedom is at most `x`.
+#[cfg(feature = "flate")]
+fn chi_square_cdf(x: f64, df: f64) -> f64 {
+    lower_gamma(df / 2.0, x / 2.0)
+}
+
+/// Regularized lower incomplete gamma function P(a, x), by its series
+/// for small x and its continued fraction otherwise.
+#[cfg(feature = "flate")]
+fn lower_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let front = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        return sum * front;
+    }
+    // modified Lentz's method
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        d = if d.abs() < tiny { 1.0 / tiny } else { 1.0 / d };
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    1.0 - front * h
+}
+
+/// ln Γ(x) for x > 0, Lanczos approximation.
+#[cfg(feature = "flate")]
+fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let mut y = x;
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000000000190015;
+    for g in G {
+        y += 1.0;
+        series += g / y;
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stego::{EmbedOptions, MANIFEST_TYPE, PART_TYPE};
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    /// Deterministic noise for the tests.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let png = Png::try_from(IMG).unwrap();
+        assert!(png.scan_anomalies().is_clean(), "{}", png.scan_anomalies());
+        assert_eq!(png.scan_anomalies().to_string(), "no anomalies");
+    }
+
+    #[test]
+    fn test_chunk_anomalies() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.embed_payload("msg", &noise(1000), &EmbedOptions::default()).unwrap();
+        let text = [b"Comment\0".as_slice(), &[b'a'; 20_000]].concat();
+        png.insert_before_type("IEND", Chunk::new(ChunkType::TEXT, text)).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::TIME, vec![0; 7]));
+
+        let report = png.scan_anomalies();
+        let iend = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IEND).unwrap();
+        let anomalies = report.anomalies();
+        assert_eq!(anomalies[0], Anomaly::NonStandardChunk { index: iend - 3, chunk_type: MANIFEST_TYPE });
+        assert_eq!(anomalies[1], Anomaly::NonStandardChunk { index: iend - 2, chunk_type: PART_TYPE });
+        assert_eq!(anomalies[2], Anomaly::DataAfterIend { chunks: 1, bytes: 19 });
+        match anomalies[3] {
+            Anomaly::HighEntropy { index, entropy, .. } => assert!(index == iend - 2 && entropy > 7.5),
+            ref a => panic!("{:?}", a),
+        }
+        assert_eq!(
+            anomalies[4],
+            Anomaly::OversizedText { index: iend - 1, chunk_type: ChunkType::TEXT, length: 20_008 }
+        );
+        assert_eq!(anomalies.len(), 5);
+        assert_eq!(report.to_string().lines().count(), 5);
+    }
+
+    #[test]
+    fn test_trailer_anomaly() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_trailer(b"appended".to_vec());
+        assert_eq!(png.scan_anomalies().anomalies(), [Anomaly::DataAfterIend { chunks: 0, bytes: 8 }]);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[7; 100]), 0.0);
+        assert_eq!(entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+        assert!(entropy(&noise(4096)) > ENTROPY_LIMIT);
+        assert!(entropy(b"plain english text is far from random, even when long") < 5.0);
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_compressed_chunk_entropy() {
+        use std::io::Write;
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&noise(2000)).unwrap();
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(encoder.finish().unwrap());
+        let mut png = Png::try_from(IMG).unwrap();
+        png.insert_before_type("IEND", Chunk::new(ChunkType::ZTXT, data)).unwrap();
+        assert!(matches!(png.scan_anomalies().anomalies(), [Anomaly::HighEntropy { .. }]));
+
+        // ordinary compressed text is fine
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&b"a long and boring comment ".repeat(100)).unwrap();
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(encoder.finish().unwrap());
+        let mut png = Png::try_from(IMG).unwrap();
+        png.insert_before_type("IEND", Chunk::new(ChunkType::ZTXT, data)).unwrap();
+        assert!(png.scan_anomalies().is_clean());
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_lsb_chi_square() {
+        use crate::encode::EncodeOptions;
+        use crate::ihdr::ColorType;
+
+        // a cover with only even values, far from what embedding leaves behind
+        let cover: Vec<u8> = noise(64 * 64).iter().map(|s| (s / 2) & 0xfe).collect();
+        let png = Png::from_pixels(64, 64, ColorType::Grayscale, &cover, EncodeOptions::default()).unwrap();
+        assert!(png.lsb_probability().unwrap() < 0.01);
+        assert!(png.scan_anomalies().is_clean());
+
+        let bits = noise(64 * 64 + 7);
+        let stego: Vec<u8> = cover.iter().zip(&bits[7..]).map(|(s, b)| s | (b >> 3 & 1)).collect();
+        let png = Png::from_pixels(64, 64, ColorType::Grayscale, &stego, EncodeOptions::default()).unwrap();
+        assert!(png.lsb_probability().unwrap() > 0.95);
+        assert!(matches!(png.scan_anomalies().anomalies(), [Anomaly::LsbEmbedding { .. }]));
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_lsb_huge_header() {
+        use crate::ihdr::{ColorType, Ihdr};
+
+        // a small file whose header claims 100000x100000 RGBA
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_header(&Ihdr::new(100_000, 100_000, 8, ColorType::Rgba).unwrap());
+        assert_eq!(png.lsb_probability(), None);
+        assert!(png.scan_anomalies().is_clean());
+
+        // under the limit, but far more than the IDAT can hold
+        png.set_header(&Ihdr::new(10_000, 10_000, 8, ColorType::Grayscale).unwrap());
+        assert_eq!(png.lsb_probability(), None);
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_chi_square_cdf() {
+        // df = 2 is exponential with mean 2
+        assert!((chi_square_cdf(2.0, 2.0) - (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+        assert!((chi_square_cdf(3.84, 1.0) - 0.95).abs() < 1e-3);
+        assert!((chi_square_cdf(124.3, 100.0) - 0.95).abs() < 1e-3);
+        assert_eq!(chi_square_cdf(0.0, 5.0), 0.0);
+    }
+}
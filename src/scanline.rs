@@ -0,0 +1,202 @@
+use std::convert::TryFrom;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::decode::{filter_bpp, filtered_len};
+use crate::error::Error;
+use crate::filter::{unfilter, FilterType};
+use crate::ihdr::Ihdr;
+use crate::image::Image;
+use crate::parse::Limits;
+use crate::png::Png;
+
+/// One unfiltered row of pixels, packed like a row of `Image`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scanline {
+    y: u32,
+    filter: FilterType,
+    data: Vec<u8>,
+}
+
+impl Scanline {
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    /// The filter the row was stored with.
+    pub fn filter(&self) -> FilterType {
+        self.filter
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Iterator returned by `Png::scanlines`.
+pub struct Scanlines {
+    ihdr: Ihdr,
+    y: u32,
+    source: Source,
+    done: bool,
+}
+
+enum Source {
+    Stream { decoder: ZlibDecoder<std::io::Cursor<Vec<u8>>>, stride: usize, prev: Vec<u8>, line: Vec<u8> },
+    // interlaced rows are spread over the whole stream
+    Decoded(Image),
+}
+
+impl Png {
+    /// Iterates over the rows of the image, inflating and unfiltering
+    /// one at a time so only two rows are held at once. Interlaced
+    /// images are an exception: their rows are spread over the whole
+    /// stream, so they are decoded in full up front.
+    ///
+    /// # Errors
+    /// returns an Error if the header is invalid or there is no IDAT,
+    /// `LimitExceeded` if the header asks for more image data than
+    /// `Limits::default` allows, the iterator yields errors for broken
+    /// image data.
+    pub fn scanlines(&self) -> Result<Scanlines, Error> {
+        let ihdr = self.header()?;
+        let source = if ihdr.is_interlaced() {
+            Source::Decoded(self.decode_pixels()?)
+        } else {
+            filtered_len(&ihdr, ihdr.width() as usize, ihdr.height() as usize, Limits::default().max_decompressed)?;
+            let stride = ihdr.row_bytes(ihdr.width());
+            let stream = std::io::Cursor::new(self.idat_compressed()?);
+            // the rows grow as data is inflated into them, so a header
+            // claiming huge rows costs no more than the stream holds
+            Source::Stream { decoder: ZlibDecoder::new(stream), stride, prev: Vec::new(), line: Vec::new() }
+        };
+        Ok(Scanlines { ihdr, y: 0, source, done: false })
+    }
+}
+
+impl Scanlines {
+    fn read_row(&mut self) -> Result<Scanline, Error> {
+        let y = self.y;
+        match &mut self.source {
+            Source::Decoded(image) => {
+                Ok(Scanline { y, filter: FilterType::None, data: image.row(y).to_vec() })
+            }
+            Source::Stream { decoder, stride, prev, line } => {
+                line.clear();
+                decoder.by_ref().take(*stride as u64 + 1).read_to_end(line)?;
+                if line.len() <= *stride {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+                if prev.is_empty() {
+                    prev.resize(*stride, 0);
+                }
+                let filter = FilterType::try_from(line[0])?;
+                let mut row = line[1..].to_vec();
+                unfilter(filter, filter_bpp(&self.ihdr), prev, &mut row);
+                prev.copy_from_slice(&row);
+                Ok(Scanline { y, filter, data: row })
+            }
+        }
+    }
+}
+
+impl Iterator for Scanlines {
+    type Item = Result<Scanline, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.y >= self.ihdr.height() {
+            return None;
+        }
+        let row = self.read_row();
+        self.y += 1;
+        // rows after a broken one can not be unfiltered
+        self.done = row.is_err();
+        Some(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::encode::EncodeOptions;
+    use crate::ihdr::ColorType;
+    use std::io::Write;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_scanlines() {
+        let png = Png::try_from(IMG).unwrap();
+        let image = png.decode_pixels().unwrap();
+        let mut count = 0;
+        for (row, expected) in png.scanlines().unwrap().zip(image.rows()) {
+            let row = row.unwrap();
+            assert_eq!(row.y(), count);
+            assert_eq!(row.data(), expected);
+            count += 1;
+        }
+        assert_eq!(count, image.height());
+    }
+
+    #[test]
+    fn test_scanlines_interlaced() {
+        // 3x3 gray, pixel value = index, see decode tests
+        let ihdr = Ihdr::new(3, 3, 8, ColorType::Grayscale).unwrap().with_interlace(true);
+        let raw = [0, 0, 0, 2, 0, 6, 8, 0, 1, 0, 7, 0, 3, 4, 5];
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, encoder.finish().unwrap()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        let rows: Vec<Vec<u8>> = png.scanlines().unwrap().map(|r| r.unwrap().into_data()).collect();
+        assert_eq!(rows, vec![vec![0, 1, 2], vec![3, 4, 5], vec![6, 7, 8]]);
+    }
+
+    #[test]
+    fn test_scanlines_encoded() {
+        let pixels: Vec<u8> = (0..9 * 5 * 3).map(|n| (n * 5) as u8).collect();
+        let png = Png::from_pixels(9, 5, ColorType::Rgb, &pixels, EncodeOptions::default()).unwrap();
+        let rows: Vec<Vec<u8>> = png.scanlines().unwrap().map(|r| r.unwrap().into_data()).collect();
+        assert_eq!(rows.concat(), pixels);
+    }
+
+    #[test]
+    fn test_scanlines_truncated() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let idat = png.chunk_by_type_mut("IDAT").unwrap();
+        let half = idat.data()[..idat.data().len() / 2].to_vec();
+        idat.set_data(half);
+        let rows: Vec<_> = png.scanlines().unwrap().collect();
+        assert!(rows.last().unwrap().is_err());
+        assert!(rows[0].is_ok());
+    }
+
+    #[test]
+    fn test_scanlines_huge_header() {
+        let stream = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default()).finish().unwrap();
+        let png_of = |ihdr: Ihdr| {
+            Png::from_chunks(vec![
+                ihdr.to_chunk(),
+                Chunk::new(ChunkType::IDAT, stream.clone()),
+                Chunk::new(ChunkType::IEND, Vec::new()),
+            ])
+        };
+        let huge = png_of(Ihdr::new(i32::MAX as u32, 1, 16, ColorType::Rgba).unwrap());
+        assert!(matches!(huge.scanlines(), Err(Error::LimitExceeded("max_decompressed"))));
+
+        // under the limit, but the stream is empty so no row is allocated in full
+        let wide = png_of(Ihdr::new(100_000_000, 2, 8, ColorType::Grayscale).unwrap());
+        let rows: Vec<_> = wide.scanlines().unwrap().collect();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].is_err());
+    }
+}
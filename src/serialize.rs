@@ -0,0 +1,180 @@
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Bytes as base64 for human readable formats like JSON,
+/// as plain bytes for binary ones like CBOR.
+struct Data(Vec<u8>);
+
+impl Serialize for Data {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&BASE64.encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Data {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Data, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            BASE64.decode(s).map(Data).map_err(D::Error::custom)
+        } else {
+            deserialize_bytes(deserializer).map(Data)
+        }
+    }
+}
+
+/// Reads a byte buffer, or a sequence of bytes from formats that
+/// have no buffer type.
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "bytes")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element()? {
+                bytes.push(b);
+            }
+            Ok(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(Visitor)
+}
+
+/// The four letters, e.g. `"IHDR"`.
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ChunkType, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        ChunkType::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkRepr {
+    #[serde(rename = "type")]
+    chunk_type: ChunkType,
+    #[serde(default)]
+    length: Option<u32>,
+    data: Data,
+    #[serde(default)]
+    crc: Option<u32>,
+}
+
+/// Type, length, data and crc as they are, so a chunk with a wrong
+/// length or crc comes back the same. Both are optional when
+/// deserializing and computed from the data when left out.
+impl Serialize for Chunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ChunkRepr {
+            chunk_type: *self.chunk_type(),
+            length: Some(self.length()),
+            data: Data(self.data().to_vec()),
+            crc: Some(self.crc()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Chunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Chunk, D::Error> {
+        let repr = ChunkRepr::deserialize(deserializer)?;
+        let data = repr.data.0;
+        let length = repr.length.unwrap_or(data.len() as u32);
+        let crc = repr.crc.unwrap_or_else(|| Chunk::calculate_crc(&repr.chunk_type, &data));
+        Ok(Chunk::from_parts(length, repr.chunk_type, data, crc))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PngRepr {
+    chunks: Vec<Chunk>,
+    #[serde(default = "no_trailer")]
+    trailer: Data,
+}
+
+fn no_trailer() -> Data {
+    Data(Vec::new())
+}
+
+/// The chunks in order and the trailer, which may be left out.
+impl Serialize for Png {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PngRepr { chunks: self.chunks().to_vec(), trailer: Data(self.trailer().to_vec()) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Png {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Png, D::Error> {
+        let repr = PngRepr::deserialize(deserializer)?;
+        let mut png = Png::from_chunks(repr.chunks);
+        png.set_trailer(repr.trailer.0);
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ParseOptions;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_json_roundtrip() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_trailer(b"after".to_vec());
+        let json = serde_json::to_string(&png).unwrap();
+        assert!(json.starts_with(r#"{"chunks":[{"type":"IHDR","length":13,"data":""#));
+        assert!(json.ends_with(r#""trailer":"YWZ0ZXI="}"#));
+        let back: Png = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, png);
+        assert_eq!(back.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_chunk_json() {
+        let chunk: Chunk = serde_json::from_str(r#"{"type":"tEXt","data":"aGk="}"#).unwrap();
+        assert_eq!(chunk, Chunk::new(ChunkType::TEXT, b"hi".to_vec()));
+        assert!(serde_json::from_str::<Chunk>(r#"{"type":"t3Xt","data":""}"#).is_err());
+        assert!(serde_json::from_str::<Chunk>(r#"{"type":"tEXt","data":"!"}"#).is_err());
+
+        // a bad crc is kept as is
+        let mut bytes = Png::try_from(IMG).unwrap().as_bytes();
+        bytes[8 + 12 + 13 - 1] ^= 1;
+        let png = Png::from_bytes_with(&bytes, &ParseOptions::lenient()).unwrap();
+        let back: Png = serde_json::from_str(&serde_json::to_string(&png).unwrap()).unwrap();
+        assert!(!back.chunks()[0].has_valid_crc());
+        assert_eq!(back.as_bytes(), bytes);
+    }
+}
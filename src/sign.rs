@@ -0,0 +1,186 @@
+use ed25519_dalek::{Signature, Signer, Verifier};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// Holds a signature: scope, public key and the Ed25519 signature.
+/// Private, ancillary and safe to copy, so editors keep it.
+pub const SIGNATURE_TYPE: ChunkType = ChunkType::from_bytes_const(*b"sgNt");
+
+/// Prefixed to every signed message, so a signature over an image can
+/// not be passed off as one over anything else.
+const CONTEXT: &[u8] = b"nice_pics signature v1";
+
+/// What `Png::sign` covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignScope {
+    /// The decoded pixels with their dimensions, format, palette and
+    /// transparency. Survives metadata edits and recompression.
+    Pixels,
+    /// The type and data of every chunk in order, signatures aside.
+    AllChunks,
+}
+
+impl SignScope {
+    fn to_byte(self) -> u8 {
+        match self {
+            SignScope::Pixels => 0,
+            SignScope::AllChunks => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<SignScope, Error> {
+        Ok(match byte {
+            0 => SignScope::Pixels,
+            1 => SignScope::AllChunks,
+            _ => bail!("unknown signature scope: {}", byte),
+        })
+    }
+}
+
+impl Png {
+    /// Signs the image with Ed25519 and stores the detached signature,
+    /// with `scope` and the public key, in a `sgNt` chunk before IEND.
+    /// A signature by the same key is replaced, others are kept so an
+    /// image can carry several.
+    ///
+    /// # Errors
+    /// returns an Error if `scope` is `Pixels` and the image can not be decoded.
+    pub fn sign(&mut self, signing_key: &SigningKey, scope: SignScope) -> Result<(), Error> {
+        let public = signing_key.verifying_key();
+        self.remove_signatures(&public);
+        let signature = signing_key.sign(&self.signed_message(scope)?);
+
+        let mut data = vec![scope.to_byte()];
+        data.extend(public.as_bytes());
+        data.extend(signature.to_bytes());
+        let chunk = Chunk::new(SIGNATURE_TYPE, data);
+        let at = self.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IEND);
+        let at = at.unwrap_or(self.chunks().len());
+        self.chunks_mut().insert(at, chunk);
+        Ok(())
+    }
+
+    /// Checks the signature made with the key of `public_key`, returning
+    /// what it covers.
+    ///
+    /// # Errors
+    /// returns an Error if there is no signature by that key, it is
+    /// malformed, or the image changed in the scope it covers.
+    pub fn verify(&self, public_key: &VerifyingKey) -> Result<SignScope, Error> {
+        let data = match self.signatures().find(|d| d[1..33] == public_key.as_bytes()[..]) {
+            Some(d) => d,
+            None => bail!("no signature by this key"),
+        };
+        let scope = SignScope::from_byte(data[0])?;
+        let signature = Signature::from_slice(&data[33..]).map_err(|e| Error::Invalid(e.to_string()))?;
+        if public_key.verify(&self.signed_message(scope)?, &signature).is_err() {
+            bail!("signature does not match, the image was changed");
+        }
+        Ok(scope)
+    }
+
+    /// Removes the signatures by `public_key`, returning how many there were.
+    pub fn remove_signatures(&mut self, public_key: &VerifyingKey) -> usize {
+        let before = self.chunks().len();
+        let key = &public_key.as_bytes()[..];
+        let by_key = |c: &Chunk| *c.chunk_type() == SIGNATURE_TYPE && c.data().get(1..33) == Some(key);
+        self.chunks_mut().retain(|c| !by_key(c));
+        before - self.chunks().len()
+    }
+
+    /// The data of every well formed signature chunk.
+    fn signatures(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter()
+            .filter(|c| *c.chunk_type() == SIGNATURE_TYPE && c.length() == 1 + 32 + 64)
+            .map(|c| c.data())
+    }
+
+    /// The bytes signed for `scope`.
+    fn signed_message(&self, scope: SignScope) -> Result<Vec<u8>, Error> {
+        let mut message = CONTEXT.to_vec();
+        message.push(scope.to_byte());
+        match scope {
+            SignScope::Pixels => message.extend(self.pixel_content()?),
+            SignScope::AllChunks => {
+                for c in self.iter().filter(|c| *c.chunk_type() != SIGNATURE_TYPE) {
+                    message.extend(c.length().to_be_bytes());
+                    message.extend(c.chunk_type().bytes());
+                    message.extend(c.data());
+                }
+            }
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::EncodeOptions;
+    use crate::ihdr::ColorType;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn text(s: &str) -> Chunk {
+        Chunk::new(ChunkType::TEXT, s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_sign_pixels() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut png = Png::try_from(IMG).unwrap();
+        png.sign(&key, SignScope::Pixels).unwrap();
+        assert_eq!(png.verify(&key.verifying_key()).unwrap(), SignScope::Pixels);
+
+        // metadata edits and recompression keep it valid
+        png.insert_before_type("IEND", text("Comment\0edited")).unwrap();
+        png.deinterlace().unwrap();
+        png.interlace_adam7().unwrap();
+        assert!(png.verify(&key.verifying_key()).is_ok());
+
+        let mut palette = png.chunk_by_type("PLTE").unwrap().data().to_vec();
+        palette[0] ^= 1;
+        png.chunk_by_type_mut("PLTE").unwrap().set_data(palette);
+        assert!(png.verify(&key.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_sign_pixel_change() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let pixels = [1, 2, 3, 4];
+        let options = EncodeOptions::default();
+        let mut png = Png::from_pixels(2, 2, ColorType::Grayscale, &pixels, options.clone()).unwrap();
+        png.sign(&key, SignScope::Pixels).unwrap();
+        let signature = png.chunk_by_type("sgNt").unwrap().clone();
+
+        let mut forged = Png::from_pixels(2, 2, ColorType::Grayscale, &[1, 2, 3, 5], options).unwrap();
+        forged.insert_before_type("IEND", signature).unwrap();
+        assert!(forged.verify(&key.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_sign_all_chunks() {
+        let key = SigningKey::from_bytes(&[2; 32]);
+        let other = SigningKey::from_bytes(&[3; 32]);
+        let mut png = Png::try_from(IMG).unwrap();
+        png.sign(&key, SignScope::AllChunks).unwrap();
+        png.sign(&other, SignScope::Pixels).unwrap();
+        // signing again replaces the earlier signature
+        png.sign(&key, SignScope::AllChunks).unwrap();
+        assert_eq!(png.chunks_of_type("sgNt").count(), 2);
+        assert_eq!(png.verify(&key.verifying_key()).unwrap(), SignScope::AllChunks);
+
+        png.insert_before_type("IEND", text("Comment\0edited")).unwrap();
+        assert!(png.verify(&key.verifying_key()).is_err());
+        assert!(png.verify(&other.verifying_key()).is_ok());
+
+        assert_eq!(png.remove_signatures(&key.verifying_key()), 1);
+        assert!(png.verify(&key.verifying_key()).is_err());
+        assert!(png.verify(&SigningKey::from_bytes(&[4; 32]).verifying_key()).is_err());
+    }
+}
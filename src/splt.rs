@@ -0,0 +1,155 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::known::KnownChunk;
+use crate::text::check_keyword;
+
+/// One colour of a suggested palette, with samples at the palette depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SuggestedColor {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+    pub a: u16,
+    /// How often the colour is used, relative to the others.
+    pub frequency: u16,
+}
+
+/// A palette suggested for displays with few colours, from sPLT.
+/// An image can have several, each with its own name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SuggestedPalette {
+    name: String,
+    depth: u8,
+    entries: Vec<SuggestedColor>,
+}
+
+impl SuggestedPalette {
+    /// # Errors
+    /// returns an Error if `name` is not a valid keyword, see
+    /// `TextChunk::to_chunk`, `depth` is not 8 or 16, or a sample does
+    /// not fit it.
+    pub fn new(name: &str, depth: u8, entries: Vec<SuggestedColor>) -> Result<SuggestedPalette, Error> {
+        check_keyword(name)?;
+        if depth != 8 && depth != 16 {
+            bail!("sPLT depth must be 8 or 16, got {}", depth);
+        }
+        let too_big = |e: &SuggestedColor| [e.r, e.g, e.b, e.a].iter().any(|s| *s > 255);
+        if depth == 8 && entries.iter().any(too_big) {
+            bail!("sPLT samples must fit 8 bits");
+        }
+        Ok(SuggestedPalette { name: name.to_string(), depth, entries })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The sample depth, 8 or 16.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn entries(&self) -> &[SuggestedColor] {
+        &self.entries
+    }
+}
+
+impl KnownChunk for SuggestedPalette {
+    const TYPE: ChunkType = ChunkType::SPLT;
+    const BEFORE: &'static [ChunkType] = &[ChunkType::IDAT];
+
+    fn parse(chunk: &Chunk) -> Result<SuggestedPalette, Error> {
+        if *chunk.chunk_type() != ChunkType::SPLT {
+            bail!("expected sPLT, got {}", chunk.chunk_type());
+        }
+        let data = chunk.data();
+        let split = match data.iter().position(|b| *b == 0) {
+            Some(n) => n,
+            None => bail!("sPLT has no name separator"),
+        };
+        let name: String = data[..split].iter().map(|b| *b as char).collect();
+        let (depth, rest) = match data.get(split + 1) {
+            Some(depth) => (*depth, &data[split + 2..]),
+            None => bail!("sPLT has no sample depth"),
+        };
+        let size = match depth {
+            8 => 6,
+            16 => 10,
+            _ => bail!("sPLT depth must be 8 or 16, got {}", depth),
+        };
+        if !rest.len().is_multiple_of(size) {
+            bail!("sPLT entries of {} bytes do not divide {}", size, rest.len());
+        }
+        let sample = |bytes: &[u8], n: usize| match depth {
+            8 => bytes[n] as u16,
+            _ => u16::from_be_bytes([bytes[n * 2], bytes[n * 2 + 1]]),
+        };
+        let entries = rest
+            .chunks(size)
+            .map(|e| SuggestedColor {
+                r: sample(e, 0),
+                g: sample(e, 1),
+                b: sample(e, 2),
+                a: sample(e, 3),
+                frequency: u16::from_be_bytes([e[size - 2], e[size - 1]]),
+            })
+            .collect();
+        SuggestedPalette::new(&name, depth, entries)
+    }
+
+    fn to_chunk(&self) -> Chunk {
+        let mut data: Vec<u8> = self.name.chars().map(|c| c as u8).collect();
+        data.extend([0, self.depth]);
+        for e in &self.entries {
+            for s in [e.r, e.g, e.b, e.a] {
+                match self.depth {
+                    8 => data.push(s as u8),
+                    _ => data.extend(s.to_be_bytes()),
+                }
+            }
+            data.extend(e.frequency.to_be_bytes());
+        }
+        Chunk::new(ChunkType::SPLT, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn color(r: u16, frequency: u16) -> SuggestedColor {
+        SuggestedColor { r, g: 0, b: 0, a: 255, frequency }
+    }
+
+    #[test]
+    fn test_suggested_palette() {
+        let palette = SuggestedPalette::new("web", 8, vec![color(255, 10), color(0, 1)]).unwrap();
+        let chunk = palette.to_chunk();
+        assert_eq!(chunk.data(), b"web\0\x08\xff\0\0\xff\0\x0a\0\0\0\xff\0\x01");
+        assert_eq!(SuggestedPalette::parse(&chunk).unwrap(), palette);
+
+        let deep = SuggestedPalette::new("deep", 16, vec![color(1000, 2)]).unwrap();
+        assert_eq!(deep.to_chunk().length(), 5 + 1 + 10);
+        assert_eq!(SuggestedPalette::parse(&deep.to_chunk()).unwrap(), deep);
+
+        assert!(SuggestedPalette::new("web", 8, vec![color(256, 0)]).is_err());
+        assert!(SuggestedPalette::new("web", 4, vec![]).is_err());
+        assert!(SuggestedPalette::new("", 8, vec![]).is_err());
+        assert!(SuggestedPalette::parse(&Chunk::new(ChunkType::SPLT, b"web\0\x08\0".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_png_suggested_palettes() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let a = SuggestedPalette::new("a", 8, vec![color(1, 1)]).unwrap();
+        let b = SuggestedPalette::new("b", 16, vec![]).unwrap();
+        png.set_typed_chunk(&a);
+        png.set_typed_chunk(&b);
+        assert_eq!(png.typed_chunks_of::<SuggestedPalette>().unwrap(), vec![a, b]);
+        assert_eq!(png.remove_typed_chunks::<SuggestedPalette>(), 2);
+    }
+}
@@ -0,0 +1,245 @@
+use core::fmt::Display;
+
+use alloc::vec::Vec;
+
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "std")]
+use crate::known;
+
+/// Where a chunk type may appear in a file, as laid down by the spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OrderingConstraint {
+    /// Must be the first chunk.
+    First,
+    /// Must come before PLTE and IDAT.
+    BeforePlte,
+    /// Must come after PLTE but before IDAT.
+    AfterPlte,
+    /// Must come before IDAT.
+    BeforeIdat,
+    /// No constraint.
+    Anywhere,
+    /// Multiple chunks of this type must be consecutive.
+    Consecutive,
+    /// Must come after IDAT.
+    AfterIdat,
+    /// Must be the last chunk.
+    Last,
+}
+
+/// An entry in the table of known chunk types.
+#[derive(Debug, Clone)]
+pub struct StandardChunk {
+    pub chunk_type: ChunkType,
+    pub description: &'static str,
+    pub ordering: OrderingConstraint,
+    /// Whether the type may appear more than once.
+    pub multiple: bool,
+    /// True for the 18 types of the core spec, false for
+    /// registered extensions.
+    pub core: bool,
+}
+
+const fn entry(
+    chunk_type: ChunkType,
+    description: &'static str,
+    ordering: OrderingConstraint,
+    multiple: bool,
+    core: bool,
+) -> StandardChunk {
+    StandardChunk { chunk_type, description, ordering, multiple, core }
+}
+
+use OrderingConstraint::*;
+
+/// Every chunk type defined by the PNG spec or registered as an extension.
+pub const STANDARD_CHUNKS: [StandardChunk; 31] = [
+    entry(ChunkType::IHDR, "image header: dimensions, bit depth and colour type", First, false, true),
+    entry(ChunkType::PLTE, "palette of RGB entries for indexed images", BeforeIdat, false, true),
+    entry(ChunkType::IDAT, "compressed image data", Consecutive, true, true),
+    entry(ChunkType::IEND, "end of the image", Last, false, true),
+    entry(ChunkType::CHRM, "chromaticities of the primaries and white point", BeforePlte, false, true),
+    entry(ChunkType::GAMA, "image gamma", BeforePlte, false, true),
+    entry(ChunkType::ICCP, "embedded ICC colour profile", BeforePlte, false, true),
+    entry(ChunkType::SBIT, "significant bits in the original samples", BeforePlte, false, true),
+    entry(ChunkType::SRGB, "image uses the sRGB colour space with a rendering intent", BeforePlte, false, true),
+    entry(ChunkType::BKGD, "default background colour", AfterPlte, false, true),
+    entry(ChunkType::HIST, "usage frequency of each palette entry", AfterPlte, false, true),
+    entry(ChunkType::TRNS, "simple transparency", AfterPlte, false, true),
+    entry(ChunkType::PHYS, "physical pixel size or aspect ratio", BeforeIdat, false, true),
+    entry(ChunkType::SPLT, "suggested reduced palette", BeforeIdat, true, true),
+    entry(ChunkType::TIME, "time of last modification", Anywhere, false, true),
+    entry(ChunkType::ITXT, "international UTF-8 text, optionally compressed", Anywhere, true, true),
+    entry(ChunkType::TEXT, "Latin-1 text with a keyword", Anywhere, true, true),
+    entry(ChunkType::ZTXT, "compressed Latin-1 text with a keyword", Anywhere, true, true),
+    entry(ChunkType::EXIF, "Exif metadata", BeforeIdat, false, false),
+    entry(ChunkType::CICP, "coding independent code points for video colour", BeforePlte, false, false),
+    entry(ChunkType::MDCV, "mastering display colour volume", BeforePlte, false, false),
+    entry(ChunkType::CLLI, "content light level information", BeforePlte, false, false),
+    entry(ChunkType::ACTL, "APNG animation control: frame and play count", BeforeIdat, false, false),
+    entry(ChunkType::FCTL, "APNG frame control: frame geometry and timing", Anywhere, true, false),
+    entry(ChunkType::FDAT, "APNG frame data", AfterIdat, true, false),
+    entry(ChunkType::OFFS, "image offset from the page origin", BeforeIdat, false, false),
+    entry(ChunkType::PCAL, "calibration of pixel values to physical values", BeforeIdat, false, false),
+    entry(ChunkType::SCAL, "physical scale of the image subject", BeforeIdat, false, false),
+    entry(ChunkType::STER, "stereo image layout indicator", BeforeIdat, false, false),
+    entry(ChunkType::GIFG, "GIF graphic control extension", Anywhere, true, false),
+    entry(ChunkType::GIFX, "GIF application extension", Anywhere, true, false),
+];
+
+/// How much of the spec `ChunkType::validate` holds a type to.
+/// Each level checks everything the ones before it do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Strictness {
+    /// Anything made of ASCII letters, which every `ChunkType` is.
+    Permissive,
+    /// The reserved bit is clear and critical types are known.
+    #[default]
+    Standard,
+    /// Every type is known, either in `STANDARD_CHUNKS` or the
+    /// global `known::ChunkRegistry`, which needs the `std` feature.
+    Strict,
+}
+
+/// A rule of `Strictness` a chunk type breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeViolation {
+    /// The third letter is lowercase.
+    ReservedBit,
+    /// A critical type no decoder is required to know.
+    UnknownCritical,
+    /// An ancillary type that is not known.
+    Unregistered,
+}
+
+impl Display for TypeViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TypeViolation::ReservedBit => write!(f, "reserved bit set, third letter is lowercase"),
+            TypeViolation::UnknownCritical => write!(f, "unknown critical chunk type"),
+            TypeViolation::Unregistered => write!(f, "chunk type is not registered"),
+        }
+    }
+}
+
+impl core::error::Error for TypeViolation {}
+
+/// Looks up `chunk_type` in `STANDARD_CHUNKS`.
+pub fn lookup(chunk_type: &ChunkType) -> Option<&'static StandardChunk> {
+    STANDARD_CHUNKS.iter().find(|s| s.chunk_type == *chunk_type)
+}
+
+impl ChunkType {
+    /// Returns true if this type is defined by the PNG spec
+    /// or registered as an extension.
+    pub fn is_standard(&self) -> bool {
+        lookup(self).is_some()
+    }
+
+    /// A short explanation of what the chunk holds, if the type is known.
+    pub fn description(&self) -> Option<&'static str> {
+        lookup(self).map(|s| s.description)
+    }
+
+    /// Where the chunk may go in a file.
+    /// Unknown types are `Anywhere`.
+    pub fn ordering_constraint(&self) -> OrderingConstraint {
+        lookup(self).map_or(Anywhere, |s| s.ordering)
+    }
+
+    /// Returns true if more than one chunk of this type is allowed.
+    /// Unknown types are assumed to allow it.
+    pub fn allows_multiple(&self) -> bool {
+        lookup(self).is_none_or(|s| s.multiple)
+    }
+
+    /// Checks the type against the rules of `strictness`, where
+    /// `is_valid` only checks the reserved bit. Types registered with
+    /// `known::register` count as known.
+    ///
+    /// # Errors
+    /// returns every rule broken, not only the first.
+    pub fn validate(&self, strictness: Strictness) -> Result<(), Vec<TypeViolation>> {
+        let mut violations = Vec::new();
+        if strictness >= Strictness::Standard {
+            if !self.is_reserved_bit_valid() {
+                violations.push(TypeViolation::ReservedBit);
+            }
+            #[cfg(feature = "std")]
+            let known = self.is_standard()
+                || known::global().read().unwrap_or_else(|e| e.into_inner()).is_registered(self);
+            #[cfg(not(feature = "std"))]
+            let known = self.is_standard();
+            if !known && self.is_critical() {
+                violations.push(TypeViolation::UnknownCritical);
+            } else if !known && strictness == Strictness::Strict {
+                violations.push(TypeViolation::Unregistered);
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+
+    #[test]
+    fn test_is_standard() {
+        assert!(ChunkType::PHYS.is_standard());
+        assert!(ChunkType::from_str("sCAL").unwrap().is_standard());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_standard());
+        assert_eq!(STANDARD_CHUNKS.iter().filter(|s| s.core).count(), 18);
+    }
+
+    #[test]
+    fn test_description() {
+        assert_eq!(ChunkType::ICCP.description(), Some("embedded ICC colour profile"));
+        assert_eq!(ChunkType::from_str("RuSt").unwrap().description(), None);
+    }
+
+    #[test]
+    fn test_ordering_constraint() {
+        assert_eq!(ChunkType::IHDR.ordering_constraint(), First);
+        assert_eq!(ChunkType::GAMA.ordering_constraint(), BeforePlte);
+        assert_eq!(ChunkType::TRNS.ordering_constraint(), AfterPlte);
+        assert_eq!(ChunkType::TEXT.ordering_constraint(), Anywhere);
+        assert_eq!(ChunkType::from_str("RuSt").unwrap().ordering_constraint(), Anywhere);
+    }
+
+    #[test]
+    fn test_allows_multiple() {
+        assert!(!ChunkType::TIME.allows_multiple());
+        assert!(ChunkType::IDAT.allows_multiple());
+        assert!(ChunkType::from_str("RuSt").unwrap().allows_multiple());
+    }
+
+    #[test]
+    fn test_validate_strictness() {
+        let private = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(private.validate(Strictness::Standard), Ok(()));
+        assert_eq!(private.validate(Strictness::Strict), Err(vec![TypeViolation::Unregistered]));
+        assert_eq!(ChunkType::TEXT.validate(Strictness::Strict), Ok(()));
+
+        let critical = ChunkType::from_str("Rust").unwrap();
+        assert_eq!(critical.validate(Strictness::Permissive), Ok(()));
+        assert_eq!(
+            critical.validate(Strictness::Standard),
+            Err(vec![TypeViolation::ReservedBit, TypeViolation::UnknownCritical])
+        );
+        let upper = ChunkType::from_str("RUST").unwrap();
+        assert_eq!(upper.validate(Strictness::Standard), Err(vec![TypeViolation::UnknownCritical]));
+    }
+
+    #[test]
+    fn test_table_has_no_duplicates() {
+        for (n, s) in STANDARD_CHUNKS.iter().enumerate() {
+            assert!(STANDARD_CHUNKS[n + 1..].iter().all(|o| o.chunk_type != s.chunk_type));
+        }
+    }
+}
@@ -0,0 +1,1410 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crc::crc32;
+use crate::error::{bail, Error};
+use crate::parse::Limits;
+use crate::png::Png;
+use crate::validate::MAX_CHUNK_LENGTH;
+
+/// Manifest of an embedded payload: its key, size, number of parts and crc.
+/// Private, ancillary and safe to copy, so editors keep it.
+pub const MANIFEST_TYPE: ChunkType = ChunkType::from_bytes_const(*b"stIx");
+/// One part of an embedded payload.
+pub const PART_TYPE: ChunkType = ChunkType::from_bytes_const(*b"stDt");
+
+/// Layout version written to the manifest, the only one read back.
+const VERSION: u8 = 2;
+
+/// Manifest flag: the stored bytes are encrypted, salt and nonce
+/// follow the fixed manifest fields.
+const ENCRYPTED: u8 = 0b0000_0001;
+/// Manifest flag: the payload was compressed with zlib before encryption.
+const DEFLATE: u8 = 0b0000_0010;
+/// Manifest flag: the payload was compressed with zstd before encryption.
+const ZSTD: u8 = 0b0000_0100;
+/// Manifest flag: the payload is a file, its name, size and crc come
+/// first inside the payload so they are compressed and encrypted too.
+const FILE: u8 = 0b0000_1000;
+/// Manifest flag: the payload is one share of a secret split by
+/// `split_across`, its threshold, x coordinate and set id come first.
+const SHARE: u8 = 0b0001_0000;
+/// Length of the random id telling apart shares of different splits.
+#[cfg(feature = "shamir")]
+const SET_ID_LEN: usize = 8;
+/// Length of the Argon2 salt stored in the manifest.
+const SALT_LEN: usize = 16;
+/// Length of the ChaCha20-Poly1305 nonce stored in the manifest.
+const NONCE_LEN: usize = 12;
+/// Length of the Poly1305 tag added to encrypted payloads.
+const TAG_LEN: usize = 16;
+/// Longest payload key, as for tEXt keywords.
+const MAX_KEY_LEN: usize = 79;
+/// Payload bytes per part chunk unless `EmbedOptions::chunk_size` says otherwise.
+const DEFAULT_CHUNK_SIZE: u32 = 1 << 16;
+/// Keyword of the text chunks payloads are hidden in, a registered one.
+const COVER_KEYWORD: &[u8] = b"Comment";
+/// A big-endian TIFF header pointing at an empty IFD, so an Exif cover
+/// reads as valid Exif with no tags. The hidden chunks follow it.
+const EXIF_HEADER: [u8; 14] = *b"MM\0*\0\0\0\x08\0\0\0\0\0\0";
+
+/// How a payload is compressed before it is stored.
+/// Every variant but `None` needs the feature of its codec:
+/// `flate` for `Deflate` and `zstd` for `Zstd`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zlib level from 0 to 9.
+    #[cfg(feature = "flate")]
+    Deflate(u8),
+    /// zstd level from 1 to 22.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+/// Where a payload is hidden, for `Png::embed_capacity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmbedMethod {
+    /// Manifest and part chunks as `Png::embed_payload` writes them.
+    /// There is no real limit, so `budget` caps the whole file size.
+    Chunks { budget: u64 },
+    /// The lowest `bits` of every sample, at most the bit depth or 8.
+    Lsb { bits: u8 },
+    /// One standard chunk, see `EmbedOptions::cover`.
+    Cover(CoverChunk),
+}
+
+/// A standard ancillary chunk a payload can hide in instead of the
+/// `stIx` and `stDt` chunks, so the file passes checkers such as
+/// pngcheck without unknown chunks. The manifest and parts are stored
+/// inside it, base64 encoded for the text chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoverChunk {
+    /// zTXt under the keyword `Comment`.
+    #[cfg(feature = "flate")]
+    ZTxt,
+    /// Uncompressed iTXt under the keyword `Comment`.
+    ITxt,
+    /// Bytes after an empty Exif directory. An image can only have one
+    /// eXIf, so this fails if it already has one.
+    Exif,
+}
+
+impl CoverChunk {
+    /// Packs the manifest and part chunks into one cover chunk.
+    fn wrap(&self, chunks: &[Chunk]) -> Result<Chunk, Error> {
+        let bytes: Vec<u8> = chunks.iter().flat_map(|c| c.as_bytes()).collect();
+        let (chunk_type, data) = match self {
+            #[cfg(feature = "flate")]
+            CoverChunk::ZTxt => {
+                use std::io::Write;
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(&base64_encode(&bytes))?;
+                let mut data = [COVER_KEYWORD, b"\0\0"].concat();
+                data.extend(encoder.finish()?);
+                (ChunkType::ZTXT, data)
+            }
+            CoverChunk::ITxt => {
+                let mut data = [COVER_KEYWORD, b"\0\0\0\0\0"].concat();
+                data.extend(base64_encode(&bytes));
+                (ChunkType::ITXT, data)
+            }
+            CoverChunk::Exif => (ChunkType::EXIF, [EXIF_HEADER.as_slice(), &bytes].concat()),
+        };
+        if data.len() > MAX_CHUNK_LENGTH as usize {
+            bail!("payload does not fit in one {} chunk", chunk_type);
+        }
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    /// Bytes a single cover chunk has room for, before base64.
+    fn room(&self) -> u64 {
+        let max = MAX_CHUNK_LENGTH as u64;
+        match self {
+            CoverChunk::Exif => max - EXIF_HEADER.len() as u64,
+            // compression only makes room, base64 of random bytes barely shrinks
+            _ => (max - COVER_KEYWORD.len() as u64 - 5) / 4 * 3,
+        }
+    }
+}
+
+/// The manifest and part chunks hidden in `chunk`, if it is a cover chunk.
+/// Anything else, like an ordinary comment, gives `None`.
+fn unwrap_cover(chunk: &Chunk) -> Option<Vec<Chunk>> {
+    let data = chunk.data();
+    let bytes = match *chunk.chunk_type() {
+        ChunkType::ITXT => {
+            let text = data.strip_prefix(COVER_KEYWORD)?.strip_prefix(b"\0\0\0\0\0")?;
+            base64_decode(text)?
+        }
+        #[cfg(feature = "flate")]
+        ChunkType::ZTXT => {
+            let compressed = data.strip_prefix(COVER_KEYWORD)?.strip_prefix(b"\0\0")?;
+            let text = crate::text::inflate_limited(compressed, Limits::default().max_decompressed).ok()?;
+            base64_decode(&text)?
+        }
+        ChunkType::EXIF => data.strip_prefix(EXIF_HEADER.as_slice())?.to_vec(),
+        _ => return None,
+    };
+    let mut chunks = Vec::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let chunk = Chunk::try_from(rest.get(..length.checked_add(12)?)?).ok()?;
+        if *chunk.chunk_type() != MANIFEST_TYPE && *chunk.chunk_type() != PART_TYPE {
+            return None;
+        }
+        chunks.push(chunk);
+        rest = &rest[length + 12..];
+    }
+    (!chunks.is_empty()).then_some(chunks)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with padding.
+fn base64_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let n = group.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize]);
+            } else {
+                out.push(b'=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes `base64_encode`, `None` if `text` is not base64.
+fn base64_decode(text: &[u8]) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    for (g, group) in text.chunks(4).enumerate() {
+        let last = g == text.len() / 4 - 1;
+        let padding = group.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, b) in group[..4 - padding].iter().enumerate() {
+            let value = BASE64.iter().position(|c| c == b)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        out.extend(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+/// How many payload bytes fit in `room` bytes of manifest and part chunks,
+/// keeping room for the longest key and for encryption.
+fn chunked_capacity(room: u64) -> u64 {
+    // length, type and crc of a chunk, then the key and its null
+    let chunk = (12 + MAX_KEY_LEN + 1) as u64;
+    let manifest = chunk + (2 + 8 + 4 + 4 + SALT_LEN + NONCE_LEN) as u64;
+    let left = room.saturating_sub(manifest);
+    let part = chunk + 4;
+    let size = DEFAULT_CHUNK_SIZE as u64;
+    let full = left / (size + part);
+    let rest = (left % (size + part)).saturating_sub(part);
+    (full * size + rest).saturating_sub(TAG_LEN as u64)
+}
+
+/// How `Png::embed_payload` stores a payload.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct EmbedOptions {
+    chunk_size: u32,
+    compression: Compression,
+    passphrase: Option<String>,
+    cover: Option<CoverChunk>,
+}
+
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        EmbedOptions {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            compression: Compression::None,
+            passphrase: None,
+            cover: None,
+        }
+    }
+}
+
+// keeps the passphrase out of logs
+impl std::fmt::Debug for EmbedOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbedOptions")
+            .field("chunk_size", &self.chunk_size)
+            .field("compression", &self.compression)
+            .field("encrypted", &self.passphrase.is_some())
+            .field("cover", &self.cover)
+            .finish()
+    }
+}
+
+impl EmbedOptions {
+    pub fn new() -> EmbedOptions {
+        EmbedOptions::default()
+    }
+
+    /// Most payload bytes per chunk, 64 KiB by default. Some tools choke
+    /// on huge chunks, and no chunk can be over `MAX_CHUNK_LENGTH`.
+    /// Clamped to 1 up to `MAX_CHUNK_LENGTH` less room for the part header.
+    pub fn chunk_size(mut self, chunk_size: u32) -> EmbedOptions {
+        self.chunk_size = chunk_size.clamp(1, MAX_CHUNK_LENGTH - 84);
+        self
+    }
+
+    /// Compresses the payload before it is encrypted and stored, which
+    /// keeps large text or file payloads from bloating the image.
+    /// The method is recorded in the manifest and undone on extraction.
+    pub fn compression(mut self, compression: Compression) -> EmbedOptions {
+        self.compression = compression;
+        self
+    }
+
+    /// Hides the payload in one standard chunk instead of custom ones.
+    /// Extraction finds it either way.
+    pub fn cover(mut self, cover: CoverChunk) -> EmbedOptions {
+        self.cover = Some(cover);
+        self
+    }
+
+    /// Encrypts the payload with ChaCha20-Poly1305, using a key derived
+    /// from `passphrase` with Argon2id. A random salt and nonce are stored
+    /// in the manifest. Read it back with `Png::decrypt_payload`.
+    #[cfg(feature = "encrypt")]
+    pub fn encrypt(mut self, passphrase: &str) -> EmbedOptions {
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+}
+
+/// What the manifest chunk says about a payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+    key: String,
+    flags: u8,
+    length: u64,
+    parts: u32,
+    crc: u32,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+}
+
+impl Manifest {
+    fn to_chunk(&self) -> Chunk {
+        let mut data = key_prefix(&self.key);
+        data.push(VERSION);
+        data.push(self.flags);
+        data.extend(self.length.to_be_bytes());
+        data.extend(self.parts.to_be_bytes());
+        data.extend(self.crc.to_be_bytes());
+        if self.flags & ENCRYPTED != 0 {
+            data.extend(self.salt);
+            data.extend(self.nonce);
+        }
+        Chunk::new(MANIFEST_TYPE, data)
+    }
+
+    fn parse(chunk: &Chunk) -> Result<Manifest, Error> {
+        let (key, rest) = split_key(chunk.data())?;
+        if rest.len() < 2 || rest[0] != VERSION {
+            bail!("unknown payload version: {:?}", rest.first());
+        }
+        let flags = rest[1];
+        let expected = if flags & ENCRYPTED != 0 { 18 + SALT_LEN + NONCE_LEN } else { 18 };
+        if rest.len() != expected {
+            bail!("manifest of {} is {} bytes, should be {}", key, rest.len(), expected);
+        }
+        let mut manifest = Manifest {
+            key,
+            flags,
+            length: u64::from_be_bytes(rest[2..10].try_into()?),
+            parts: u32::from_be_bytes(rest[10..14].try_into()?),
+            crc: u32::from_be_bytes(rest[14..18].try_into()?),
+            salt: [0; SALT_LEN],
+            nonce: [0; NONCE_LEN],
+        };
+        if flags & ENCRYPTED != 0 {
+            manifest.salt.copy_from_slice(&rest[18..18 + SALT_LEN]);
+            manifest.nonce.copy_from_slice(&rest[18 + SALT_LEN..]);
+        }
+        Ok(manifest)
+    }
+
+    /// What the crc and the encryption tag cover besides the stored
+    /// bytes: the key and the image header, so a payload left behind by
+    /// an editor that changed the image no longer checks out.
+    fn bound(&self, ihdr: &[u8]) -> Vec<u8> {
+        [self.key.as_bytes(), ihdr].concat()
+    }
+
+    /// The crc of `bound` and the stored bytes.
+    fn checksum(&self, ihdr: &[u8], stored: &[u8]) -> u32 {
+        let data = [self.bound(ihdr).as_slice(), stored].concat();
+        crc32(&data, data.len())
+    }
+}
+
+/// The name, size and CRC-32 of an embedded file, see `Png::embed_file`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileInfo {
+    name: String,
+    size: u64,
+    crc: u32,
+}
+
+impl FileInfo {
+    /// The file name it was embedded from, without directories.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// CRC-32 of the contents.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.name.len() as u16).to_be_bytes().to_vec();
+        bytes.extend(self.name.as_bytes());
+        bytes.extend(self.size.to_be_bytes());
+        bytes.extend(self.crc.to_be_bytes());
+        bytes
+    }
+
+    /// Parses the header at the start of a file payload,
+    /// returning it and the contents after it.
+    fn parse(data: &[u8]) -> Result<(FileInfo, &[u8]), Error> {
+        let name_len = match data.get(..2) {
+            Some(b) => u16::from_be_bytes([b[0], b[1]]) as usize,
+            None => bail!("file header too short"),
+        };
+        let rest = &data[2..];
+        if rest.len() < name_len + 12 {
+            bail!("file header too short");
+        }
+        let name = String::from_utf8(rest[..name_len].to_vec())?;
+        if !is_plain_name(&name) {
+            bail!("file name {:?} is not a plain file name", name);
+        }
+        let size = u64::from_be_bytes(rest[name_len..name_len + 8].try_into()?);
+        let crc = u32::from_be_bytes(rest[name_len + 8..name_len + 12].try_into()?);
+        let contents = &rest[name_len + 12..];
+        if contents.len() as u64 != size || crc32(contents, contents.len()) != crc {
+            bail!("contents of {:?} do not match its size or crc", name);
+        }
+        Ok((FileInfo { name, size, crc }, contents))
+    }
+}
+
+/// True if `name` is a single file name: not empty, `.` or `..`, and
+/// without separators, so joining it to a directory stays inside it.
+/// Names come from the payload, which may be crafted.
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '\0', ':'])
+        && Path::new(name).file_name().is_some_and(|n| n == name)
+}
+
+/// The key followed by a null separator, as at the start of both chunk types.
+fn key_prefix(key: &str) -> Vec<u8> {
+    let mut data = key.as_bytes().to_vec();
+    data.push(0);
+    data
+}
+
+/// Splits chunk data into the key and what follows the separator.
+fn split_key(data: &[u8]) -> Result<(String, &[u8]), Error> {
+    match data.iter().position(|b| *b == 0) {
+        Some(n) => Ok((String::from_utf8(data[..n].to_vec())?, &data[n + 1..])),
+        None => bail!("payload chunk without key"),
+    }
+}
+
+fn check_key(key: &str) -> Result<(), Error> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN || key.contains('\0') {
+        bail!("payload key must be 1 to 79 bytes without nulls: {:?}", key);
+    }
+    Ok(())
+}
+
+/// Derives the cipher key for `passphrase` and the salt of `manifest`.
+#[cfg(feature = "encrypt")]
+fn cipher(manifest: &Manifest, passphrase: &str) -> Result<chacha20poly1305::ChaCha20Poly1305, Error> {
+    use chacha20poly1305::KeyInit;
+
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &manifest.salt, &mut key)
+        .map_err(|e| Error::Invalid(format!("key derivation failed: {}", e)))?;
+    Ok(chacha20poly1305::ChaCha20Poly1305::new(&key.into()))
+}
+
+/// Encrypts `data`, filling in a fresh salt and nonce. The Poly1305 tag
+/// also covers `Manifest::bound`, so parts can not be moved to another
+/// key or image.
+#[cfg(feature = "encrypt")]
+fn encrypt(
+    manifest: &mut Manifest,
+    passphrase: &str,
+    ihdr: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, Error> {
+    use chacha20poly1305::aead::rand_core::RngCore;
+    use chacha20poly1305::aead::{Aead, OsRng, Payload};
+
+    OsRng.fill_bytes(&mut manifest.salt);
+    OsRng.fill_bytes(&mut manifest.nonce);
+    let aad = manifest.bound(ihdr);
+    let payload = Payload { msg: data, aad: &aad };
+    cipher(manifest, passphrase)?
+        .encrypt(&manifest.nonce.into(), payload)
+        .map_err(|_| Error::Invalid("encryption failed".to_string()))
+}
+
+#[cfg(feature = "encrypt")]
+fn decrypt(manifest: &Manifest, passphrase: &str, ihdr: &[u8], stored: &[u8]) -> Result<Vec<u8>, Error> {
+    use chacha20poly1305::aead::{Aead, Payload};
+
+    let aad = manifest.bound(ihdr);
+    let payload = Payload { msg: stored, aad: &aad };
+    cipher(manifest, passphrase)?
+        .decrypt(&manifest.nonce.into(), payload)
+        .map_err(|_| {
+            Error::Invalid(format!("wrong passphrase, or payload {:?} or its image changed", manifest.key))
+        })
+}
+
+/// Compresses `data`, returning the manifest flag for the method used.
+fn compress(compression: Compression, data: &[u8]) -> Result<(u8, Vec<u8>), Error> {
+    Ok(match compression {
+        Compression::None => (0, data.to_vec()),
+        #[cfg(feature = "flate")]
+        Compression::Deflate(level) => {
+            use std::io::Write;
+            let level = flate2::Compression::new(level.min(9) as u32);
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), level);
+            encoder.write_all(data)?;
+            (DEFLATE, encoder.finish()?)
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(level) => (ZSTD, zstd::encode_all(data, level)?),
+    })
+}
+
+/// Undoes the compression recorded in `flags`, failing once the output
+/// would go over `max` bytes.
+#[cfg_attr(not(any(feature = "flate", feature = "zstd")), allow(unused_variables))]
+fn decompress(flags: u8, data: Vec<u8>, max: u64) -> Result<Vec<u8>, Error> {
+    if flags & DEFLATE != 0 {
+        #[cfg(feature = "flate")]
+        return crate::text::inflate_limited(&data, max);
+        #[cfg(not(feature = "flate"))]
+        bail!("payload is deflate compressed, which needs the flate feature");
+    }
+    if flags & ZSTD != 0 {
+        #[cfg(feature = "zstd")]
+        {
+            use std::io::Read;
+            let mut out = Vec::new();
+            zstd::stream::read::Decoder::new(data.as_slice())?.take(max.saturating_add(1)).read_to_end(&mut out)?;
+            if out.len() as u64 > max {
+                return Err(Error::LimitExceeded("max_decompressed"));
+            }
+            return Ok(out);
+        }
+        #[cfg(not(feature = "zstd"))]
+        bail!("payload is zstd compressed, which needs the zstd feature");
+    }
+    Ok(data)
+}
+
+/// The contents of a payload, without the file header if it has one.
+fn strip_file_header(flags: u8, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if flags & SHARE != 0 {
+        bail!("payload is one share of a secret, combine the shares with combine_from");
+    }
+    if flags & FILE == 0 {
+        return Ok(data);
+    }
+    Ok(FileInfo::parse(&data)?.1.to_vec())
+}
+
+/// Writes the file in `data` to `out_path`, see `Png::extract_file`.
+fn write_file(flags: u8, data: &[u8], out_path: &Path) -> Result<FileInfo, Error> {
+    if flags & FILE == 0 {
+        bail!("payload is not a file");
+    }
+    let (info, contents) = FileInfo::parse(data)?;
+    let path = if out_path.is_dir() {
+        // `parse` already refused anything but a plain name, this keeps
+        // it that way should the check ever be loosened
+        match Path::new(&info.name).file_name() {
+            Some(name) => out_path.join(name),
+            None => bail!("file name {:?} is not a plain file name", info.name),
+        }
+    } else {
+        out_path.to_path_buf()
+    };
+    fs::write(path, contents)?;
+    Ok(info)
+}
+
+/// Splits `data` with Shamir's secret sharing and embeds one share under
+/// `key` in each of `images`, so that any `threshold` of them recover it
+/// with `combine_from` and fewer tell nothing about it. Each share is as
+/// big as `data` and is stored as `Png::embed_payload` would with
+/// `options`. At most 255 images can share a secret.
+///
+/// # Errors
+/// returns an Error if `threshold` is 0 or more than there are images,
+/// there are over 255 images, or as `Png::embed_payload`.
+#[cfg(feature = "shamir")]
+pub fn split_across(
+    images: &mut [Png],
+    key: &str,
+    data: &[u8],
+    threshold: usize,
+    options: &EmbedOptions,
+) -> Result<(), Error> {
+    if threshold == 0 || threshold > images.len() {
+        bail!("threshold must be 1 to {}, the number of images, got {}", images.len(), threshold);
+    }
+    if images.len() > 255 {
+        bail!("a secret can be split across at most 255 images, got {}", images.len());
+    }
+    let mut set_id = [0u8; SET_ID_LEN];
+    getrandom::getrandom(&mut set_id).map_err(std::io::Error::from)?;
+    // the constant term of each byte's polynomial is the byte itself
+    let mut coefficients = vec![0u8; data.len() * (threshold - 1)];
+    getrandom::getrandom(&mut coefficients).map_err(std::io::Error::from)?;
+
+    for (n, png) in images.iter_mut().enumerate() {
+        let x = n as u8 + 1;
+        let mut share = vec![threshold as u8, x];
+        share.extend(set_id);
+        let polynomials = coefficients.chunks((threshold - 1).max(1)).chain(std::iter::repeat(&[][..]));
+        for (byte, terms) in data.iter().zip(polynomials) {
+            // Horner's rule, the secret byte is the constant term
+            let higher = terms.iter().rev().fold(0, |acc, c| gf_mul(acc, x) ^ c);
+            share.push(gf_mul(higher, x) ^ byte);
+        }
+        png.embed(key, SHARE, &share, options)?;
+    }
+    Ok(())
+}
+
+/// Recovers a secret split by `split_across` from the shares under `key`.
+/// Images without a share are skipped, it only takes `threshold` of them.
+///
+/// # Errors
+/// returns an Error if there are fewer shares than the threshold, they
+/// come from different splits, a share is encrypted, or as
+/// `Png::extract_payload`.
+#[cfg(feature = "shamir")]
+pub fn combine_from(images: &[Png], key: &str) -> Result<Vec<u8>, Error> {
+    combine(images, key, None)
+}
+
+/// `combine_from` for shares embedded with `EmbedOptions::encrypt`.
+///
+/// # Errors
+/// as `combine_from` and `Png::decrypt_payload`.
+#[cfg(all(feature = "shamir", feature = "encrypt"))]
+pub fn decrypt_from(images: &[Png], key: &str, passphrase: &str) -> Result<Vec<u8>, Error> {
+    combine(images, key, Some(passphrase))
+}
+
+#[cfg(feature = "shamir")]
+fn combine(images: &[Png], key: &str, passphrase: Option<&str>) -> Result<Vec<u8>, Error> {
+    let mut shares: Vec<Vec<u8>> = Vec::new();
+    for png in images {
+        if png.payload_manifest(key)?.is_none() {
+            continue;
+        }
+        let (manifest, share) = png.open_payload(key, passphrase)?;
+        if manifest.flags & SHARE == 0 || share.len() < 2 + SET_ID_LEN || share[1] == 0 {
+            bail!("payload {:?} is not a share of a secret", key);
+        }
+        // the same share twice adds nothing
+        if !shares.iter().any(|s| s[1] == share[1]) {
+            shares.push(share);
+        }
+    }
+    let first = match shares.first() {
+        Some(s) => s,
+        None => bail!("no image has a share under {:?}", key),
+    };
+    let threshold = first[0] as usize;
+    let same_split = |s: &Vec<u8>| s[0] == first[0] && s[2..2 + SET_ID_LEN] == first[2..2 + SET_ID_LEN];
+    if !shares.iter().all(|s| same_split(s) && s.len() == first.len()) {
+        bail!("the shares under {:?} come from different splits", key);
+    }
+    if shares.len() < threshold {
+        bail!("{} shares are needed to recover {:?}, found {}", threshold, key, shares.len());
+    }
+    let shares = &shares[..threshold];
+
+    // Lagrange basis polynomials at 0, subtraction is xor in GF(2^8)
+    let xs: Vec<u8> = shares.iter().map(|s| s[1]).collect();
+    let basis: Vec<u8> = (0..threshold)
+        .map(|j| {
+            let others = (0..threshold).filter(|m| *m != j);
+            others.fold(1, |acc, m| gf_mul(acc, gf_mul(xs[m], gf_inv(xs[m] ^ xs[j]))))
+        })
+        .collect();
+    let secret = (2 + SET_ID_LEN..first.len())
+        .map(|i| shares.iter().zip(&basis).fold(0, |acc, (s, l)| acc ^ gf_mul(s[i], *l)))
+        .collect();
+    Ok(secret)
+}
+
+/// Multiplication in GF(2^8) with the AES polynomial.
+#[cfg(feature = "shamir")]
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8), a^254.
+#[cfg(feature = "shamir")]
+fn gf_inv(a: u8) -> u8 {
+    let (mut result, mut base, mut exponent) = (1, a, 254);
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+impl Png {
+    /// How many payload bytes fit with `method`, to check before embedding.
+    ///
+    /// For `Chunks` this is what fits in the budget left over by the file
+    /// as it is, with the default chunk size. It is a lower bound: room is
+    /// kept for the longest key and for encryption, and compression can
+    /// fit more. `Cover` is the same within the one cover chunk.
+    /// For `Lsb` it is the bits available in the samples.
+    /// An image without a valid header has no capacity.
+    pub fn embed_capacity(&self, method: EmbedMethod) -> usize {
+        match method {
+            EmbedMethod::Chunks { budget } => {
+                chunked_capacity(budget.saturating_sub(self.file_size())) as usize
+            }
+            EmbedMethod::Cover(cover) => chunked_capacity(cover.room()) as usize,
+            EmbedMethod::Lsb { bits } => match self.header() {
+                Ok(ihdr) => {
+                    let pixels = ihdr.width() as usize * ihdr.height() as usize;
+                    let samples = pixels * ihdr.color_type().channels() as usize;
+                    samples * bits.min(ihdr.bit_depth()).min(8) as usize / 8
+                }
+                Err(_) => 0,
+            },
+        }
+    }
+
+    /// Hides `data` under `key`, split over as many chunks as needed, with
+    /// a manifest chunk recording the size and crc so `extract_payload`
+    /// can check it. Replaces any payload already stored under `key`.
+    /// The chunks go before IEND, or at the end if there is none.
+    /// The size and crc are those of the stored bytes, so an encrypted
+    /// payload can be checked without the passphrase. The crc and the
+    /// encryption tag also cover the IHDR, so extraction fails once an
+    /// editor has re-encoded the image with another header.
+    ///
+    /// The crc only catches accidental damage: anyone can change an
+    /// unencrypted payload and write a matching crc. Encryption with
+    /// `EmbedOptions::encrypt` is the only authenticated mode, its
+    /// Poly1305 tag fails `decrypt_payload` on any change to the payload,
+    /// its key or the IHDR.
+    ///
+    /// # Errors
+    /// returns an Error if `key` is empty, over 79 bytes or holds a null,
+    /// the payload needs more than `u32::MAX` chunks, or encryption fails.
+    pub fn embed_payload(&mut self, key: &str, data: &[u8], options: &EmbedOptions) -> Result<(), Error> {
+        self.embed(key, 0, data, options)
+    }
+
+    /// Stores `data` with the payload flags in `flags`, see `embed_payload`.
+    fn embed(&mut self, key: &str, flags: u8, data: &[u8], options: &EmbedOptions) -> Result<(), Error> {
+        check_key(key)?;
+        let mut manifest = Manifest {
+            key: key.to_string(),
+            flags,
+            length: 0,
+            parts: 0,
+            crc: 0,
+            salt: [0; SALT_LEN],
+            nonce: [0; NONCE_LEN],
+        };
+        let (flag, packed) = compress(options.compression, data)?;
+        manifest.flags |= flag;
+        let stored = match &options.passphrase {
+            #[cfg(feature = "encrypt")]
+            Some(passphrase) => {
+                manifest.flags |= ENCRYPTED;
+                encrypt(&mut manifest, passphrase, self.ihdr_data(), &packed)?
+            }
+            _ => packed,
+        };
+        let data = stored.as_slice();
+        let pieces = data.chunks(options.chunk_size as usize);
+        manifest.length = data.len() as u64;
+        manifest.parts = pieces.len().try_into()?;
+        manifest.crc = manifest.checksum(self.ihdr_data(), data);
+
+        self.remove_payload(key);
+        let mut chunks = vec![manifest.to_chunk()];
+        for (n, piece) in pieces.enumerate() {
+            let mut part = key_prefix(key);
+            part.extend((n as u32).to_be_bytes());
+            part.extend(piece);
+            chunks.push(Chunk::new(PART_TYPE, part));
+        }
+        let before = match options.cover {
+            Some(CoverChunk::Exif) if self.chunk_by_type("eXIf").is_some() => {
+                bail!("the image already has an eXIf chunk to cover the payload")
+            }
+            // eXIf has to come before the image data
+            Some(CoverChunk::Exif) => [ChunkType::IDAT, ChunkType::IEND].as_slice(),
+            _ => [ChunkType::IEND].as_slice(),
+        };
+        if let Some(cover) = options.cover {
+            chunks = vec![cover.wrap(&chunks)?];
+        }
+        let at = self.chunks().iter().position(|c| before.contains(c.chunk_type()));
+        let at = at.unwrap_or(self.chunks().len());
+        self.chunks_mut().splice(at..at, chunks);
+        Ok(())
+    }
+
+    /// Reassembles the payload stored under `key`, in whatever order its
+    /// parts are in, and checks it against the manifest. For a file
+    /// this is its contents. The crc check is not authentication, see
+    /// `embed_payload`.
+    ///
+    /// # Errors
+    /// returns an Error if there is no payload under `key`, a part is
+    /// missing or repeated, the size or crc does not match, the payload
+    /// is encrypted, or it is compressed with a codec that is not enabled.
+    pub fn extract_payload(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let (manifest, data) = self.open_payload(key, None)?;
+        strip_file_header(manifest.flags, data)
+    }
+
+    /// Reassembles and decrypts the payload stored under `key` with
+    /// `passphrase`. See `EmbedOptions::encrypt`.
+    ///
+    /// # Errors
+    /// as `extract_payload`, and if the payload is not encrypted or the
+    /// passphrase is wrong.
+    #[cfg(feature = "encrypt")]
+    pub fn decrypt_payload(&self, key: &str, passphrase: &str) -> Result<Vec<u8>, Error> {
+        let (manifest, data) = self.open_payload(key, Some(passphrase))?;
+        strip_file_header(manifest.flags, data)
+    }
+
+    /// Embeds the file at `path` under its file name, which is returned,
+    /// keeping the name, size and CRC-32 with the contents.
+    /// See `embed_payload`.
+    ///
+    /// # Errors
+    /// returns an Error if the file can not be read, its name is not
+    /// valid UTF-8, holds a `\\` or `:`, or can not be a key, or as
+    /// `embed_payload`.
+    pub fn embed_file<P: AsRef<Path>>(&mut self, path: P, options: &EmbedOptions) -> Result<String, Error> {
+        let path = path.as_ref();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(n) if is_plain_name(n) => n.to_string(),
+            _ => bail!("no usable file name in {}", path.display()),
+        };
+        let contents = fs::read(path)?;
+        let crc = crc32(&contents, contents.len());
+        let info = FileInfo { name: name.clone(), size: contents.len() as u64, crc };
+        let mut data = info.to_bytes();
+        data.extend(contents);
+        self.embed(&name, FILE, &data, options)?;
+        Ok(name)
+    }
+
+    /// Writes the file embedded under `key` to `out_path`, or into it
+    /// under its original name if `out_path` is a directory.
+    /// Returns the stored name, size and crc, which are checked first.
+    /// A stored name that is not a plain file name, like `../x` or an
+    /// absolute path, is refused so a crafted image can not write
+    /// outside `out_path`.
+    ///
+    /// # Errors
+    /// returns an Error if the payload is not a file, its name is not a
+    /// plain file name, it does not match its size or crc, writing fails,
+    /// or as `extract_payload`.
+    pub fn extract_file<P: AsRef<Path>>(&self, key: &str, out_path: P) -> Result<FileInfo, Error> {
+        let (manifest, data) = self.open_payload(key, None)?;
+        write_file(manifest.flags, &data, out_path.as_ref())
+    }
+
+    /// `extract_file` for files embedded with `EmbedOptions::encrypt`.
+    ///
+    /// # Errors
+    /// as `extract_file` and `decrypt_payload`.
+    #[cfg(feature = "encrypt")]
+    pub fn decrypt_file<P: AsRef<Path>>(
+        &self,
+        key: &str,
+        passphrase: &str,
+        out_path: P,
+    ) -> Result<FileInfo, Error> {
+        let (manifest, data) = self.open_payload(key, Some(passphrase))?;
+        write_file(manifest.flags, &data, out_path.as_ref())
+    }
+
+    /// Reads, decrypts and decompresses the payload under `key`.
+    fn open_payload(&self, key: &str, passphrase: Option<&str>) -> Result<(Manifest, Vec<u8>), Error> {
+        let (manifest, stored) = self.read_payload(key)?;
+        let encrypted = manifest.flags & ENCRYPTED != 0;
+        let packed = match passphrase {
+            None if encrypted => bail!("payload {:?} is encrypted, use decrypt_payload", key),
+            None => stored,
+            Some(_) if !encrypted => bail!("payload {:?} is not encrypted", key),
+            #[cfg(feature = "encrypt")]
+            Some(passphrase) => decrypt(&manifest, passphrase, self.ihdr_data(), &stored)?,
+            #[cfg(not(feature = "encrypt"))]
+            Some(_) => unreachable!("passphrases are only given with the encrypt feature"),
+        };
+        let data = decompress(manifest.flags, packed, Limits::default().max_decompressed)?;
+        Ok((manifest, data))
+    }
+
+    /// The manifest and stored bytes of the payload under `key`,
+    /// checked against the size and crc.
+    fn read_payload(&self, key: &str) -> Result<(Manifest, Vec<u8>), Error> {
+        let manifest = match self.payload_manifest(key)? {
+            Some(m) => m,
+            None => bail!("no payload with key {:?}", key),
+        };
+
+        // the manifest can claim any number of parts, only what is
+        // really there is allocated for
+        let mut parts = BTreeMap::new();
+        for (index, data) in self.payload_parts(key) {
+            if index >= manifest.parts {
+                bail!("part {} of {:?} is past the {} in the manifest", index, key, manifest.parts);
+            }
+            if parts.insert(index, data).is_some() {
+                bail!("part {} of {:?} is repeated", index, key);
+            }
+        }
+        if parts.len() as u64 != manifest.parts as u64 {
+            let missing = parts.keys().zip(0..).find(|(&index, n)| index != *n).map_or(parts.len() as u32, |(_, n)| n);
+            bail!("part {} of {:?} is missing", missing, key);
+        }
+        let length: usize = parts.values().map(Vec::len).sum();
+        if length as u64 != manifest.length {
+            bail!("payload {:?} is {} bytes, manifest says {}", key, length, manifest.length);
+        }
+        let mut payload = Vec::with_capacity(length);
+        for data in parts.into_values() {
+            payload.extend(data);
+        }
+        if manifest.checksum(self.ihdr_data(), &payload) != manifest.crc {
+            bail!("payload {:?} does not match its crc, it or the image header was changed", key);
+        }
+        Ok((manifest, payload))
+    }
+
+    /// The IHDR data payloads are bound to, empty if there is none.
+    fn ihdr_data(&self) -> &[u8] {
+        self.chunk_by_type("IHDR").map_or(&[], |c| c.data())
+    }
+
+    /// Removes the manifest and parts of the payload under `key`, or the
+    /// cover chunk holding them, returning how many chunks were removed.
+    pub fn remove_payload(&mut self, key: &str) -> usize {
+        let prefix = key_prefix(key);
+        let before = self.chunks().len();
+        let is_payload = |c: &Chunk| *c.chunk_type() == MANIFEST_TYPE || *c.chunk_type() == PART_TYPE;
+        let is_ours = |c: &Chunk| match unwrap_cover(c) {
+            Some(hidden) => hidden[0].data().starts_with(&prefix),
+            None => is_payload(c) && c.data().starts_with(&prefix),
+        };
+        self.chunks_mut().retain(|c| !is_ours(c));
+        before - self.chunks().len()
+    }
+
+    /// The keys of every embedded payload, in file order.
+    pub fn payload_keys(&self) -> Vec<String> {
+        self.payload_chunks()
+            .iter()
+            .filter(|c| *c.chunk_type() == MANIFEST_TYPE)
+            .filter_map(|c| split_key(c.data()).ok())
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// The manifest and part chunks, including those in cover chunks, in file order.
+    fn payload_chunks(&self) -> Vec<Cow<'_, Chunk>> {
+        let mut chunks = Vec::new();
+        for c in self.iter() {
+            if *c.chunk_type() == MANIFEST_TYPE || *c.chunk_type() == PART_TYPE {
+                chunks.push(Cow::Borrowed(c));
+            } else if let Some(hidden) = unwrap_cover(c) {
+                chunks.extend(hidden.into_iter().map(Cow::Owned));
+            }
+        }
+        chunks
+    }
+
+    fn payload_manifest(&self, key: &str) -> Result<Option<Manifest>, Error> {
+        let prefix = key_prefix(key);
+        self.payload_chunks()
+            .iter()
+            .find(|c| *c.chunk_type() == MANIFEST_TYPE && c.data().starts_with(&prefix))
+            .map(|c| Manifest::parse(c))
+            .transpose()
+    }
+
+    /// Index and data of each part stored under `key`, in file order.
+    fn payload_parts(&self, key: &str) -> impl Iterator<Item = (u32, Vec<u8>)> {
+        let prefix = key_prefix(key);
+        let parts: Vec<(u32, Vec<u8>)> = self
+            .payload_chunks()
+            .iter()
+            .filter(|c| *c.chunk_type() == PART_TYPE)
+            .filter_map(|c| {
+                let rest = c.data().strip_prefix(prefix.as_slice())?;
+                let index = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+                Some((index, rest[4..].to_vec()))
+            })
+            .collect();
+        parts.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|n| (n * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let data = payload(10_000);
+        png.embed_payload("notes", &data, &EmbedOptions::new().chunk_size(3000)).unwrap();
+        assert_eq!(png.chunks_of_type("stDt").count(), 4);
+        assert_eq!(png.chunks().last().unwrap().chunk_type(), &ChunkType::IEND);
+
+        // survives a round trip through bytes
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.extract_payload("notes").unwrap(), data);
+        assert!(png.extract_payload("other").is_err());
+        assert!(png.validate().is_ok());
+    }
+
+    #[test]
+    fn test_several_payloads() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.embed_payload("a", b"first", &EmbedOptions::default()).unwrap();
+        png.embed_payload("ab", b"second", &EmbedOptions::default()).unwrap();
+        png.embed_payload("empty", b"", &EmbedOptions::default()).unwrap();
+        png.embed_payload("a", b"replaced", &EmbedOptions::default()).unwrap();
+        assert_eq!(png.payload_keys(), vec!["ab", "empty", "a"]);
+        assert_eq!(png.extract_payload("a").unwrap(), b"replaced");
+        assert_eq!(png.extract_payload("ab").unwrap(), b"second");
+        assert_eq!(png.extract_payload("empty").unwrap(), b"");
+
+        assert_eq!(png.remove_payload("ab"), 2);
+        assert_eq!(png.remove_payload("ab"), 0);
+        assert!(png.embed_payload("", b"x", &EmbedOptions::default()).is_err());
+        assert!(png.embed_payload(&"k".repeat(80), b"x", &EmbedOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_extract_checks() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.embed_payload("k", &payload(100), &EmbedOptions::new().chunk_size(40)).unwrap();
+
+        // parts may be in any order
+        let mut shuffled = png.clone();
+        let first = shuffled.chunks().iter().position(|c| *c.chunk_type() == PART_TYPE).unwrap();
+        shuffled.chunks_mut().swap(first, first + 2);
+        assert_eq!(shuffled.extract_payload("k").unwrap(), payload(100));
+
+        let mut missing = png.clone();
+        missing.chunks_mut().remove(first + 1);
+        assert!(missing.extract_payload("k").is_err());
+
+        let mut repeated = png.clone();
+        let part = repeated.chunks()[first].clone();
+        repeated.chunks_mut().insert(first, part);
+        assert!(repeated.extract_payload("k").is_err());
+
+        let mut corrupt = png.clone();
+        let mut data = corrupt.chunks()[first].data().to_vec();
+        *data.last_mut().unwrap() ^= 1;
+        corrupt.chunks_mut()[first].set_data(data);
+        assert!(corrupt.extract_payload("k").is_err());
+
+        // a forged manifest can not make extraction allocate for parts
+        // or bytes that are not there
+        let at = png.chunks().iter().position(|c| *c.chunk_type() == MANIFEST_TYPE).unwrap();
+        for (parts, length) in [(u32::MAX, 100), (3, u64::MAX)] {
+            let mut manifest = png.payload_manifest("k").unwrap().unwrap();
+            (manifest.parts, manifest.length) = (parts, length);
+            let mut forged = png.clone();
+            forged.chunks_mut()[at] = manifest.to_chunk();
+            assert!(forged.extract_payload("k").is_err());
+        }
+    }
+
+    #[test]
+    fn test_embed_capacity() {
+        let mut png = Png::try_from(IMG).unwrap();
+        assert_eq!(png.embed_capacity(EmbedMethod::Lsb { bits: 1 }), 225 * 225 / 8);
+        assert_eq!(png.embed_capacity(EmbedMethod::Lsb { bits: 2 }), 225 * 225 / 4);
+        assert_eq!(png.embed_capacity(EmbedMethod::Lsb { bits: 0 }), 0);
+        assert_eq!(png.embed_capacity(EmbedMethod::Lsb { bits: 9 }), 225 * 225);
+        assert_eq!(png.embed_capacity(EmbedMethod::Chunks { budget: png.file_size() }), 0);
+
+        let budget = png.file_size() + 200_000;
+        let capacity = png.embed_capacity(EmbedMethod::Chunks { budget });
+        assert!(capacity > 190_000);
+        let key = "k".repeat(MAX_KEY_LEN);
+        png.embed_payload(&key, &vec![7; capacity], &EmbedOptions::default()).unwrap();
+        assert!(png.file_size() <= budget);
+        assert!(png.file_size() + 200 > budget);
+    }
+
+    #[test]
+    fn test_payload_bound_to_header() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.embed_payload("msg", b"hello", &EmbedOptions::default()).unwrap();
+        let ihdr = png.header().unwrap();
+        let mut other = png.clone();
+        other.set_header(&ihdr.clone().with_interlace(true));
+        assert!(other.extract_payload("msg").is_err());
+        other.set_header(&ihdr);
+        assert_eq!(other.extract_payload("msg").unwrap(), b"hello");
+
+        // version 1 manifests, crc over the stored bytes only, are refused
+        let at = png.chunks().iter().position(|c| *c.chunk_type() == MANIFEST_TYPE).unwrap();
+        let mut data = png.chunks()[at].data().to_vec();
+        data[4] = 1;
+        data[18..22].copy_from_slice(&crc32(b"hello", 5).to_be_bytes());
+        png.chunks_mut()[at] = Chunk::new(MANIFEST_TYPE, data);
+        png.set_header(&ihdr.with_interlace(true));
+        assert!(png.extract_payload("msg").is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        for len in 0..8 {
+            let data = payload(len);
+            assert_eq!(base64_decode(&base64_encode(&data)).unwrap(), data);
+        }
+        assert_eq!(base64_encode(b"hello"), b"aGVsbG8=");
+        assert!(base64_decode(b"aGVsbG8").is_none());
+        assert!(base64_decode(b"aG=sbG8=").is_none());
+        assert!(base64_decode(b"Just a comment.!").is_none());
+    }
+
+    #[test]
+    fn test_cover_chunks() {
+        let covers = [
+            CoverChunk::ITxt,
+            CoverChunk::Exif,
+            #[cfg(feature = "flate")]
+            CoverChunk::ZTxt,
+        ];
+        for cover in covers {
+            let mut png = Png::try_from(IMG).unwrap();
+            let comment = [COVER_KEYWORD, b"\0\0\0\0\0Just a comment."].concat();
+            png.insert_before_type("IEND", Chunk::new(ChunkType::ITXT, comment)).unwrap();
+            let before = png.chunks().len();
+            let data = payload(3000);
+            let options = EmbedOptions::new().chunk_size(1000).cover(cover);
+            png.embed_payload("msg", &data, &options).unwrap();
+
+            assert_eq!(png.chunks().len(), before + 1);
+            assert!(png.chunks().iter().all(|c| c.chunk_type().is_valid() && c.chunk_type().is_public()));
+            assert_eq!(png.payload_keys(), vec!["msg"]);
+            assert_eq!(png.extract_payload("msg").unwrap(), data);
+            // embedding again replaces it
+            png.embed_payload("msg", b"again", &options).unwrap();
+            assert_eq!(png.chunks().len(), before + 1);
+            assert_eq!(png.extract_payload("msg").unwrap(), b"again");
+            assert_eq!(png.remove_payload("msg"), 1);
+            assert_eq!(png.chunks().len(), before);
+        }
+    }
+
+    #[test]
+    fn test_exif_cover() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let options = EmbedOptions::new().cover(CoverChunk::Exif);
+        png.embed_payload("a", b"one", &options).unwrap();
+        let exif = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::EXIF).unwrap();
+        let idat = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IDAT).unwrap();
+        assert!(exif < idat);
+        assert!(png.embed_payload("b", b"two", &options).is_err());
+        png.embed_payload("b", b"two", &EmbedOptions::new().cover(CoverChunk::ITxt)).unwrap();
+        assert_eq!(png.payload_keys(), vec!["a", "b"]);
+
+        let capacity = png.embed_capacity(EmbedMethod::Cover(CoverChunk::Exif));
+        assert!(capacity as u64 > MAX_CHUNK_LENGTH as u64 - (1 << 23));
+        assert!(png.embed_capacity(EmbedMethod::Cover(CoverChunk::ITxt)) < capacity / 4 * 3 + 1);
+    }
+
+    #[cfg(feature = "shamir")]
+    #[test]
+    fn test_gf256() {
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[cfg(feature = "shamir")]
+    #[test]
+    fn test_split_across() {
+        let mut images = vec![Png::try_from(IMG).unwrap(); 5];
+        let secret = b"the vault code is 4711";
+        split_across(&mut images, "vault", secret, 3, &EmbedOptions::default()).unwrap();
+        let share = images[0].open_payload("vault", None).unwrap().1;
+        assert_ne!(&share[2 + SET_ID_LEN..], secret);
+        assert!(images[0].extract_payload("vault").is_err());
+
+        assert_eq!(combine_from(&images, "vault").unwrap(), secret);
+        assert_eq!(combine_from(&images[2..], "vault").unwrap(), secret);
+        let some = [images[4].clone(), Png::try_from(IMG).unwrap(), images[0].clone(), images[2].clone()];
+        assert_eq!(combine_from(&some, "vault").unwrap(), secret);
+        assert!(combine_from(&images[..2], "vault").is_err());
+        assert!(combine_from(&[images[0].clone(), images[0].clone(), images[1].clone()], "vault").is_err());
+        assert!(combine_from(&images, "other").is_err());
+
+        // shares of another split of the same secret do not mix
+        let mut again = vec![Png::try_from(IMG).unwrap(); 3];
+        split_across(&mut again, "vault", secret, 3, &EmbedOptions::default()).unwrap();
+        assert!(combine_from(&[images[0].clone(), images[1].clone(), again[2].clone()], "vault").is_err());
+
+        assert!(split_across(&mut images, "vault", secret, 0, &EmbedOptions::default()).is_err());
+        assert!(split_across(&mut images, "vault", secret, 6, &EmbedOptions::default()).is_err());
+        split_across(&mut images[..1], "one", secret, 1, &EmbedOptions::default()).unwrap();
+        assert_eq!(combine_from(&images, "one").unwrap(), secret);
+    }
+
+    #[cfg(all(feature = "shamir", feature = "encrypt"))]
+    #[test]
+    fn test_decrypt_from() {
+        let mut images = vec![Png::try_from(IMG).unwrap(); 3];
+        let options = EmbedOptions::new().encrypt("pw");
+        split_across(&mut images, "k", b"secret", 2, &options).unwrap();
+        assert!(combine_from(&images, "k").is_err());
+        assert_eq!(decrypt_from(&images[1..], "k", "pw").unwrap(), b"secret");
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("nice_pics_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_embed_file() {
+        let dir = temp_dir("embed_file");
+        let path = dir.join("settings.toml");
+        fs::write(&path, b"volume = 11\n").unwrap();
+
+        let mut png = Png::try_from(IMG).unwrap();
+        let key = png.embed_file(&path, &EmbedOptions::default()).unwrap();
+        assert_eq!(key, "settings.toml");
+        assert_eq!(png.extract_payload(&key).unwrap(), b"volume = 11\n");
+
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        let info = png.extract_file(&key, &out).unwrap();
+        assert_eq!((info.name(), info.size()), ("settings.toml", 12));
+        assert_eq!(info.crc(), crc32(b"volume = 11\n", 12));
+        assert_eq!(fs::read(out.join("settings.toml")).unwrap(), b"volume = 11\n");
+        png.extract_file(&key, dir.join("renamed.toml")).unwrap();
+        assert_eq!(fs::read(dir.join("renamed.toml")).unwrap(), b"volume = 11\n");
+
+        png.embed_payload("plain", b"x", &EmbedOptions::default()).unwrap();
+        assert!(png.extract_file("plain", &out).is_err());
+        assert!(png.embed_file(dir.join("missing"), &EmbedOptions::default()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_header_checked() {
+        let info = FileInfo { name: "a".to_string(), size: 3, crc: crc32(b"abc", 3) };
+        let mut data = info.to_bytes();
+        data.extend(b"abc");
+        assert_eq!(FileInfo::parse(&data).unwrap(), (info, &b"abc"[..]));
+        *data.last_mut().unwrap() = b'd';
+        assert!(FileInfo::parse(&data).is_err());
+        assert!(FileInfo::parse(&data[..5]).is_err());
+
+        for name in ["", ".", "..", "../x", "a/../../x", "/etc/x", "dir\\x", "C:x"] {
+            let info = FileInfo { name: name.to_string(), size: 3, crc: crc32(b"abc", 3) };
+            let mut data = info.to_bytes();
+            data.extend(b"abc");
+            assert!(FileInfo::parse(&data).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_extract_file_traversal() {
+        let dir = temp_dir("extract_traversal");
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        let escaped = dir.join("escaped");
+        for name in ["../escaped".to_string(), escaped.display().to_string()] {
+            let info = FileInfo { name: name.clone(), size: 3, crc: crc32(b"abc", 3) };
+            let mut data = info.to_bytes();
+            data.extend(b"abc");
+            let mut png = Png::try_from(IMG).unwrap();
+            png.embed("evil", FILE, &data, &EmbedOptions::default()).unwrap();
+            assert!(png.extract_file("evil", &out).is_err(), "{:?}", name);
+            assert!(!escaped.exists());
+            assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_deflate_payload() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let text = "all work and no play ".repeat(500);
+        let plain = png.file_size();
+        png.embed_payload("text", text.as_bytes(), &EmbedOptions::default()).unwrap();
+        let stored = png.file_size() - plain;
+
+        let mut packed = Png::try_from(IMG).unwrap();
+        let options = EmbedOptions::new().compression(Compression::Deflate(9));
+        packed.embed_payload("text", text.as_bytes(), &options).unwrap();
+        assert!((packed.file_size() - plain) * 10 < stored);
+        assert_eq!(packed.extract_payload("text").unwrap(), text.as_bytes());
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_decompress_limit() {
+        let (flag, packed) = compress(Compression::Deflate(9), &[0; 100_000]).unwrap();
+        assert_eq!(decompress(flag, packed.clone(), 100_000).unwrap().len(), 100_000);
+        assert!(matches!(decompress(flag, packed, 99_999), Err(Error::LimitExceeded("max_decompressed"))));
+
+        #[cfg(feature = "zstd")]
+        {
+            let (flag, packed) = compress(Compression::Zstd(3), &[0; 100_000]).unwrap();
+            assert_eq!(decompress(flag, packed.clone(), 100_000).unwrap().len(), 100_000);
+            assert!(matches!(decompress(flag, packed, 99_999), Err(Error::LimitExceeded("max_decompressed"))));
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_payload() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let data = payload(50_000);
+        let options = EmbedOptions::new().compression(Compression::Zstd(3)).chunk_size(1000);
+        png.embed_payload("z", &data, &options).unwrap();
+        assert!(png.chunks_of_type("stDt").count() < 50);
+        assert_eq!(png.extract_payload("z").unwrap(), data);
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn test_encrypted_payload() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let secret = b"meet at the usual place";
+        let options = EmbedOptions::new().chunk_size(10).encrypt("hunter2");
+        png.embed_payload("msg", secret, &options).unwrap();
+        assert!(!format!("{:?}", options).contains("hunter2"));
+
+        let stored: Vec<u8> = png.payload_parts("msg").flat_map(|(_, d)| d.to_vec()).collect();
+        assert!(!stored.windows(4).any(|w| w == b"meet"));
+        assert_eq!(png.decrypt_payload("msg", "hunter2").unwrap(), secret);
+        assert!(png.decrypt_payload("msg", "hunter3").is_err());
+        assert!(png.extract_payload("msg").is_err());
+
+        // the key is authenticated, moving parts to another key fails
+        let mut moved = png.clone();
+        for c in moved.chunks_mut().iter_mut().filter(|c| c.data().starts_with(b"msg\0")) {
+            let mut data = b"msh\0".to_vec();
+            data.extend(&c.data()[4..]);
+            c.set_data(data);
+        }
+        assert!(moved.decrypt_payload("msh", "hunter2").is_err());
+
+        // so is the header, even with the crc fixed up to match
+        let mut resaved = png.clone();
+        let ihdr = resaved.header().unwrap().with_interlace(true);
+        resaved.set_header(&ihdr);
+        let mut manifest = resaved.payload_manifest("msg").unwrap().unwrap();
+        let (_, stored) = png.read_payload("msg").unwrap();
+        manifest.crc = manifest.checksum(resaved.ihdr_data(), &stored);
+        let at = resaved.chunks().iter().position(|c| *c.chunk_type() == MANIFEST_TYPE).unwrap();
+        resaved.chunks_mut()[at] = manifest.to_chunk();
+        assert!(resaved.read_payload("msg").is_ok());
+        assert!(resaved.decrypt_payload("msg", "hunter2").is_err());
+
+        png.embed_payload("plain", b"x", &EmbedOptions::default()).unwrap();
+        assert!(png.decrypt_payload("plain", "hunter2").is_err());
+
+        #[cfg(feature = "flate")]
+        {
+            let text = "secret ".repeat(100);
+            let options = EmbedOptions::new().compression(Compression::Deflate(6)).encrypt("pw");
+            png.embed_payload("both", text.as_bytes(), &options).unwrap();
+            assert_eq!(png.decrypt_payload("both", "pw").unwrap(), text.as_bytes());
+        }
+
+        let dir = temp_dir("decrypt_file");
+        fs::write(dir.join("key.txt"), b"0xdeadbeef").unwrap();
+        png.embed_file(dir.join("key.txt"), &EmbedOptions::new().encrypt("pw")).unwrap();
+        assert!(png.extract_file("key.txt", &dir).is_err());
+        fs::remove_file(dir.join("key.txt")).unwrap();
+        png.decrypt_file("key.txt", "pw", &dir).unwrap();
+        assert_eq!(fs::read(dir.join("key.txt")).unwrap(), b"0xdeadbeef");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
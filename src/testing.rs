@@ -0,0 +1,134 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crc::adler32;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+const COLOR_TYPES: [ColorType; 5] =
+    [ColorType::Grayscale, ColorType::Rgb, ColorType::Indexed, ColorType::GrayscaleAlpha, ColorType::Rgba];
+
+/// Chunk types that pass `ChunkType::is_valid`: four letters with
+/// the reserved bit clear.
+pub fn chunk_type() -> impl Strategy<Value = ChunkType> {
+    "[a-zA-Z]{2}[A-Z][a-zA-Z]".prop_map(|s| s.parse().unwrap())
+}
+
+/// Chunks of a valid type with up to 255 bytes of data and a correct crc.
+pub fn chunk() -> impl Strategy<Value = Chunk> {
+    (chunk_type(), vec(any::<u8>(), 0..256)).prop_map(|(chunk_type, data)| Chunk::new(chunk_type, data))
+}
+
+/// A colour type with one of the bit depths it allows.
+pub fn format() -> impl Strategy<Value = (ColorType, u8)> {
+    select(&COLOR_TYPES[..]).prop_flat_map(|c| (Just(c), select(c.allowed_bit_depths())))
+}
+
+/// Well formed PNGs of up to 16x16 pixels in any format: IHDR, a PLTE
+/// for indexed images, one IDAT holding random pixels and IEND.
+pub fn minimal_png() -> impl Strategy<Value = Png> {
+    (1u32..=16, 1u32..=16, format())
+        .prop_flat_map(|(width, height, (color_type, bit_depth))| {
+            let ihdr = Ihdr::new(width, height, bit_depth, color_type).unwrap();
+            let pixels = vec(any::<u8>(), ihdr.row_bytes(width) * height as usize);
+            (Just(ihdr), pixels)
+        })
+        .prop_map(|(ihdr, pixels)| {
+            let row = ihdr.row_bytes(ihdr.width());
+            let mut raw = Vec::with_capacity(pixels.len() + ihdr.height() as usize);
+            for line in pixels.chunks(row) {
+                raw.push(0);
+                raw.extend(line);
+            }
+            let (color_type, bit_depth) = (ihdr.color_type(), ihdr.bit_depth());
+            let mut builder = Png::builder(ihdr);
+            if color_type == ColorType::Indexed {
+                // every index the bit depth can hold is in range
+                let entries = 1usize << bit_depth;
+                builder = builder.palette((0..entries * 3).map(|n| n as u8).collect());
+            }
+            builder.idat(stored_zlib(&raw)).build().unwrap()
+        })
+}
+
+/// The bytes of a `minimal_png` with one corruption: a flipped bit past
+/// the signature, a truncation, or a broken signature. Parsing them fails,
+/// except for a truncation between chunks which leaves a file that
+/// parses but does not validate.
+pub fn corrupted_png() -> impl Strategy<Value = Vec<u8>> {
+    minimal_png().prop_map(|png| png.as_bytes()).prop_flat_map(|bytes| {
+        let len = bytes.len();
+        let flip = (Just(bytes.clone()), 8..len, 0u8..8).prop_map(|(mut bytes, n, bit)| {
+            bytes[n] ^= 1 << bit;
+            bytes
+        });
+        let truncate = (Just(bytes.clone()), 8..len).prop_map(|(mut bytes, n)| {
+            bytes.truncate(n);
+            bytes
+        });
+        let signature = (Just(bytes), 0..8usize).prop_map(|(mut bytes, n)| {
+            bytes[n] = !bytes[n];
+            bytes
+        });
+        prop_oneof![flip, truncate, signature]
+    })
+}
+
+/// Wraps `data` in a zlib stream of stored blocks, so building an IDAT
+/// needs no compressor.
+fn stored_zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(u16::MAX as usize).collect();
+    for (n, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+        out.push((n + 1 == blocks.len()) as u8);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(*block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    proptest! {
+        #[test]
+        fn test_chunk_type_is_valid(chunk_type in chunk_type()) {
+            prop_assert!(chunk_type.is_valid());
+        }
+
+        #[test]
+        fn test_chunk_roundtrips(chunk in chunk()) {
+            prop_assert!(chunk.has_valid_crc());
+            prop_assert_eq!(Chunk::try_from(chunk.as_bytes().as_ref()).unwrap(), chunk);
+        }
+
+        #[test]
+        fn test_minimal_png_is_valid(png in minimal_png()) {
+            prop_assert!(png.validate().is_ok());
+            prop_assert_eq!(Png::try_from(png.as_bytes().as_ref()).unwrap(), png);
+        }
+
+        #[test]
+        fn test_corrupted_png_fails(bytes in corrupted_png()) {
+            prop_assert!(Png::try_from(bytes.as_ref()).map_or(true, |png| png.validate().is_err()));
+        }
+    }
+
+    #[cfg(feature = "flate")]
+    proptest! {
+        #[test]
+        fn test_minimal_png_inflates(png in minimal_png()) {
+            let ihdr = png.header().unwrap();
+            let len = (ihdr.row_bytes(ihdr.width()) + 1) * ihdr.height() as usize;
+            prop_assert_eq!(png.idat_data().unwrap().len(), len);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::parse::Limits;
+use crate::png::Png;
+
+/// A decoded tEXt, zTXt or iTXt chunk.
+///
+/// `to_chunk` picks the smallest type that can hold it: tEXt for
+/// Latin-1 text, zTXt if that is compressed, and iTXt for anything
+/// else or if there is a language or translated keyword.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+    language: String,
+    translated_keyword: String,
+    compressed: bool,
+}
+
+impl TextChunk {
+    /// Uncompressed `text` under `keyword`, with no language.
+    pub fn new(keyword: &str, text: &str) -> TextChunk {
+        TextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            language: String::new(),
+            translated_keyword: String::new(),
+            compressed: false,
+        }
+    }
+
+    /// Sets the language tag, like `en-GB`, and the keyword in that
+    /// language. Only iTXt can hold them.
+    pub fn language(mut self, language: &str, translated_keyword: &str) -> TextChunk {
+        self.language = language.to_string();
+        self.translated_keyword = translated_keyword.to_string();
+        self
+    }
+
+    /// Sets whether the text is zlib compressed, which needs the flate feature.
+    pub fn compressed(mut self, compressed: bool) -> TextChunk {
+        self.compressed = compressed;
+        self
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The language tag, empty if there is none.
+    pub fn language_tag(&self) -> &str {
+        &self.language
+    }
+
+    /// The keyword translated into the language, empty if there is none.
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Encodes the entry as a tEXt, zTXt or iTXt chunk.
+    ///
+    /// # Errors
+    /// returns an Error if the keyword is not 1 to 79 printable Latin-1
+    /// characters without leading, trailing or double spaces, the
+    /// language tag is not ASCII, or it is compressed without the flate feature.
+    pub fn to_chunk(&self) -> Result<Chunk, Error> {
+        check_keyword(&self.keyword)?;
+        if !self.language.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            bail!("invalid language tag: {:?}", self.language);
+        }
+        let mut data = latin1(&self.keyword).unwrap_or_default();
+        data.push(0);
+
+        let plain = self.language.is_empty() && self.translated_keyword.is_empty();
+        let chunk_type = match latin1(&self.text) {
+            Some(text) if plain && !self.compressed => {
+                data.extend(text);
+                ChunkType::TEXT
+            }
+            Some(text) if plain => {
+                data.push(0);
+                data.extend(deflate(&text)?);
+                ChunkType::ZTXT
+            }
+            _ => {
+                data.extend([self.compressed as u8, 0]);
+                data.extend(self.language.as_bytes());
+                data.push(0);
+                data.extend(self.translated_keyword.as_bytes());
+                data.push(0);
+                match self.compressed {
+                    true => data.extend(deflate(self.text.as_bytes())?),
+                    false => data.extend(self.text.as_bytes()),
+                }
+                ChunkType::ITXT
+            }
+        };
+        Ok(Chunk::new(chunk_type, data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+
+    /// Decodes a tEXt, zTXt or iTXt chunk.
+    ///
+    /// # Errors
+    /// returns an Error if the chunk is another type, is malformed, or
+    /// is compressed and can not be inflated.
+    fn try_from(chunk: &Chunk) -> Result<TextChunk, Error> {
+        let data = chunk.data();
+        let split = match data.iter().position(|b| *b == 0) {
+            Some(n) => n,
+            None => bail!("{} has no keyword separator", chunk.chunk_type()),
+        };
+        let mut entry = TextChunk::new(&from_latin1(&data[..split]), "");
+        let rest = &data[split + 1..];
+        match *chunk.chunk_type() {
+            ChunkType::TEXT => entry.text = from_latin1(rest),
+            ChunkType::ZTXT => {
+                match rest.first() {
+                    Some(0) => {}
+                    Some(method) => bail!("unknown zTXt compression method: {}", method),
+                    None => bail!("zTXt has no compression method"),
+                }
+                entry.text = from_latin1(&inflate(&rest[1..])?);
+                entry.compressed = true;
+            }
+            ChunkType::ITXT => {
+                let (flag, method) = match rest {
+                    [flag, method, ..] => (*flag, *method),
+                    _ => bail!("iTXt is missing its compression flag and method"),
+                };
+                let rest = &rest[2..];
+                let language = match rest.iter().position(|b| *b == 0) {
+                    Some(n) => n,
+                    None => bail!("iTXt has no language tag separator"),
+                };
+                let translated = match rest[language + 1..].iter().position(|b| *b == 0) {
+                    Some(n) => language + 1 + n,
+                    None => bail!("iTXt has no translated keyword separator"),
+                };
+                entry.language = String::from_utf8(rest[..language].to_vec())?;
+                entry.translated_keyword = String::from_utf8(rest[language + 1..translated].to_vec())?;
+                let text = &rest[translated + 1..];
+                entry.text = match (flag, method) {
+                    (0, _) => String::from_utf8(text.to_vec())?,
+                    (1, 0) => String::from_utf8(inflate(text)?)?,
+                    (1, method) => bail!("unknown iTXt compression method: {}", method),
+                    (flag, _) => bail!("invalid iTXt compression flag: {}", flag),
+                };
+                entry.compressed = flag == 1;
+            }
+            _ => bail!("{} is not a text chunk", chunk.chunk_type()),
+        }
+        Ok(entry)
+    }
+}
+
+impl Png {
+    /// Decodes every tEXt, zTXt and iTXt chunk in file order. Malformed
+    /// chunks, and compressed ones without the flate feature, are left out.
+    pub fn text_entries(&self) -> Vec<TextChunk> {
+        self.iter()
+            .filter(|c| is_text(c.chunk_type()))
+            .filter_map(|c| TextChunk::try_from(c).ok())
+            .collect()
+    }
+
+    /// The text of the first entry under `keyword`.
+    pub fn text(&self, keyword: &str) -> Option<String> {
+        self.text_entries().into_iter().find(|e| e.keyword == keyword).map(|e| e.text)
+    }
+
+    /// Stores `value` under `keyword` uncompressed, replacing any text
+    /// with that keyword. See `set_text_entry`.
+    ///
+    /// # Errors
+    /// returns an Error if the keyword is invalid.
+    pub fn set_text(&mut self, keyword: &str, value: &str) -> Result<(), Error> {
+        self.set_text_entry(&TextChunk::new(keyword, value))
+    }
+
+    /// Stores `entry` before IEND, replacing any text with its keyword.
+    ///
+    /// # Errors
+    /// returns an Error if the entry can not be encoded, see `TextChunk::to_chunk`.
+    pub fn set_text_entry(&mut self, entry: &TextChunk) -> Result<(), Error> {
+        let chunk = entry.to_chunk()?;
+        self.remove_text(&entry.keyword);
+        let at = self.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IEND);
+        let at = at.unwrap_or(self.chunks().len());
+        self.chunks_mut().insert(at, chunk);
+        Ok(())
+    }
+
+    /// Removes every text chunk with `keyword`, returning how many there were.
+    /// Works on compressed chunks without the flate feature.
+    pub fn remove_text(&mut self, keyword: &str) -> usize {
+        let before = self.chunks().len();
+        let key = latin1(keyword);
+        self.chunks_mut().retain(|c| {
+            let found = c.data().iter().position(|b| *b == 0).map(|n| &c.data()[..n]);
+            !(is_text(c.chunk_type()) && found.is_some() && found == key.as_deref())
+        });
+        before - self.chunks().len()
+    }
+}
+
+fn is_text(chunk_type: &ChunkType) -> bool {
+    matches!(*chunk_type, ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT)
+}
+
+/// Checks a keyword against the spec: 1 to 79 printable Latin-1
+/// characters, no leading, trailing or consecutive spaces.
+pub(crate) fn check_keyword(keyword: &str) -> Result<(), Error> {
+    let printable = |c: char| (' '..='~').contains(&c) || ('\u{a1}'..='\u{ff}').contains(&c);
+    if keyword.is_empty() || keyword.chars().count() > 79 {
+        bail!("keyword must be 1 to 79 characters: {:?}", keyword);
+    }
+    if !keyword.chars().all(printable) {
+        bail!("keyword must be printable Latin-1: {:?}", keyword);
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        bail!("keyword has leading, trailing or double spaces: {:?}", keyword);
+    }
+    Ok(())
+}
+
+/// Encodes `s` as Latin-1, `None` if it has characters past U+00FF.
+fn latin1(s: &str) -> Option<Vec<u8>> {
+    s.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+#[cfg(feature = "flate")]
+pub(crate) fn deflate(data: &[u8]) -> Result<Vec<u8>, Error> {
+    use std::io::Write;
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "flate"))]
+pub(crate) fn deflate(_data: &[u8]) -> Result<Vec<u8>, Error> {
+    bail!("compressed text needs the flate feature")
+}
+
+pub(crate) fn inflate(data: &[u8]) -> Result<Vec<u8>, Error> {
+    inflate_limited(data, Limits::default().max_decompressed)
+}
+
+/// Inflates `data`, failing once the output would go over `max` bytes.
+#[cfg(feature = "flate")]
+pub(crate) fn inflate_limited(data: &[u8], max: u64) -> Result<Vec<u8>, Error> {
+    use std::io::Read;
+    let mut out = Vec::new();
+    flate2::read::ZlibDecoder::new(data).take(max.saturating_add(1)).read_to_end(&mut out)?;
+    if out.len() as u64 > max {
+        return Err(Error::LimitExceeded("max_decompressed"));
+    }
+    Ok(out)
+}
+
+#[cfg(not(feature = "flate"))]
+pub(crate) fn inflate_limited(_data: &[u8], _max: u64) -> Result<Vec<u8>, Error> {
+    bail!("compressed text needs the flate feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_text_chunk_types() {
+        let chunk = TextChunk::new("Author", "Zoë").to_chunk().unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::TEXT);
+        assert_eq!(chunk.data(), b"Author\0Zo\xeb");
+
+        let chunk = TextChunk::new("Title", "日本").to_chunk().unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::ITXT);
+        assert_eq!(chunk.data(), "Title\0\0\0\0\0日本".as_bytes());
+
+        let entry = TextChunk::new("Title", "Hi").language("fr", "Titre");
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Title\0\0\0fr\0Titre\0Hi");
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        for keyword in ["", " lead", "trail ", "dou  ble", "tab\t", "日本", &"k".repeat(80)] {
+            assert!(TextChunk::new(keyword, "x").to_chunk().is_err(), "{:?}", keyword);
+        }
+        assert!(TextChunk::new(&"k".repeat(79), "x").to_chunk().is_ok());
+        assert!(TextChunk::new("Title", "x").language("en GB", "").to_chunk().is_err());
+        assert!(TextChunk::try_from(&Chunk::new(ChunkType::TEXT, b"no separator".to_vec())).is_err());
+        assert!(TextChunk::try_from(&Chunk::new(ChunkType::ITXT, b"Title\0\x02\0\0\0".to_vec())).is_err());
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_compressed() {
+        let long = "a long comment ".repeat(50);
+        let entry = TextChunk::new("Comment", &long).compressed(true);
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::ZTXT);
+        assert!(chunk.data().len() < long.len());
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), entry);
+
+        let entry = TextChunk::new("Comment", "ünïcødé ☃").compressed(true);
+        let chunk = entry.to_chunk().unwrap();
+        assert_eq!(*chunk.chunk_type(), ChunkType::ITXT);
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_png_text() {
+        let mut png = Png::try_from(IMG).unwrap();
+        let existing = png.text_entries().len();
+        png.set_text("Author", "someone").unwrap();
+        png.set_text("Title", "☃").unwrap();
+        png.set_text("Author", "someone else").unwrap();
+        assert_eq!(png.text("Author").unwrap(), "someone else");
+        assert_eq!(png.text("Title").unwrap(), "☃");
+        assert_eq!(png.text_entries().len(), existing + 2);
+        assert_eq!(*png.chunks().last().unwrap().chunk_type(), ChunkType::IEND);
+
+        assert_eq!(png.remove_text("Author"), 1);
+        assert_eq!(png.remove_text("Author"), 0);
+        assert!(png.text("Author").is_none());
+        assert!(png.set_text("", "x").is_err());
+    }
+}
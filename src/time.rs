@@ -0,0 +1,225 @@
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::png::Png;
+
+/// A UTC date and time as stored in tIME. Seconds go up to 60 for leap seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl Timestamp {
+    /// # Errors
+    /// returns an Error if any field is out of range or the day is not in the month.
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Result<Timestamp, Error> {
+        if !(1..=12).contains(&month) {
+            bail!("invalid month: {}", month);
+        }
+        if day == 0 || day > days_in_month(year, month) {
+            bail!("invalid day {} for {}-{:02}", day, year, month);
+        }
+        if hour > 23 || minute > 59 || second > 60 {
+            bail!("invalid time: {:02}:{:02}:{:02}", hour, minute, second);
+        }
+        Ok(Timestamp { year, month, day, hour, minute, second })
+    }
+
+    /// The current time, from the system clock.
+    pub fn now() -> Timestamp {
+        Timestamp::from(SystemTime::now())
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.year.to_be_bytes().to_vec();
+        data.extend([self.month, self.day, self.hour, self.minute, self.second]);
+        Chunk::new(ChunkType::TIME, data)
+    }
+}
+
+impl TryFrom<&Chunk> for Timestamp {
+    type Error = Error;
+
+    /// # Errors
+    /// returns an Error if the chunk is not a 7 byte tIME or has an invalid date.
+    fn try_from(chunk: &Chunk) -> Result<Timestamp, Error> {
+        if *chunk.chunk_type() != ChunkType::TIME {
+            bail!("expected tIME, got {}", chunk.chunk_type());
+        }
+        match *chunk.data() {
+            [y1, y2, month, day, hour, minute, second] => {
+                Timestamp::new(u16::from_be_bytes([y1, y2]), month, day, hour, minute, second)
+            }
+            _ => bail!("tIME data must be 7 bytes, got {}", chunk.length()),
+        }
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    /// Converts to UTC. Times before 1970 become the epoch and leap
+    /// seconds are not counted, like in `SystemTime`.
+    fn from(time: SystemTime) -> Timestamp {
+        let secs = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        let (year, month, day) = civil_from_days(secs / 86400);
+        let secs = secs % 86400;
+        Timestamp {
+            year: year.min(u16::MAX as u64) as u16,
+            month,
+            day,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+}
+
+impl Display for Timestamp {
+    /// Formats as RFC 3339, e.g. `2024-02-29T13:05:09Z`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+impl Png {
+    /// When the image was last changed, from tIME.
+    ///
+    /// # Errors
+    /// returns an Error if the tIME chunk is malformed.
+    pub fn modified_time(&self) -> Result<Option<Timestamp>, Error> {
+        self.chunk_by_type("tIME").map(Timestamp::try_from).transpose()
+    }
+
+    /// Replaces tIME with `time`, or adds it before IEND.
+    pub fn set_modified_time(&mut self, time: Timestamp) {
+        self.replace_unique(time.to_chunk(), &[]);
+    }
+
+    /// Sets tIME to now, returning the time written.
+    pub fn touch(&mut self) -> Timestamp {
+        let now = Timestamp::now();
+        self.set_modified_time(now);
+        now
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The year, month and day `days` days after 1970-01-01, in the
+/// proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    // shifted so years start in March and the leap day comes last
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_timestamp_new() {
+        assert!(Timestamp::new(2024, 2, 29, 23, 59, 60).is_ok());
+        assert!(Timestamp::new(2023, 2, 29, 0, 0, 0).is_err());
+        assert!(Timestamp::new(1900, 2, 29, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2000, 2, 29, 0, 0, 0).is_ok());
+        assert!(Timestamp::new(2024, 13, 1, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2024, 4, 31, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2024, 1, 1, 24, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_chunk() {
+        let time = Timestamp::new(2024, 2, 29, 13, 5, 9).unwrap();
+        let chunk = time.to_chunk();
+        assert_eq!(chunk.data(), [0x07, 0xe8, 2, 29, 13, 5, 9]);
+        assert_eq!(Timestamp::try_from(&chunk).unwrap(), time);
+        assert_eq!(time.to_string(), "2024-02-29T13:05:09Z");
+
+        assert!(Timestamp::try_from(&Chunk::new(ChunkType::TIME, vec![7, 0xe8, 2])).is_err());
+        assert!(Timestamp::try_from(&Chunk::new(ChunkType::TIME, vec![7, 0xe8, 0, 1, 0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_from_system_time() {
+        let at = |secs| Timestamp::from(UNIX_EPOCH + Duration::from_secs(secs)).to_string();
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_709_211_909), "2024-02-29T13:05:09Z");
+        assert_eq!(at(4_102_444_799), "2099-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_png_modified_time() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_chunks("tIME");
+        assert_eq!(png.modified_time().unwrap(), None);
+
+        let time = Timestamp::new(2001, 9, 9, 1, 46, 40).unwrap();
+        png.set_modified_time(time);
+        assert_eq!(png.modified_time().unwrap(), Some(time));
+        assert_eq!(*png.chunks()[png.chunks().len() - 2].chunk_type(), ChunkType::TIME);
+
+        png.append_chunk(time.to_chunk());
+        let now = png.touch();
+        assert!(now > time);
+        assert_eq!(png.modified_time().unwrap(), Some(now));
+        assert_eq!(png.chunks_of_type("tIME").count(), 1);
+    }
+}
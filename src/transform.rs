@@ -0,0 +1,291 @@
+use crate::decode::{copy_pixel, crop};
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::Image;
+use crate::png::Png;
+
+/// How `Image::resize` picks the colour of each new pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ResizeFilter {
+    /// The closest old pixel, fast and keeps hard edges.
+    Nearest,
+    /// The average of the old pixels the new one covers, best for shrinking.
+    #[default]
+    Box,
+    /// Blends the four closest old pixels, smooth when enlarging.
+    Bilinear,
+}
+
+impl Image {
+    /// Copies the `width` x `height` rectangle at `x`, `y`.
+    ///
+    /// # Errors
+    /// returns an Error if the rectangle is empty or not inside the image.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Image, Error> {
+        if width == 0
+            || height == 0
+            || x as u64 + width as u64 > self.width() as u64
+            || y as u64 + height as u64 > self.height() as u64
+        {
+            bail!("rectangle {}x{} at {},{} is not inside the image", width, height, x, y);
+        }
+        let pixels = crop(self.header(), self.data(), x, y, width, height);
+        Ok(self.with_pixels(width, height, pixels))
+    }
+
+    /// Mirrors the image left to right.
+    pub fn flip_horizontal(&self) -> Image {
+        let w = self.width() - 1;
+        self.resample(self.width(), self.height(), |x, y| (w - x, y))
+    }
+
+    /// Mirrors the image top to bottom.
+    pub fn flip_vertical(&self) -> Image {
+        let h = self.height() - 1;
+        self.resample(self.width(), self.height(), |x, y| (x, h - y))
+    }
+
+    /// Turns the image a quarter clockwise.
+    pub fn rotate90(&self) -> Image {
+        let h = self.height() - 1;
+        self.resample(self.height(), self.width(), |x, y| (y, h - x))
+    }
+
+    /// Turns the image upside down.
+    pub fn rotate180(&self) -> Image {
+        let (w, h) = (self.width() - 1, self.height() - 1);
+        self.resample(self.width(), self.height(), |x, y| (w - x, h - y))
+    }
+
+    /// Turns the image a quarter anticlockwise.
+    pub fn rotate270(&self) -> Image {
+        let w = self.width() - 1;
+        self.resample(self.height(), self.width(), |x, y| (w - y, x))
+    }
+
+    /// Scales the image to `width` x `height`. Samples are blended at
+    /// the bit depth of the image and it keeps its format. Palette indices
+    /// can not be blended, so indexed images always use `Nearest`.
+    ///
+    /// # Errors
+    /// returns an Error if `width` or `height` is 0.
+    pub fn resize(&self, width: u32, height: u32, filter: ResizeFilter) -> Result<Image, Error> {
+        if width == 0 || height == 0 {
+            bail!("can not resize to {}x{}", width, height);
+        }
+        let (sw, sh) = (self.width() as u64, self.height() as u64);
+        let filter = if self.color_type() == ColorType::Indexed { ResizeFilter::Nearest } else { filter };
+        let image = match filter {
+            ResizeFilter::Nearest => self.resample(width, height, |x, y| {
+                // centre of the new pixel, in old pixels
+                let sx = ((2 * x as u64 + 1) * sw / (2 * width as u64)) as u32;
+                let sy = ((2 * y as u64 + 1) * sh / (2 * height as u64)) as u32;
+                (sx, sy)
+            }),
+            ResizeFilter::Box => self.blend(width, height, |x, y, c| {
+                let x0 = x as u64 * sw / width as u64;
+                let x1 = ((x as u64 + 1) * sw / width as u64).max(x0 + 1);
+                let y0 = y as u64 * sh / height as u64;
+                let y1 = ((y as u64 + 1) * sh / height as u64).max(y0 + 1);
+                let mut sum = 0u64;
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        sum += self.sample(sx as u32, sy as u32, c) as u64;
+                    }
+                }
+                let n = (x1 - x0) * (y1 - y0);
+                ((sum + n / 2) / n) as u16
+            }),
+            ResizeFilter::Bilinear => self.blend(width, height, |x, y, c| {
+                let (fx, x0, x1) = neighbours(x, width, self.width());
+                let (fy, y0, y1) = neighbours(y, height, self.height());
+                let s = |sx, sy| self.sample(sx, sy, c) as f32;
+                let top = s(x0, y0) * (1.0 - fx) + s(x1, y0) * fx;
+                let bottom = s(x0, y1) * (1.0 - fx) + s(x1, y1) * fx;
+                (top * (1.0 - fy) + bottom * fy).round() as u16
+            }),
+        };
+        Ok(image)
+    }
+
+    /// Builds a `width` x `height` image of the same format where
+    /// pixel `x`, `y` is pixel `source(x, y)` of this one.
+    fn resample(&self, width: u32, height: u32, source: impl Fn(u32, u32) -> (u32, u32)) -> Image {
+        let stride = self.header().row_bytes(width);
+        let bits = self.header().bits_per_pixel();
+        let mut out = vec![0u8; stride * height as usize];
+        for (y, dest) in out.chunks_exact_mut(stride).enumerate() {
+            for x in 0..width {
+                let (sx, sy) = source(x, y as u32);
+                copy_pixel(self.row(sy), sx as usize, dest, x as usize, bits);
+            }
+        }
+        self.with_pixels(width, height, out)
+    }
+
+    /// Builds a `width` x `height` image of the same format where
+    /// sample `c` of pixel `x`, `y` is `sample(x, y, c)`.
+    fn blend(&self, width: u32, height: u32, sample: impl Fn(u32, u32, usize) -> u16) -> Image {
+        let channels = self.color_type().channels() as usize;
+        let depth = self.bit_depth() as usize;
+        let stride = self.header().row_bytes(width);
+        let mut out = vec![0u8; stride * height as usize];
+        for (y, row) in out.chunks_exact_mut(stride).enumerate() {
+            for x in 0..width as usize {
+                for c in 0..channels {
+                    let value = sample(x as u32, y as u32, c);
+                    let n = x * channels + c;
+                    match depth {
+                        16 => row[n * 2..n * 2 + 2].copy_from_slice(&value.to_be_bytes()),
+                        8 => row[n] = value as u8,
+                        _ => row[n * depth / 8] |= (value as u8) << (8 - depth - n * depth % 8),
+                    }
+                }
+            }
+        }
+        self.with_pixels(width, height, out)
+    }
+
+    /// An image of the same format, palette and tRNS with other pixels.
+    fn with_pixels(&self, width: u32, height: u32, pixels: Vec<u8>) -> Image {
+        let ihdr = self.header();
+        // the size comes from this image or a part of it, so it is valid
+        let ihdr = Ihdr::new(width, height, ihdr.bit_depth(), ihdr.color_type())
+            .unwrap()
+            .with_interlace(ihdr.is_interlaced());
+        Image::new(ihdr, pixels, self.palette().map(|p| p.to_vec()), self.trns().map(|t| t.to_vec()))
+    }
+}
+
+/// Where the centre of new pixel `n` of `new` falls between two of
+/// `old` pixels: the weight of the second and both indices.
+fn neighbours(n: u32, new: u32, old: u32) -> (f32, u32, u32) {
+    let pos = ((n as f32 + 0.5) * old as f32 / new as f32 - 0.5).clamp(0.0, (old - 1) as f32);
+    let first = pos.floor() as u32;
+    (pos - first as f32, first, (first + 1).min(old - 1))
+}
+
+impl Png {
+    /// Decodes the image, shrinks it with `ResizeFilter::Box` so neither
+    /// side is over `max_size`, keeping the aspect ratio, and encodes it
+    /// as a new `Png`. Images that already fit are not scaled. Only the
+    /// chunks needed to show the image are kept.
+    ///
+    /// # Errors
+    /// returns an Error if `max_size` is 0 or the image can not be decoded.
+    pub fn thumbnail(&self, max_size: u32) -> Result<Png, Error> {
+        if max_size == 0 {
+            bail!("thumbnail size must not be 0");
+        }
+        let image = self.decode_pixels()?;
+        let (width, height) = (image.width() as u64, image.height() as u64);
+        let longest = width.max(height);
+        if longest <= max_size as u64 {
+            return image.to_png();
+        }
+        let scaled = |side: u64| ((side * max_size as u64 + longest / 2) / longest).max(1) as u32;
+        image.resize(scaled(width), scaled(height), ResizeFilter::Box)?.to_png()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    // 3x2 gray, pixel value = index
+    fn gray() -> Image {
+        Image::new(Ihdr::new(3, 2, 8, ColorType::Grayscale).unwrap(), (0..6).collect(), None, None)
+    }
+
+    #[test]
+    fn test_flip_rotate() {
+        let image = gray();
+        assert_eq!(image.flip_horizontal().data(), &[2, 1, 0, 5, 4, 3]);
+        assert_eq!(image.flip_vertical().data(), &[3, 4, 5, 0, 1, 2]);
+        assert_eq!(image.rotate180().data(), &[5, 4, 3, 2, 1, 0]);
+
+        let turned = image.rotate90();
+        assert_eq!((turned.width(), turned.height()), (2, 3));
+        assert_eq!(turned.data(), &[3, 0, 4, 1, 5, 2]);
+        assert_eq!(image.rotate270().data(), &[2, 5, 1, 4, 0, 3]);
+        assert_eq!(turned.rotate270(), image);
+    }
+
+    #[test]
+    fn test_transform_packed() {
+        // 3x1 2-bit: 1, 2, 3
+        let ihdr = Ihdr::new(3, 1, 2, ColorType::Grayscale).unwrap();
+        let image = Image::new(ihdr, vec![0b0110_1100], None, None);
+        assert_eq!(image.flip_horizontal().data(), &[0b1110_0100]);
+        assert_eq!(image.rotate90().data(), &[0b0100_0000, 0b1000_0000, 0b1100_0000]);
+        assert_eq!(image.crop(1, 0, 2, 1).unwrap().data(), &[0b1011_0000]);
+    }
+
+    #[test]
+    fn test_crop() {
+        let image = gray();
+        let part = image.crop(1, 1, 2, 1).unwrap();
+        assert_eq!(part.data(), &[4, 5]);
+        assert!(image.crop(2, 0, 2, 1).is_err());
+        assert!(image.crop(0, 0, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_resize_nearest() {
+        let image = gray();
+        let big = image.resize(6, 4, ResizeFilter::Nearest).unwrap();
+        assert_eq!(big.row(0), &[0, 0, 1, 1, 2, 2]);
+        assert_eq!(big.row(3), &[3, 3, 4, 4, 5, 5]);
+        assert_eq!(big.resize(3, 2, ResizeFilter::Nearest).unwrap(), image);
+        assert!(image.resize(0, 1, ResizeFilter::Box).is_err());
+    }
+
+    #[test]
+    fn test_resize_box_bilinear() {
+        // 4x2 gray 16 bit
+        let samples: [u16; 8] = [0, 100, 200, 300, 1000, 1100, 1200, 1300];
+        let data = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+        let image = Image::new(Ihdr::new(4, 2, 16, ColorType::Grayscale).unwrap(), data, None, None);
+        let small = image.resize(2, 1, ResizeFilter::Box).unwrap();
+        assert_eq!(small.samples_u16(), vec![550, 750]);
+        let wide = image.resize(8, 2, ResizeFilter::Bilinear).unwrap();
+        assert_eq!(wide.samples_u16()[..8], [0, 25, 75, 125, 175, 225, 275, 300]);
+
+        // packed samples blend too
+        let ihdr = Ihdr::new(2, 1, 4, ColorType::Grayscale).unwrap();
+        let packed = Image::new(ihdr, vec![0x0f], None, None);
+        assert_eq!(packed.resize(1, 1, ResizeFilter::Box).unwrap().data(), &[0x80]);
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let png = Png::try_from(IMG).unwrap();
+        let thumb = png.thumbnail(64).unwrap();
+        assert!(thumb.validate().is_ok());
+        let ihdr = thumb.header().unwrap();
+        assert_eq!((ihdr.width(), ihdr.height()), (64, 64));
+        assert_eq!(ihdr.color_type(), ColorType::Indexed);
+
+        let pixels = [7; 300 * 100 * 3];
+        let wide = Png::from_pixels(300, 100, ColorType::Rgb, &pixels, Default::default()).unwrap();
+        let thumb = wide.thumbnail(30).unwrap();
+        assert_eq!((thumb.header().unwrap().width(), thumb.header().unwrap().height()), (30, 10));
+        assert!(thumb.decode_pixels().unwrap().data().iter().all(|b| *b == 7));
+        assert_eq!(wide.thumbnail(1000).unwrap().decode_pixels().unwrap(), wide.decode_pixels().unwrap());
+        assert!(wide.thumbnail(0).is_err());
+    }
+
+    #[test]
+    fn test_to_png() {
+        let png = Png::try_from(IMG).unwrap();
+        let image = png.decode_pixels().unwrap();
+        let turned = image.rotate90().crop(10, 20, 100, 50).unwrap();
+        let encoded = turned.to_png().unwrap();
+        assert!(encoded.validate().is_ok());
+        assert_eq!(encoded.palette().unwrap(), png.palette().unwrap());
+        assert_eq!(encoded.decode_pixels().unwrap(), turned);
+        assert_eq!(turned.to_rgba8().unwrap().pixel(0, 0), image.to_rgba8().unwrap().pixel(20, 214));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::bkgd::{parse_samples, sample_data};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+/// The transparency from tRNS, in the form the colour type needs.
+/// Images with an alpha channel can not have one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Transparency {
+    /// Alpha for the first palette entries, the rest are opaque.
+    Alphas(Vec<u8>),
+    /// The grey level that is fully transparent.
+    Gray(u16),
+    /// The red, green and blue that is fully transparent.
+    Rgb(u16, u16, u16),
+}
+
+impl Transparency {
+    /// # Errors
+    /// returns an Error if the variant does not match the colour type, a
+    /// sample does not fit the bit depth, or there are more alphas than
+    /// `palette_len` entries.
+    pub fn to_chunk(&self, ihdr: &Ihdr, palette_len: usize) -> Result<Chunk, Error> {
+        let data = match (self, ihdr.color_type()) {
+            (Transparency::Alphas(a), ColorType::Indexed) if a.len() <= palette_len => a.clone(),
+            (Transparency::Alphas(a), ColorType::Indexed) => {
+                bail!("{} alphas for {} palette entries", a.len(), palette_len)
+            }
+            (Transparency::Gray(g), ColorType::Grayscale) => sample_data(&[*g], ihdr.bit_depth())?,
+            (Transparency::Rgb(r, g, b), ColorType::Rgb) => sample_data(&[*r, *g, *b], ihdr.bit_depth())?,
+            (transparency, color_type) => bail!("{:?} does not fit a {:?} image", transparency, color_type),
+        };
+        Ok(Chunk::new(ChunkType::TRNS, data))
+    }
+
+    /// # Errors
+    /// returns an Error if the chunk is not tRNS or its length does not
+    /// match the colour type.
+    pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr) -> Result<Transparency, Error> {
+        if *chunk.chunk_type() != ChunkType::TRNS {
+            bail!("expected tRNS, got {}", chunk.chunk_type());
+        }
+        Ok(match (ihdr.color_type(), chunk.data()) {
+            (ColorType::Indexed, alphas) if alphas.len() <= 256 => Transparency::Alphas(alphas.to_vec()),
+            (ColorType::Grayscale, [_, _]) => Transparency::Gray(parse_samples(chunk.data())[0]),
+            (ColorType::Rgb, [_, _, _, _, _, _]) => match *parse_samples(chunk.data()) {
+                [r, g, b] => Transparency::Rgb(r, g, b),
+                _ => unreachable!(),
+            },
+            (color_type, data) => bail!("tRNS of {} bytes does not fit a {:?} image", data.len(), color_type),
+        })
+    }
+}
+
+impl Png {
+    /// The transparency from tRNS.
+    ///
+    /// # Errors
+    /// returns an Error if there is no valid IHDR or tRNS is malformed.
+    pub fn transparency(&self) -> Result<Option<Transparency>, Error> {
+        match self.chunk_by_type("tRNS") {
+            Some(c) => Ok(Some(Transparency::from_chunk(c, &self.header()?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces tRNS, or adds it before the image data.
+    ///
+    /// # Errors
+    /// returns an Error if there is no valid IHDR or `transparency` does
+    /// not fit the image, see `Transparency::to_chunk`.
+    pub fn set_transparency(&mut self, transparency: &Transparency) -> Result<(), Error> {
+        let palette_len = self.chunk_by_type("PLTE").map_or(0, |c| c.data().len() / 3);
+        let chunk = transparency.to_chunk(&self.header()?, palette_len)?;
+        self.replace_unique(chunk, &[ChunkType::IDAT]);
+        Ok(())
+    }
+
+    /// Removes tRNS, returning whether there was one.
+    pub fn remove_transparency(&mut self) -> bool {
+        !self.remove_chunks("tRNS").is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    #[test]
+    fn test_transparency_chunk() {
+        let rgb = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap();
+        let chunk = Transparency::Rgb(1, 2, 255).to_chunk(&rgb, 0).unwrap();
+        assert_eq!(chunk.data(), [0, 1, 0, 2, 0, 255]);
+        assert_eq!(Transparency::from_chunk(&chunk, &rgb).unwrap(), Transparency::Rgb(1, 2, 255));
+        assert!(Transparency::Rgb(256, 0, 0).to_chunk(&rgb, 0).is_err());
+
+        let rgba = Ihdr::new(1, 1, 8, ColorType::Rgba).unwrap();
+        assert!(Transparency::Rgb(0, 0, 0).to_chunk(&rgba, 0).is_err());
+        assert!(Transparency::from_chunk(&chunk, &rgba).is_err());
+
+        let indexed = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        assert_eq!(Transparency::Alphas(vec![0, 128]).to_chunk(&indexed, 2).unwrap().data(), [0, 128]);
+        assert!(Transparency::Alphas(vec![0; 3]).to_chunk(&indexed, 2).is_err());
+    }
+
+    #[test]
+    fn test_png_transparency() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.remove_transparency();
+        assert_eq!(png.transparency().unwrap(), None);
+        png.set_transparency(&Transparency::Alphas(vec![0])).unwrap();
+        assert_eq!(png.transparency().unwrap(), Some(Transparency::Alphas(vec![0])));
+        assert_eq!(png.palette().unwrap().unwrap().get(0).unwrap().a, 0);
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn test_gray_key() {
+        use crate::encode::EncodeOptions;
+        let pixels = [10, 20, 30, 40];
+        let options = EncodeOptions::default();
+        let mut gray = Png::from_pixels(2, 2, ColorType::Grayscale, &pixels, options).unwrap();
+        gray.set_transparency(&Transparency::Gray(20)).unwrap();
+        let rgba = gray.decode_pixels().unwrap().to_rgba8().unwrap();
+        assert_eq!(rgba.data()[3..8], [255, 20, 20, 20, 0]);
+        assert!(gray.remove_transparency());
+    }
+}
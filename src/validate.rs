@@ -0,0 +1,272 @@
+use std::fmt::Display;
+
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+/// Largest length a chunk may declare, 2^31 - 1.
+pub const MAX_CHUNK_LENGTH: u32 = i32::MAX as u32;
+
+/// A way a `Png` breaks the structural rules of the PNG spec.
+/// Chunk indexes count from 0 and do not include the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A chunk the file must have is missing.
+    MissingChunk(ChunkType),
+    /// A chunk that may only appear once appears `count` times.
+    DuplicateChunk { chunk_type: ChunkType, count: usize },
+    /// The first chunk is not IHDR.
+    IhdrNotFirst,
+    /// The last chunk is not IEND.
+    IendNotLast,
+    /// The IHDR chunk could not be parsed.
+    InvalidHeader(String),
+    /// PLTE is required for indexed images.
+    MissingPalette,
+    /// PLTE must not appear in greyscale images.
+    UnexpectedPalette(ColorType),
+    /// PLTE length is not a multiple of 3 or has too many entries.
+    InvalidPaletteLength(u32),
+    /// PLTE must come before the first IDAT.
+    PaletteAfterIdat,
+    /// IDAT chunks must be consecutive, `index` is the first one out of line.
+    NonContiguousIdat { index: usize },
+    /// A chunk is longer than `MAX_CHUNK_LENGTH`.
+    ChunkTooLong { index: usize, length: u32 },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::MissingChunk(t) => write!(f, "missing {} chunk", t),
+            ValidationError::DuplicateChunk { chunk_type, count } => {
+                write!(f, "{} appears {} times, only one allowed", chunk_type, count)
+            }
+            ValidationError::IhdrNotFirst => write!(f, "IHDR is not the first chunk"),
+            ValidationError::IendNotLast => write!(f, "IEND is not the last chunk"),
+            ValidationError::InvalidHeader(e) => write!(f, "invalid IHDR: {}", e),
+            ValidationError::MissingPalette => write!(f, "indexed image without PLTE"),
+            ValidationError::UnexpectedPalette(c) => write!(f, "PLTE not allowed for {:?}", c),
+            ValidationError::InvalidPaletteLength(l) => write!(f, "invalid PLTE length: {}", l),
+            ValidationError::PaletteAfterIdat => write!(f, "PLTE after IDAT"),
+            ValidationError::NonContiguousIdat { index } => {
+                write!(f, "IDAT at chunk {} is not next to the other IDATs", index)
+            }
+            ValidationError::ChunkTooLong { index, length } => {
+                write!(f, "chunk {} is {} bytes, over the 2^31 - 1 limit", index, length)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Png {
+    /// Checks the chunks against the structural rules of the spec:
+    /// IHDR first and IEND last, each only once, a valid IHDR,
+    /// PLTE rules for the colour type, IDATs present and consecutive,
+    /// and chunk lengths under the limit. The signature is not
+    /// checked here as a `Png` always writes the standard one.
+    ///
+    /// # Errors
+    /// returns every problem found, not only the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let chunks = self.chunks();
+
+        for (index, c) in chunks.iter().enumerate() {
+            if c.length() > MAX_CHUNK_LENGTH {
+                errors.push(ValidationError::ChunkTooLong { index, length: c.length() });
+            }
+        }
+
+        for t in [ChunkType::IHDR, ChunkType::PLTE, ChunkType::IEND] {
+            let count = chunks.iter().filter(|c| *c.chunk_type() == t).count();
+            if count == 0 && t != ChunkType::PLTE {
+                errors.push(ValidationError::MissingChunk(t));
+            } else if count > 1 {
+                errors.push(ValidationError::DuplicateChunk { chunk_type: t, count });
+            }
+        }
+
+        if let Some(c) = chunks.first() {
+            if c.chunk_type().bytes() != *b"IHDR" && self.chunk_by_type("IHDR").is_some() {
+                errors.push(ValidationError::IhdrNotFirst);
+            }
+        }
+        if let Some(c) = chunks.last() {
+            if c.chunk_type().bytes() != *b"IEND" && self.chunk_by_type("IEND").is_some() {
+                errors.push(ValidationError::IendNotLast);
+            }
+        }
+
+        let is_type = |i: usize, t: &[u8; 4]| chunks[i].chunk_type().bytes() == *t;
+        let idats: Vec<usize> = (0..chunks.len()).filter(|&i| is_type(i, b"IDAT")).collect();
+        match idats.first() {
+            None => errors.push(ValidationError::MissingChunk(ChunkType::IDAT)),
+            Some(&first) => {
+                if let Some(n) = idats.iter().enumerate().find(|(n, &i)| i != first + n) {
+                    errors.push(ValidationError::NonContiguousIdat { index: *n.1 });
+                }
+            }
+        }
+
+        let plte = (0..chunks.len()).find(|&i| is_type(i, b"PLTE"));
+        if let (Some(p), Some(&i)) = (plte, idats.first()) {
+            if p > i {
+                errors.push(ValidationError::PaletteAfterIdat);
+            }
+        }
+
+        if let Some(c) = self.chunk_by_type("IHDR") {
+            match Ihdr::try_from(c) {
+                Ok(ihdr) => check_palette(self, &ihdr, &mut errors),
+                Err(e) => errors.push(ValidationError::InvalidHeader(e.to_string())),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Indices of the chunks whose crc does not match, only possible
+    /// when parsed leniently. Checked in parallel with the `rayon` feature.
+    pub fn invalid_crcs(&self) -> Vec<usize> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            self.chunks()
+                .par_iter()
+                .enumerate()
+                .filter(|(_, c)| !c.has_valid_crc())
+                .map(|(n, _)| n)
+                .collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            self.iter().enumerate().filter(|(_, c)| !c.has_valid_crc()).map(|(n, _)| n).collect()
+        }
+    }
+}
+
+fn check_palette(png: &Png, ihdr: &Ihdr, errors: &mut Vec<ValidationError>) {
+    let plte = png.chunk_by_type("PLTE");
+    match (ihdr.color_type(), plte) {
+        (ColorType::Indexed, None) => errors.push(ValidationError::MissingPalette),
+        (c @ (ColorType::Grayscale | ColorType::GrayscaleAlpha), Some(_)) => {
+            errors.push(ValidationError::UnexpectedPalette(c));
+        }
+        (c, Some(p)) => {
+            let max = match c {
+                ColorType::Indexed => 1u32 << ihdr.bit_depth(),
+                _ => 256,
+            };
+            let len = p.length();
+            if len == 0 || len % 3 != 0 || len / 3 > max {
+                errors.push(ValidationError::InvalidPaletteLength(len));
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr(color_type: ColorType) -> Chunk {
+        Ihdr::new(1, 1, 8, color_type).unwrap().to_chunk()
+    }
+
+    fn valid_chunks() -> Vec<Chunk> {
+        vec![
+            ihdr(ColorType::Rgb),
+            chunk("tEXt", b"Comment\0hi"),
+            chunk("IDAT", &[1, 2]),
+            chunk("IDAT", &[3]),
+            chunk("IEND", &[]),
+        ]
+    }
+
+    #[test]
+    fn test_valid_png() {
+        assert!(Png::from_chunks(valid_chunks()).validate().is_ok());
+    }
+
+    #[test]
+    fn test_missing_chunks() {
+        let errors = Png::from_chunks(vec![]).validate().unwrap_err();
+        assert!(errors.contains(&ValidationError::MissingChunk(ChunkType::IHDR)));
+        assert!(errors.contains(&ValidationError::MissingChunk(ChunkType::IDAT)));
+        assert!(errors.contains(&ValidationError::MissingChunk(ChunkType::IEND)));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut chunks = valid_chunks();
+        chunks.swap(0, 1);
+        let iend = chunks.pop().unwrap();
+        chunks.insert(3, iend);
+        let errors = Png::from_chunks(chunks).validate().unwrap_err();
+        assert!(errors.contains(&ValidationError::IhdrNotFirst));
+        assert!(errors.contains(&ValidationError::IendNotLast));
+        assert!(errors.contains(&ValidationError::NonContiguousIdat { index: 4 }));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut chunks = valid_chunks();
+        chunks.insert(1, ihdr(ColorType::Rgb));
+        let errors = Png::from_chunks(chunks).validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::DuplicateChunk { chunk_type: ChunkType::IHDR, count: 2 }]);
+    }
+
+    fn palette_png(color_type: ColorType, plte: Option<(usize, &[u8])>) -> Png {
+        let mut chunks = valid_chunks();
+        chunks[0] = ihdr(color_type);
+        if let Some((index, data)) = plte {
+            chunks.insert(index, chunk("PLTE", data));
+        }
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_invalid_crcs() {
+        let png = palette_png(ColorType::Rgb, None);
+        assert!(png.invalid_crcs().is_empty());
+
+        let mut bytes = png.as_bytes();
+        let first_crc = 8 + 12 + 13 - 1;
+        bytes[first_crc] ^= 1;
+        let parsed = Png::from_bytes_with(&bytes, &crate::parse::ParseOptions::lenient()).unwrap();
+        assert_eq!(parsed.invalid_crcs(), vec![0]);
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        assert!(palette_png(ColorType::Indexed, Some((1, &[0, 0, 0]))).validate().is_ok());
+        assert!(palette_png(ColorType::Rgb, None).validate().is_ok());
+
+        let errors = palette_png(ColorType::Indexed, None).validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::MissingPalette]);
+
+        let errors = palette_png(ColorType::Indexed, Some((4, &[0, 0, 0]))).validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::PaletteAfterIdat]);
+
+        let errors = palette_png(ColorType::Indexed, Some((1, &[0, 0]))).validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::InvalidPaletteLength(2)]);
+
+        let errors = palette_png(ColorType::Grayscale, Some((1, &[0, 0, 0]))).validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::UnexpectedPalette(ColorType::Grayscale)]);
+    }
+}
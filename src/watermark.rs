@@ -0,0 +1,384 @@
+use std::convert::TryInto;
+
+use crate::chunk_type::ChunkType;
+use crate::crc::crc32;
+use crate::encode::EncodeOptions;
+use crate::error::{bail, Error};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+/// Marks the start of an invisible watermark in the sample LSBs.
+const MAGIC: &[u8; 4] = b"npWm";
+
+/// Where `Png::watermark_text` puts the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// 5x7 glyphs for ASCII 32 to 126, one byte per column, top row in the lowest bit.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5f, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7f, 0x14, 0x7f, 0x14],
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1c, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1c, 0x00],
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08],
+    [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3e, 0x51, 0x49, 0x45, 0x3e],
+    [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1e],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x00, 0x08, 0x14, 0x22, 0x41],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x41, 0x22, 0x14, 0x08, 0x00],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3e],
+    [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36],
+    [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c],
+    [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x01, 0x01],
+    [0x3e, 0x41, 0x41, 0x51, 0x32],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f],
+    [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01],
+    [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x04, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f],
+    [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06],
+    [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01],
+    [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f],
+    [0x7f, 0x20, 0x18, 0x20, 0x7f],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x00, 0x7f, 0x41, 0x41],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x41, 0x41, 0x7f, 0x00, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7f],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7e, 0x09, 0x01, 0x02],
+    [0x08, 0x14, 0x54, 0x54, 0x3c],
+    [0x7f, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7d, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3d, 0x00],
+    [0x00, 0x7f, 0x10, 0x28, 0x44],
+    [0x00, 0x41, 0x7f, 0x40, 0x00],
+    [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7c, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7c],
+    [0x7c, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20],
+    [0x3c, 0x40, 0x40, 0x20, 0x7c],
+    [0x1c, 0x20, 0x40, 0x20, 0x1c],
+    [0x3c, 0x40, 0x30, 0x40, 0x3c],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7f, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// The glyph of `c`, `?` for anything outside printable ASCII.
+fn glyph(c: char) -> &'static [u8; 5] {
+    match c {
+        ' '..='~' => &FONT[c as usize - 32],
+        _ => &FONT['?' as usize - 32],
+    }
+}
+
+/// Which pixels of a `width` x `height` box `text` covers at `scale`,
+/// row by row. Glyphs are 5 by 7 with a column between them.
+fn rasterize(text: &str, scale: u32) -> (u32, u32, Vec<bool>) {
+    let chars: Vec<char> = text.chars().collect();
+    let width = (chars.len() as u32 * 6).saturating_sub(1) * scale;
+    let height = 7 * scale;
+    let mut mask = vec![false; width as usize * height as usize];
+    for (n, c) in chars.iter().enumerate() {
+        for (col, bits) in glyph(*c).iter().enumerate() {
+            for row in 0..7 {
+                if bits >> row & 1 == 0 {
+                    continue;
+                }
+                let (x, y) = ((n as u32 * 6 + col as u32) * scale, row * scale);
+                for dy in 0..scale {
+                    let start = ((y + dy) * width + x) as usize;
+                    mask[start..start + scale as usize].fill(true);
+                }
+            }
+        }
+    }
+    (width, height, mask)
+}
+
+impl Png {
+    /// Draws `text` in white with a dark shadow in `corner`, blended in
+    /// with `opacity` from 0 to 1. The text is scaled with the image and
+    /// cut off if it does not fit, characters outside ASCII show as `?`.
+    ///
+    /// The image is stored again as 8-bit RGB, or RGBA if it has any
+    /// transparency, so PLTE, tRNS, bKGD, hIST and sBIT are removed.
+    /// Interlacing is kept.
+    ///
+    /// # Errors
+    /// returns an Error if the image can not be decoded.
+    pub fn watermark_text(&mut self, text: &str, corner: Corner, opacity: f32) -> Result<(), Error> {
+        let image = self.decode_pixels()?;
+        let alpha = matches!(image.color_type(), ColorType::GrayscaleAlpha | ColorType::Rgba)
+            || image.trns().is_some();
+        let mut rgba = image.to_rgba8()?.into_data();
+        let (width, height) = (image.width(), image.height());
+
+        let scale = (width.min(height) / 150).max(1);
+        let (text_width, text_height, mask) = rasterize(text, scale);
+        let margin = 2 * scale;
+        let left = match corner {
+            Corner::TopLeft | Corner::BottomLeft => margin as i64,
+            _ => width as i64 - (text_width + margin + scale) as i64,
+        };
+        let top = match corner {
+            Corner::TopLeft | Corner::TopRight => margin as i64,
+            _ => height as i64 - (text_height + margin + scale) as i64,
+        };
+        let covered = |x: i64, y: i64| {
+            (0..text_width as i64).contains(&x)
+                && (0..text_height as i64).contains(&y)
+                && mask[(y * text_width as i64 + x) as usize]
+        };
+
+        let opacity = opacity.clamp(0.0, 1.0);
+        for y in top.max(0)..(top + (text_height + scale) as i64).min(height as i64) {
+            for x in left.max(0)..(left + (text_width + scale) as i64).min(width as i64) {
+                let (tx, ty) = (x - left, y - top);
+                // the shadow is the text moved down and right by one scaled pixel
+                let target = if covered(tx, ty) {
+                    255.0
+                } else if covered(tx - scale as i64, ty - scale as i64) {
+                    0.0
+                } else {
+                    continue;
+                };
+                let at = (y as usize * width as usize + x as usize) * 4;
+                for v in &mut rgba[at..at + 3] {
+                    *v = (*v as f32 * (1.0 - opacity) + target * opacity).round() as u8;
+                }
+                let a = rgba[at + 3] as f32;
+                rgba[at + 3] = (a + (255.0 - a) * opacity).round() as u8;
+            }
+        }
+
+        let (color_type, pixels) = if alpha {
+            (ColorType::Rgba, rgba)
+        } else {
+            (ColorType::Rgb, rgba.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect())
+        };
+        let ihdr = Ihdr::new(width, height, 8, color_type)?.with_interlace(image.header().is_interlaced());
+        for t in [ChunkType::PLTE, ChunkType::TRNS, ChunkType::BKGD, ChunkType::HIST, ChunkType::SBIT] {
+            self.chunks_mut().retain(|c| *c.chunk_type() != t);
+        }
+        self.replace_pixels(&ihdr, &pixels, &EncodeOptions::default())
+    }
+
+    /// Hides `mark` in the least significant bit of the samples, a change
+    /// of at most one level nobody can see. It starts at the first pixel
+    /// with a marker, length and crc so `extract_watermark` finds it.
+    /// Re-encoding keeps it, anything that changes pixels removes it.
+    ///
+    /// # Errors
+    /// returns an Error if the image is indexed or under 8 bits, which an
+    /// LSB change would visibly alter, `mark` does not fit, or the image
+    /// can not be decoded.
+    pub fn watermark_invisible(&mut self, mark: &str) -> Result<(), Error> {
+        let ihdr = self.header()?;
+        check_lsb_format(&ihdr)?;
+        let mut pixels = self.decode_pixels()?.data().to_vec();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((mark.len() as u32).to_be_bytes());
+        bytes.extend(crc32(mark.as_bytes(), mark.len()).to_be_bytes());
+        bytes.extend(mark.as_bytes());
+        let step = ihdr.bit_depth() as usize / 8;
+        if bytes.len() * 8 > pixels.len() / step {
+            bail!("a {} byte watermark does not fit in {} samples", mark.len(), pixels.len() / step);
+        }
+        // the low byte of each sample, big endian for 16 bits
+        let samples = pixels.iter_mut().skip(step - 1).step_by(step);
+        for (sample, bit) in samples.zip(bytes.iter().flat_map(|b| (0..8).rev().map(move |i| b >> i & 1))) {
+            *sample = *sample & !1 | bit;
+        }
+        let options = EncodeOptions { bit_depth: ihdr.bit_depth(), ..Default::default() };
+        self.replace_pixels(&ihdr, &pixels, &options)
+    }
+
+    /// Reads a watermark written by `watermark_invisible`.
+    ///
+    /// # Errors
+    /// returns an Error if there is none, it does not match its crc, the
+    /// format could not hold one, or the image can not be decoded.
+    pub fn extract_watermark(&self) -> Result<String, Error> {
+        let ihdr = self.header()?;
+        check_lsb_format(&ihdr)?;
+        let image = self.decode_pixels()?;
+        let step = ihdr.bit_depth() as usize / 8;
+        let mut bits = image.data().iter().skip(step - 1).step_by(step).map(|s| s & 1);
+        let mut read = |n: usize| -> Vec<u8> {
+            (0..n).map(|_| bits.by_ref().take(8).fold(0, |b, bit| b << 1 | bit)).collect()
+        };
+
+        if read(4) != MAGIC {
+            bail!("no watermark");
+        }
+        let len = u32::from_be_bytes(read(4).as_slice().try_into()?) as usize;
+        let crc = u32::from_be_bytes(read(4).as_slice().try_into()?);
+        let max = image.data().len() / step / 8;
+        if len > max {
+            bail!("watermark length {} is more than the image holds", len);
+        }
+        let mark = read(len);
+        if mark.len() != len || crc32(&mark, len) != crc {
+            bail!("watermark does not match its crc, the pixels were changed");
+        }
+        Ok(String::from_utf8(mark)?)
+    }
+}
+
+fn check_lsb_format(ihdr: &Ihdr) -> Result<(), Error> {
+    if ihdr.color_type() == ColorType::Indexed || ihdr.bit_depth() < 8 {
+        let (depth, color_type) = (ihdr.bit_depth(), ihdr.color_type());
+        bail!("invisible watermarks need 8 or 16-bit samples, not {}-bit {:?}", depth, color_type);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    fn gray(width: u32, height: u32, value: u8) -> Png {
+        let pixels = vec![value; (width * height) as usize];
+        Png::from_pixels(width, height, ColorType::Grayscale, &pixels, EncodeOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let (width, height, mask) = rasterize("Hi", 1);
+        assert_eq!((width, height), (11, 7));
+        let art: Vec<String> = mask
+            .chunks(width as usize)
+            .map(|row| row.iter().map(|b| if *b { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(art[0], "#...#...#..");
+        assert_eq!(art[3], "#####...#..");
+        assert_eq!(art[6], "#...#..###.");
+        let (width, height, mask) = rasterize("é", 2);
+        assert_eq!((width, height), (10, 14));
+        assert_eq!(mask.len(), 140);
+        assert_eq!(rasterize("", 3).0, 0);
+    }
+
+    #[test]
+    fn test_watermark_text() {
+        let mut png = gray(40, 20, 100);
+        png.watermark_text("AB", Corner::BottomRight, 1.0).unwrap();
+        let image = png.decode_pixels().unwrap();
+        assert_eq!((image.color_type(), image.bit_depth()), (ColorType::Rgb, 8));
+        // "AB" is 11 x 7 with a shadow, 2 pixels in from the bottom right
+        let at = |x: u32, y: u32| &image.row(y)[x as usize * 3..x as usize * 3 + 3];
+        assert_eq!(at(26, 10), [100, 100, 100]); // the top left of A is off
+        assert_eq!(at(26, 12), [255, 255, 255]);
+        assert_eq!(at(27, 17), [0, 0, 0]); // shadow of the bottom of A
+        assert_eq!(at(0, 0), [100, 100, 100]);
+        assert_eq!(at(39, 19), [100, 100, 100]);
+
+        let mut faint = gray(40, 20, 100);
+        faint.watermark_text("AB", Corner::TopLeft, 0.5).unwrap();
+        let image = faint.decode_pixels().unwrap();
+        assert_eq!(&image.row(3)[6..9], [178, 178, 178]);
+        assert_eq!(&image.row(0)[..3], [100, 100, 100]);
+    }
+
+    #[test]
+    fn test_watermark_text_keeps_transparency() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.insert_after_type("PLTE", crate::chunk::Chunk::new(ChunkType::TRNS, vec![0])).unwrap();
+        let before = png.decode_pixels().unwrap().to_rgba8().unwrap();
+        png.watermark_text("very long text that does not fit", Corner::TopRight, 0.3).unwrap();
+        let after = png.decode_pixels().unwrap();
+        assert_eq!(after.color_type(), ColorType::Rgba);
+        assert!(png.chunk_by_type("PLTE").is_none());
+        assert_eq!(after.row(224), before.data()[224 * 225 * 4..].to_vec().as_slice());
+    }
+
+    #[test]
+    fn test_invisible_watermark() {
+        let mut png = gray(32, 32, 77);
+        png.watermark_invisible("© 2026 nice_pics").unwrap();
+        assert_eq!(png.extract_watermark().unwrap(), "© 2026 nice_pics");
+        let pixels = png.decode_pixels().unwrap();
+        assert!(pixels.data().iter().all(|p| *p == 76 || *p == 77));
+
+        let options = EncodeOptions { bit_depth: 16, ..Default::default() };
+        let pixels = vec![0x12; 16 * 16 * 3 * 2];
+        let mut deep = Png::from_pixels(16, 16, ColorType::Rgb, &pixels, options).unwrap();
+        deep.watermark_invisible("deep").unwrap();
+        assert_eq!(deep.extract_watermark().unwrap(), "deep");
+        assert!(deep.decode_pixels().unwrap().data().chunks(2).all(|s| s[0] == 0x12));
+
+        assert!(gray(32, 32, 77).extract_watermark().is_err());
+        assert!(gray(4, 4, 0).watermark_invisible("too long").is_err());
+        assert!(Png::try_from(IMG).unwrap().watermark_invisible("x").is_err());
+    }
+
+    #[test]
+    fn test_invisible_watermark_tampered() {
+        let mut png = gray(32, 32, 10);
+        png.watermark_invisible("mark").unwrap();
+        let ihdr = png.header().unwrap();
+        let mut pixels = png.decode_pixels().unwrap().data().to_vec();
+        pixels[12 * 8 + 3] ^= 1;
+        png.replace_pixels(&ihdr, &pixels, &EncodeOptions::default()).unwrap();
+        assert!(png.extract_watermark().is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::text::TextChunk;
+
+/// The iTXt keyword XMP packets are stored under.
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+impl Png {
+    /// The XMP packet from the `XML:com.adobe.xmp` iTXt chunk.
+    pub fn xmp(&self) -> Option<String> {
+        self.iter()
+            .filter(|c| *c.chunk_type() == ChunkType::ITXT)
+            .filter_map(|c| TextChunk::try_from(c).ok())
+            .find(|e| e.keyword() == XMP_KEYWORD)
+            .map(|e| e.text().to_string())
+    }
+
+    /// Stores `xml` as the XMP packet, replacing any other. It is written
+    /// as uncompressed iTXt before the image data, where tools that only
+    /// scan the start of a file look for it.
+    pub fn set_xmp(&mut self, xml: &str) {
+        self.remove_xmp();
+        let mut data = XMP_KEYWORD.as_bytes().to_vec();
+        data.extend([0, 0, 0, 0, 0]);
+        data.extend(xml.as_bytes());
+        let at = self
+            .chunks()
+            .iter()
+            .position(|c| matches!(*c.chunk_type(), ChunkType::IDAT | ChunkType::IEND))
+            .unwrap_or(self.chunks().len());
+        self.chunks_mut().insert(at, Chunk::new(ChunkType::ITXT, data));
+    }
+
+    /// Removes the XMP packet, returning whether there was one.
+    pub fn remove_xmp(&mut self) -> bool {
+        self.remove_text(XMP_KEYWORD) > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &[u8] = include_bytes!("../img.png");
+
+    const PACKET: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:creator>Zoë</dc:creator></rdf:Description>
+</rdf:RDF></x:xmpmeta><?xpacket end="w"?>"#;
+
+    #[test]
+    fn test_xmp() {
+        let mut png = Png::try_from(IMG).unwrap();
+        assert_eq!(png.xmp(), None);
+        png.set_xmp("<old/>");
+        png.set_xmp(PACKET);
+        assert_eq!(png.xmp().unwrap(), PACKET);
+        assert_eq!(png.text_entries().iter().filter(|e| e.keyword() == XMP_KEYWORD).count(), 1);
+
+        let chunk = png.chunks_of_type("iTXt").find(|c| c.data().starts_with(b"XML:")).unwrap();
+        assert!(chunk.data().starts_with(b"XML:com.adobe.xmp\0\0\0\0\0<?xpacket"));
+        let itxt = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::ITXT).unwrap();
+        let idat = png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IDAT).unwrap();
+        assert!(itxt < idat);
+
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(reparsed.xmp().unwrap(), PACKET);
+        assert!(png.remove_xmp());
+        assert!(!png.remove_xmp());
+    }
+
+    #[test]
+    fn test_xmp_ignores_other_types() {
+        let mut png = Png::try_from(IMG).unwrap();
+        png.set_text("XML:com.adobe.xmp", "<not-itxt/>").unwrap();
+        assert_eq!(png.xmp(), None);
+    }
+}